@@ -3,14 +3,22 @@ battling    = Battle Info
 chara_image = Chara Image
 disable     = Disable
 empty       = ---
-exact_match = Exact
 folder      = Folder
 graph       = Graph
 next        = Next
 passage     = Passage
 result_max  = Result Max
 search      = Search
+saved_search      = Saved searches
+saved_search_name = Search name
 status      = Status
+target_fps  = Analysis FPS
+api_server  = Local API
+text_output = Text Output
+trash_retention_days = Days in trash
+export      = Export
+per_battle  = Row per battle
+save        = Save
 million     = M
 play        = Play
 play_list   = Play List
@@ -21,6 +29,7 @@ warning     = Warning
 win_rate    = Win Rate
 win_lose    = Win/Lose
 wins        = Wins
+losses      = Losses
 
 # グラフのラベル
 losing      = Losing
@@ -33,6 +42,21 @@ winning     = Winning
 battle_information  = Battle Information
 battle_history      = Battle History
 gsp                 = GSP
+edit_battle         = Edit battle
+new_battle          = Add battle manually
+battle_count        = Battles
+tag                 = Tag
+tag_hint            = Tags (comma separated)
+note                = Note
+character           = Character
+rule                = Rule
+max_stock           = Max stock
+order               = Order
+date_range          = Date range
+player_count        = Players
+deleted_battle      = Moved the battle to trash
+undo                = Undo
+empty_trash         = Empty trash
 
 # 検出に関する文字列
 window          = Window
@@ -43,6 +67,8 @@ desktop         = Desktop
 tab_battle_history      = Result
 tab_character_table     = Chara Table
 tab_character_history   = Chara History
+tab_tag_history         = Tags
+tab_trash               = Trash
 
 # 設定タブに関する文字列
 config          = Config
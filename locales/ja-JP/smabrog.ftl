@@ -3,14 +3,22 @@ battling    = 戦闘中情報
 chara_image = キャラ画像
 disable     = 無効化
 empty       = ---
-exact_match = 完全一致
 folder      = フォルダ
 graph       = グラフ
 next        = 次
 passage     = 推移
 result_max  = 結果取得限界
 search      = 検索
+saved_search      = 保存した検索
+saved_search_name = 検索条件の名前
 status      = 状態
+target_fps  = 解析FPS
+api_server  = ローカルAPI
+text_output = テキスト出力
+trash_retention_days = ゴミ箱に残す日数
+export      = エクスポート
+per_battle  = 1試合1行
+save        = 保存
 million     = 万
 play        = 再生
 play_list   = 再生リスト
@@ -21,6 +29,7 @@ warning     = 警告
 win_rate    = 勝率
 win_lose    = 勝敗
 wins        = 連勝
+losses      = 連敗
 
 # グラフのラベル
 losing      = 負け
@@ -33,6 +42,21 @@ winning     = 勝ち
 battle_information  = 対戦情報
 battle_history      = 戦歴
 gsp                 = 世界戦闘力
+edit_battle         = 戦歴の修正
+new_battle          = 試合の手動入力
+battle_count        = 試合数
+tag                 = タグ
+tag_hint            = タグ (, 区切り)
+note                = メモ
+character           = キャラ
+rule                = ルール
+max_stock           = 最大残基
+order               = 順位
+date_range          = 期間
+player_count        = 人数
+deleted_battle      = 戦歴をゴミ箱に入れました
+undo                = 元に戻す
+empty_trash         = ゴミ箱を空にする
 
 # 検出に関する文字列
 window          = ウィンドウ
@@ -43,6 +67,8 @@ desktop         = デスクトップ
 tab_battle_history      = 戦の履歴
 tab_character_table     = 対キャラ表
 tab_character_history   = 対キャラ戦歴
+tab_tag_history         = タグ別
+tab_trash               = ゴミ箱
 
 # 設定タブに関する文字列
 config          = 設定
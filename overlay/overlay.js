@@ -0,0 +1,105 @@
+// smabrog overlay
+// /api/overlay の内容を表示して、/api/events にイベントが届く度に取得し直す
+(() => {
+    // EngineEvent::SCHEMA_VERSION と合わせる
+    const SCHEMA_VERSION = 1;
+    // WebSocket が切れた時に繋ぎ直すまでの時間と、念の為に取得し直す間隔 [ms]
+    const RECONNECT_INTERVAL = 3000;
+    const POLLING_INTERVAL = 10000;
+    // イベントが続けて来た時にまとめて取得する為の待ち時間 [ms]
+    const UPDATE_DELAY = 200;
+
+    const params = new URLSearchParams(location.search);
+    document.body.dataset.layout = params.get('layout') || 'standard';
+
+    const streakElement = document.getElementById('streak');
+    const sessionElement = document.getElementById('session');
+    const gspElement = document.getElementById('gsp');
+
+    function renderPlayer(playerNumber, player) {
+        const element = document.querySelector(`.player[data-player="${playerNumber}"]`);
+        const icon = element.querySelector('.icon');
+        const character = player ? player.character : '';
+        const src = player && player.has_icon ? `/overlay/icon/${encodeURIComponent(character)}` : '';
+        if (icon.getAttribute('src') !== src) {
+            icon.setAttribute('src', src);
+        }
+        element.querySelector('.character').textContent = character;
+    }
+
+    function renderSparkline(powerList) {
+        const polyline = gspElement.querySelector('polyline');
+        if (powerList.length < 2) {
+            polyline.setAttribute('points', '');
+            return;
+        }
+
+        const min = Math.min(...powerList);
+        const max = Math.max(...powerList);
+        const range = Math.max(max - min, 1);
+        const points = powerList.map((power, index) => {
+            const x = index / (powerList.length - 1) * 100;
+            const y = 30 - (power - min) / range * 30;
+            return `${x.toFixed(2)},${y.toFixed(2)}`;
+        });
+        polyline.setAttribute('points', points.join(' '));
+    }
+
+    function render(overlay) {
+        renderPlayer(0, overlay.player_list[0]);
+        renderPlayer(1, overlay.player_list[1]);
+
+        const streak = overlay.streak;
+        streakElement.classList.toggle('win', 0 < streak);
+        streakElement.classList.toggle('lose', streak < 0);
+        streakElement.querySelector('.value').textContent = Math.abs(streak);
+        streakElement.querySelector('.label').textContent = 0 <= streak ? overlay.streak_label.win : overlay.streak_label.lose;
+
+        const session = overlay.session;
+        sessionElement.querySelector('.win_rate').textContent = 0 < session.battle_count
+            ? `${(session.win_rate * 100).toFixed(1)}%`
+            : '--%';
+        sessionElement.querySelector('.win_lose').textContent = `${session.win} - ${session.lose}`;
+
+        renderSparkline(overlay.power_list);
+        const lastPower = overlay.power_list[overlay.power_list.length - 1];
+        gspElement.querySelector('.value').textContent = lastPower ? lastPower.toLocaleString() : '-';
+    }
+
+    async function update() {
+        try {
+            const response = await fetch('/api/overlay');
+            if (response.ok) {
+                render(await response.json());
+            }
+        } catch (e) {
+            // smabrog が止まっている間は何もしない
+        }
+    }
+
+    let updateTimer = null;
+    function scheduleUpdate() {
+        if (updateTimer === null) {
+            updateTimer = setTimeout(() => {
+                updateTimer = null;
+                update();
+            }, UPDATE_DELAY);
+        }
+    }
+
+    function connect() {
+        const socket = new WebSocket(`ws://${location.host}/api/events`);
+        socket.onmessage = (message) => {
+            const event = JSON.parse(message.data);
+            if (event.schema_version === SCHEMA_VERSION) {
+                scheduleUpdate();
+            }
+        };
+        socket.onopen = scheduleUpdate;
+        socket.onclose = () => setTimeout(connect, RECONNECT_INTERVAL);
+    }
+
+    update();
+    connect();
+    setInterval(update, POLLING_INTERVAL);
+})();
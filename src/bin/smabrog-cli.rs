@@ -0,0 +1,226 @@
+/*
+
+    smabrog-cli
+
+    GUI を起動せずに戦歴を扱う為のコマンド
+
+*/
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+use std::path::Path;
+
+use smabrog::context::AppContext;
+use smabrog::data::{
+    BattleRule,
+    SmashbrosData,
+    SmashbrosDataTrait,
+};
+use smabrog::export::{
+    BattleHistoryExporter,
+    ExportLayout,
+};
+use smabrog::import::BattleHistoryImporter;
+use smabrog::resource::{
+    BattleHistoryFilter,
+    BattleHistoryTrait,
+};
+
+
+const USAGE: &str = "\
+usage: smabrog-cli <command> [options]
+
+commands:
+    export <path.csv|path.xlsx>     filter に合う戦歴を新しい順に書き出す
+        --character <name>          自分 (1P) のキャラクター
+        --opponent <name>           相手 (2P) のキャラクター
+        --rule <rule>               Time, Stock, Stamina, Tournament
+        --since <date>              この日時以降 (YYYY-MM-DD か RFC3339)
+        --until <date>              この日時より前 (YYYY-MM-DD か RFC3339)
+        --manual_entry <true|false> 手動で入力した試合だけか、キャプチャした試合だけ
+        --tag <tag,...>             この全てのタグが付いた試合
+        --max_stock <count>         自分 (1P) の最大ストック数
+        --result <win|lose>         勝った試合だけか、負けた試合だけ
+        --min_power <gsp>           自分 (1P) の世界戦闘力がこれ以上
+        --max_power <gsp>           自分 (1P) の世界戦闘力がこれ以下
+        --bgm <name>                BGM
+        --player_count <count>      プレイヤーの人数
+        --limit <count>             書き出す上限 (既定 10000)
+        --per-battle                1 試合を 1 行にして、プレイヤー毎の列を横に並べる
+    import <path.csv|path.json>     外部の戦歴 (CSV, smabrog/旧 Python 版の JSON) を取り込む
+        --apply                     差分を表示した後に保存する (無ければ表示のみ)
+    add                             キャプチャしていない試合を手動で入力する
+        --character <name>          自分 (1P) のキャラクター (必須)
+        --opponent <name>           相手 (2P) のキャラクター (必須)
+        --result <win|lose>         勝敗 (必須)
+        --rule <rule>               Time, Stock, Stamina, Tournament (既定 Stock)
+        --power <gsp>               自分の世界戦闘力
+        --opponent_power <gsp>      相手の世界戦闘力
+        --date <date>               試合の日時 (YYYY-MM-DD か RFC3339, 既定は今)
+        --tag <tag,...>             試合に付けるタグ
+        --note <text>               試合のメモ
+";
+
+// 値を取らないオプション
+const FLAG_LIST: [&str; 2] = ["per-battle", "apply"];
+const DEFAULT_EXPORT_LIMIT: i64 = 10000;
+
+// (位置引数, --name value, --flag) に分ける
+fn parse_args(args: &[String]) -> Result<(Vec<String>, HashMap<String, String>, HashSet<String>), String> {
+    let mut positional_list = Vec::new();
+    let mut option_list = HashMap::new();
+    let mut flag_list = HashSet::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let name = match arg.strip_prefix("--") {
+            Some(name) => name,
+            None => {
+                positional_list.push(arg.clone());
+                continue;
+            },
+        };
+        if FLAG_LIST.contains(&name) {
+            flag_list.insert(name.to_string());
+            continue;
+        }
+        match args.next() {
+            Some(value) => option_list.insert(name.to_string(), value.clone()),
+            None => return Err(format!("--{} needs a value.", name)),
+        };
+    }
+
+    Ok((positional_list, option_list, flag_list))
+}
+
+// GUI と同じ言語でキャラ名を変換するので、設定ファイルがあれば読み込む
+fn new_context() -> AppContext {
+    let context = AppContext::new();
+    if let Err(e) = context.load_config(false) {
+        eprintln!("warning: skip load config. {}", e);
+    }
+
+    context
+}
+
+fn export(args: &[String]) -> Result<(), String> {
+    let (positional_list, option_list, flag_list) = parse_args(args)?;
+    let path = match positional_list.as_slice() {
+        [path] => Path::new(path),
+        _ => return Err("export needs one output path.".to_string()),
+    };
+    let mut context = new_context();
+    let filter = BattleHistoryFilter::from_query(&option_list, &context.smashbros_resource())?;
+    let limit = match option_list.get("limit") {
+        Some(limit) => limit.parse::<i64>().map_err(|_| "--limit is not a number.".to_string())?,
+        None => DEFAULT_EXPORT_LIMIT,
+    };
+    let layout = if flag_list.contains("per-battle") { ExportLayout::PerBattle } else { ExportLayout::PerPlayer };
+
+    let data_list = context.find_data_by_filter(&filter, 0, limit)
+        .ok_or("failed to find battle history.".to_string())?;
+    BattleHistoryExporter::write(path, &data_list, layout).map_err(|e| e.to_string())?;
+    println!("exported {} battles to {}", data_list.len(), path.display());
+
+    Ok(())
+}
+
+fn import(args: &[String]) -> Result<(), String> {
+    let (positional_list, _, flag_list) = parse_args(args)?;
+    let path = match positional_list.as_slice() {
+        [path] => Path::new(path),
+        _ => return Err("import needs one input path.".to_string()),
+    };
+
+    let mut context = new_context();
+    let _guard = context.enter();
+    let mut preview = BattleHistoryImporter::read(path, &context.smashbros_resource()).map_err(|e| e.to_string())?;
+    preview.mark_duplicated(&mut context);
+    println!("{}", preview.to_text());
+    if !flag_list.contains("apply") {
+        println!("dry run. add --apply to import.");
+        return Ok(());
+    }
+
+    let count = preview.import(&mut context);
+    println!("imported {} battles from {}", count, path.display());
+
+    Ok(())
+}
+
+fn add(args: &[String]) -> Result<(), String> {
+    use std::str::FromStr;
+    let (positional_list, option_list, _) = parse_args(args)?;
+    if !positional_list.is_empty() {
+        return Err("add takes only options.".to_string());
+    }
+
+    let mut context = new_context();
+    let _guard = context.enter();
+    let get_character = |key: &str| -> Result<String, String> {
+        let chara_name = option_list.get(key).ok_or(format!("--{} is required.", key))?;
+        match context.smashbros_resource().convert_character_name(chara_name.to_uppercase()) {
+            Some((chara_name, ratio)) if 1.0 <= ratio => Ok(chara_name),
+            Some((maybe_chara_name, _)) => Err(format!("{} is not a character. ({}?)", chara_name, maybe_chara_name)),
+            None => Err(format!("{} is not a character.", chara_name)),
+        }
+    };
+    let get_power = |key: &str| -> Result<i32, String> {
+        match option_list.get(key) {
+            Some(power) => power.replace(',', "").parse::<i32>().map_err(|_| format!("--{} is not a number.", key)),
+            None => Ok(-1),
+        }
+    };
+    let chara_list = [get_character("character")?, get_character("opponent")?];
+    let is_win = match option_list.get("result").map(|result| result.as_str()) {
+        Some("win") => true,
+        Some("lose") => false,
+        _ => return Err("--result needs win or lose.".to_string()),
+    };
+    let rule = match option_list.get("rule") {
+        Some(rule_name) => match BattleRule::from_str(rule_name) {
+            Ok(BattleRule::Unknown) | Err(_) => return Err(format!("{} is not a rule.", rule_name)),
+            Ok(rule) => rule,
+        },
+        None => BattleRule::Stock,
+    };
+    let start_time = match option_list.get("date") {
+        Some(date) => BattleHistoryFilter::parse_date_time(date).ok_or("--date is not a date (YYYY-MM-DD or RFC3339).".to_string())?,
+        None => chrono::Local::now(),
+    };
+    // 未来の日時の試合は終わっていないので保存されない
+    if chrono::Local::now() < start_time {
+        return Err(format!("--date is in the future: {}", start_time.format("%Y-%m-%d %H:%M:%S")));
+    }
+
+    let mut data = SmashbrosData::new_manual_entry(chara_list, rule, is_win, [get_power("power")?, get_power("opponent_power")?], start_time);
+    if let Some(tag_list) = option_list.get("tag") {
+        data.set_tag_list(BattleHistoryFilter::parse_tag_list(tag_list));
+    }
+    if let Some(note) = option_list.get("note") {
+        data.set_note(note.clone());
+    }
+    data.validate_manual_edit(&context.smashbros_resource())?;
+    let id = data.save_battle_with(&mut context).ok_or("failed save battle data to database.".to_string())?;
+    println!("added {} vs {} ({}) as {}", data.get_character(0), data.get_character(1), if is_win { "win" } else { "lose" }, id);
+
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(|command| command.as_str()) {
+        Some("export") => export(&args[1..]),
+        Some("import") => import(&args[1..]),
+        Some("add") => add(&args[1..]),
+        _ => {
+            print!("{}", USAGE);
+            std::process::exit(2);
+        },
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
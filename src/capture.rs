@@ -1,6 +1,5 @@
-#![cfg(windows)]
-#![windows_subsystem = "windows"]
 
+use i18n_embed::fluent::FluentLanguageLoader;
 use i18n_embed_fl::fl;
 use opencv::{
     core,
@@ -14,7 +13,6 @@ use serde::{
     Deserialize
 };
 
-use crate::resource::LANG_LOADER;
 use crate::scene::{
     ReadyToFightScene,
     SceneTrait,
@@ -22,20 +20,28 @@ use crate::scene::{
 
 pub mod codec;
 pub mod frame_store;
+#[cfg(windows)]
 pub mod retro;
 pub mod base;
+#[cfg(windows)]
 pub mod from_desktop;
 pub mod from_empty;
+pub mod from_file;
 pub mod from_video_device;
+#[cfg(windows)]
 pub mod from_window;
 
 pub use codec::*;
 pub use frame_store::*;
+#[cfg(windows)]
 pub use retro::*;
 pub use base::CaptureBase;
+#[cfg(windows)]
 pub use from_desktop::CaptureFromDesktop;
 pub use from_empty::CaptureFromEmpty;
+pub use from_file::CaptureFromFile;
 pub use from_video_device::CaptureFromVideoDevice;
+#[cfg(windows)]
 pub use from_window::CaptureFromWindow;
 
 
@@ -50,29 +56,32 @@ pub enum CaptureMode {
     Window(String, String),
 }
 impl CaptureMode {
-    pub fn new_empty() -> Self { Self::Empty { 0: fl!(LANG_LOADER().get(), "empty") } }
-    pub fn new_desktop() -> Self { Self::Desktop { 0: fl!(LANG_LOADER().get(), "desktop") } }
-    pub fn new_video_device(device_id: i32) -> Self {
-        Self::VideoDevice { 0: fl!(LANG_LOADER().get(), "video_device"), 1:device_id, 2:String::new() }
+    pub fn new_empty(lang_loader: &FluentLanguageLoader) -> Self { Self::Empty { 0: fl!(lang_loader, "empty") } }
+    pub fn new_desktop(lang_loader: &FluentLanguageLoader) -> Self { Self::Desktop { 0: fl!(lang_loader, "desktop") } }
+    pub fn new_video_device(device_id: i32, lang_loader: &FluentLanguageLoader) -> Self {
+        Self::VideoDevice { 0: fl!(lang_loader, "video_device"), 1:device_id, 2:String::new() }
     }
-    pub fn new_window(win_caption: String) -> Self {
-        Self::Window { 0: fl!(LANG_LOADER().get(), "window"), 1:win_caption }
+    pub fn new_window(win_caption: String, lang_loader: &FluentLanguageLoader) -> Self {
+        Self::Window { 0: fl!(lang_loader, "window"), 1:win_caption }
     }
 
-    pub fn is_default(&self) -> bool {
-        if Self::new_empty() == *self {
-            return true;
-        }
-        if Self::new_desktop() == *self {
-            return true;
-        }
-        if Self::new_video_device(-1) == *self {
-            return true;
+    /// 表示名 (保存されている表示名は保存した時の言語なので、表示する時はこちらを使う)
+    pub fn get_name(&self, lang_loader: &FluentLanguageLoader) -> String {
+        match self {
+            Self::Empty(_) => fl!(lang_loader, "empty"),
+            Self::Desktop(_) => fl!(lang_loader, "desktop"),
+            Self::VideoDevice(_, _, _) => fl!(lang_loader, "video_device"),
+            Self::Window(_, _) => fl!(lang_loader, "window"),
         }
-        if Self::new_window(String::new()) == *self {
-            return true;
+    }
+
+    /// 何もキャプチャしないものか (表示名の言語は問わない)
+    pub fn is_default(&self) -> bool {
+        match self {
+            Self::Empty(_) | Self::Desktop(_) => true,
+            Self::VideoDevice(_, device_id, device_name) => -1 == *device_id && device_name.is_empty(),
+            Self::Window(_, win_caption) => win_caption.is_empty(),
         }
-        return false;
     }
 
     pub fn is_empty(&self) -> bool {
@@ -90,7 +99,8 @@ impl CaptureMode {
 }
 impl Default for CaptureMode {
     fn default() -> Self {
-        Self::new_empty()
+        // 表示名は get_name で表示する時に決める
+        Self::Empty(String::new())
     }
 }
 impl std::fmt::Display for CaptureMode {
@@ -1,6 +1,7 @@
 use super::*;
 
 /// コーデックを保持するクラス
+#[derive(Clone)]
 pub struct Codec {
     pub backend: i32,
     fourcc: i32,
@@ -77,22 +78,3 @@ impl Codec {
         reader.open_file(&self.file_name.clone().unwrap(), self.backend)
     }
 }
-/// シングルトンでコーデックを保持するため
-pub struct WrappedCodec {
-    codecs: Option<Codec>,
-}
-impl WrappedCodec {
-    // 参照して返さないと、unwrap() で move 違反がおきてちぬ！
-    pub fn get_mut(&mut self) -> &mut Codec {
-        if self.codecs.is_none() {
-            self.codecs = Some(Codec::find_codec(None));
-        }
-        self.codecs.as_mut().unwrap()
-    }
-}
-static mut CODECS: WrappedCodec = WrappedCodec {
-    codecs: None
-};
-pub fn codecs() -> &'static mut WrappedCodec {
-    unsafe { &mut CODECS }
-}
@@ -10,13 +10,15 @@ pub struct CaptureFrameStore {
     filled_by_frame: bool,
     pub recoded_frame: i32,
     pub file_name: String,
+    codec: Codec,
 
     recoding_start_time: Option<Instant>,
     recoding_end_time: Option<Instant>,
     recoding_need_frame: Option<i32>,
 }
-impl Default for CaptureFrameStore {
-    fn default() -> Self {
+impl CaptureFrameStore {
+    /// codec で録画/再生する (AppContext::codec)
+    pub fn new(codec: Codec) -> Self {
         Self {
             writer: videoio::VideoWriter::default().unwrap(),
             reader: videoio::VideoCapture::default().unwrap(),
@@ -25,14 +27,14 @@ impl Default for CaptureFrameStore {
             filled_by_frame: false,
             recoded_frame: 0,
             file_name: "temp".to_string(),
+            codec,
 
             recoding_start_time: None,
             recoding_end_time: None,
             recoding_need_frame: None,
         }
     }
-}
-impl CaptureFrameStore {
+
     pub fn set_file_name(mut self, file_name: String) -> Self {
         self.file_name = file_name;
 
@@ -52,8 +54,9 @@ impl CaptureFrameStore {
             self.reader.release()?;
         }
 
-        codecs().get_mut().file_name = Some(self.file_name.clone());
-        if !codecs().get_mut().open_writer_with_codec(&mut self.writer).unwrap_or(false) {
+        let mut codec = self.codec.clone();
+        codec.file_name = Some(self.file_name.clone());
+        if !codec.open_writer_with_codec(&mut self.writer).unwrap_or(false) {
             return Err(opencv::Error::new( 0, "not found Codec for [*.mp4 or *.avi]. maybe: you install any Codec for your PC".to_string() ));
         }
 
@@ -71,8 +74,9 @@ impl CaptureFrameStore {
             self.writer.release()?;
         }
 
-        codecs().get_mut().file_name = Some(self.file_name.clone());
-        if !codecs().get_mut().open_reader_with_codec(&mut self.reader).unwrap_or(false) {
+        let mut codec = self.codec.clone();
+        codec.file_name = Some(self.file_name.clone());
+        if !codec.open_reader_with_codec(&mut self.reader).unwrap_or(false) {
             return Err(opencv::Error::new( 0, "not initialized video reader. maybe: playing temp video?".to_string() ));
         }
 
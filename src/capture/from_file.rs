@@ -0,0 +1,88 @@
+use super::*;
+
+/// 連番画像のディレクトリ か 動画ファイル から Mat
+/// 最後まで読むと Err を返す
+pub struct CaptureFromFile {
+    pub video_capture: Option<videoio::VideoCapture>,
+    pub image_path_list: Vec<std::path::PathBuf>,
+    pub frame_index: usize,
+    pub prev_image: core::Mat,
+    is_end: bool,
+}
+impl CaptureTrait for CaptureFromFile {
+    fn get_mat(&mut self) -> anyhow::Result<core::Mat> {
+        let capture_image = match self.video_capture.as_mut() {
+            Some(video_capture) => {
+                let mut capture_image = core::Mat::default();
+                if !video_capture.read(&mut capture_image)? {
+                    capture_image = core::Mat::default();
+                }
+
+                capture_image
+            },
+            None => match self.image_path_list.get(self.frame_index) {
+                Some(path) => imgcodecs::imread(&path.to_string_lossy(), imgcodecs::IMREAD_COLOR)?,
+                None => core::Mat::default(),
+            },
+        };
+        if capture_image.empty() {
+            self.is_end = true;
+            anyhow::bail!("end of capture file.");
+        }
+        self.frame_index += 1;
+
+        // 各シーンは 640x360 を想定しているので合わせる
+        if capture_image.cols() != 640 || capture_image.rows() != 360 {
+            imgproc::resize(&capture_image, &mut self.prev_image, core::Size { width: 640, height: 360 }, 0.0, 0.0, imgproc::INTER_LINEAR)?;
+        } else {
+            self.prev_image = capture_image;
+        }
+
+        Ok(self.prev_image.try_clone()?)
+    }
+}
+impl CaptureFromFile {
+    /// @param path 画像(png, jpg)が入ったディレクトリ か 動画ファイル
+    pub fn new(path: &std::path::Path) -> anyhow::Result<Self> {
+        let mut own = Self {
+            video_capture: None,
+            image_path_list: Vec::new(),
+            frame_index: 0,
+            prev_image: core::Mat::default(),
+            is_end: false,
+        };
+
+        if path.is_dir() {
+            own.image_path_list = std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| match path.extension().and_then(|ext| ext.to_str()) {
+                    Some(ext) => ["png", "jpg", "jpeg", "bmp"].contains(&ext.to_lowercase().as_str()),
+                    None => false,
+                })
+                .collect();
+            own.image_path_list.sort();
+            if own.image_path_list.is_empty() {
+                anyhow::bail!("not found image in {:?}", path);
+            }
+        } else {
+            let video_capture = videoio::VideoCapture::from_file(&path.to_string_lossy(), videoio::CAP_ANY)?;
+            if !video_capture.is_opened()? {
+                anyhow::bail!("failed to open {:?}", path);
+            }
+            own.video_capture = Some(video_capture);
+        }
+        log::info!("capture file {:?}.", path);
+
+        Ok(own)
+    }
+
+    /// 最後まで読み込んだか
+    pub fn is_end(&self) -> bool {
+        self.is_end
+    }
+
+    /// 読み込んだフレーム数
+    pub fn get_frame_index(&self) -> usize {
+        self.frame_index
+    }
+}
@@ -45,7 +45,7 @@ impl CaptureFromVideoDevice {
         };
 
         // 1回目テスト
-        let mut ready_to_fight_scene = ReadyToFightScene::default();
+        let mut ready_to_fight_scene = ReadyToFightScene::new_gray();
         let capture_image = match own.get_mat() {
             Ok(capture_image) => capture_image,
             Err(e) => {
@@ -0,0 +1,276 @@
+use i18n_embed::{
+    fluent::{
+        fluent_language_loader,
+        FluentLanguageLoader,
+    },
+    LanguageLoader,
+    unic_langid::LanguageIdentifier,
+};
+use once_cell::sync::OnceCell;
+use std::cell::RefCell;
+use std::sync::{
+    Arc,
+    Mutex,
+    MutexGuard,
+    RwLock,
+    RwLockReadGuard,
+    RwLockWriteGuard,
+};
+
+use crate::capture::Codec;
+use crate::data::SmashbrosData;
+use crate::resource::{
+    BattleHistory,
+    BattleHistoryFilter,
+    BattleHistoryPage,
+    BattleHistoryTrait,
+    BattleStats,
+    BattleStatsGroup,
+    GUIConfig,
+    Localizations,
+    SmashbrosResource,
+};
+
+
+/// アプリケーション全体で共有する状態
+/// clone したものは同じ状態を指すので、別スレッドで使う場合は clone して enter する
+#[derive(Clone)]
+pub struct AppContext {
+    inner: Arc<AppContextInner>,
+}
+struct AppContextInner {
+    lang_loader: FluentLanguageLoader,
+    // DB への接続は必要になるまで遅らせる
+    battle_history: Mutex<Option<Box<dyn BattleHistoryTrait + Send>>>,
+    smashbros_resource: OnceCell<RwLock<SmashbrosResource>>,
+    gui_config: Mutex<GUIConfig>,
+    codec: OnceCell<Codec>,
+}
+
+thread_local! {
+    static CURRENT_CONTEXT: RefCell<Option<AppContext>> = RefCell::new(None);
+}
+
+/// enter したスレッドのコンテキストを drop 時にもとに戻す
+pub struct AppContextGuard {
+    prev_context: Option<AppContext>,
+}
+impl Drop for AppContextGuard {
+    fn drop(&mut self) {
+        let prev_context = self.prev_context.take();
+        CURRENT_CONTEXT.with(|current| *current.borrow_mut() = prev_context);
+    }
+}
+
+impl Default for AppContext {
+    fn default() -> Self { Self::new() }
+}
+impl AppContext {
+    /// 戦歴を MongoDB に保存するコンテキストを作成する
+    pub fn new() -> Self {
+        Self::with_battle_history_option(None)
+    }
+
+    /// 戦歴を battle_history に保存するコンテキストを作成する (テストなど)
+    pub fn with_battle_history(battle_history: Box<dyn BattleHistoryTrait + Send>) -> Self {
+        Self::with_battle_history_option(Some(battle_history))
+    }
+
+    fn with_battle_history_option(battle_history: Option<Box<dyn BattleHistoryTrait + Send>>) -> Self {
+        Self {
+            inner: Arc::new(AppContextInner {
+                lang_loader: Self::new_lang_loader(),
+                battle_history: Mutex::new(battle_history),
+                smashbros_resource: OnceCell::new(),
+                gui_config: Mutex::new(GUIConfig::default()),
+                codec: OnceCell::new(),
+            }),
+        }
+    }
+
+    fn new_lang_loader() -> FluentLanguageLoader {
+        let loader = fluent_language_loader!();
+
+        match loader.load_fallback_language(&Localizations) {
+            Ok(()) => loader,
+            Err(_e) => FluentLanguageLoader::new(
+                "ja-JP",
+                LanguageIdentifier::from_bytes("ja-JP".as_bytes()).expect("lang parsing failed"),
+            ),
+        }
+    }
+
+    /// 現在のスレッドのコンテキストを返す
+    /// enter されていないスレッドで呼ぶのは間違いなので panic する (引数で渡すこと)
+    pub fn current() -> Self {
+        Self::try_current().expect("AppContext is not entered on this thread.")
+    }
+
+    /// 現在のスレッドのコンテキストを返す (enter されていなければ None)
+    pub fn try_current() -> Option<Self> {
+        CURRENT_CONTEXT.with(|current| current.borrow().clone())
+    }
+
+    /// 現在のスレッドのコンテキストを self にする
+    pub fn enter(&self) -> AppContextGuard {
+        let prev_context = CURRENT_CONTEXT.with(|current| current.borrow_mut().replace(self.clone()));
+
+        AppContextGuard { prev_context }
+    }
+
+    /// i18n
+    pub fn lang_loader(&self) -> &FluentLanguageLoader {
+        &self.inner.lang_loader
+    }
+
+    /// 言語の変更
+    pub fn change_language(&self, lang: LanguageIdentifier) {
+        let _result = i18n_embed::select(&self.inner.lang_loader, &Localizations, &[lang]);
+    }
+
+    /// 言語コード (ja, en など, テンプレート画像のファイル名に使う)
+    pub fn lang_code(&self) -> String {
+        self.inner.lang_loader.current_language().language.as_str().to_string()
+    }
+
+    pub fn is_lang_english(&self) -> bool {
+        self.inner.lang_loader.current_language().language.as_str() == "en"
+    }
+
+    /// 戦歴の保存先を使う
+    pub fn use_battle_history<R>(&self, callback: impl FnOnce(&mut dyn BattleHistoryTrait) -> R) -> R {
+        let mut battle_history = self.inner.battle_history.lock().unwrap();
+        let battle_history = battle_history.get_or_insert_with(|| Box::new(BattleHistory::new()));
+
+        callback(battle_history.as_mut())
+    }
+
+    /// GUI から画像を読み込んだリソースにする
+    pub fn init_smashbros_resource(&self, ctx: &egui::Context) {
+        let smashbros_resource = SmashbrosResource::new(ctx, self.lang_loader());
+        match self.inner.smashbros_resource.get() {
+            Some(lock) => *lock.write().unwrap() = smashbros_resource,
+            None => {
+                let _ = self.inner.smashbros_resource.set(RwLock::new(smashbros_resource));
+            },
+        }
+    }
+
+    fn smashbros_resource_lock(&self) -> &RwLock<SmashbrosResource> {
+        self.inner.smashbros_resource.get_or_init(|| {
+            // icon および image は GUI から init_smashbros_resource されるまで空のままにしておく
+            RwLock::new(SmashbrosResource::new_for_test(self.lang_loader()))
+        })
+    }
+
+    pub fn smashbros_resource(&self) -> RwLockReadGuard<'_, SmashbrosResource> {
+        self.smashbros_resource_lock().read().unwrap()
+    }
+
+    pub fn smashbros_resource_mut(&self) -> RwLockWriteGuard<'_, SmashbrosResource> {
+        self.smashbros_resource_lock().write().unwrap()
+    }
+
+    /// 設定ファイルを読み込んで、設定されている言語にする (GUI 以外から使う時も同じ言語でキャラ名を変換する)
+    pub fn load_config(&self, is_initialize: bool) -> anyhow::Result<()> {
+        self.gui_config().load_config(is_initialize)?;
+        let lang = self.gui_config().lang.clone();
+        if let Some(lang) = lang {
+            self.change_language(lang);
+            if let Some(lock) = self.inner.smashbros_resource.get() {
+                lock.write().unwrap().change_language(self.lang_loader());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 設定ファイル (同じ文の中で二重に lock しないように注意)
+    pub fn gui_config(&self) -> MutexGuard<'_, GUIConfig> {
+        self.inner.gui_config.lock().unwrap()
+    }
+
+    /// インストールされているコーデック
+    pub fn codec(&self) -> Codec {
+        self.inner.codec.get_or_init(|| Codec::find_codec(None)).clone()
+    }
+}
+/// SceneManager などにそのまま保存先として渡せるように
+impl BattleHistoryTrait for AppContext {
+    fn insert_data(&mut self, data: &SmashbrosData) -> Option<String> {
+        self.use_battle_history(|battle_history| battle_history.insert_data(data))
+    }
+    fn update_data(&mut self, data: &SmashbrosData) -> Option<String> {
+        self.use_battle_history(|battle_history| battle_history.update_data(data))
+    }
+    fn delete_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()> {
+        self.use_battle_history(|battle_history| battle_history.delete_data(data))
+    }
+    fn restore_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()> {
+        self.use_battle_history(|battle_history| battle_history.restore_data(data))
+    }
+    fn purge_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()> {
+        self.use_battle_history(|battle_history| battle_history.purge_data(data))
+    }
+    fn find_data_limit(&mut self, result_max: i64) -> Option<Vec<SmashbrosData>> {
+        self.use_battle_history(|battle_history| battle_history.find_data_limit(result_max))
+    }
+    fn find_data_by_chara_list(&mut self, character_list: Vec<String>, limit: i64, use_in: bool) -> Option<Vec<SmashbrosData>> {
+        self.use_battle_history(|battle_history| battle_history.find_data_by_chara_list(character_list, limit, use_in))
+    }
+    fn find_data_by_filter(&mut self, filter: &BattleHistoryFilter, skip: i64, limit: i64) -> Option<Vec<SmashbrosData>> {
+        self.use_battle_history(|battle_history| battle_history.find_data_by_filter(filter, skip, limit))
+    }
+    fn find_data_by_cursor(&mut self, filter: &BattleHistoryFilter, cursor: Option<String>, limit: i64) -> Option<BattleHistoryPage> {
+        self.use_battle_history(|battle_history| battle_history.find_data_by_cursor(filter, cursor, limit))
+    }
+    fn find_tag_list(&mut self) -> Option<Vec<String>> {
+        self.use_battle_history(|battle_history| battle_history.find_tag_list())
+    }
+    fn find_deleted_data(&mut self, limit: i64) -> Option<Vec<SmashbrosData>> {
+        self.use_battle_history(|battle_history| battle_history.find_deleted_data(limit))
+    }
+    fn purge_deleted_data(&mut self, until: chrono::DateTime<chrono::Local>) -> anyhow::Result<usize> {
+        self.use_battle_history(|battle_history| battle_history.purge_deleted_data(until))
+    }
+    fn aggregate_stats(&mut self, filter: &BattleHistoryFilter, group: BattleStatsGroup) -> Option<Vec<BattleStats>> {
+        self.use_battle_history(|battle_history| battle_history.aggregate_stats(filter, group))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enter() {
+        let context = AppContext::new();
+        {
+            let _guard = context.enter();
+            assert!(Arc::ptr_eq(&AppContext::current().inner, &context.inner));
+        }
+        assert!(AppContext::try_current().is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_current_without_enter() {
+        AppContext::current();
+    }
+
+    #[test]
+    fn test_battle_history_per_context() {
+        use crate::resource::InMemoryBattleHistory;
+
+        // コンテキスト毎に保存先が分かれている
+        let battle_history = InMemoryBattleHistory::new();
+        let mut context = AppContext::with_battle_history(Box::new(battle_history.clone()));
+        let mut other_context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
+
+        context.insert_data(&SmashbrosData::default());
+        assert_eq!(battle_history.get_data_list().len(), 1);
+        assert_eq!(context.find_data_limit(10).unwrap().len(), 1);
+        assert_eq!(other_context.find_data_limit(10).unwrap().len(), 0);
+    }
+}
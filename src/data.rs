@@ -10,6 +10,7 @@ use serde::{
 };
 use std::collections::HashMap;
 
+use crate::context::AppContext;
 use crate::resource::*;
 
 
@@ -132,6 +133,8 @@ pub trait SmashbrosDataTrait {
     fn get_start_time(&self) -> Option<DateTime<chrono::Local>>;
     /// 試合終了時刻の取得
     fn get_end_time(&self) -> Option<DateTime<chrono::Local>>;
+    /// ゴミ箱に入れた時刻の取得
+    fn get_deleted_time(&self) -> Option<DateTime<chrono::Local>>;
 
     /// プレイヤー数の取得
     fn get_player_count(&self) -> i32;
@@ -155,6 +158,12 @@ pub trait SmashbrosDataTrait {
     fn get_order(&self, player_number: i32) -> i32;
     /// プレイヤーの順位の取得
     fn get_power(&self, player_number: i32) -> i32;
+    /// 手動で修正したフィールドの取得
+    fn get_manual_field_list(&self) -> Vec<String>;
+    /// タグの取得
+    fn get_tag_list(&self) -> Vec<String>;
+    /// メモの取得
+    fn get_note(&self) -> String;
 
     // gettter
     /// DB key
@@ -167,6 +176,8 @@ pub trait SmashbrosDataTrait {
     fn set_start_time(&mut self, value: Option<DateTime<chrono::Local>>);
     /// 試合終了時刻の設定
     fn set_end_time(&mut self, value: Option<DateTime<chrono::Local>>);
+    /// ゴミ箱に入れた時刻の設定 (None でゴミ箱から戻す)
+    fn set_deleted_time(&mut self, value: Option<DateTime<chrono::Local>>);
 
     /// プレイヤー数の設定
     fn set_player_count(&mut self, value: i32);
@@ -190,12 +201,24 @@ pub trait SmashbrosDataTrait {
     fn set_order(&mut self, player_number: i32, value: i32);
     /// プレイヤーの順位の設定
     fn set_power(&mut self, player_number: i32, value: i32);
+    /// 手動で修正したフィールドの設定
+    fn set_manual_field_list(&mut self, value: Vec<String>);
+    /// 手動で入力した試合かの設定
+    fn set_manual_entry(&mut self, value: bool);
+    /// タグの設定 (前後の空白を除いて、空と重複は無視する)
+    fn set_tag_list(&mut self, value: Vec<String>);
+    /// メモの設定
+    fn set_note(&mut self, value: String);
 
     // is系
     /// 試合中かどうか
     fn is_playing_battle(&self) -> bool;
     /// 試合後かどうか
     fn is_finished_battle(&self) -> bool;
+    /// キャプチャせずに手動で入力した試合かどうか
+    fn is_manual_entry(&self) -> bool;
+    /// ゴミ箱に入っているかどうか
+    fn is_deleted(&self) -> bool;
 
     // ルールは確定しているか
     fn is_decided_rule(&self) -> bool;
@@ -223,7 +246,7 @@ pub trait SmashbrosDataTrait {
 /// ドキュメント.フィールド名
 #[derive(Clone, Copy, Debug)]
 enum SmashbrosDataField {
-    Id(&'static str),
+    Id(&'static str), SchemaVersion(&'static str),
     StartTime(&'static str), EndTime(&'static str),
     PlayerCount(&'static str), RuleName(&'static str),
     MaxTime(&'static str), MaxStockList(&'static str), MaxHpList(&'static str),
@@ -232,11 +255,17 @@ enum SmashbrosDataField {
     StockList(&'static str),
     OrderList(&'static str),
     PowerList(&'static str),
+    ManualFieldList(&'static str),
+    ManualEntry(&'static str),
+    TagList(&'static str),
+    Note(&'static str),
+    DeletedTime(&'static str),
+    BgmName(&'static str),
 }
 impl SmashbrosDataField {
     fn name(&self) -> &'static str {
         match *self {
-            Self::Id(name) |
+            Self::Id(name) | Self::SchemaVersion(name) |
             Self::StartTime(name) | Self::EndTime(name) |
             Self::PlayerCount(name) | Self::RuleName(name) |
             Self::MaxTime(name) | Self::MaxStockList(name) | Self::MaxHpList(name) |
@@ -244,131 +273,228 @@ impl SmashbrosDataField {
             Self::GroupList(name) |
             Self::StockList(name) |
             Self::OrderList(name) |
-            Self::PowerList(name) => {
+            Self::PowerList(name) |
+            Self::ManualFieldList(name) |
+            Self::ManualEntry(name) |
+            Self::TagList(name) |
+            Self::Note(name) |
+            Self::DeletedTime(name) |
+            Self::BgmName(name) => {
                 name
             },
         }
     }
 }
-impl<'de> Deserialize<'de> for SmashbrosDataField {
-    fn deserialize<D>(deserializer: D) -> Result<Self, <D as Deserializer<'de>>::Error>
-        where D: Deserializer<'de>
-    {
-        deserializer.deserialize_identifier(SmashbrosDataFieldVisitor)
-    }
-}
-/// DB コンテナ(フィールド名用)
-#[derive(Debug)]
-struct SmashbrosDataFieldVisitor;
-impl<'de> Visitor<'de> for SmashbrosDataFieldVisitor {
-    type Value = SmashbrosDataField;
-
-    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        formatter.write_str("expected field name for SmashbrosData.")
-    }
-
-    fn visit_str<E>(self, maybe_field_name: &str) -> std::result::Result<Self::Value, E>
-        where E: serde::de::Error,
-    {
-        for &field in &SmashbrosData::FIELDS {
-            if field.name() == maybe_field_name {
-                return Ok(field)
+/// DB コンテナ(データ用)
+struct SmashbrosDataVisitor;
+impl SmashbrosDataVisitor {
+    // schema_version 毎に一つ新しい形式にする (SmashbrosData::SCHEMA_VERSION を上げた時はここに足す)
+    fn upgrade_document(document: &mut serde_json::Map<String, serde_json::Value>) -> Result<(), String> {
+        let schema_version = match document.get("schema_version") {
+            Some(schema_version) => schema_version.as_i64().ok_or(format!("invalid schema_version {}", schema_version))?,
+            None => 1,
+        };
+        if SmashbrosData::SCHEMA_VERSION < schema_version {
+            // 新しいバージョンで保存されたものは、知っているフィールドだけ読む
+            log::warn!("schema_version {} is newer than {}. unknown fields are ignored.", schema_version, SmashbrosData::SCHEMA_VERSION);
+        }
+
+        if schema_version < 2 {
+            // 1: schema_version が無い (0.33.0 まで)
+            //    日時は DateTime<Local> の {:?} か UTC の ISO-8601 の文字列か BSON の日時, 未検出の日時も保存時の日時
+            for field in ["start_time", "end_time"] {
+                if let Some(value) = document.get_mut(field) {
+                    if let Some(date_time) = Self::date_time_from_value(value)? {
+                        *value = serde_json::Value::String(SmashbrosData::date_time_to_string(&date_time));
+                    }
+                }
             }
         }
 
-        Err(serde::de::Error::unknown_field(maybe_field_name, SmashbrosData::FIELD_NAMES))
+        if schema_version < 3 {
+            // 2: 手動で修正したフィールド (manual_field_list) が無い
+            document.entry("manual_field_list").or_insert(serde_json::json!([]));
+        }
+        if schema_version < 4 {
+            // 3: 手動で入力した試合か (is_manual_entry) が無い
+            document.entry("is_manual_entry").or_insert(serde_json::json!(false));
+        }
+        if schema_version < 5 {
+            // 4: タグ (tag_list) とメモ (note) が無い
+            document.entry("tag_list").or_insert(serde_json::json!([]));
+            document.entry("note").or_insert(serde_json::json!(""));
+        }
+        if schema_version < 6 {
+            // 5: ゴミ箱に入れた時刻 (deleted_time) が無い
+            document.entry("deleted_time").or_insert(serde_json::Value::Null);
+        }
+        if schema_version < 7 {
+            // 6: BGM (bgm_name) が保存されていない
+            document.entry("bgm_name").or_insert(serde_json::json!(""));
+        }
+
+        document.insert("schema_version".to_string(), serde_json::json!(SmashbrosData::SCHEMA_VERSION));
+        Ok(())
     }
-}
-/// DB コンテナ(データ用)
-struct SmashbrosDataVisitor;
-impl<'de> Visitor<'de> for SmashbrosDataVisitor {
-    type Value = SmashbrosData;
 
-    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        formatter.write_str("expected data SmashbrosData.")
+    // 日時の文字列か、BSON の日時 ({"$date": ミリ秒} か {"$date": {"$numberLong": "ミリ秒"}} か {"$date": 文字列})
+    fn date_time_from_value(value: &serde_json::Value) -> Result<Option<DateTime<chrono::Local>>, String> {
+        use chrono::TimeZone;
+        let value = match value {
+            serde_json::Value::Object(object) => match object.get("$date") {
+                Some(value) => value,
+                None => return Err(format!("invalid date time {}", value)),
+            },
+            value => value,
+        };
+        let millis = match value {
+            serde_json::Value::Null => return Ok(None),
+            serde_json::Value::String(value) => {
+                return SmashbrosData::parse_date_time(value).map(Some).map_err(|e| format!("invalid date time {:?}: {}", value, e));
+            },
+            serde_json::Value::Number(millis) => millis.as_i64(),
+            serde_json::Value::Object(object) => object.get("$numberLong").and_then(|millis| millis.as_str()?.parse::<i64>().ok()),
+            _ => None,
+        };
+
+        match millis {
+            Some(millis) => Ok(Some(chrono::Local.timestamp_millis(millis))),
+            None => Err(format!("invalid date time {}", value)),
+        }
     }
 
-    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, <A as serde::de::MapAccess<'de>>::Error>
-        where A: serde::de::MapAccess<'de>,
-    {
+    // 今の形式のドキュメントを SmashbrosData にする
+    fn document_to_data(mut document: serde_json::Map<String, serde_json::Value>) -> Result<SmashbrosData, String> {
         use std::str::FromStr;
+        fn from_value<T: serde::de::DeserializeOwned>(field: &SmashbrosDataField, value: serde_json::Value) -> Result<T, String> {
+            serde_json::from_value::<T>(value.clone()).map_err(|e| format!("invalid {} {}: {}", field.name(), value, e))
+        }
 
         let mut data = SmashbrosData::default();
         data.initialize_data();
 
-        // ?: データ値が格納されてないのと、DBにフィールドがそもそもなかったのとは別なので、一度開いて Some で閉じる
-        while let Some(key) = map.next_key::<SmashbrosDataField>()? {
-            match key {
+        // プレイヤー数で初期化されるので、フィールドの順に入れる (知らないフィールドは無視する)
+        for field in &SmashbrosData::FIELDS {
+            let value = match document.remove(field.name()) {
+                Some(value) => value,
+                None => continue,
+            };
+            // 初期化されたプレイヤー数より多いものは無視する
+            let player_count = data.get_player_count().max(0) as usize;
+            match field {
                 // ID
                 SmashbrosDataField::Id(_) => {
-                    let value: HashMap<String, String> = map.next_value::<HashMap<String, String>>()?;
-                    data.set_id(Some( value["$oid"].clone() ));
+                    let id = match &value {
+                        serde_json::Value::String(id) => Some(id.clone()),
+                        serde_json::Value::Object(object) => object.get("$oid").and_then(|id| id.as_str()).map(|id| id.to_string()),
+                        _ => None,
+                    };
+                    match id {
+                        Some(id) => data.set_id(Some(id)),
+                        None => return Err(format!("invalid {} {}", field.name(), value)),
+                    }
                 },
+                SmashbrosDataField::SchemaVersion(_) => (),
 
-                SmashbrosDataField::StartTime(_) => {
-                    data.set_start_time(Some( DateTime::<chrono::Local>::from_str(&map.next_value::<String>()?).unwrap() ));
-                },
-                SmashbrosDataField::EndTime(_) => {
-                    data.set_end_time(Some( DateTime::<chrono::Local>::from_str(&map.next_value::<String>()?).unwrap() ));
-                },
+                SmashbrosDataField::StartTime(_) => data.set_start_time(Self::date_time_from_value(&value)?),
+                SmashbrosDataField::EndTime(_) => data.set_end_time(Self::date_time_from_value(&value)?),
 
                 SmashbrosDataField::PlayerCount(_) => {
-                    data.initialize_battle(map.next_value::<i32>()?, false);
+                    data.initialize_battle(from_value::<i32>(field, value)?, false);
                     data.set_saved_time(Some( std::time::Instant::now() ));
                 },
                 SmashbrosDataField::RuleName(_) => {
-                    data.set_rule(BattleRule::from_str( &map.next_value::<String>()? ).unwrap());
+                    data.set_rule(BattleRule::from_str( &from_value::<String>(field, value)? ).unwrap_or(BattleRule::Unknown));
                 },
 
                 SmashbrosDataField::MaxTime(_) => {
-                    use std::ops::Add;
-                    let times = map.next_value::<Vec<i32>>()?;
-                    let time = std::time::Duration::from_secs((times[0] * 60) as u64)
-                        .add(std::time::Duration::from_secs(times[1] as u64));
-                    data.set_max_time(time);
+                    match from_value::<Vec<u64>>(field, value)?.as_slice() {
+                        [minutes, seconds] if 0 < player_count => data.set_max_time(std::time::Duration::from_secs(minutes * 60 + seconds)),
+                        _ => (),
+                    }
                 },
                 SmashbrosDataField::MaxStockList(_) => {
-                    for (player_number, max_stock) in map.next_value::<Vec<i32>>()?.iter().enumerate() {
-                        data.set_max_stock(player_number as i32, *max_stock);
+                    for (player_number, max_stock) in from_value::<Vec<i32>>(field, value)?.into_iter().take(player_count).enumerate() {
+                        data.set_max_stock(player_number as i32, max_stock);
                     }
                 },
                 SmashbrosDataField::MaxHpList(_) => {
-                    for (player_number, max_hp) in map.next_value::<Vec<i32>>()?.iter().enumerate() {
-                        data.set_max_hp(player_number as i32, *max_hp);
+                    for (player_number, max_hp) in from_value::<Vec<i32>>(field, value)?.into_iter().take(player_count).enumerate() {
+                        data.set_max_hp(player_number as i32, max_hp);
                     }
                 },
 
                 SmashbrosDataField::CharaList(_) => {
-                    for (player_number, chara_name) in map.next_value::<Vec<String>>()?.iter().enumerate() {
-                        data.set_character(player_number as i32, chara_name.clone());
+                    for (player_number, chara_name) in from_value::<Vec<String>>(field, value)?.into_iter().take(player_count).enumerate() {
+                        data.set_character(player_number as i32, chara_name);
                     }
                 },
                 SmashbrosDataField::GroupList(_) => {
-                    for (player_number, group_name) in map.next_value::<Vec<String>>()?.iter().enumerate() {
-                        data.set_group(player_number as i32, PlayerGroup::from_str( &group_name ).unwrap());
+                    for (player_number, group_name) in from_value::<Vec<String>>(field, value)?.into_iter().take(player_count).enumerate() {
+                        data.set_group(player_number as i32, PlayerGroup::from_str( &group_name ).unwrap_or(PlayerGroup::Unknown));
                     }
                 },
                 SmashbrosDataField::StockList(_) => {
-                    for (player_number, stock) in map.next_value::<Vec<i32>>()?.iter().enumerate() {
-                        data.set_stock(player_number as i32, *stock);
+                    for (player_number, stock) in from_value::<Vec<i32>>(field, value)?.into_iter().take(player_count).enumerate() {
+                        data.set_stock(player_number as i32, stock);
                     }
                 },
                 SmashbrosDataField::OrderList(_) => {
-                    for (player_number, order) in map.next_value::<Vec<i32>>()?.iter().enumerate() {
-                        data.set_order(player_number as i32, *order);
+                    for (player_number, order) in from_value::<Vec<i32>>(field, value)?.into_iter().take(player_count).enumerate() {
+                        data.set_order(player_number as i32, order);
                     }
                 },
                 SmashbrosDataField::PowerList(_) => {
-                    for (player_number, power) in map.next_value::<Vec<i32>>()?.iter().enumerate() {
-                        data.set_power(player_number as i32, *power);
+                    for (player_number, power) in from_value::<Vec<i32>>(field, value)?.into_iter().take(player_count).enumerate() {
+                        data.set_power(player_number as i32, power);
                     }
-                }
+                },
+                SmashbrosDataField::ManualFieldList(_) => {
+                    data.set_manual_field_list(from_value::<Vec<String>>(field, value)?);
+                },
+                SmashbrosDataField::ManualEntry(_) => {
+                    data.set_manual_entry(from_value::<bool>(field, value)?);
+                },
+                SmashbrosDataField::TagList(_) => {
+                    data.set_tag_list(from_value::<Vec<String>>(field, value)?);
+                },
+                SmashbrosDataField::Note(_) => {
+                    data.set_note(from_value::<String>(field, value)?);
+                },
+                SmashbrosDataField::DeletedTime(_) => data.set_deleted_time(Self::date_time_from_value(&value)?),
+                SmashbrosDataField::BgmName(_) => {
+                    // 検出できなかったもの ("") は未確定のままにしておく
+                    let bgm_name = from_value::<String>(field, value)?;
+                    if !bgm_name.is_empty() {
+                        data.set_bgm_name(bgm_name);
+                    }
+                },
             }
         }
 
         Ok(data)
     }
 }
+impl<'de> Visitor<'de> for SmashbrosDataVisitor {
+    type Value = SmashbrosData;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        formatter.write_str("expected data SmashbrosData.")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, <A as serde::de::MapAccess<'de>>::Error>
+        where A: serde::de::MapAccess<'de>,
+    {
+        // 一度全部読んでから、古い形式のものを今の形式にして SmashbrosData にする
+        let mut document = serde_json::Map::new();
+        while let Some((key, value)) = map.next_entry::<String, serde_json::Value>()? {
+            document.insert(key, value);
+        }
+
+        Self::upgrade_document(&mut document).map_err(serde::de::Error::custom)?;
+        Self::document_to_data(document).map_err(serde::de::Error::custom)
+    }
+}
 
 /// データ (engine 処理用)
 #[derive(Debug, Clone, PartialEq)]
@@ -396,6 +522,17 @@ pub struct SmashbrosData {
     order_list: Vec<ValueGuesser<i32>>,
     power_list: Vec<ValueGuesser<i32>>,
 
+    // 手動で修正したフィールド ("rule_name", "chara_list.0" など)
+    manual_field_list: Vec<String>,
+    // キャプチャせずに手動で入力した試合か
+    is_manual_entry: bool,
+    // タグ ("大会前", "ラグい" など)
+    tag_list: Vec<String>,
+    // メモ
+    note: String,
+    // ゴミ箱に入れた時刻 (None ならゴミ箱に入っていない)
+    deleted_time: Option<DateTime<chrono::Local>>,
+
     /* serde(skip) */
     prev_chara_list: Vec<String>,
     prev_power_list: Vec<i32>,
@@ -423,6 +560,7 @@ impl SmashbrosDataTrait for SmashbrosData {
 
     fn get_start_time(&self) -> Option<DateTime<chrono::Local>> { self.start_time.clone() }
     fn get_end_time(&self) -> Option<DateTime<chrono::Local>> { self.end_time.clone() }
+    fn get_deleted_time(&self) -> Option<DateTime<chrono::Local>> { self.deleted_time.clone() }
 
     fn get_player_count(&self) -> i32 { self.player_count }
     fn get_rule(&self) -> BattleRule { self.rule_name.clone() }
@@ -492,6 +630,9 @@ impl SmashbrosDataTrait for SmashbrosData {
 
         self.power_list[player_number as usize].get()
     }
+    fn get_manual_field_list(&self) -> Vec<String> { self.manual_field_list.clone() }
+    fn get_tag_list(&self) -> Vec<String> { self.tag_list.clone() }
+    fn get_note(&self) -> String { self.note.clone() }
 
     // setter
     fn set_id(&mut self, value: Option<String>) { self.db_collection_id = value; }
@@ -499,6 +640,7 @@ impl SmashbrosDataTrait for SmashbrosData {
 
     fn set_start_time(&mut self, value: Option<DateTime<chrono::Local>>) { self.start_time = value; }
     fn set_end_time(&mut self, value: Option<DateTime<chrono::Local>>) { self.end_time = value; }
+    fn set_deleted_time(&mut self, value: Option<DateTime<chrono::Local>>) { self.deleted_time = value; }
 
     fn set_player_count(&mut self, value: i32) { self.player_count = value; }
     fn set_rule(&mut self, value: BattleRule) { self.rule_name = value; }
@@ -512,6 +654,15 @@ impl SmashbrosDataTrait for SmashbrosData {
     fn set_stock(&mut self, player_number: i32, value: i32) { self.stock_list[player_number as usize].set(value); }
     fn set_order(&mut self, player_number: i32, value: i32) { self.order_list[player_number as usize].set(value); }
     fn set_power(&mut self, player_number: i32, value: i32) { self.power_list[player_number as usize].set(value); }
+    fn set_manual_field_list(&mut self, value: Vec<String>) { self.manual_field_list = value; }
+    fn set_manual_entry(&mut self, value: bool) { self.is_manual_entry = value; }
+    fn set_tag_list(&mut self, value: Vec<String>) {
+        self.tag_list.clear();
+        for tag in value {
+            self.add_tag(&tag);
+        }
+    }
+    fn set_note(&mut self, value: String) { self.note = value; }
 
     // is_{hoge}
     fn is_playing_battle(&self) -> bool {
@@ -543,6 +694,8 @@ impl SmashbrosDataTrait for SmashbrosData {
 
         end_time <= chrono::Local::now()
     }
+    fn is_manual_entry(&self) -> bool { self.is_manual_entry }
+    fn is_deleted(&self) -> bool { self.deleted_time.is_some() }
 
     fn is_decided_rule(&self) -> bool {
         self.rule_name != BattleRule::Unknown
@@ -595,8 +748,9 @@ impl Serialize for SmashbrosData {
         where S: Serializer,
     {
         let mut state = serializer.serialize_struct("SmashbrosData", Self::FIELDS.len())?;
-        state.serialize_field( "start_time", &format!( "{:?}", self.get_start_time().unwrap_or(chrono::Local::now())) )?;
-        state.serialize_field( "end_time", &format!( "{:?}", self.get_end_time().unwrap_or(chrono::Local::now()) ) )?;
+        state.serialize_field( "schema_version", &Self::SCHEMA_VERSION )?;
+        state.serialize_field( "start_time", &self.get_start_time().as_ref().map(Self::date_time_to_string) )?;
+        state.serialize_field( "end_time", &self.get_end_time().as_ref().map(Self::date_time_to_string) )?;
 
         state.serialize_field( "player_count", &self.get_player_count() )?;
         state.serialize_field( "rule_name", &format!("{:?}", self.get_rule()) )?;
@@ -617,6 +771,12 @@ impl Serialize for SmashbrosData {
         state.serialize_field( "stock_list", &self.stock_list.iter().map(|value| value.get() ).collect::<Vec<i32>>() )?;
         state.serialize_field( "order_list", &self.order_list.iter().map(|value| value.get() ).collect::<Vec<i32>>() )?;
         state.serialize_field( "power_list", &self.power_list.iter().map(|value| value.get() ).collect::<Vec<i32>>() )?;
+        state.serialize_field( "manual_field_list", &self.manual_field_list )?;
+        state.serialize_field( "is_manual_entry", &self.is_manual_entry )?;
+        state.serialize_field( "tag_list", &self.tag_list )?;
+        state.serialize_field( "note", &self.note )?;
+        state.serialize_field( "deleted_time", &self.get_deleted_time().as_ref().map(Self::date_time_to_string) )?;
+        state.serialize_field( "bgm_name", &self.get_bgm_name() )?;
 
         state.end()
     }
@@ -631,7 +791,7 @@ impl<'de> Deserialize<'de> for SmashbrosData {
 impl SmashbrosData {
     // db にあるフィールド名
     const FIELD_NAMES: &'static [&'static str] = &[
-        "_id",
+        "_id", "schema_version",
         "start_time", "end_time",
         "player_count",
         "rule_name",
@@ -640,11 +800,16 @@ impl SmashbrosData {
         "group_list",
         "stock_list",
         "order_list",
-        "power_list"
+        "power_list",
+        "manual_field_list",
+        "is_manual_entry",
+        "tag_list", "note",
+        "deleted_time",
+        "bgm_name"
     ];
     // db に突っ込むときのフィールド名
-    const FIELDS: [SmashbrosDataField; 13] = [
-        SmashbrosDataField::Id{ 0:"_id" },
+    const FIELDS: [SmashbrosDataField; 20] = [
+        SmashbrosDataField::Id{ 0:"_id" }, SmashbrosDataField::SchemaVersion{ 0:"schema_version" },
         SmashbrosDataField::StartTime{ 0:"start_time" }, SmashbrosDataField::EndTime{ 0:"end_time" },
         SmashbrosDataField::PlayerCount{ 0: "player_count" },
         SmashbrosDataField::RuleName{ 0: "rule_name" },
@@ -654,8 +819,15 @@ impl SmashbrosData {
         SmashbrosDataField::StockList{ 0: "stock_list" },
         SmashbrosDataField::OrderList{ 0: "order_list" },
         SmashbrosDataField::PowerList{ 0: "power_list" },
+        SmashbrosDataField::ManualFieldList{ 0: "manual_field_list" },
+        SmashbrosDataField::ManualEntry{ 0: "is_manual_entry" },
+        SmashbrosDataField::TagList{ 0: "tag_list" }, SmashbrosDataField::Note{ 0: "note" },
+        SmashbrosDataField::DeletedTime{ 0: "deleted_time" },
+        SmashbrosDataField::BgmName{ 0: "bgm_name" },
 
     ];
+    /// 保存する形式のバージョン (形式を変えた時は上げて、SmashbrosDataVisitor::upgrade_document に変換を足す)
+    pub const SCHEMA_VERSION: i64 = 7;
     // キャラクター名が不明時の文字列
     pub const CHARACTER_NAME_UNKNOWN: &'static str = "unknown";
 
@@ -686,6 +858,12 @@ impl SmashbrosData {
             order_list: vec![ValueGuesser::new(-1)],
             power_list: vec![ValueGuesser::new(-1)],
 
+            manual_field_list: vec![],
+            is_manual_entry: false,
+            tag_list: vec![],
+            note: String::new(),
+            deleted_time: None,
+
             prev_chara_list: vec![],
             prev_power_list: vec![],
 
@@ -694,6 +872,41 @@ impl SmashbrosData {
         }
     }
 
+    /// 保存する形式の日時にする (UTC の ISO-8601, ミリ秒まで)
+    /// タイムゾーンが違っても文字列の大小と日時の前後が一致する
+    pub fn date_time_to_string(date_time: &DateTime<chrono::Local>) -> String {
+        date_time.with_timezone(&chrono::Utc).to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+    }
+
+    /// 保存されている日時を読む (ISO-8601 と、以前の {:?} の形式)
+    pub fn parse_date_time(value: &str) -> Result<DateTime<chrono::Local>, chrono::ParseError> {
+        use std::str::FromStr;
+        DateTime::parse_from_rfc3339(value)
+            .or_else(|_| DateTime::<chrono::FixedOffset>::from_str(value))
+            .map(|date_time| date_time.with_timezone(&chrono::Local))
+    }
+
+    /// キャプチャせずに手動で入力した 1 on 1 の試合を作る (キャラは公式英名, 戦闘力が不明なら -1)
+    pub fn new_manual_entry(chara_list: [String; 2], rule: BattleRule, is_win: bool, power_list: [i32; 2], start_time: DateTime<chrono::Local>) -> Self {
+        let mut data = Self::default();
+        data.initialize_battle(2, true);
+        data.set_start_time(Some(start_time));
+        data.set_end_time(Some(start_time));
+        data.set_rule(rule);
+        for (player_number, (chara_name, power)) in chara_list.into_iter().zip(power_list).enumerate() {
+            let player_number = player_number as i32;
+            data.set_character(player_number, chara_name);
+            if -1 != power {
+                data.set_power(player_number, power);
+            }
+        }
+        data.set_order(0, if is_win { 1 } else { 2 });
+        data.set_order(1, if is_win { 2 } else { 1 });
+        data.set_manual_entry(true);
+
+        data
+    }
+
     /// データの初期化
     /// @return bool false:初期化せず true:初期化済み
     fn initialize_data(&mut self) -> bool {
@@ -724,6 +937,11 @@ impl SmashbrosData {
         // 削除
         self.start_time = None;
         self.end_time = None;
+        self.manual_field_list.clear();
+        self.is_manual_entry = false;
+        self.tag_list.clear();
+        self.note.clear();
+        self.deleted_time = None;
         
         self.rule_name = BattleRule::Unknown;
 
@@ -821,57 +1039,50 @@ impl SmashbrosData {
     pub fn finish_battle(&mut self) {
         self.end_time = Some(chrono::Local::now());
     }
-    /// 試合情報の保存
-    pub fn save_battle(&mut self) {
+    /// 試合情報を context の保存先に保存して、保存できたら ID を返す
+    /// (既に保存済みか、バトルが終わっていない (開始時刻が未来も) ものは保存しないで None を返す)
+    pub fn save_battle_with(&mut self, context: &mut AppContext) -> Option<String> {
         if self.db_collection_id.is_some() || !self.is_finished_battle() {
             // 既に保存済み or バトルが終わってない
-            return;
+            return None;
         }
         log::info!("save_battle: {:?}", self.get_id());
 
-        // DBに保存するときだけ chara_list を ja に合わせておく(クエリを単純にするため)
-        let back_chara_list = self.chara_list.clone();
-        for i in 0..self.chara_list.len() {
-            if let Some(to_name) = SMASHBROS_RESOURCE().get_mut().i18n_convert_list.get(&self.chara_list[i].get()) {
-                // i18n_list にあるものだけ変換する
-                log::info!("saved i18n: {} -> {}", self.chara_list[i].get(), to_name);
-                self.chara_list[i].set(to_name.clone());
-            }
-        }
-
         // データを保存
+        let saved_data = self.to_saved_data(&context.smashbros_resource());
         self.db_collection_id = match self.player_count {
-            2 => BATTLE_HISTORY().get_mut().insert_data(self),
+            2 => context.insert_data(&saved_data),
             _ => None,
         };
 
-        // chara_list を元に戻す
-        self.chara_list = back_chara_list;
-
         self.saved_time = Some(std::time::Instant::now());
+        self.get_id()
     }
-    /// 試合情報の更新
-    pub fn update_battle(&mut self) {
+    /// context の保存先の試合情報を更新して、更新できたら ID を返す
+    pub fn update_battle_with(&mut self, context: &mut AppContext) -> Option<String> {
         log::info!("update_battle: {:?}", self.get_id());
 
-        // DBに保存するときだけ chara_list を ja に合わせておく(クエリを単純にするため)
-        let back_chara_list = self.chara_list.clone();
-        for i in 0..self.chara_list.len() {
-            if let Some(to_name) = SMASHBROS_RESOURCE().get_mut().i18n_convert_list.get(&self.chara_list[i].get()) {
+        // データを保存
+        let saved_data = self.to_saved_data(&context.smashbros_resource());
+        match self.player_count {
+            2 => context.update_data(&saved_data),
+            _ => None,
+        }
+    }
+    /// DBに保存するときだけ chara_list を ja に合わせたものを返す(クエリを単純にするため)
+    /// (self の chara_list は表示している言語のまま変えない)
+    pub fn to_saved_data(&self, smashbros_resource: &SmashbrosResource) -> Self {
+        let mut saved_data = self.clone();
+        for chara_name in saved_data.chara_list.iter_mut() {
+            let to_name = smashbros_resource.normalize_character_name(&chara_name.get());
+            if to_name != chara_name.get() {
                 // i18n_list にあるものだけ変換する
-                log::info!("saved i18n: {} -> {}", self.chara_list[i].get(), to_name);
-                self.chara_list[i].set(to_name.clone());
+                log::info!("saved i18n: {} -> {}", chara_name.get(), to_name);
+                chara_name.set(to_name);
             }
         }
 
-        // データを保存
-        let _db_collection_id = match self.player_count {
-            2 => BATTLE_HISTORY().get_mut().update_data(self),
-            _ => None,
-        };
-
-        // chara_list を元に戻す
-        self.chara_list = back_chara_list;
+        saved_data
     }
     /// 有効試合かどうか
     pub fn is_valid_battle(&self) -> bool {
@@ -962,13 +1173,13 @@ impl SmashbrosData {
     }
 
     /// プレイヤーが使用しているキャラクターの設定
-    pub fn guess_character_name(&mut self, player_number: i32, maybe_character_name: String) {
+    pub fn guess_character_name(&mut self, player_number: i32, maybe_character_name: String, smashbros_resource: &SmashbrosResource) {
         if self.is_decided_character_name(player_number) {
             // 一致度が 100% だと比較しない
             return;
         }
         
-        if let Some((chara_name, ratio)) = SmashbrosResource::convert_character_name(maybe_character_name.clone()) {
+        if let Some((chara_name, ratio)) = smashbros_resource.convert_character_name(maybe_character_name.clone()) {
             if 1.0 == ratio {
                 self.set_character(player_number, chara_name);
             } else {
@@ -1133,13 +1344,17 @@ impl SmashbrosData {
     pub fn get_bgm_name(&self) -> String {
         self.bgm_name.get()
     }
+    /// BGM の設定 (保存されていたもの)
+    pub fn set_bgm_name(&mut self, value: String) {
+        self.bgm_name.set(value);
+    }
     /// BGM の推測
-    pub fn guess_bgm_name(&mut self, maybe_bgm_name: String) {
+    pub fn guess_bgm_name(&mut self, maybe_bgm_name: String, smashbros_resource: &SmashbrosResource) {
         if self.bgm_name.is_decided() {
             return;
         }
 
-        let (bgm_name, ratio) = match SmashbrosResource::convert_bgm_list(maybe_bgm_name.to_string()) {
+        let (bgm_name, ratio) = match smashbros_resource.convert_bgm_list(maybe_bgm_name.to_string()) {
             Some(bgm) => bgm,
             None => return,
         };
@@ -1170,8 +1385,8 @@ impl SmashbrosData {
         ((self.is_playing_battle() || self.is_finished_battle()) && !self.bgm_name.get().is_empty() )
     }
     /// BGM リストに載っていて、許可されている BGM かどうか
-    pub fn is_valid_bgm_name(&self) -> bool {
-        if let Some(bgm_value) = SMASHBROS_RESOURCE().get_mut().bgm_list.get(&self.bgm_name.get()) {
+    pub fn is_valid_bgm_name(&self, smashbros_resource: &SmashbrosResource) -> bool {
+        if let Some(bgm_value) = smashbros_resource.bgm_list.get(&self.bgm_name.get()) {
             return *bgm_value;
         }
 
@@ -1183,6 +1398,71 @@ impl SmashbrosData {
         self.all_decided_power() && self.all_decided_order()
     }
 
+    /// 手動で修正したフィールドとして記録する ("rule_name", "chara_list.0" など)
+    pub fn set_manual_field(&mut self, field: String) {
+        if !self.manual_field_list.contains(&field) {
+            self.manual_field_list.push(field);
+        }
+    }
+    /// 手動で修正した値が有効か (キャラ名, 順位, 戦闘力)
+    pub fn validate_manual_edit(&self, smashbros_resource: &SmashbrosResource) -> Result<(), String> {
+        for player_number in 0..self.player_count {
+            let chara_name = self.get_character(player_number);
+            if Self::CHARACTER_NAME_UNKNOWN != chara_name && !smashbros_resource.character_list.contains_key(&chara_name) {
+                return Err(format!("unknown character {}p: {}", player_number + 1, chara_name));
+            }
+        }
+
+        // 順位は全員未確定か、有効な組み合わせのみ
+        let order_list = (0..self.player_count).map(|player_number| self.get_order(player_number)).collect::<Vec<i32>>();
+        if order_list.iter().any(|order| -1 != *order) && !self.is_valid_order() {
+            return Err(format!("invalid order: {:?}", order_list));
+        }
+
+        // 戦闘力は相手との差で確認するので、全員分ある時だけ
+        let power_list = (0..self.player_count).map(|player_number| self.get_power(player_number)).collect::<Vec<i32>>();
+        if power_list.iter().any(|power| *power < -1) {
+            return Err(format!("invalid power: {:?}", power_list));
+        }
+        if power_list.iter().all(|power| -1 != *power) {
+            for (player_number, power) in power_list.iter().enumerate() {
+                if !self.is_valid_power(player_number as i32, *power, None, None, false).unwrap_or(true) {
+                    return Err(format!("invalid power {}p: {:?}", player_number + 1, power_list));
+                }
+            }
+        }
+
+        Ok(())
+    }
+    /// 手動で修正したフィールドか
+    pub fn is_manual_field(&self, field: &str) -> bool {
+        self.manual_field_list.iter().any(|manual_field| manual_field == field)
+    }
+
+    /// タグを付ける (前後の空白を除いて、空と付いているものは無視する)
+    /// @return bool true:付けた
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        let tag = tag.trim();
+        if tag.is_empty() || self.has_tag(tag) {
+            return false;
+        }
+        self.tag_list.push(tag.to_string());
+
+        true
+    }
+    /// タグを外す
+    /// @return bool true:外した
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let prev_len = self.tag_list.len();
+        self.tag_list.retain(|saved_tag| saved_tag != tag);
+
+        prev_len != self.tag_list.len()
+    }
+    /// タグが付いているか
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tag_list.iter().any(|saved_tag| saved_tag == tag)
+    }
+
     // 勝ちか負けかを返す。None の場合は無効試合
     pub fn is_win(&self) -> Option<bool> {
         if !self.is_valid_order() {
@@ -1199,16 +1479,38 @@ impl SmashbrosData {
     }
 }
 
+#[cfg(test)]
+impl SmashbrosData {
+    /// テスト用の 1 on 1 の戦歴 (is_win で 1P の勝敗, power は 1P の戦闘力で負なら未検出, 開始時刻は今)
+    pub fn new_for_test(character: &str, opponent: &str, rule: BattleRule, is_win: bool, power: i32) -> Self {
+        let mut data = Self::default();
+        data.initialize_battle(2, true);
+        data.set_character(0, character.to_string());
+        data.set_character(1, opponent.to_string());
+        data.set_rule(rule);
+        data.set_order(0, if is_win { 1 } else { 2 });
+        data.set_order(1, if is_win { 2 } else { 1 });
+        if 0 <= power {
+            data.set_power(0, power);
+        }
+        data.set_start_time(Some(chrono::Local::now()));
+
+        data
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_character() {
+        let context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
+
         // resource との一致でのキャラクターの推測
         let mut data = SmashbrosData::default();
         data.initialize_battle(2, true);
-        data.guess_character_name(0, "MARIO".to_string());
+        data.guess_character_name(0, "MARIO".to_string(), &context.smashbros_resource());
         assert_eq!(data.is_decided_character_name(0), true);
         assert_eq!(data.get_character(0), "MARIO".to_string());
 
@@ -1216,7 +1518,7 @@ mod tests {
         let mut data = SmashbrosData::default();
         data.initialize_battle(2, true);
         for _ in 0..ValueGuesser::<i32>::DEFAULT_MAX_BORDER {
-            data.guess_character_name(0, "MARI".to_string());
+            data.guess_character_name(0, "MARI".to_string(), &context.smashbros_resource());
         }
         assert_eq!(data.is_decided_character_name(0), true);
         assert_eq!(data.get_character(0), "MARIO".to_string());
@@ -1333,4 +1635,199 @@ mod tests {
         assert_eq!(data.is_decided_power(0), true);
 
     }
+
+    #[test]
+    fn test_serialize_date_time() {
+        let mut data = SmashbrosData::default();
+        data.initialize_battle(2, true);
+
+        // 保存されていない日時は null のまま
+        let value = serde_json::to_value(&data).unwrap();
+        assert!(value["start_time"].is_null());
+        let restored_data: SmashbrosData = serde_json::from_value(value).unwrap();
+        assert_eq!(restored_data.get_start_time(), None);
+
+        // UTC で保存して、読み込むとローカルの同じ日時になる
+        let start_time = DateTime::parse_from_rfc3339("2022-01-02T12:34:56.789+09:00").unwrap().with_timezone(&chrono::Local);
+        data.set_start_time(Some(start_time));
+        let mut value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value["start_time"], "2022-01-02T03:34:56.789Z");
+        let restored_data: SmashbrosData = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(restored_data.get_start_time(), Some(start_time));
+
+        // 以前の形式 ({:?}) も読める
+        assert_eq!(SmashbrosData::parse_date_time(&format!("{:?}", start_time)).unwrap(), start_time);
+
+        // 読めない日時は panic せずにエラーになる
+        value["start_time"] = serde_json::json!("2022/01/02");
+        assert!(serde_json::from_value::<SmashbrosData>(value).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_schema_version() {
+        let start_time = DateTime::parse_from_rfc3339("2022-01-02T12:34:56.789+09:00").unwrap().with_timezone(&chrono::Local);
+
+        // 1: 0.33.0 までの形式 (schema_version が無く、日時は DateTime<Local> の {:?})
+        let version_1 = serde_json::json!({
+            "_id": { "$oid": "61d1e6a0b0a0a0a0a0a0a0a0" },
+            "start_time": format!("{:?}", start_time),
+            "end_time": format!("{:?}", start_time + chrono::Duration::minutes(3)),
+            "player_count": 2, "rule_name": "Stock",
+            "max_time": [7, 0], "max_stock_list": [3, 3], "max_hp_list": [-1, -1],
+            "chara_list": ["MARIO", "LINK"], "group_list": ["Unknown", "Unknown"],
+            "stock_list": [1, 0], "order_list": [1, 2], "power_list": [5000000, 5100000],
+        });
+        let data: SmashbrosData = serde_json::from_value(version_1.clone()).unwrap();
+        assert_eq!(data.get_id(), Some("61d1e6a0b0a0a0a0a0a0a0a0".to_string()));
+        assert_eq!(data.get_start_time(), Some(start_time));
+        assert_eq!(data.get_rule(), BattleRule::Stock);
+        assert_eq!(data.get_max_time().as_secs(), 7 * 60);
+        assert_eq!(data.get_max_stock(1), 3);
+        assert_eq!(data.get_character(1), "LINK");
+        assert_eq!(data.is_win(), Some(true));
+
+        // 1: 日時を UTC で保存するようにした後 (日時は BSON の日時か ISO-8601 の文字列, 未検出なら null)
+        let mut version_1_utc = version_1.clone();
+        version_1_utc["start_time"] = serde_json::json!({ "$date": { "$numberLong": start_time.timestamp_millis().to_string() } });
+        version_1_utc["end_time"] = serde_json::Value::Null;
+        let data: SmashbrosData = serde_json::from_value(version_1_utc).unwrap();
+        assert_eq!(data.get_start_time(), Some(start_time));
+        assert_eq!(data.get_end_time(), None);
+        version_1_utc["start_time"] = serde_json::json!(SmashbrosData::date_time_to_string(&start_time));
+        let data: SmashbrosData = serde_json::from_value(version_1_utc).unwrap();
+        assert_eq!(data.get_start_time(), Some(start_time));
+
+        // 2: schema_version が入った (manual_field_list, is_manual_entry はまだ無い)
+        let mut version_2 = version_1.clone();
+        version_2["schema_version"] = serde_json::json!(2);
+        version_2["start_time"] = serde_json::json!(SmashbrosData::date_time_to_string(&start_time));
+        let data: SmashbrosData = serde_json::from_value(version_2).unwrap();
+        assert_eq!(data.get_start_time(), Some(start_time));
+        assert!(data.get_manual_field_list().is_empty());
+        assert!(!data.is_manual_entry());
+
+        // 3: manual_field_list が入った (is_manual_entry はまだ無い)
+        let mut version_3 = serde_json::to_value(&data).unwrap();
+        version_3["schema_version"] = serde_json::json!(3);
+        version_3.as_object_mut().unwrap().remove("is_manual_entry");
+        let data: SmashbrosData = serde_json::from_value(version_3).unwrap();
+        assert!(!data.is_manual_entry());
+
+        // 4: is_manual_entry が入った (tag_list, note はまだ無い)
+        let mut version_4 = serde_json::to_value(&data).unwrap();
+        version_4["schema_version"] = serde_json::json!(4);
+        version_4.as_object_mut().unwrap().remove("tag_list");
+        version_4.as_object_mut().unwrap().remove("note");
+        let data: SmashbrosData = serde_json::from_value(version_4).unwrap();
+        assert!(data.get_tag_list().is_empty());
+        assert!(data.get_note().is_empty());
+
+        // 5: tag_list, note が入った (deleted_time はまだ無い)
+        let mut version_5 = serde_json::to_value(&data).unwrap();
+        version_5["schema_version"] = serde_json::json!(5);
+        version_5.as_object_mut().unwrap().remove("deleted_time");
+        let data: SmashbrosData = serde_json::from_value(version_5).unwrap();
+        assert!(!data.is_deleted());
+
+        // 6: deleted_time が入った (bgm_name はまだ保存されていない)
+        let mut version_6 = serde_json::to_value(&data).unwrap();
+        version_6["schema_version"] = serde_json::json!(6);
+        version_6.as_object_mut().unwrap().remove("bgm_name");
+        let mut data: SmashbrosData = serde_json::from_value(version_6).unwrap();
+        assert!(data.get_bgm_name().is_empty());
+
+        // 7: 今の形式 (手動で修正したフィールドやタグ, ゴミ箱に入れた時刻, BGM も残る)
+        data.set_manual_entry(true);
+        data.set_manual_field("power_list.1".to_string());
+        data.set_tag_list(vec!["laggy".to_string(), " tilted ".to_string(), "laggy".to_string(), "".to_string()]);
+        data.set_note("崖上がりを狩られすぎた".to_string());
+        data.set_deleted_time(Some(start_time));
+        data.set_bgm_name("戦場".to_string());
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value["schema_version"], SmashbrosData::SCHEMA_VERSION);
+        let restored_data: SmashbrosData = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(restored_data.get_start_time(), data.get_start_time());
+        assert_eq!(restored_data.get_power(1), 5100000);
+        assert!(restored_data.is_manual_field("power_list.1"));
+        assert!(restored_data.is_manual_entry());
+        assert_eq!(restored_data.get_tag_list(), vec!["laggy".to_string(), "tilted".to_string()]);
+        assert_eq!(restored_data.get_note(), "崖上がりを狩られすぎた");
+        assert_eq!(restored_data.get_deleted_time(), Some(start_time));
+        assert_eq!(restored_data.get_bgm_name(), "戦場");
+
+        // 新しいバージョンで保存されたものも、知らないフィールドを無視して読める
+        let mut newer_value = value;
+        newer_value["schema_version"] = serde_json::json!(SmashbrosData::SCHEMA_VERSION + 1);
+        newer_value["unknown_field"] = serde_json::json!(["value"]);
+        let restored_data: SmashbrosData = serde_json::from_value(newer_value).unwrap();
+        assert_eq!(restored_data.get_character(0), "MARIO");
+
+        // 壊れた値は panic せずにエラーになる
+        let broken_list = [
+            ("_id", serde_json::json!({ "oid": "61d1e6a0b0a0a0a0a0a0a0a0" })),
+            ("schema_version", serde_json::json!("2")),
+            ("player_count", serde_json::json!("2")),
+            ("chara_list", serde_json::json!("MARIO")),
+            ("start_time", serde_json::json!({ "date": 0 })),
+        ];
+        for (field, broken_value) in broken_list {
+            let mut value = version_1.clone();
+            value[field] = broken_value;
+            assert!(serde_json::from_value::<SmashbrosData>(value).is_err(), "{}", field);
+        }
+    }
+
+    #[test]
+    fn test_validate_manual_edit() {
+        let context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
+        let smashbros_resource = context.smashbros_resource();
+        let mut data = SmashbrosData::default();
+        data.initialize_battle(2, true);
+        data.set_character(0, "MARIO".to_string());
+        assert_eq!(data.validate_manual_edit(&smashbros_resource), Ok(()));
+
+        data.set_character(1, "HAM".to_string());
+        assert!(data.validate_manual_edit(&smashbros_resource).is_err());
+        data.set_character(1, "LINK".to_string());
+
+        // 片方だけの順位や同じ順位は無効
+        data.set_order(0, 1);
+        assert!(data.validate_manual_edit(&smashbros_resource).is_err());
+        data.set_order(1, 1);
+        assert!(data.validate_manual_edit(&smashbros_resource).is_err());
+        data.set_order(1, 2);
+        assert_eq!(data.validate_manual_edit(&smashbros_resource), Ok(()));
+
+        // 相手と離れすぎている戦闘力は無効
+        data.set_power(0, 5000000);
+        assert_eq!(data.validate_manual_edit(&smashbros_resource), Ok(()));
+        data.set_power(1, 500000);
+        assert!(data.validate_manual_edit(&smashbros_resource).is_err());
+        data.set_power(1, 5100000);
+        assert_eq!(data.validate_manual_edit(&smashbros_resource), Ok(()));
+    }
+
+    #[test]
+    fn test_new_manual_entry() {
+        let mut battle_history = InMemoryBattleHistory::new();
+        let mut context = AppContext::with_battle_history(Box::new(battle_history.clone()));
+        let start_time = chrono::Local::now() - chrono::Duration::minutes(5);
+        let mut data = SmashbrosData::new_manual_entry(["MARIO".to_string(), "LINK".to_string()], BattleRule::Stock, false, [5000000, -1], start_time);
+        assert_eq!(data.validate_manual_edit(&context.smashbros_resource()), Ok(()));
+        data.save_battle_with(&mut context);
+        assert!(data.get_id().is_some());
+
+        let data_list = battle_history.get_data_list();
+        assert!(data_list[0].is_manual_entry());
+        assert_eq!(data_list[0].get_character(1), "LINK");
+        assert_eq!(data_list[0].is_win(), Some(false));
+        assert_eq!(data_list[0].get_power(0), 5000000);
+        assert_eq!(data_list[0].get_power(1), -1);
+
+        // 手動で入力したものだけ (か、それ以外だけ) に絞り込める
+        let filter = BattleHistoryFilter { manual_entry: Some(true), ..Default::default() };
+        assert_eq!(battle_history.find_data_by_filter(&filter, 0, 10).unwrap().len(), 1);
+        let filter = BattleHistoryFilter { manual_entry: Some(false), ..Default::default() };
+        assert_eq!(battle_history.find_data_by_filter(&filter, 0, 10).unwrap().len(), 0);
+    }
 }
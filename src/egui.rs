@@ -10,26 +10,57 @@ use eframe::{
 use i18n_embed_fl::fl;
 use linked_hash_map::LinkedHashMap;
 use opencv::prelude::MatTraitConst;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use crate::capture::CaptureMode;
+use crate::context::AppContext;
 use crate::data::{
     SmashbrosData,
     SmashbrosDataTrait,
 };
+use crate::event::{
+    EngineEvent,
+    EngineEventCallback,
+    EventBus,
+    SubscriptionId,
+};
 use crate::engine::{
+    EngineCommand,
+    EngineMessage,
+    EngineSnapshot,
+    EngineWorker,
     SmashBrogEngine,
-    SMASHBROS_ENGINE,
 };
+use crate::export::{
+    BattleHistoryExporter,
+    ExportFormat,
+    ExportLayout,
+};
+use crate::hook::{
+    run_hooks,
+    HookCommand,
+    HookEvent,
+};
+use crate::output::TextOutput;
 use crate::resource::{
+    BattleHistoryFilter,
+    BattleHistoryTrait,
+    BattleStatsGroup,
+    SavedSearch,
+    SoundManager,
     SoundType,
-    BATTLE_HISTORY,
-    GUI_CONFIG,
-    LANG_LOADER,
-    SMASHBROS_RESOURCE,
-    SOUND_MANAGER,
 };
 use crate::scene::SceneList;
+use crate::server::{
+    ApiLiveState,
+    ApiServer,
+};
+use crate::webhook::{
+    Webhook,
+    WebhookQueue,
+};
 
 
 pub async fn run_gui() -> anyhow::Result<()> {
@@ -39,7 +70,10 @@ pub async fn run_gui() -> anyhow::Result<()> {
     native_options.resizable = false;
     native_options.drag_and_drop_support = true;
 
-    let app = GUI::new();
+    // GUI スレッドで使うコンテキスト
+    let context = AppContext::new();
+    let _guard = context.enter();
+    let app = GUI::new(context)?;
 
     eframe::run_native(Box::new(app), native_options)
 }
@@ -64,33 +98,47 @@ enum GUIIdList {
     BattleInformationGrid,
     BattleInformationChildGrid,
     CharacterHistoryGrid,
+    TagTableGrid,
+    UndoDeleteArea,
     PowerPlot,
     CharacterPlot,
 }
 
 // GUI の子ウィンドウが持つ
 trait GUIModelTrait {
-    fn name(&self) -> String;
-    fn show(&mut self, ctx: &egui::Context);
-    fn setup(&mut self, _ctx: &egui::Context) {}
+    fn name(&self, context: &AppContext) -> String;
+    fn show(&mut self, ctx: &egui::Context, context: &AppContext);
+    fn setup(&mut self, _ctx: &egui::Context, _context: &AppContext) {}
 }
 trait  GUIViewTrait {
-    fn ui(&mut self, ui: &mut egui::Ui);
+    fn ui(&mut self, ui: &mut egui::Ui, context: &AppContext);
 }
 
 
 pub struct GUI {
+    context: AppContext,
+    engine_worker: EngineWorker,
+    event_bus: EventBus,
+    api_server: Option<ApiServer>,
+    text_output: Option<TextOutput>,
+    webhook: Option<Webhook>,
     window_battle_information: WindowBattleInformation,
     window_battle_history: WindowBattleHistory,
     window_configuration: WindowConfiguration,
 }
 impl GUI {
-    fn new() -> Self {
-        Self {
+    fn new(context: AppContext) -> anyhow::Result<Self> {
+        Ok(Self {
+            engine_worker: EngineWorker::spawn(context.clone(), EngineWorker::DEFAULT_TARGET_FPS)?,
+            context,
+            event_bus: EventBus::new(),
+            api_server: None,
+            text_output: None,
+            webhook: None,
             window_battle_information: WindowBattleInformation::default(),
             window_battle_history: WindowBattleHistory::default(),
             window_configuration: WindowConfiguration::default(),
-        }
+        })
     }
 
     // GUI の icon を返す
@@ -107,8 +155,8 @@ impl GUI {
     }
 
     // data の player_id のキャラ画像を指定 size で返す
-    pub fn get_chara_image(chara_name: String, size: egui::Vec2) -> Option<egui::Image> {
-        if let Some(chara_texture) = SMASHBROS_RESOURCE().get_mut().get_image_handle(chara_name) {
+    pub fn get_chara_image(chara_name: String, size: egui::Vec2, context: &AppContext) -> Option<egui::Image> {
+        if let Some(chara_texture) = context.smashbros_resource().get_image_handle(chara_name) {
             return Some(egui::Image::new( chara_texture.id(), size ));
         }
 
@@ -122,7 +170,7 @@ impl GUI {
     pub fn get_title_bar_height() -> f32 { 32.0 }
 
     // フォントの設定
-    pub fn set_font(ctx: &egui::Context, font_family: Option<String>, font_size: i32) {
+    pub fn set_font(ctx: &egui::Context, font_family: Option<String>, font_size: i32, context: &AppContext) {
         let default_fonts = (
             "Mamelon".to_string(),
             egui::FontData::from_static(include_bytes!("../fonts/Mamelon-5-Hi-Regular.otf"))
@@ -159,77 +207,133 @@ impl GUI {
         default_style.text_styles.insert(egui::TextStyle::Name("battle_information".into()), egui::FontId::new(font_size_base, egui::FontFamily::Proportional));
         ctx.set_style(default_style);
 
-        GUI_CONFIG().get_mut().font_size = Some(font_size);
-        GUI_CONFIG().get_mut().font_family = Some(font_datas.0);
+        let mut gui_config = context.gui_config();
+        gui_config.font_size = Some(font_size);
+        gui_config.font_family = Some(font_datas.0);
+    }
+
+    // イベントの購読 (ワーカースレッドから EngineEvent が届いたら GUI スレッドで呼ぶ)
+    pub fn subscribe(&mut self, callback: EngineEventCallback) -> SubscriptionId {
+        self.event_bus.subscribe(callback)
+    }
+
+    // イベントを別スレッドで受け取る為の購読 (外部連携用)
+    pub fn subscribe_channel(&mut self) -> (SubscriptionId, std::sync::mpsc::Receiver<EngineEvent>) {
+        self.event_bus.subscribe_channel()
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.event_bus.unsubscribe(id)
     }
 
     // イベントの設定
-    pub fn set_event() {
-        SMASHBROS_ENGINE().get_mut().registory_scene_event(
-            SceneList::Unknown,
-            SceneList::DecidedRules,
-            Box::new(|smashbros_data| {
-                // 試合中、最大ストックが警告未満になったら警告音を再生
-                if smashbros_data.is_decided_max_stock(0) {
-                    if smashbros_data.get_max_stock(0) < GUI_CONFIG().get_mut().gui_state_config.stock_warning_under {
-                        if !GUI_CONFIG().get_mut().stock_alert_command.is_empty() {
-                            let command_result = std::process::Command::new("cmd")
-                                .args(["/K", "start", &GUI_CONFIG().get_mut().stock_alert_command])
-                                .output();
-                            
-                            match command_result {
-                                Ok(result) => {
-                                    if result.status.success() {
-                                        log::info!("stock alert command: {}", String::from_utf8_lossy(&result.stdout));
-                                    } else {
-                                        log::warn!("Failed to execute command: {}", String::from_utf8_lossy(&result.stderr));
-                                    }
-                                },
-                                Err(e) => log::error!("{}", e),
-                            }
-                        }
-                    }
+    pub fn set_event(&mut self) {
+        let context = self.context.clone();
+        self.subscribe(Box::new(move |event| {
+            // 設定されたフックを実行する (試合中、最大ストックが警告未満になったら警告のファイルも開く)
+            let (stock_warning_under, hook_command_list) = {
+                let gui_config = context.gui_config();
+                let mut hook_command_list = gui_config.hook_list.clone();
+                if !gui_config.stock_alert_command.is_empty() {
+                    hook_command_list.push(HookCommand::open_file(HookEvent::StockUnder, &gui_config.stock_alert_command));
                 }
-            })
-        );
 
-        SMASHBROS_ENGINE().get_mut().registory_scene_event(
-            SceneList::Unknown,
-            SceneList::DecidedBgm,
-            Box::new(|smashbros_data| {
-                // BGM が確定していて, BGM が許可されていないなら、変わりの BGM を再生する
-                if !SMASHBROS_RESOURCE().get_mut().bgm_list.is_empty() {
-                    if smashbros_data.is_decided_bgm_name() {
-                        if let Some(is_playble) = SMASHBROS_RESOURCE().get_mut().bgm_list.get(&smashbros_data.get_bgm_name()) {
-                            if !*is_playble {
-                                SOUND_MANAGER().get_mut().play_bgm_random();
-                            }
+                (gui_config.gui_state_config.stock_warning_under, hook_command_list)
+            };
+            run_hooks(event, stock_warning_under, &hook_command_list);
+        }));
+
+        let context = self.context.clone();
+        let sound_manager = Rc::clone(&self.window_configuration.sound_manager);
+        self.subscribe(Box::new(move |event| {
+            match event {
+                EngineEvent::BgmDecided { bgm_name, .. } => {
+                    // BGM が確定していて, BGM が許可されていないなら、変わりの BGM を再生する
+                    let smashbros_resource = context.smashbros_resource();
+                    if let Some(is_playble) = smashbros_resource.bgm_list.get(bgm_name) {
+                        if !*is_playble {
+                            sound_manager.borrow_mut().play_bgm_random();
                         }
                     }
-                }
-            })
-        );
+                },
+                EngineEvent::SceneChanged { before: SceneList::GamePlaying, after: SceneList::GameEnd | SceneList::ReadyToFight, .. } => {
+                    // もし BGM が再生中なら止める
+                    let mut sound_manager = sound_manager.borrow_mut();
+                    if sound_manager.is_playing(Some(SoundType::Bgm)) {
+                        sound_manager.stop(Some(SoundType::Bgm));
+                    }
+                },
+                _ => (),
+            }
+        }));
+    }
 
-        let bgm_callback = Box::new(|_smashbros_data: &mut SmashbrosData| {
-            // もし BGM が再生中なら止める
-            if SOUND_MANAGER().get_mut().is_playing(Some(SoundType::Bgm)) {
-                SOUND_MANAGER().get_mut().stop(Some(SoundType::Bgm));
+    // ローカル API サーバーを設定に合わせて起動/停止する
+    fn update_api_server(&mut self) {
+        let api_server_config = self.context.gui_config().api_server.clone();
+        if !api_server_config.enabled {
+            self.api_server = None;
+            return;
+        }
+        if let Some(api_server) = self.api_server.as_ref() {
+            if api_server.get_port() == api_server_config.port {
+                return;
             }
-        });
-        SMASHBROS_ENGINE().get_mut().registory_scene_event(SceneList::GamePlaying, SceneList::GameEnd, bgm_callback.clone());
-        SMASHBROS_ENGINE().get_mut().registory_scene_event(SceneList::GamePlaying, SceneList::ReadyToFight, bgm_callback.clone());
+        }
+
+        // 同じポートで待ち受け直せるように、先に止めておく
+        // (止めた方の Receiver は drop されるので、次の publish で EventBus から外れる)
+        self.api_server = None;
+        let (_, event_receiver) = self.event_bus.subscribe_channel();
+        self.api_server = match ApiServer::spawn(self.context.clone(), api_server_config.port, event_receiver) {
+            Ok(api_server) => Some(api_server),
+            Err(e) => {
+                log::error!("{}", e);
+                None
+            },
+        };
+    }
+
+    // 配信ツール向けのテキスト出力を設定に合わせて起動し直す/停止する
+    fn update_text_output(&mut self) {
+        self.text_output = None;
+        let text_output_config = self.context.gui_config().text_output.clone();
+        if !text_output_config.enabled {
+            return;
+        }
+
+        let (_, event_receiver) = self.event_bus.subscribe_channel();
+        self.text_output = match TextOutput::spawn(self.context.clone(), text_output_config, event_receiver) {
+            Ok(text_output) => Some(text_output),
+            Err(e) => {
+                log::error!("{}", e);
+                None
+            },
+        };
+    }
+
+    // 戦歴を送る webhook を設定に合わせて起動する (送り先は config.json で設定する)
+    fn update_webhook(&mut self) {
+        self.webhook = None;
+        let webhook_list = self.context.gui_config().webhook_list.clone();
+        if webhook_list.is_empty() {
+            return;
+        }
+
+        let (_, event_receiver) = self.event_bus.subscribe_channel();
+        self.webhook = match Webhook::spawn(self.context.clone(), webhook_list, WebhookQueue::QUEUE_FILE.into(), event_receiver) {
+            Ok(webhook) => Some(webhook),
+            Err(e) => {
+                log::error!("{}", e);
+                None
+            },
+        };
     }
 
     // キャプチャ方法の変更
-    pub fn change_capture_mode() {
-        let _ = SMASHBROS_ENGINE().get_mut().change_capture_mode(&GUI_CONFIG().get_mut().capture_mode).map_err(|e|{
-            match GUI_CONFIG().get_mut().capture_mode {
-                CaptureMode::Empty(_) => (
-                    // デフォルト値に選択したことは知らせなくていいため、何もしない
-                ),
-                _ => log::warn!("{}", e),
-            }
-        });
+    pub fn change_capture_mode(&mut self) {
+        let capture_mode = self.context.gui_config().capture_mode.clone();
+        self.engine_worker.send(EngineCommand::ChangeCaptureMode(capture_mode));
     }
 
     // 幅が 0 の egui::Grid を返す
@@ -249,47 +353,55 @@ impl GUI {
 
     // デフォルトフォントの設定
     fn set_default_font(&mut self, ctx: &egui::Context) {
-        Self::set_font(ctx, GUI_CONFIG().get_mut().font_family.clone(), GUI_CONFIG().get_mut().font_size.unwrap_or(12));
-
-        self.window_configuration.font_size = GUI_CONFIG().get_mut().font_size.clone().unwrap();
-        self.window_configuration.font_family = GUI_CONFIG().get_mut().font_family.clone().unwrap();
+        let app = self.context.clone();
+        let font_family = app.gui_config().font_family.clone();
+        let font_size = app.gui_config().font_size.unwrap_or(12);
+        Self::set_font(ctx, font_family, font_size, &app);
+
+        let gui_config = app.gui_config();
+        self.window_configuration.font_size = gui_config.font_size.clone().unwrap();
+        self.window_configuration.font_family = gui_config.font_family.clone().unwrap();
     }
 
     // 対戦情報の更新
-    fn update_battle_informations(&mut self) {
+    fn update_battle_informations(&mut self, snapshot: EngineSnapshot) {
         // 検出状態
-        self.window_configuration.now_scene = SMASHBROS_ENGINE().get_mut().get_captured_scene();
-        self.window_configuration.prev_match_ratio = SMASHBROS_ENGINE().get_mut().get_prev_match_ratio();
+        self.window_configuration.now_scene = snapshot.now_scene;
+        self.window_configuration.prev_match_ratio = snapshot.prev_match_ratio;
 
-        if GUI_CONFIG().get_mut().gui_state_config.show_captured {
+        if let Some(now_image) = snapshot.now_image.as_ref() {
             // 検出しているフレームを表示
-            let _ = opencv::highgui::imshow("smabrog - captured", SMASHBROS_ENGINE().get_mut().get_now_image());
+            let _ = opencv::highgui::imshow("smabrog - captured", now_image);
+        }
+
+        if let Some(api_server) = self.api_server.as_ref() {
+            api_server.set_live_state(ApiLiveState {
+                scene: snapshot.now_scene,
+                match_ratio: snapshot.prev_match_ratio,
+                data: snapshot.ref_now_data.clone(),
+            });
         }
 
         // 対戦中情報
-        self.window_battle_information.battle_information.set_data( SMASHBROS_ENGINE().get_mut().ref_now_data().clone() );
+        self.window_battle_information.battle_information.set_data( snapshot.ref_now_data );
 
         // 下記から、戦歴情報の変動があったときだけにしたい処理
-        if !SMASHBROS_ENGINE().get_mut().is_update_now_data() {
-            return;
-        }
-
-        // 戦歴
-        self.window_battle_history.battle_information_list.clear();
-        let data_latest = SMASHBROS_ENGINE().get_mut().get_data_latest();
-        for data in data_latest.clone() {
-            let mut battle_information = WindowBattleInformationGroup::default();
-            battle_information.set_data(data);
+        let data_list = match snapshot.data_list {
+            Some(data_list) => data_list,
+            None => return,
+        };
 
-            self.window_battle_history.battle_information_list.push(battle_information);
-        }
-        let all_data_list = SMASHBROS_ENGINE().get_mut().get_data_all_by_now_chara();
+        // 戦歴 (一覧は次に表示する時に読み直す)
+        let data_latest = data_list.data_latest;
+        self.window_battle_history.history_list.reload();
         self.window_battle_history.set_data(
-            SmashBrogEngine::get_wins_by_data_list_groupby_character(&all_data_list));
+            data_list.stats_by_opponent.iter()
+                .map(|stats| (stats.opponent.clone(), (stats.get_win_rate(), stats.get_battle_count())))
+                .collect());
 
-        let chara_data_list = SMASHBROS_ENGINE().get_mut().get_data_latest_by_now_chara();
+        let chara_data_list = data_list.data_latest_by_chara;
         self.window_battle_information.wins_graph.set_data(
-            SMASHBROS_ENGINE().get_mut().get_now_data(),
+            data_list.now_data,
             data_latest.clone(),
             SmashBrogEngine::get_win_lose_by_data_list(&data_latest),
             SmashBrogEngine::get_wins_by_data_list(&chara_data_list),
@@ -299,71 +411,127 @@ impl GUI {
 
     // BGM の更新
     fn update_bgm(&mut self) {
-        self.window_configuration.update_bgm();
+        self.window_configuration.update_bgm(&self.context);
     }
 
     // 言語の更新
     fn update_language(&mut self, is_initialize: bool) {
         use i18n_embed::LanguageLoader;
 
-        let now_lang = LANG_LOADER().get().current_language();
-        if let Some(lang) = GUI_CONFIG().get_mut().lang.as_ref() {
+        let app = self.context.clone();
+        let now_lang = app.lang_loader().current_language();
+        if let Some(lang) = app.gui_config().lang.as_ref() {
             if !is_initialize && now_lang.language == lang.language {
                 return;
             }
         }
 
-        GUI_CONFIG().get_mut().lang = Some(now_lang.clone());
-        SMASHBROS_RESOURCE().get_mut().change_language();
-        SMASHBROS_ENGINE().get_mut().change_language();
+        app.gui_config().lang = Some(now_lang.clone());
+        app.smashbros_resource_mut().change_language(app.lang_loader());
+        self.engine_worker.send(EngineCommand::ChangeLanguage);
+    }
+
+    // ワーカースレッドからの通知を反映する
+    fn update_engine_messages(&mut self) -> anyhow::Result<()> {
+        for message in self.engine_worker.try_recv_all() {
+            match message {
+                EngineMessage::Snapshot(snapshot) => self.update_battle_informations(*snapshot),
+                EngineMessage::Event(event) => self.event_bus.publish(&event),
+                EngineMessage::Error(e) => anyhow::bail!(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    // 設定した日数より前にゴミ箱に入れた戦歴を完全に削除する
+    fn purge_trash(&mut self) {
+        let trash_retention_days = self.context.gui_config().trash_retention_days;
+        if trash_retention_days <= 0 {
+            return;
+        }
+
+        match self.context.purge_deleted_data(chrono::Local::now() - chrono::Duration::days(trash_retention_days)) {
+            Ok(0) => (),
+            Ok(count) => log::info!("purged {} battles in trash.", count),
+            Err(e) => log::error!("failed purge trash. {}", e),
+        }
+    }
+
+    // 子ウィンドウからの要求を engine に反映する
+    fn update_requests(&mut self) {
+        if std::mem::take(&mut self.window_configuration.is_changed_capture_mode) {
+            self.change_capture_mode();
+        }
+        if std::mem::take(&mut self.window_configuration.is_changed_result_max) {
+            self.engine_worker.send(EngineCommand::ChangeResultMax);
+        }
+        if std::mem::take(&mut self.window_configuration.is_changed_target_fps) {
+            let target_fps = self.context.gui_config().target_fps;
+            self.engine_worker.send(EngineCommand::ChangeTargetFps(target_fps));
+        }
+        if std::mem::take(&mut self.window_configuration.is_changed_api_server) {
+            self.update_api_server();
+        }
+        if std::mem::take(&mut self.window_configuration.is_changed_text_output) {
+            self.update_text_output();
+        }
+        if std::mem::take(&mut self.window_battle_history.is_deleted_data) {
+            self.engine_worker.send(EngineCommand::UpdateLatestData);
+        }
+        if std::mem::take(&mut self.window_battle_history.is_deleted_chara_data) {
+            self.engine_worker.send(EngineCommand::UpdateCharaFindData);
+        }
     }
 }
 impl epi::App for GUI {
     fn name(&self) -> &str { "smabrog" }
 
     fn setup(&mut self, ctx: &egui::Context, _frame: &epi::Frame, _storage: Option<&dyn epi::Storage>) {
-        SMASHBROS_RESOURCE().init(Some(ctx));
-        GUI_CONFIG().get_mut().load_config(true).expect("Failed to load config");
+        let app = self.context.clone();
+        app.init_smashbros_resource(ctx);
+        app.load_config(true).expect("Failed to load config");
+        self.purge_trash();
 
-        if let Some(lang) = GUI_CONFIG().get_mut().lang.as_ref() {
-            LANG_LOADER().change(lang.clone());
-        }
         self.update_language(true);
         self.set_default_font(ctx);
 
-        Self::set_event();
-        Self::change_capture_mode();
+        self.set_event();
+        self.change_capture_mode();
+        let target_fps = app.gui_config().target_fps;
+        self.engine_worker.send(EngineCommand::ChangeTargetFps(target_fps));
+        self.update_api_server();
+        self.update_text_output();
+        self.update_webhook();
 
-        self.window_battle_information.setup(ctx);
-        self.window_battle_history.setup(ctx);
-        self.window_configuration.setup(ctx);
+        self.window_battle_information.setup(ctx, &app);
+        self.window_battle_history.setup(ctx, &app);
+        self.window_configuration.setup(ctx, &app);
 
         self.window_battle_information.battle_information = WindowBattleInformationGroup::default();
-        self.update_battle_informations();
     }
 
     fn on_exit(&mut self) {
-        let _ = GUI_CONFIG().get_mut().save_config(true);
+        let _ = self.context.gui_config().save_config(true);
     }
 
     fn update(&mut self, ctx: &egui::Context, frame: &epi::Frame) {
-        self.update_battle_informations();
-        self.update_language(false);
-        self.update_bgm();
-        
-        // 動作
-        if let Err(e) = SMASHBROS_ENGINE().get_mut().update() {
+        // 動作 (解析はワーカースレッドで行われているので、その結果を受け取る)
+        if let Err(e) = self.update_engine_messages() {
             // quit
             // TODO:ゆくゆくはエラー回復とかもできるようにしたい
             log::error!("quit. [{}]", e);
             frame.quit();
             return;
         }
+        self.update_language(false);
+        self.update_bgm();
 
         // 表示 (戦歴が最前面になるように下から描画)
-        self.window_configuration.show(ctx);
-        self.window_battle_history.show(ctx);
-        self.window_battle_information.show(ctx);
+        self.window_configuration.show(ctx, &self.context);
+        self.window_battle_history.show(ctx, &self.context);
+        self.window_battle_information.show(ctx, &self.context);
+        self.update_requests();
 
         // frame.repaint_signal();
         ctx.request_repaint();
@@ -391,20 +559,20 @@ impl WindowBattleInformation {
     }
 }
 impl GUIModelTrait for WindowBattleInformation {
-    fn name(&self) -> String { fl!(LANG_LOADER().get(), "battle_information") }
-    fn show(&mut self, ctx: &egui::Context) {
-        egui::Window::new(self.name())
+    fn name(&self, context: &AppContext) -> String { fl!(context.lang_loader(), "battle_information") }
+    fn show(&mut self, ctx: &egui::Context, context: &AppContext) {
+        egui::Window::new(self.name(context))
             .default_rect(Self::get_initial_window_rect())
-            .show(ctx, |ui| self.ui(ui));
+            .show(ctx, |ui| self.ui(ui, context));
     }
 }
 impl GUIViewTrait for WindowBattleInformation {
-    fn ui(&mut self, ui: &mut egui::Ui) {
-        if GUI_CONFIG().get_mut().gui_state_config.battling {
-            self.battle_information.show_ui(ui, |_ui| {});
+    fn ui(&mut self, ui: &mut egui::Ui, context: &AppContext) {
+        if context.gui_config().gui_state_config.battling {
+            self.battle_information.show_ui(ui, |_ui| {}, context);
             ui.separator();
         }
-        self.wins_graph.show_ui( ui, fl!(LANG_LOADER().get(), "gsp") );
+        self.wins_graph.show_ui( ui, fl!(context.lang_loader(), "gsp"), context );
 
         ui.allocate_space(ui.available_size());
     }
@@ -416,6 +584,8 @@ enum WindowBattleHistoryTab {
     BattleHistory,
     CharacterTable,
     CharacterHistory,
+    TagHistory,
+    Trash,
 }
 impl Default for WindowBattleHistoryTab {
     fn default() -> Self { WindowBattleHistoryTab::BattleHistory }
@@ -424,15 +594,23 @@ impl Default for WindowBattleHistoryTab {
 // 戦歴
 #[derive(Default)]
 struct WindowBattleHistory {
-    pub battle_information_list: Vec<WindowBattleInformationGroup>,
     pub all_battle_rate_list: LinkedHashMap<String, (f32, i32)>,  // キャラ別, (勝率と試合数)
     window_battle_history_tab: WindowBattleHistoryTab,
     chara_plot_list: HashMap<String, plot::Value>,
-    find_character_list: Vec<String>,
+    history_list: WindowBattleHistoryList,
+    history_search: WindowHistorySearch,
     character_history_list: Vec<WindowBattleInformationGroup>,
     character_history_graph: WindowWinsGraph,
-    is_exact_match: bool,
+    find_tag_list: String,
+    tag_history_list: Vec<WindowBattleInformationGroup>,
+    tag_battle_rate_list: LinkedHashMap<String, (f32, i32)>,  // タグ別, (勝率と試合数)
     max_battle_count: f32,
+    history_export: WindowHistoryExport,
+    battle_editor: WindowBattleEditor,
+    battle_trash: WindowBattleTrash,
+    undo_delete: WindowUndoDelete,
+    pub is_deleted_data: bool,
+    pub is_deleted_chara_data: bool,
 }
 impl WindowBattleHistory {
     pub fn get_initial_window_size() -> egui::Vec2 {
@@ -451,6 +629,8 @@ impl WindowBattleHistory {
     const CHARA_IMAGE_ZOOM: f32 = 5.0;
     const CHARA_Y_GROUP_COUNT: i32 = 10;
     const CHARA_TABLE_WIDTH: f64 = 50.0 - 2.5;
+    // 履歴として表示する数
+    const HISTORY_VIEW_LIMIT: i64 = 100;
     pub fn set_data(&mut self, all_battle_rate_list: LinkedHashMap<String, (f32, i32)>) {
         self.all_battle_rate_list = all_battle_rate_list;
 
@@ -478,53 +658,60 @@ impl WindowBattleHistory {
     }
 
     // N 戦の履歴表示
-    fn battle_history_view(&mut self, ui: &mut egui::Ui) {
-        if WindowBattleInformationGroup::show_group_list_with_delete(ui, &mut self.battle_information_list) {
-            SMASHBROS_ENGINE().get_mut().update_latest_n_data();
+    fn battle_history_view(&mut self, ui: &mut egui::Ui, context: &AppContext) {
+        if ui.button(format!("➕ {}", fl!(context.lang_loader(), "new_battle"))).clicked() {
+            self.battle_editor.open_new(context);
+        }
+        self.history_export.show_ui(ui, &self.history_list.group_list, context);
+        ui.separator();
+
+        if let Some(data) = self.history_list.show_ui(ui, &mut self.battle_editor, context) {
+            self.is_deleted_data = true;
+            self.undo_delete.open(data);
         }
     }
 
     // キャラ表のラベルの表示
-    fn show_table_label(ui: &mut plot::PlotUi) {
+    fn show_table_label(ui: &mut plot::PlotUi, context: &AppContext) {
         ui.line(
             plot::Line::new(
                 plot::Values::from_values(vec![plot::Value::new(-2.5, 0.0), plot::Value::new(25.5, 0.0), plot::Value::new(Self::CHARA_TABLE_WIDTH, 0.0)]),
             ).color(egui::Color32::RED)
             .fill(10.0)
-            .name(fl!(LANG_LOADER().get(), "losing")),
+            .name(fl!(context.lang_loader(), "losing")),
         );
         ui.line(
             plot::Line::new(
                 plot::Values::from_values(vec![plot::Value::new(-2.5, 10.0), plot::Value::new(25.5, 10.0), plot::Value::new(Self::CHARA_TABLE_WIDTH, 10.0)]),
             ).color(egui::Color32::LIGHT_RED)
             .fill(40.0)
-            .name(fl!(LANG_LOADER().get(), "not_good"))
+            .name(fl!(context.lang_loader(), "not_good"))
         );
         ui.line(
             plot::Line::new(
                 plot::Values::from_values(vec![plot::Value::new(-2.5, 40.0), plot::Value::new(25.5, 40.0), plot::Value::new(Self::CHARA_TABLE_WIDTH, 40.0)]),
             ).color(egui::Color32::YELLOW)
             .fill(60.0)
-            .name(fl!(LANG_LOADER().get(), "just"))
+            .name(fl!(context.lang_loader(), "just"))
         );
         ui.line(
             plot::Line::new(
                 plot::Values::from_values(vec![plot::Value::new(-2.5, 60.0), plot::Value::new(25.5, 60.0), plot::Value::new(Self::CHARA_TABLE_WIDTH, 60.0)]),
             ).color(egui::Color32::LIGHT_GREEN)
             .fill(90.0)
-            .name(fl!(LANG_LOADER().get(), "good"))
+            .name(fl!(context.lang_loader(), "good"))
         );
         ui.line(
             plot::Line::new(
                 plot::Values::from_values(vec![plot::Value::new(-2.5, 90.0), plot::Value::new(25.5, 90.0), plot::Value::new(Self::CHARA_TABLE_WIDTH, 90.0)]),
             ).color(egui::Color32::LIGHT_BLUE)
             .fill(100.0)
-            .name(fl!(LANG_LOADER().get(), "winning"))
+            .name(fl!(context.lang_loader(), "winning"))
         );
     }
 
     // キャラ別のグラフ表示
-    fn character_table_view(&mut self, ui: &mut egui::Ui) {
+    fn character_table_view(&mut self, ui: &mut egui::Ui, context: &AppContext) {
         let available_size = ui.available_size();
         GUI::new_grid(GUIIdList::AppearanceTab, 2, egui::Vec2::new(30.0, 5.0))
             .striped(true)
@@ -535,12 +722,12 @@ impl WindowBattleHistory {
                     .legend(plot::Legend::default().text_style(egui::TextStyle::Body))
                     .show_axes([false, true])
                     .show(ui, |ui| {
-                        Self::show_table_label(ui);
+                        Self::show_table_label(ui, context);
                         for (chara_name, (wins_rate, _battle_count)) in &self.all_battle_rate_list {
                             if !self.chara_plot_list.contains_key(chara_name) {
                                 continue;
                             }
-                            let chara_texture = match SMASHBROS_RESOURCE().get_mut().get_image_handle(chara_name.clone()) {
+                            let chara_texture = match context.smashbros_resource().get_image_handle(chara_name.clone()) {
                                 Some(chara_texture) => chara_texture,
                                 None => return,
                             };
@@ -562,103 +749,934 @@ impl WindowBattleHistory {
             });
     }
 
-    // 対キャラの戦歴表示
-    fn character_history_view(&mut self, ui: &mut egui::Ui) {
-        use crate::resource::SmashbrosResource;
-
-        let one_width = ui.available_size().x / 4.0;
-        GUI::new_grid(GUIIdList::CharacterHistoryGrid, 4, egui::Vec2::new(5.0, 0.0))
-            .show(ui, |ui| {
-                ui.checkbox( &mut self.is_exact_match, fl!(LANG_LOADER().get(), "exact_match") );
-                ui.add_sized([one_width, 18.0],
-                    egui::TextEdit::singleline(&mut self.find_character_list[0])
-                        .hint_text("1p")
+    // 条件を組み合わせた戦歴の検索
+    fn character_history_view(&mut self, ui: &mut egui::Ui, context: &AppContext) {
+        if let Some(data_list) = self.history_search.show_ui(ui, context) {
+            self.character_history_list = data_list.iter()
+                .map(|data| {
+                    let mut battle_information = WindowBattleInformationGroup::default();
+                    battle_information.set_data(data.clone());
+                    battle_information
+                })
+                .collect();
+
+            if !data_list.is_empty() {
+                self.character_history_graph.set_data(
+                    data_list[0].clone(),
+                    data_list.clone(),
+                    SmashBrogEngine::get_win_lose_by_data_list(&data_list),
+                    SmashBrogEngine::get_wins_by_data_list(&data_list),
+                    WinsGraphKind::Rate
                 );
-                ui.add_sized([one_width, 18.0],
-                    egui::TextEdit::singleline(&mut self.find_character_list[1])
-                        .hint_text("2p")
-                );
-                if ui.button(fl!( LANG_LOADER().get(), "search" )).clicked() {
-                    // キャラ名推測をする
-                    self.find_character_list = self.find_character_list.iter_mut().map(|chara_name| {
-                        if let Some((new_chara_name, _)) = SmashbrosResource::convert_character_name(chara_name.to_uppercase()) {
-                            return new_chara_name;
-                        }
+            }
+            if self.character_history_list.is_empty() {
+                // 検索結果がなにもない場合は default の SmashbrosData を突っ込む
+                let mut battle_information = WindowBattleInformationGroup::default();
+                battle_information.set_data( SmashbrosData::default() );
+                self.character_history_list.push(battle_information);
+            }
+        }
 
-                        chara_name.clone()
-                    }).collect();
-                    log::info!("search character history: {:?}", self.find_character_list);
+        ui.separator();
+        self.character_history_graph.show_ui( ui, fl!(context.lang_loader(), "passage"), context );
+        
+        ui.separator();
+        self.history_export.show_ui(ui, &self.character_history_list, context);
+        if let Some(data) = WindowBattleInformationGroup::show_group_list_with_delete(ui, &mut self.character_history_list, &mut self.battle_editor, context) {
+            self.is_deleted_data = true;
+            self.is_deleted_chara_data = true;
+            self.undo_delete.open(data);
+        }
+    }
 
-                    if let Some(data_list) = BATTLE_HISTORY().get_mut().find_data_by_chara_list(self.find_character_list.clone(), 100, !self.is_exact_match) {
-                        self.character_history_list.clear();
-                        for data in data_list.clone() {
-                            let mut battle_information = WindowBattleInformationGroup::default();
-                            battle_information.set_data(data);
-                            self.character_history_list.push(battle_information);
-                        }
+    // タグ別の戦歴表示
+    fn tag_history_view(&mut self, ui: &mut egui::Ui, context: &AppContext) {
+        let half_width = ui.available_size().x / 2.0;
+        ui.horizontal(|ui| {
+            ui.add_sized([half_width, 18.0],
+                egui::TextEdit::singleline(&mut self.find_tag_list)
+                    .hint_text(fl!(context.lang_loader(), "tag_hint"))
+            );
+            if ui.button(fl!(context.lang_loader(), "search" )).clicked() {
+                self.update_tag_history(context);
+            }
+        });
 
-                        if !data_list.is_empty() {
-                            self.character_history_graph.set_data(
-                                data_list[0].clone(),
-                                data_list.clone(),
-                                SmashBrogEngine::get_win_lose_by_data_list(&data_list),
-                                SmashBrogEngine::get_wins_by_data_list(&data_list),
-                                WinsGraphKind::Rate
-                            );
-                        }
-                    }
-                    if self.character_history_list.is_empty() {
-                        // 検索結果がなにもない場合は default の SmashbrosData を突っ込む
-                        let mut battle_information = WindowBattleInformationGroup::default();
-                        battle_information.set_data( SmashbrosData::default() );
-                        self.character_history_list.push(battle_information);
+        ui.separator();
+        GUI::new_grid(GUIIdList::TagTableGrid, 3, egui::Vec2::new(10.0, 2.0))
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label(fl!(context.lang_loader(), "tag"));
+                ui.label(fl!(context.lang_loader(), "win_rate"));
+                ui.label(fl!(context.lang_loader(), "battle_count"));
+                ui.end_row();
+                for (tag, (win_rate, battle_count)) in &self.tag_battle_rate_list {
+                    if ui.small_button(format!("🏷 {}", tag)).clicked() {
+                        self.find_tag_list = tag.clone();
                     }
+                    ui.label(format!("{:3.1}%", win_rate * 100.0));
+                    ui.label(battle_count.to_string());
+                    ui.end_row();
                 }
             });
 
         ui.separator();
-        self.character_history_graph.show_ui( ui, fl!(LANG_LOADER().get(), "passage") );
-        
-        ui.separator();
-        if WindowBattleInformationGroup::show_group_list_with_delete(ui, &mut self.character_history_list) {
-            SMASHBROS_ENGINE().get_mut().update_latest_n_data();
-            SMASHBROS_ENGINE().get_mut().update_chara_find_data();
+        self.history_export.show_ui(ui, &self.tag_history_list, context);
+        if let Some(data) = WindowBattleInformationGroup::show_group_list_with_delete(ui, &mut self.tag_history_list, &mut self.battle_editor, context) {
+            self.is_deleted_data = true;
+            self.is_deleted_chara_data = true;
+            self.undo_delete.open(data);
         }
     }
+
+    // 入力されたタグが全て付いた戦歴と、タグ別の勝率を読み直す
+    fn update_tag_history(&mut self, context: &AppContext) {
+        let filter = BattleHistoryFilter { tag_list: BattleHistoryFilter::parse_tag_list(&self.find_tag_list), ..Default::default() };
+        log::info!("search tag history: {:?}", filter.tag_list);
+
+        // タグ別の勝率は件数の上限なく DB 側で集計して、戦歴は表示する分だけ取得する
+        let stats_list = match context.use_battle_history(|battle_history| battle_history.aggregate_stats(&filter, BattleStatsGroup::Tag)) {
+            Some(stats_list) => stats_list,
+            None => return,
+        };
+        let data_list = match context.use_battle_history(|battle_history| battle_history.find_data_by_filter(&filter, 0, Self::HISTORY_VIEW_LIMIT)) {
+            Some(data_list) => data_list,
+            None => return,
+        };
+        self.tag_battle_rate_list = stats_list.into_iter()
+            .map(|stats| (stats.tag.clone(), (stats.get_win_rate(), stats.get_battle_count())))
+            .collect();
+        self.tag_history_list = data_list.into_iter()
+            .map(|data| {
+                let mut battle_information = WindowBattleInformationGroup::default();
+                battle_information.set_data(data);
+                battle_information
+            })
+            .collect();
+    }
 }
 impl GUIModelTrait for WindowBattleHistory {
-    fn setup(&mut self, _ctx: &egui::Context) {
-        self.find_character_list = vec![String::new(); 2];
-        self.is_exact_match = true;
-    }
-    fn name(&self) -> String { fl!(LANG_LOADER().get(), "battle_history") }
-    fn show(&mut self, ctx: &egui::Context) {
-        egui::Window::new(self.name())
+    fn name(&self, context: &AppContext) -> String { fl!(context.lang_loader(), "battle_history") }
+    fn show(&mut self, ctx: &egui::Context, context: &AppContext) {
+        // 戦の履歴は一覧の中でスクロールする
+        egui::Window::new(self.name(context))
             .default_rect(Self::get_initial_window_rect())
-            .vscroll(true)
+            .vscroll(WindowBattleHistoryTab::BattleHistory != self.window_battle_history_tab)
             .hscroll(true)
-            .show(ctx, |ui| self.ui(ui));
+            .show(ctx, |ui| self.ui(ui, context));
+
+        self.battle_editor.show(ctx, context);
+        self.undo_delete.show(ctx, context);
+        let is_restored_data = std::mem::take(&mut self.undo_delete.is_restored_data) | std::mem::take(&mut self.battle_trash.is_restored_data);
+        if is_restored_data && WindowBattleHistoryTab::Trash == self.window_battle_history_tab {
+            self.battle_trash.update(context);
+        }
+        if std::mem::take(&mut self.battle_editor.is_updated_data) | is_restored_data {
+            // 修正したものやゴミ箱から戻したものを読み直す
+            self.is_deleted_data = true;
+            self.is_deleted_chara_data = true;
+            if WindowBattleHistoryTab::TagHistory == self.window_battle_history_tab {
+                self.update_tag_history(context);
+            }
+        }
     }
 }
 impl GUIViewTrait for WindowBattleHistory {
-    fn ui(&mut self, ui: &mut egui::Ui) {
+    fn ui(&mut self, ui: &mut egui::Ui, context: &AppContext) {
         ui.horizontal(|ui| {
-            ui.selectable_value(&mut self.window_battle_history_tab, WindowBattleHistoryTab::BattleHistory, format!("{} {}", self.battle_information_list.len(), fl!(LANG_LOADER().get(), "tab_battle_history")));
-            ui.selectable_value(&mut self.window_battle_history_tab, WindowBattleHistoryTab::CharacterTable, fl!(LANG_LOADER().get(), "tab_character_table"));
-            ui.selectable_value(&mut self.window_battle_history_tab, WindowBattleHistoryTab::CharacterHistory, fl!(LANG_LOADER().get(), "tab_character_history"));
+            ui.selectable_value(&mut self.window_battle_history_tab, WindowBattleHistoryTab::BattleHistory, format!("{}{} {}", self.history_list.group_list.len(), if self.history_list.is_loaded_all { "" } else { "+" }, fl!(context.lang_loader(), "tab_battle_history")));
+            ui.selectable_value(&mut self.window_battle_history_tab, WindowBattleHistoryTab::CharacterTable, fl!(context.lang_loader(), "tab_character_table"));
+            ui.selectable_value(&mut self.window_battle_history_tab, WindowBattleHistoryTab::CharacterHistory, fl!(context.lang_loader(), "tab_character_history"));
+            if ui.selectable_value(&mut self.window_battle_history_tab, WindowBattleHistoryTab::TagHistory, fl!(context.lang_loader(), "tab_tag_history")).clicked() {
+                self.update_tag_history(context);
+            }
+            if ui.selectable_value(&mut self.window_battle_history_tab, WindowBattleHistoryTab::Trash, fl!(context.lang_loader(), "tab_trash")).clicked() {
+                self.battle_trash.update(context);
+            }
         });
         ui.separator();
 
         match self.window_battle_history_tab {
-            WindowBattleHistoryTab::BattleHistory => self.battle_history_view(ui),
-            WindowBattleHistoryTab::CharacterTable => self.character_table_view(ui),
-            WindowBattleHistoryTab::CharacterHistory => self.character_history_view(ui),
+            WindowBattleHistoryTab::BattleHistory => self.battle_history_view(ui, context),
+            WindowBattleHistoryTab::CharacterTable => self.character_table_view(ui, context),
+            WindowBattleHistoryTab::CharacterHistory => self.character_history_view(ui, context),
+            WindowBattleHistoryTab::TagHistory => self.tag_history_view(ui, context),
+            WindowBattleHistoryTab::Trash => self.battle_trash.show_ui(ui, context),
         }
 
         ui.allocate_space(ui.available_size());
     }
 }
 
+// 保存された戦歴の手動修正と、キャプチャしていない試合の手動入力
+#[derive(Default)]
+struct WindowBattleEditor {
+    data: Option<SmashbrosData>,
+    chara_name_list: Vec<String>,
+    tag: String,
+    tag_candidate_list: Vec<String>,
+    message: String,
+    pub is_updated_data: bool,
+}
+impl WindowBattleEditor {
+    // キャラ名の候補の表示数
+    const CANDIDATE_MAX: usize = 6;
+
+    // data の修正を始める
+    fn open(&mut self, data: &SmashbrosData, context: &AppContext) {
+        if data.get_id().is_none() || 2 != data.get_player_count() {
+            // 保存されていないものと 1 on 1 以外は修正しない
+            return;
+        }
+        self.chara_name_list = (0..data.get_player_count()).map(|player_number| data.get_character(player_number)).collect();
+        self.data = Some(data.clone());
+        self.reset_tag(context);
+        self.message.clear();
+    }
+
+    // キャプチャしていない (今終わった) 試合の入力を始める
+    fn open_new(&mut self, context: &AppContext) {
+        use crate::data::BattleRule;
+        let chara_list = [SmashbrosData::CHARACTER_NAME_UNKNOWN.to_string(), SmashbrosData::CHARACTER_NAME_UNKNOWN.to_string()];
+        self.chara_name_list = vec![String::new(); 2];
+        self.data = Some(SmashbrosData::new_manual_entry(chara_list, BattleRule::Stock, true, [-1, -1], chrono::Local::now()));
+        self.reset_tag(context);
+        self.message.clear();
+    }
+
+    // 入力中のタグを消して、今までに付けたタグを候補にする
+    fn reset_tag(&mut self, context: &AppContext) {
+        self.tag.clear();
+        self.tag_candidate_list = context.use_battle_history(|battle_history| battle_history.find_tag_list()).unwrap_or_default();
+    }
+
+    // chara_name に部分一致するキャラ名 (公式英名か公式名) を返す
+    fn get_candidate_list(chara_name: &str, context: &AppContext) -> Vec<String> {
+        if chara_name.is_empty() {
+            return Vec::new();
+        }
+        let upper_chara_name = chara_name.to_uppercase();
+        let mut candidate_list: Vec<String> = context.smashbros_resource().character_list.iter()
+            .filter(|(name, official_name)| name.contains(&upper_chara_name) || official_name.contains(chara_name))
+            .map(|(name, _)| name.clone())
+            .collect();
+        candidate_list.sort();
+        candidate_list.truncate(Self::CANDIDATE_MAX);
+
+        candidate_list
+    }
+
+    // 入力されたキャラ名を反映して、有効なら保存する
+    fn save(data: &mut SmashbrosData, chara_name_list: &[String], context: &AppContext) -> Result<(), String> {
+        for (player_number, chara_name) in chara_name_list.iter().enumerate() {
+            let player_number = player_number as i32;
+            if &data.get_character(player_number) == chara_name {
+                continue;
+            }
+            match context.smashbros_resource().convert_character_name(chara_name.to_uppercase()) {
+                Some((chara_name, ratio)) if 1.0 <= ratio => data.set_character(player_number, chara_name),
+                _ => return Err(format!("unknown character {}p: {}", player_number + 1, chara_name)),
+            }
+            data.set_manual_field(format!("chara_list.{}", player_number));
+        }
+        data.validate_manual_edit(&context.smashbros_resource())?;
+
+        if data.get_id().is_none() {
+            // 手動で入力した試合は全部手動なので、修正したフィールドは残さない
+            data.set_manual_field_list(Vec::new());
+            return match data.save_battle_with(&mut context.clone()) {
+                Some(id) => {
+                    log::info!("added manual entry battle data: {}", id);
+                    Ok(())
+                },
+                None => Err("failed save battle data.".to_string()),
+            };
+        }
+
+        // 表示している言語のキャラ名のまま、保存する時だけ変換する
+        match data.update_battle_with(&mut context.clone()) {
+            Some(_) => {
+                log::info!("manually corrected battle data: {:?} {:?}", data.get_id(), data.get_manual_field_list());
+                Ok(())
+            },
+            None => Err("failed update battle data.".to_string()),
+        }
+    }
+
+    // 値が変わったら data に設定して、手動で修正したフィールドとして記録する
+    fn edit_value(ui: &mut egui::Ui, data: &mut SmashbrosData, field: String, value: i32, range: std::ops::RangeInclusive<i32>, set_value: impl FnOnce(&mut SmashbrosData, i32)) {
+        let mut value = value;
+        if ui.add(egui::DragValue::new(&mut value).clamp_range(range)).changed() {
+            set_value(data, value);
+            data.set_manual_field(field);
+        }
+    }
+
+    // 付いているタグ (クリックで外す) と、タグを付ける入力欄
+    fn tag_ui(ui: &mut egui::Ui, data: &mut SmashbrosData, tag: &mut String, tag_candidate_list: &[String], context: &AppContext) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label(fl!(context.lang_loader(), "tag"));
+            for saved_tag in data.get_tag_list() {
+                if ui.small_button(format!("🏷 {} ❌", saved_tag)).clicked() {
+                    data.remove_tag(&saved_tag);
+                }
+            }
+        });
+        ui.horizontal_wrapped(|ui| {
+            let response = ui.text_edit_singleline(tag);
+            let is_entered = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+            if (ui.button("➕").clicked() || is_entered) && data.add_tag(tag) {
+                tag.clear();
+            }
+            // 今までに付けたタグから部分一致するものを候補にする
+            let candidate_list: Vec<&String> = tag_candidate_list.iter()
+                .filter(|candidate| candidate.contains(tag.trim()) && !data.has_tag(candidate))
+                .take(Self::CANDIDATE_MAX)
+                .collect();
+            for candidate in candidate_list {
+                if ui.small_button(candidate).clicked() {
+                    data.add_tag(candidate);
+                    tag.clear();
+                }
+            }
+        });
+    }
+
+    fn show(&mut self, ctx: &egui::Context, context: &AppContext) {
+        if self.data.is_none() {
+            return;
+        }
+
+        let title = if self.data.as_ref().map_or(false, |data| data.get_id().is_none()) {
+            fl!(context.lang_loader(), "new_battle")
+        } else {
+            fl!(context.lang_loader(), "edit_battle")
+        };
+        let mut is_open = true;
+        egui::Window::new(title)
+            .open(&mut is_open)
+            .collapsible(false)
+            .show(ctx, |ui| self.ui(ui, context));
+        if !is_open {
+            self.data = None;
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, context: &AppContext) {
+        use crate::data::BattleRule;
+        let Self { data, chara_name_list, tag, tag_candidate_list, message, is_updated_data } = self;
+        let data_ref = match data.as_mut() {
+            Some(data) => data,
+            None => return,
+        };
+
+        GUI::new_grid("battle_editor_grid", 3, egui::Vec2::new(10.0, 5.0))
+            .show(ui, |ui| {
+                ui.label("");
+                ui.label("1p");
+                ui.label("2p");
+                ui.end_row();
+
+                ui.label(fl!(context.lang_loader(), "character"));
+                for chara_name in chara_name_list.iter_mut() {
+                    ui.vertical(|ui| {
+                        ui.text_edit_singleline(chara_name);
+                        for candidate in Self::get_candidate_list(chara_name, context) {
+                            if candidate != *chara_name && ui.small_button(&candidate).clicked() {
+                                *chara_name = candidate;
+                            }
+                        }
+                    });
+                }
+                ui.end_row();
+
+                ui.label(fl!(context.lang_loader(), "rule"));
+                let mut rule = data_ref.get_rule();
+                egui::ComboBox::from_id_source("battle_editor_rule")
+                    .selected_text(format!("{:?}", rule))
+                    .show_ui(ui, |ui| {
+                        for rule_value in [BattleRule::Time, BattleRule::Stock, BattleRule::Stamina, BattleRule::Tournament, BattleRule::Unknown] {
+                            let text = format!("{:?}", rule_value);
+                            ui.selectable_value(&mut rule, rule_value, text);
+                        }
+                    });
+                if rule != data_ref.get_rule() {
+                    data_ref.set_rule(rule);
+                    data_ref.set_manual_field("rule_name".to_string());
+                }
+                ui.label("");
+                ui.end_row();
+
+                ui.label(fl!(context.lang_loader(), "max_stock"));
+                let max_stock = data_ref.get_max_stock(0);
+                Self::edit_value(ui, data_ref, "max_stock_list".to_string(), max_stock, -1..=3, |data, max_stock| {
+                    for player_number in 0..data.get_player_count() {
+                        data.set_max_stock(player_number, max_stock);
+                    }
+                });
+                ui.label("");
+                ui.end_row();
+
+                ui.label(fl!(context.lang_loader(), "stock"));
+                for player_number in 0..2 {
+                    let stock = data_ref.get_stock(player_number);
+                    Self::edit_value(ui, data_ref, format!("stock_list.{}", player_number), stock, -1..=99, |data, stock| data.set_stock(player_number, stock));
+                }
+                ui.end_row();
+
+                if data_ref.get_id().is_none() {
+                    // 手動で入力する試合は勝敗で順位を決める
+                    ui.label(fl!(context.lang_loader(), "win_lose"));
+                    ui.horizontal(|ui| {
+                        let is_win = 1 == data_ref.get_order(0);
+                        for (is_win_value, text) in [(true, fl!(context.lang_loader(), "winning")), (false, fl!(context.lang_loader(), "losing"))] {
+                            if ui.selectable_label(is_win == is_win_value, text).clicked() {
+                                data_ref.set_order(0, if is_win_value { 1 } else { 2 });
+                                data_ref.set_order(1, if is_win_value { 2 } else { 1 });
+                            }
+                        }
+                    });
+                    ui.label("");
+                } else {
+                    ui.label(fl!(context.lang_loader(), "order"));
+                    for player_number in 0..2 {
+                        let order = data_ref.get_order(player_number);
+                        Self::edit_value(ui, data_ref, format!("order_list.{}", player_number), order, -1..=2, |data, order| data.set_order(player_number, order));
+                    }
+                }
+                ui.end_row();
+
+                ui.label(fl!(context.lang_loader(), "gsp"));
+                for player_number in 0..2 {
+                    let power = data_ref.get_power(player_number);
+                    Self::edit_value(ui, data_ref, format!("power_list.{}", player_number), power, -1..=99_999_999, |data, power| data.set_power(player_number, power));
+                }
+                ui.end_row();
+            });
+
+        ui.separator();
+        Self::tag_ui(ui, data_ref, tag, tag_candidate_list, context);
+        ui.label(fl!(context.lang_loader(), "note"));
+        let mut note = data_ref.get_note();
+        if ui.add(egui::TextEdit::multiline(&mut note).desired_rows(3)).changed() {
+            data_ref.set_note(note);
+        }
+
+        let mut is_saved = false;
+        ui.horizontal(|ui| {
+            if ui.button(fl!(context.lang_loader(), "save")).clicked() {
+                let mut edited_data = data_ref.clone();
+                match Self::save(&mut edited_data, chara_name_list, context) {
+                    Ok(()) => {
+                        is_saved = true;
+                        message.clear();
+                    },
+                    Err(e) => {
+                        log::error!("{}", e);
+                        *message = e;
+                    },
+                }
+            }
+            ui.label(message.as_str());
+        });
+        if is_saved {
+            *is_updated_data = true;
+            *data = None;
+        }
+    }
+}
+
+// 削除した直後に表示する、元に戻すボタン
+#[derive(Default)]
+struct WindowUndoDelete {
+    data: Option<SmashbrosData>,
+    deleted_time: Option<std::time::Instant>,
+    pub is_restored_data: bool,
+}
+impl WindowUndoDelete {
+    // 元に戻せる時間
+    const SHOW_DURATION: std::time::Duration = std::time::Duration::from_secs(10);
+
+    fn open(&mut self, data: SmashbrosData) {
+        self.data = Some(data);
+        self.deleted_time = Some(std::time::Instant::now());
+    }
+
+    fn show(&mut self, ctx: &egui::Context, context: &AppContext) {
+        if self.deleted_time.map_or(true, |deleted_time| Self::SHOW_DURATION < deleted_time.elapsed()) {
+            self.data = None;
+        }
+        if self.data.is_none() {
+            return;
+        }
+
+        egui::Area::new(GUIIdList::UndoDeleteArea)
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::Vec2::new(0.0, -16.0))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(fl!(context.lang_loader(), "deleted_battle"));
+                        if ui.button(fl!(context.lang_loader(), "undo")).clicked() {
+                            match context.use_battle_history(|battle_history| battle_history.restore_data(self.data.as_ref().unwrap())) {
+                                Ok(()) => {
+                                    log::info!("restore battle data: {:?}", self.data);
+                                    self.is_restored_data = true;
+                                },
+                                Err(e) => log::error!("failed restore battle data. {}", e),
+                            }
+                            self.data = None;
+                        }
+                        if ui.small_button("❌").clicked() {
+                            self.data = None;
+                        }
+                    });
+                });
+            });
+    }
+}
+
+// ゴミ箱に入れた戦歴の一覧 (元に戻すか、完全に削除する)
+#[derive(Default)]
+struct WindowBattleTrash {
+    group_list: Vec<WindowBattleInformationGroup>,
+    pub is_restored_data: bool,
+}
+impl WindowBattleTrash {
+    // ゴミ箱の一覧として表示する数
+    const TRASH_VIEW_LIMIT: i64 = 100;
+
+    // ゴミ箱の戦歴を読み直す
+    fn update(&mut self, context: &AppContext) {
+        self.group_list = context.use_battle_history(|battle_history| battle_history.find_deleted_data(Self::TRASH_VIEW_LIMIT)).unwrap_or_default()
+            .into_iter()
+            .map(|data| {
+                let mut battle_information = WindowBattleInformationGroup::default();
+                battle_information.set_data(data);
+                battle_information
+            })
+            .collect();
+    }
+
+    fn show_ui(&mut self, ui: &mut egui::Ui, context: &AppContext) {
+        ui.horizontal(|ui| {
+            let trash_retention_days = context.gui_config().trash_retention_days;
+            ui.label(format!("{} ({}: {})", self.group_list.len(), fl!(context.lang_loader(), "trash_retention_days"), trash_retention_days));
+            if ui.add_enabled(!self.group_list.is_empty(), egui::Button::new(format!("❌🗑 {}", fl!(context.lang_loader(), "empty_trash")))).clicked() {
+                match context.use_battle_history(|battle_history| battle_history.purge_deleted_data(chrono::Local::now())) {
+                    Ok(count) => log::info!("purged {} battles in trash.", count),
+                    Err(e) => log::error!("failed purge trash. {}", e),
+                }
+                self.update(context);
+            }
+        });
+        ui.separator();
+
+        let mut restore_index = None;
+        let mut purge_index = None;
+        let len = self.group_list.len();
+        for (index, group) in self.group_list.iter_mut().enumerate() {
+            let deleted_time = group.data.as_ref().and_then(|data| data.get_deleted_time());
+            group.show_ui(ui, |ui: &mut egui::Ui| {
+                ui.add_space(16.0);
+                ui.add(egui::Separator::default().vertical());
+                if let Some(deleted_time) = deleted_time {
+                    ui.label("🗑").on_hover_text(deleted_time.format("%Y-%m-%d %H:%M:%S").to_string());
+                }
+                if ui.button("↩").on_hover_text(fl!(context.lang_loader(), "undo")).clicked() {
+                    restore_index = Some(index);
+                }
+                if ui.add(egui::Button::new("❌").fill(egui::Color32::RED)).clicked() {
+                    purge_index = Some(index);
+                }
+            }, context);
+
+            if index < len - 1 {
+                ui.separator();
+            }
+        }
+
+        if let Some(data) = restore_index.and_then(|index| self.group_list[index].data.clone()) {
+            match context.use_battle_history(|battle_history| battle_history.restore_data(&data)) {
+                Ok(()) => {
+                    log::info!("restore battle data: {:?}", data);
+                    self.is_restored_data = true;
+                },
+                Err(e) => log::error!("failed restore battle data. {}", e),
+            }
+        }
+        if let Some(data) = purge_index.and_then(|index| self.group_list[index].data.clone()) {
+            match context.use_battle_history(|battle_history| battle_history.purge_data(&data)) {
+                Ok(()) => log::info!("purge battle data: {:?}", data),
+                Err(e) => log::error!("failed purge battle data. {}", e),
+            }
+            self.update(context);
+        }
+    }
+}
+
+// 全ての戦歴を新しい順に並べた一覧 (見えている行だけ表示して、足りなくなったら次のページを読む)
+#[derive(Default)]
+struct WindowBattleHistoryList {
+    pub group_list: Vec<WindowBattleInformationGroup>,
+    next_cursor: Option<String>,
+    is_loaded_all: bool,
+    row_height: f32,
+}
+impl WindowBattleHistoryList {
+    // 1 回に読む数
+    const PAGE_SIZE: i64 = 100;
+    // 1 行の高さを測るまでの仮の高さ
+    const DEFAULT_ROW_HEIGHT: f32 = 40.0;
+
+    // 読んだものを捨てて、次に表示する時に最初のページから読み直す
+    fn reload(&mut self) {
+        self.group_list.clear();
+        self.next_cursor = None;
+        self.is_loaded_all = false;
+    }
+
+    // 次のページを読んで後ろに足す
+    fn load_next_page(&mut self, context: &AppContext) {
+        let page = match context.use_battle_history(|battle_history| battle_history.find_data_by_cursor(&BattleHistoryFilter::default(), self.next_cursor.clone(), Self::PAGE_SIZE)) {
+            Some(page) => page,
+            None => {
+                // 読めなかった時に毎フレーム読み直さないようにする
+                self.is_loaded_all = true;
+                return;
+            },
+        };
+        self.is_loaded_all = page.next_cursor.is_none();
+        self.next_cursor = page.next_cursor;
+        self.group_list.extend(page.data_list.into_iter().map(|data| {
+            let mut battle_information = WindowBattleInformationGroup::default();
+            battle_information.set_data(data);
+            battle_information
+        }));
+    }
+
+    // 一覧を表示して、削除したものを返す
+    fn show_ui(&mut self, ui: &mut egui::Ui, battle_editor: &mut WindowBattleEditor, context: &AppContext) -> Option<SmashbrosData> {
+        if self.group_list.is_empty() && !self.is_loaded_all {
+            self.load_next_page(context);
+        }
+
+        let row_height = if 0.0 < self.row_height { self.row_height } else { Self::DEFAULT_ROW_HEIGHT };
+        let row_count = self.group_list.len();
+        let mut deleted_data = None;
+        let mut is_needed_next_page = false;
+        ui.scope(|ui| {
+            // 行の間隔は測った行の高さに含める
+            ui.spacing_mut().item_spacing.y = 0.0;
+            egui::ScrollArea::vertical()
+                .auto_shrink([false, false])
+                .show_rows(ui, row_height, row_count, |ui, row_range| {
+                    is_needed_next_page = !self.is_loaded_all && row_count <= row_range.end + 1;
+                    let visible_count = row_range.len();
+                    let response = ui.scope(|ui| {
+                        deleted_data = WindowBattleInformationGroup::show_group_range_with_delete(ui, &mut self.group_list, row_range, battle_editor, context);
+                    }).response;
+                    if 0 < visible_count {
+                        self.row_height = response.rect.height() / visible_count as f32;
+                    }
+                });
+        });
+        if is_needed_next_page {
+            self.load_next_page(context);
+        }
+
+        deleted_data
+    }
+}
+
+// 戦歴の検索条件の入力と、検索結果のページ送り
+#[derive(Default)]
+struct WindowHistorySearch {
+    since: String,
+    until: String,
+    character: String,
+    opponent: String,
+    rule: Option<crate::data::BattleRule>,
+    max_stock: Option<i32>,
+    is_win: Option<bool>,
+    min_power: String,
+    max_power: String,
+    bgm: String,
+    tag: String,
+    player_count: Option<i32>,
+    filter: BattleHistoryFilter,
+    page: i64,
+    has_next_page: bool,
+    saved_search_name: String,
+    message: String,
+}
+impl WindowHistorySearch {
+    // 1 ページに表示する数
+    const PAGE_SIZE: i64 = 50;
+
+    // 入力された条件を BattleHistoryFilter::from_query の形式にする (空のものは絞り込まない)
+    fn to_query(&self) -> HashMap<String, String> {
+        let mut query = HashMap::new();
+        for (key, value) in [
+            ("since", &self.since), ("until", &self.until),
+            ("character", &self.character), ("opponent", &self.opponent),
+            ("min_power", &self.min_power), ("max_power", &self.max_power),
+            ("bgm", &self.bgm), ("tag", &self.tag),
+        ] {
+            if !value.trim().is_empty() {
+                query.insert(key.to_string(), value.trim().to_string());
+            }
+        }
+        if let Some(rule) = &self.rule {
+            query.insert("rule".to_string(), format!("{:?}", rule));
+        }
+        if let Some(max_stock) = self.max_stock {
+            query.insert("max_stock".to_string(), max_stock.to_string());
+        }
+        if let Some(is_win) = self.is_win {
+            query.insert("result".to_string(), if is_win { "win" } else { "lose" }.to_string());
+        }
+        if let Some(player_count) = self.player_count {
+            query.insert("player_count".to_string(), player_count.to_string());
+        }
+
+        query
+    }
+
+    // 保存した検索条件を入力に戻す
+    fn set_query(&mut self, query: &HashMap<String, String>, context: &AppContext) {
+        let get = |key: &str| query.get(key).cloned().unwrap_or_default();
+        let filter = BattleHistoryFilter::from_query(query, &context.smashbros_resource()).unwrap_or_default();
+        *self = Self {
+            since: get("since"),
+            until: get("until"),
+            character: get("character"),
+            opponent: get("opponent"),
+            rule: filter.rule,
+            max_stock: filter.max_stock,
+            is_win: filter.is_win,
+            min_power: get("min_power"),
+            max_power: get("max_power"),
+            bgm: get("bgm"),
+            tag: get("tag"),
+            player_count: filter.player_count,
+            saved_search_name: std::mem::take(&mut self.saved_search_name),
+            ..Default::default()
+        };
+    }
+
+    // 入力された BGM を推測して、検索条件を決める (キャラ名は from_query で保存されているキャラ名にする)
+    fn update_filter(&mut self, context: &AppContext) -> Result<(), String> {
+        let smashbros_resource = context.smashbros_resource();
+        if !self.bgm.trim().is_empty() {
+            if let Some((new_bgm_name, _)) = smashbros_resource.convert_bgm_list(self.bgm.trim().to_string()) {
+                self.bgm = new_bgm_name;
+            }
+        }
+
+        self.filter = BattleHistoryFilter::from_query(&self.to_query(), &smashbros_resource)?;
+        self.page = 0;
+
+        Ok(())
+    }
+
+    // 今のページの戦歴を取得する (次のページがあるかを知る為に 1 件多く取得する)
+    fn find_page(&mut self, context: &AppContext) -> Vec<SmashbrosData> {
+        log::info!("search history: {:?} page {}", self.filter, self.page);
+        let mut data_list = context.use_battle_history(|battle_history| battle_history.find_data_by_filter(&self.filter, self.page * Self::PAGE_SIZE, Self::PAGE_SIZE + 1)).unwrap_or_default();
+        self.has_next_page = Self::PAGE_SIZE < data_list.len() as i64;
+        data_list.truncate(Self::PAGE_SIZE as usize);
+
+        data_list
+    }
+
+    // 条件の入力欄を表示して、検索したかページを送った時はそのページの戦歴を返す
+    fn show_ui(&mut self, ui: &mut egui::Ui, context: &AppContext) -> Option<Vec<SmashbrosData>> {
+        use crate::data::BattleRule;
+        let empty = fl!(context.lang_loader(), "empty");
+        let input_width = ui.available_size().x / 3.0;
+        GUI::new_grid(GUIIdList::CharacterHistoryGrid, 2, egui::Vec2::new(5.0, 2.0))
+            .show(ui, |ui| {
+                ui.label(fl!(context.lang_loader(), "date_range"));
+                ui.horizontal(|ui| {
+                    ui.add_sized([input_width, 18.0], egui::TextEdit::singleline(&mut self.since).hint_text("YYYY-MM-DD"));
+                    ui.label("~");
+                    ui.add_sized([input_width, 18.0], egui::TextEdit::singleline(&mut self.until).hint_text("YYYY-MM-DD"));
+                });
+                ui.end_row();
+
+                ui.label(fl!(context.lang_loader(), "character"));
+                ui.horizontal(|ui| {
+                    ui.add_sized([input_width, 18.0], egui::TextEdit::singleline(&mut self.character).hint_text("1p"));
+                    ui.label("vs");
+                    ui.add_sized([input_width, 18.0], egui::TextEdit::singleline(&mut self.opponent).hint_text("2p"));
+                });
+                ui.end_row();
+
+                ui.label(fl!(context.lang_loader(), "rule"));
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("history_search_rule")
+                        .selected_text(self.rule.as_ref().map_or(empty.clone(), |rule| format!("{:?}", rule)))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.rule, None, empty.as_str());
+                            for rule in [BattleRule::Time, BattleRule::Stock, BattleRule::Stamina, BattleRule::Tournament] {
+                                let text = format!("{:?}", rule);
+                                ui.selectable_value(&mut self.rule, Some(rule), text);
+                            }
+                        });
+                    ui.label(fl!(context.lang_loader(), "max_stock"));
+                    egui::ComboBox::from_id_source("history_search_max_stock")
+                        .selected_text(self.max_stock.map_or(empty.clone(), |max_stock| max_stock.to_string()))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.max_stock, None, empty.as_str());
+                            for max_stock in 1..=3 {
+                                ui.selectable_value(&mut self.max_stock, Some(max_stock), max_stock.to_string());
+                            }
+                        });
+                });
+                ui.end_row();
+
+                ui.label(fl!(context.lang_loader(), "win_lose"));
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.is_win, None, empty.as_str());
+                    ui.selectable_value(&mut self.is_win, Some(true), fl!(context.lang_loader(), "winning"));
+                    ui.selectable_value(&mut self.is_win, Some(false), fl!(context.lang_loader(), "losing"));
+                    ui.label(fl!(context.lang_loader(), "player_count"));
+                    egui::ComboBox::from_id_source("history_search_player_count")
+                        .selected_text(self.player_count.map_or(empty.clone(), |player_count| player_count.to_string()))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.player_count, None, empty.as_str());
+                            for player_count in [2, 4] {
+                                ui.selectable_value(&mut self.player_count, Some(player_count), player_count.to_string());
+                            }
+                        });
+                });
+                ui.end_row();
+
+                ui.label(fl!(context.lang_loader(), "gsp"));
+                ui.horizontal(|ui| {
+                    ui.add_sized([input_width, 18.0], egui::TextEdit::singleline(&mut self.min_power).hint_text("min"));
+                    ui.label("~");
+                    ui.add_sized([input_width, 18.0], egui::TextEdit::singleline(&mut self.max_power).hint_text("max"));
+                });
+                ui.end_row();
+
+                ui.label("BGM");
+                ui.add_sized([input_width * 2.0, 18.0], egui::TextEdit::singleline(&mut self.bgm));
+                ui.end_row();
+
+                ui.label(fl!(context.lang_loader(), "tag"));
+                ui.add_sized([input_width * 2.0, 18.0], egui::TextEdit::singleline(&mut self.tag).hint_text(fl!(context.lang_loader(), "tag_hint")));
+                ui.end_row();
+            });
+
+        // 検索条件の保存と読み込み
+        ui.horizontal(|ui| {
+            let mut load_index = None;
+            let mut remove_index = None;
+            egui::ComboBox::from_id_source("history_search_saved_list")
+                .selected_text(fl!(context.lang_loader(), "saved_search"))
+                .show_ui(ui, |ui| {
+                    for (index, saved_search) in context.gui_config().saved_search_list.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            if ui.selectable_label(false, saved_search.name.as_str()).clicked() {
+                                load_index = Some(index);
+                            }
+                            if ui.small_button("❌").clicked() {
+                                remove_index = Some(index);
+                            }
+                        });
+                    }
+                });
+            if let Some(index) = load_index {
+                let saved_search = context.gui_config().saved_search_list[index].clone();
+                self.saved_search_name = saved_search.name;
+                self.set_query(&saved_search.query, context);
+            }
+            if let Some(index) = remove_index {
+                context.gui_config().saved_search_list.remove(index);
+            }
+
+            ui.add_sized([input_width, 18.0], egui::TextEdit::singleline(&mut self.saved_search_name).hint_text(fl!(context.lang_loader(), "saved_search_name")));
+            if ui.add_enabled(!self.saved_search_name.trim().is_empty(), egui::Button::new(fl!(context.lang_loader(), "save"))).clicked() {
+                let saved_search = SavedSearch { name: self.saved_search_name.trim().to_string(), query: self.to_query() };
+                let mut gui_config = context.gui_config();
+                match gui_config.saved_search_list.iter_mut().find(|saved| saved.name == saved_search.name) {
+                    Some(saved) => *saved = saved_search,
+                    None => gui_config.saved_search_list.push(saved_search),
+                }
+            }
+        });
+
+        let mut data_list = None;
+        ui.horizontal(|ui| {
+            if ui.button(fl!(context.lang_loader(), "search")).clicked() {
+                match self.update_filter(context) {
+                    Ok(()) => {
+                        self.message.clear();
+                        data_list = Some(self.find_page(context));
+                    },
+                    Err(e) => self.message = e,
+                }
+            }
+            if ui.add_enabled(0 < self.page, egui::Button::new("◀")).clicked() {
+                self.page -= 1;
+                data_list = Some(self.find_page(context));
+            }
+            ui.label((self.page + 1).to_string());
+            if ui.add_enabled(self.has_next_page, egui::Button::new("▶")).clicked() {
+                self.page += 1;
+                data_list = Some(self.find_page(context));
+            }
+            ui.label(&self.message);
+        });
+
+        data_list
+    }
+}
+
+// 表示している戦歴の書き出し
+#[derive(Default)]
+struct WindowHistoryExport {
+    format: ExportFormat,
+    layout: ExportLayout,
+    message: String,
+}
+impl WindowHistoryExport {
+    const EXPORT_FOLDER: &'static str = "export";
+
+    // group_list の戦歴を EXPORT_FOLDER に書き出す
+    fn show_ui(&mut self, ui: &mut egui::Ui, group_list: &[WindowBattleInformationGroup], context: &AppContext) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.format, ExportFormat::Csv, "CSV");
+            ui.selectable_value(&mut self.format, ExportFormat::Xlsx, "XLSX");
+            let mut is_per_battle = self.layout == ExportLayout::PerBattle;
+            if ui.checkbox(&mut is_per_battle, fl!(context.lang_loader(), "per_battle")).changed() {
+                self.layout = if is_per_battle { ExportLayout::PerBattle } else { ExportLayout::PerPlayer };
+            }
+
+            if ui.button(fl!(context.lang_loader(), "export")).clicked() {
+                // 検索結果が無い時の空のデータは書き出さない
+                let data_list: Vec<SmashbrosData> = group_list.iter()
+                    .filter_map(|group| group.data.clone())
+                    .filter(|data| data.get_id().is_some())
+                    .collect();
+                let path = std::path::Path::new(Self::EXPORT_FOLDER)
+                    .join(format!("smabrog_{}.{}", chrono::Local::now().format("%Y%m%d_%H%M%S"), self.format.get_extension()));
+                let result = std::fs::create_dir_all(Self::EXPORT_FOLDER).map_err(anyhow::Error::from)
+                    .and_then(|_| BattleHistoryExporter::write(&path, &data_list, self.layout));
+                self.message = match result {
+                    Ok(()) => path.display().to_string(),
+                    Err(e) => {
+                        log::error!("{}", e);
+                        e.to_string()
+                    },
+                };
+            }
+            ui.label(&self.message);
+        });
+    }
+}
+
 // 設定タブ
 #[derive(PartialEq)]
 enum ConfigTab {
@@ -682,6 +1700,12 @@ struct WindowConfiguration {
     bgm_device_list: HashMap<String, HashMap<String, wasapi::SimpleAudioVolume>>,
     before_volume: Option<f32>,
 
+    pub sound_manager: Rc<RefCell<SoundManager>>,
+    pub is_changed_capture_mode: bool,
+    pub is_changed_result_max: bool,
+    pub is_changed_target_fps: bool,
+    pub is_changed_api_server: bool,
+    pub is_changed_text_output: bool,
     pub now_scene: SceneList,
     pub prev_match_ratio: f64,
     pub font_family: String,
@@ -693,6 +1717,7 @@ impl Default for WindowConfiguration {
 impl WindowConfiguration {
     fn new() -> Self {
         // WASAPI のほうを先に初期化しないと rodio と競合するっぽい
+        let bgm_device_list = Self::init_wasapi();
         Self {
             config_tab: ConfigTab::Source,
 
@@ -701,9 +1726,16 @@ impl WindowConfiguration {
             video_device_list: Vec::new(),
             video_device_id: 0,
             font_family_list: Vec::new(),
-            bgm_device_list: Self::init_wasapi(),
+            bgm_device_list,
             before_volume: None,
 
+            sound_manager: Rc::new(RefCell::new(SoundManager::default())),
+            is_changed_capture_mode: false,
+            is_changed_result_max: false,
+            is_changed_target_fps: false,
+            is_changed_api_server: false,
+            is_changed_text_output: false,
+
             now_scene: SceneList::default(),
             prev_match_ratio: 0.0,
             font_family: String::new(),
@@ -784,18 +1816,18 @@ impl WindowConfiguration {
         )
     }
 
-    pub fn update_bgm(&mut self) {
+    pub fn update_bgm(&mut self, context: &AppContext) {
         // 選択されているデバイスを取得
-        let bgm_device_name = match GUI_CONFIG().get_mut().bgm_device_name.as_ref() {
+        let bgm_device_name = match context.gui_config().bgm_device_name.clone() {
             Some(bgm_device_name) => bgm_device_name,
             None => return,
         };
-        let bgm_session_name = match GUI_CONFIG().get_mut().bgm_session_name.as_ref() {
+        let bgm_session_name = match context.gui_config().bgm_session_name.clone() {
             Some(bgm_session_name) => bgm_session_name,
             None => return,
         };
-        let simple_audio_volume = match self.bgm_device_list.get(bgm_device_name) {
-            Some(device) => match device.get(bgm_session_name) {
+        let simple_audio_volume = match self.bgm_device_list.get(&bgm_device_name) {
+            Some(device) => match device.get(&bgm_session_name) {
                 Some(simple_audio_volume) => simple_audio_volume,
                 None => return,
             },
@@ -804,7 +1836,7 @@ impl WindowConfiguration {
 
         if let Some(before_volume) = self.before_volume {
             // BGM が停止していて、音量を変更した痕跡があるならもとに戻す
-            if SOUND_MANAGER().get_mut().is_playing(Some(SoundType::Bgm)) {
+            if self.sound_manager.borrow().is_playing(Some(SoundType::Bgm)) {
                 return;
             }
             if let Err(err) = simple_audio_volume.set_master_volume(before_volume) {
@@ -813,21 +1845,22 @@ impl WindowConfiguration {
             self.before_volume = None;
         } else {
             // 音量を変更する必要があり、BGM が再生中なら、現在の音量を記憶して変更する
-            if GUI_CONFIG().get_mut().gui_state_config.disable_volume == 1.0 {
+            let disable_volume = context.gui_config().gui_state_config.disable_volume;
+            if disable_volume == 1.0 {
                 return;
             }
-            if !SOUND_MANAGER().get_mut().is_playing(Some(SoundType::Bgm)) {
+            if !self.sound_manager.borrow().is_playing(Some(SoundType::Bgm)) {
                 return;
             }
             self.before_volume = Some(simple_audio_volume.get_master_volume().unwrap_or(1.0));
-            if let Err(err) = simple_audio_volume.set_master_volume(self.before_volume.unwrap_or(1.0) * GUI_CONFIG().get_mut().gui_state_config.disable_volume) {
+            if let Err(err) = simple_audio_volume.set_master_volume(self.before_volume.unwrap_or(1.0) * disable_volume) {
                 log::error!("{}", err);
             }
         }
     }
 
     // キャプチャモードの設定の view を返す
-    fn source_settings_view(&mut self, ui: &mut egui::Ui) {
+    fn source_settings_view(&mut self, ui: &mut egui::Ui, context: &AppContext) {
         use crate::capture::{
             CaptureFromWindow,
             CaptureFromVideoDevice,
@@ -837,24 +1870,25 @@ impl WindowConfiguration {
             .striped(true)
             .max_col_width(ui.available_size().x - 5.0)
             .show(ui, |ui| {
+                let capture_mode = context.gui_config().capture_mode.clone();
                 egui::ComboBox::from_id_source(GUIIdList::SourceKind)
-                .selected_text(format!("{}", GUI_CONFIG().get_mut().capture_mode))
+                .selected_text(capture_mode.get_name(context.lang_loader()))
                 .show_ui(ui, |ui| {
-                    if ui.add(egui::SelectableLabel::new( GUI_CONFIG().get_mut().capture_mode.is_empty(), fl!(LANG_LOADER().get(), "empty") )).clicked() {
-                        GUI_CONFIG().get_mut().capture_mode = CaptureMode::new_empty();
-                        GUI::change_capture_mode();
+                    if ui.add(egui::SelectableLabel::new( capture_mode.is_empty(), fl!(context.lang_loader(), "empty") )).clicked() {
+                        context.gui_config().capture_mode = CaptureMode::new_empty(context.lang_loader());
+                        self.is_changed_capture_mode = true;
                     }
-                    if ui.add(egui::SelectableLabel::new( GUI_CONFIG().get_mut().capture_mode.is_window(), fl!(LANG_LOADER().get(), "window") )).clicked() {
-                        GUI_CONFIG().get_mut().capture_mode = CaptureMode::new_window(self.window_caption.clone());
+                    if ui.add(egui::SelectableLabel::new( capture_mode.is_window(), fl!(context.lang_loader(), "window") )).clicked() {
+                        context.gui_config().capture_mode = CaptureMode::new_window(self.window_caption.clone(), context.lang_loader());
                         self.window_caption_list = CaptureFromWindow::get_window_list();
                     }
-                    if ui.add(egui::SelectableLabel::new( GUI_CONFIG().get_mut().capture_mode.is_video_device(), fl!(LANG_LOADER().get(), "video_device") )).clicked() {
-                        GUI_CONFIG().get_mut().capture_mode = CaptureMode::new_video_device(self.video_device_id);
+                    if ui.add(egui::SelectableLabel::new( capture_mode.is_video_device(), fl!(context.lang_loader(), "video_device") )).clicked() {
+                        context.gui_config().capture_mode = CaptureMode::new_video_device(self.video_device_id, context.lang_loader());
                         self.video_device_list = CaptureFromVideoDevice::get_device_list();
                     }
-                    if ui.add(egui::SelectableLabel::new( GUI_CONFIG().get_mut().capture_mode.is_desktop(), fl!(LANG_LOADER().get(), "desktop") )).clicked() {
-                        GUI_CONFIG().get_mut().capture_mode = CaptureMode::new_desktop();
-                        GUI::change_capture_mode();
+                    if ui.add(egui::SelectableLabel::new( capture_mode.is_desktop(), fl!(context.lang_loader(), "desktop") )).clicked() {
+                        context.gui_config().capture_mode = CaptureMode::new_desktop(context.lang_loader());
+                        self.is_changed_capture_mode = true;
                     }
                 });
                 ui.end_row();
@@ -864,9 +1898,11 @@ impl WindowConfiguration {
                     window_caption_list,
                     window_caption,
                     video_device_id,
+                    is_changed_capture_mode,
                     ..
                 } = self;
-                match &mut GUI_CONFIG().get_mut().capture_mode {
+                // この match 内では gui_config を lock したままなので、再度 lock しないこと
+                match &mut context.gui_config().capture_mode {
                     CaptureMode::Window(_, cm_window_caption) => {
                         egui::ComboBox::from_id_source(GUIIdList::WindowList)
                             .selected_text(Self::get_small_caption(cm_window_caption.clone(), 40))
@@ -876,13 +1912,13 @@ impl WindowConfiguration {
                                     if ui.add(egui::SelectableLabel::new( wc == cm_window_caption, wc.as_str() )).clicked() {
                                         *cm_window_caption = wc.clone();
                                         *window_caption = wc.clone();
-                                        GUI::change_capture_mode();
+                                        *is_changed_capture_mode = true;
                                     }
                                 }
                             });
                     },
                     CaptureMode::VideoDevice(_, cm_device_id, _) => {
-                        let selected_text = format!( "{}", video_device_list.get(*cm_device_id as usize).unwrap_or(&fl!(LANG_LOADER().get(), "unselected")) );
+                        let selected_text = format!( "{}", video_device_list.get(*cm_device_id as usize).unwrap_or(&fl!(context.lang_loader(), "unselected")) );
                         let selected_text = Self::get_small_caption(selected_text.clone(), 40);
                         egui::ComboBox::from_id_source(GUIIdList::DeviceList)
                             .selected_text(selected_text)
@@ -892,7 +1928,7 @@ impl WindowConfiguration {
                                     if ui.add(egui::SelectableLabel::new(*cm_device_id == id as i32, name)).clicked() {
                                         *cm_device_id = id as i32;
                                         *video_device_id = id as i32;
-                                        GUI::change_capture_mode();
+                                        *is_changed_capture_mode = true;
                                     }
                                 }
                             });
@@ -903,17 +1939,17 @@ impl WindowConfiguration {
         
                 // 状態の表示
                 ui.checkbox(
-                    &mut GUI_CONFIG().get_mut().gui_state_config.show_captured,
+                    &mut context.gui_config().gui_state_config.show_captured,
                     format!(
-                        "{}:{:?} {}:{:.0}%", fl!(LANG_LOADER().get(), "status"), self.now_scene,
-                        fl!(LANG_LOADER().get(), "next"), self.prev_match_ratio * 100.0
+                        "{}:{:?} {}:{:.0}%", fl!(context.lang_loader(), "status"), self.now_scene,
+                        fl!(context.lang_loader(), "next"), self.prev_match_ratio * 100.0
                     )
                 );
             });
     }
 
     // 外観の設定の view を返す
-    fn appearance_settings_view(&mut self, ui: &mut egui::Ui) {
+    fn appearance_settings_view(&mut self, ui: &mut egui::Ui, context: &AppContext) {
         use i18n_embed::LanguageLoader;
         use crate::resource::Localizations;
 
@@ -923,29 +1959,29 @@ impl WindowConfiguration {
             .show(ui, |ui| {
                 // テーマ
                 let style = (*ui.ctx().style()).clone();
-                ui.label(fl!(LANG_LOADER().get(), "theme"));
+                ui.label(fl!(context.lang_loader(), "theme"));
                 ui.horizontal(|ui| {
                     if ui.add(egui::SelectableLabel::new(style.visuals == Visuals::dark(), "🌙 Dark")).clicked() {
                         ui.ctx().set_visuals(Visuals::dark());
-                        GUI_CONFIG().get_mut().visuals = Some(Visuals::dark());
+                        context.gui_config().visuals = Some(Visuals::dark());
                     }
                     if ui.add(egui::SelectableLabel::new(style.visuals == Visuals::light(), "☀ Light")).clicked() {
                         ui.ctx().set_visuals(Visuals::light());
-                        GUI_CONFIG().get_mut().visuals = Some(Visuals::light());
+                        context.gui_config().visuals = Some(Visuals::light());
                     }
                 });
                 ui.end_row();
 
                 // 言語
-                let now_lang = LANG_LOADER().get().current_language();
-                let lang_list = LANG_LOADER().get().available_languages(&Localizations).unwrap();
-                ui.label(fl!(LANG_LOADER().get(), "language"));
+                let now_lang = context.lang_loader().current_language();
+                let lang_list = context.lang_loader().available_languages(&Localizations).unwrap();
+                ui.label(fl!(context.lang_loader(), "language"));
                 egui::ComboBox::from_id_source(GUIIdList::LanguageComboBox)
                     .selected_text(format!("{}-{}", now_lang.language, now_lang.region.unwrap().as_str()))
                     .show_ui(ui, |ui| {
                         for lang in &lang_list {
                             if ui.add(egui::SelectableLabel::new(&now_lang == lang, format!("{}-{}", lang.language, lang.region.unwrap().as_str()))).clicked() {
-                                LANG_LOADER().change(lang.clone());
+                                context.change_language(lang.clone());
                             }
                         }
                     });
@@ -953,14 +1989,14 @@ impl WindowConfiguration {
 
                 // フォント
                 use eframe::egui::Widget;
-                ui.label(fl!(LANG_LOADER().get(), "font"));
+                ui.label(fl!(context.lang_loader(), "font"));
                 ui.scope(|ui| {
                     // フォントサイズ
                     if egui::DragValue::new(&mut self.font_size)
                         .clamp_range(1..=1000)
                         .ui(ui).changed()
                     {
-                        GUI::set_font(ui.ctx(), Some(self.font_family.clone()), self.font_size);
+                        GUI::set_font(ui.ctx(), Some(self.font_family.clone()), self.font_size, context);
                     }
 
                     // フォント一覧
@@ -970,7 +2006,7 @@ impl WindowConfiguration {
                         .show_ui(ui, |ui| {
                             for font_family in &self.font_family_list {
                                 if ui.selectable_value(&mut self.font_family, font_family.clone(), font_family.clone()).changed() {
-                                    GUI::set_font(ui.ctx(), Some(self.font_family.clone()), self.font_size);
+                                    GUI::set_font(ui.ctx(), Some(self.font_family.clone()), self.font_size, context);
                                 }
                             }
                     });
@@ -979,42 +2015,87 @@ impl WindowConfiguration {
     }
 
     // 詳細の設定の view を返す
-    fn detail_settings_view(&mut self, ui: &mut egui::Ui) {
+    fn detail_settings_view(&mut self, ui: &mut egui::Ui, context: &AppContext) {
         use eframe::egui::Widget;
         GUI::new_grid(GUIIdList::DetailTab, 2, egui::Vec2::new(10.0, 5.0))
             .striped(true)
             .max_col_width(ui.available_size().x / 2.0)
             .show(ui, |ui| {
                 // 結果を取得する限界数
-                ui.label(fl!(LANG_LOADER().get(), "result_max"));
-                if egui::DragValue::new(&mut GUI_CONFIG().get_mut().result_max)
+                ui.label(fl!(context.lang_loader(), "result_max"));
+                if egui::DragValue::new(&mut context.gui_config().result_max)
                     .clamp_range(1..=1000)
                     .speed(0.5)
                     .ui(ui).changed()
                 {
-                    SMASHBROS_ENGINE().get_mut().change_result_max();
+                    self.is_changed_result_max = true;
+                }
+                ui.end_row();
+
+                // 1 秒間に解析する目標フレーム数 (0 で無制限)
+                ui.label(fl!(context.lang_loader(), "target_fps"));
+                if egui::DragValue::new(&mut context.gui_config().target_fps)
+                    .clamp_range(0.0..=60.0)
+                    .speed(0.5)
+                    .ui(ui).changed()
+                {
+                    self.is_changed_target_fps = true;
                 }
                 ui.end_row();
 
+                // 配信ツールなどから戦歴を取得する為のローカル API (http://127.0.0.1:{port}/api/)
+                ui.label(fl!(context.lang_loader(), "api_server"));
+                ui.scope(|ui| {
+                    if ui.checkbox(&mut context.gui_config().api_server.enabled, "").changed() {
+                        self.is_changed_api_server = true;
+                    }
+                    if egui::DragValue::new(&mut context.gui_config().api_server.port)
+                        .clamp_range(1024..=65535)
+                        .ui(ui).changed()
+                    {
+                        self.is_changed_api_server = true;
+                    }
+                });
+                ui.end_row();
+
+                // 配信ツール向けにテキストファイルを書き出すフォルダ (書き出す内容は config.json で変えられる)
+                ui.label(fl!(context.lang_loader(), "text_output"));
+                ui.scope(|ui| {
+                    if ui.checkbox(&mut context.gui_config().text_output.enabled, "").changed() {
+                        self.is_changed_text_output = true;
+                    }
+                    if ui.text_edit_singleline(&mut context.gui_config().text_output.folder).lost_focus() {
+                        self.is_changed_text_output = true;
+                    }
+                });
+                ui.end_row();
+
+                // ゴミ箱の戦歴を完全に削除するまでの日数 (0 で削除しない, 起動時に削除される)
+                ui.label(fl!(context.lang_loader(), "trash_retention_days"));
+                egui::DragValue::new(&mut context.gui_config().trash_retention_days)
+                    .clamp_range(0..=365)
+                    .ui(ui);
+                ui.end_row();
+
                 // BGM で無効にした時の音量, デバイス, プロセス名
-                ui.label(&format!( "{} {}", fl!(LANG_LOADER().get(), "disable"), fl!(LANG_LOADER().get(), "volume") ));
-                egui::DragValue::new(&mut GUI_CONFIG().get_mut().gui_state_config.disable_volume)
+                ui.label(&format!( "{} {}", fl!(context.lang_loader(), "disable"), fl!(context.lang_loader(), "volume") ));
+                egui::DragValue::new(&mut context.gui_config().gui_state_config.disable_volume)
                     .clamp_range(0.0..=1.0)
                     .speed(0.01)
                     .ui(ui);
                 ui.end_row();
 
-                let now_device_name = GUI_CONFIG().get_mut().bgm_device_name.clone().unwrap_or(fl!(LANG_LOADER().get(), "empty"));
+                let now_device_name = context.gui_config().bgm_device_name.clone().unwrap_or(fl!(context.lang_loader(), "empty"));
                 egui::ComboBox::from_id_source(GUIIdList::BgmDeviceComboBox)
                     .selected_text(Self::get_small_caption( now_device_name.clone(), 10 ))
                     .show_ui(ui, |ui| {
                         for (device_name, _) in &self.bgm_device_list {
                             if ui.add(egui::SelectableLabel::new(&now_device_name == device_name, device_name)).clicked() {
-                                GUI_CONFIG().get_mut().bgm_device_name = Some(device_name.clone());
+                                context.gui_config().bgm_device_name = Some(device_name.clone());
                             }
                         }
                     });
-                let now_session_name = GUI_CONFIG().get_mut().bgm_session_name.clone().unwrap_or(fl!(LANG_LOADER().get(), "empty"));
+                let now_session_name = context.gui_config().bgm_session_name.clone().unwrap_or(fl!(context.lang_loader(), "empty"));
                 egui::ComboBox::from_id_source(GUIIdList::BgmSessionComboBox)
                     .selected_text(Self::get_small_caption( now_session_name.clone(), 10 ))
                     .show_ui(ui, |ui| {
@@ -1023,56 +2104,59 @@ impl WindowConfiguration {
                         }
                         for (session_name, _) in &self.bgm_device_list[&now_device_name] {
                             if ui.add(egui::SelectableLabel::new(&now_session_name == session_name, session_name)).clicked() {
-                                GUI_CONFIG().get_mut().bgm_session_name = Some(session_name.clone());
+                                context.gui_config().bgm_session_name = Some(session_name.clone());
                             }
                         }
                     });
                 ui.end_row();
                 
                 // 代わりに再生する BGM リストフォルダ
-                ui.label(fl!(LANG_LOADER().get(), "play_list"));
+                ui.label(fl!(context.lang_loader(), "play_list"));
                 ui.add(
-                    egui::TextEdit::singleline(&mut GUI_CONFIG().get_mut().bgm_playlist_folder)
-                        .hint_text(fl!(LANG_LOADER().get(), "folder"))
+                    egui::TextEdit::singleline(&mut context.gui_config().bgm_playlist_folder)
+                        .hint_text(fl!(context.lang_loader(), "folder"))
                 );
                 if !ui.ctx().input().raw.dropped_files.is_empty() {
                     if let Some(path_buf) = ui.ctx().input().raw.dropped_files[0].path.clone() {
                         if path_buf.is_dir() {
-                            GUI_CONFIG().get_mut().bgm_playlist_folder = path_buf.to_string_lossy().to_string();
+                            context.gui_config().bgm_playlist_folder = path_buf.to_string_lossy().to_string();
                         }
                     }
                 }
                 ui.end_row();
 
                 // BGM リスト音量
-                ui.label(&format!( "{} {}", fl!(LANG_LOADER().get(), "play_list"), fl!(LANG_LOADER().get(), "volume") ));
-                ui.add_enabled_ui(!SOUND_MANAGER().get().is_playing(Some(SoundType::Beep)), |ui| {
-                    egui::DragValue::new(&mut GUI_CONFIG().get_mut().gui_state_config.play_list_volume)
+                ui.label(&format!( "{} {}", fl!(context.lang_loader(), "play_list"), fl!(context.lang_loader(), "volume") ));
+                let is_playing_beep = self.sound_manager.borrow().is_playing(Some(SoundType::Beep));
+                ui.add_enabled_ui(!is_playing_beep, |ui| {
+                    egui::DragValue::new(&mut context.gui_config().gui_state_config.play_list_volume)
                         .clamp_range(0.0..=1.0)
                         .speed(0.01)
                         .ui(ui);
-                    if ui.button(fl!(LANG_LOADER().get(), "play")).clicked() {
-                        SOUND_MANAGER().get_mut().set_volume(GUI_CONFIG().get_mut().gui_state_config.play_list_volume);
-                        SOUND_MANAGER().get_mut().beep(440.0, std::time::Duration::from_millis(500));
+                    if ui.button(fl!(context.lang_loader(), "play")).clicked() {
+                        let play_list_volume = context.gui_config().gui_state_config.play_list_volume;
+                        let mut sound_manager = self.sound_manager.borrow_mut();
+                        sound_manager.set_volume(play_list_volume);
+                        sound_manager.beep(440.0, std::time::Duration::from_millis(500));
                     }
                 });
                 ui.end_row();
 
                 // ストック警告
-                ui.label(&format!( "{} {}", fl!(LANG_LOADER().get(), "stock"), fl!(LANG_LOADER().get(), "warning") ));
+                ui.label(&format!( "{} {}", fl!(context.lang_loader(), "stock"), fl!(context.lang_loader(), "warning") ));
                 ui.scope(|ui| {
-                    egui::DragValue::new(&mut GUI_CONFIG().get_mut().gui_state_config.stock_warning_under)
+                    egui::DragValue::new(&mut context.gui_config().gui_state_config.stock_warning_under)
                         .clamp_range(2..=4)
                         .ui(ui);
                     ui.add(
-                        egui::TextEdit::singleline(&mut GUI_CONFIG().get_mut().stock_alert_command)
+                        egui::TextEdit::singleline(&mut context.gui_config().stock_alert_command)
                             .hint_text("command")
                     );
                 });
                 if !ui.ctx().input().raw.dropped_files.is_empty() {
                     if let Some(path_buf) = ui.ctx().input().raw.dropped_files[0].path.clone() {
                         if path_buf.is_file() {
-                            GUI_CONFIG().get_mut().stock_alert_command = path_buf.to_string_lossy().to_string();
+                            context.gui_config().stock_alert_command = path_buf.to_string_lossy().to_string();
                         }
                     }
                 }
@@ -1080,60 +2164,61 @@ impl WindowConfiguration {
     }
 
     // カスタマイズの設定の view を返す
-    fn customize_settings_view(&mut self, ui: &mut egui::Ui) {
+    fn customize_settings_view(&mut self, ui: &mut egui::Ui, context: &AppContext) {
         GUI::new_grid(GUIIdList::CustomizeTab, 3, egui::Vec2::new(0.0, 5.0))
             .striped(true)
             .show(ui, |ui| {
-                ui.checkbox(&mut GUI_CONFIG().get_mut().gui_state_config.chara_image, fl!(LANG_LOADER().get(), "chara_image"));
-                ui.checkbox(&mut GUI_CONFIG().get_mut().gui_state_config.win_rate, fl!(LANG_LOADER().get(), "win_rate"));
-                ui.checkbox(&mut GUI_CONFIG().get_mut().gui_state_config.wins, fl!(LANG_LOADER().get(), "wins"));
+                ui.checkbox(&mut context.gui_config().gui_state_config.chara_image, fl!(context.lang_loader(), "chara_image"));
+                ui.checkbox(&mut context.gui_config().gui_state_config.win_rate, fl!(context.lang_loader(), "win_rate"));
+                ui.checkbox(&mut context.gui_config().gui_state_config.wins, fl!(context.lang_loader(), "wins"));
                 ui.end_row();
 
-                ui.checkbox(&mut GUI_CONFIG().get_mut().gui_state_config.win_lose, fl!(LANG_LOADER().get(), "win_lose"));
-                ui.checkbox(&mut GUI_CONFIG().get_mut().gui_state_config.graph, fl!(LANG_LOADER().get(), "graph"));
-                ui.checkbox(&mut GUI_CONFIG().get_mut().gui_state_config.gsp, fl!(LANG_LOADER().get(), "gsp"));
+                ui.checkbox(&mut context.gui_config().gui_state_config.win_lose, fl!(context.lang_loader(), "win_lose"));
+                ui.checkbox(&mut context.gui_config().gui_state_config.graph, fl!(context.lang_loader(), "graph"));
+                ui.checkbox(&mut context.gui_config().gui_state_config.gsp, fl!(context.lang_loader(), "gsp"));
                 ui.end_row();
 
-                ui.checkbox(&mut GUI_CONFIG().get_mut().gui_state_config.battling, fl!(LANG_LOADER().get(), "battling"));
+                ui.checkbox(&mut context.gui_config().gui_state_config.battling, fl!(context.lang_loader(), "battling"));
                 ui.end_row();
             });
     }
 }
 impl GUIModelTrait for WindowConfiguration {
-    fn name(&self) -> String { fl!(LANG_LOADER().get(), "config") }
-    fn show(&mut self, ctx: &egui::Context) {
-        egui::Window::new( self.name() )
+    fn name(&self, context: &AppContext) -> String { fl!(context.lang_loader(), "config") }
+    fn show(&mut self, ctx: &egui::Context, context: &AppContext) {
+        egui::Window::new( self.name(context) )
             .default_rect(Self::get_initial_window_rect())
             .vscroll(true)
-            .show(ctx, |ui| self.ui(ui));
+            .show(ctx, |ui| self.ui(ui, context));
     }
-    fn setup(&mut self, ctx: &egui::Context) {
-        if let Some(visuals) = GUI_CONFIG().get_mut().visuals.as_ref() {
+    fn setup(&mut self, ctx: &egui::Context, context: &AppContext) {
+        if let Some(visuals) = context.gui_config().visuals.as_ref() {
             ctx.set_visuals(visuals.clone());
         }
         self.video_device_id = -1;
         self.font_family_list = font_kit::source::SystemSource::new().all_families().unwrap();
-        match SOUND_MANAGER().get_mut().load(GUI_CONFIG().get_mut().bgm_playlist_folder.clone()) {
+        let bgm_playlist_folder = context.gui_config().bgm_playlist_folder.clone();
+        match self.sound_manager.borrow_mut().load(bgm_playlist_folder) {
             Ok(_) => (),
             Err(err) => log::error!("{}", err),
         }
     }
 }
 impl GUIViewTrait for WindowConfiguration {
-    fn ui(&mut self, ui: &mut egui::Ui) {
+    fn ui(&mut self, ui: &mut egui::Ui, context: &AppContext) {
         ui.horizontal(|ui| {
-            ui.selectable_value(&mut self.config_tab, ConfigTab::Source, fl!(LANG_LOADER().get(), "tab_source"));
-            ui.selectable_value(&mut self.config_tab, ConfigTab::Appearance, fl!(LANG_LOADER().get(), "tab_appearance"));
-            ui.selectable_value(&mut self.config_tab, ConfigTab::Detail, fl!(LANG_LOADER().get(), "tab_detail"));
-            ui.selectable_value(&mut self.config_tab, ConfigTab::Customize, fl!(LANG_LOADER().get(), "tab_customize"));
+            ui.selectable_value(&mut self.config_tab, ConfigTab::Source, fl!(context.lang_loader(), "tab_source"));
+            ui.selectable_value(&mut self.config_tab, ConfigTab::Appearance, fl!(context.lang_loader(), "tab_appearance"));
+            ui.selectable_value(&mut self.config_tab, ConfigTab::Detail, fl!(context.lang_loader(), "tab_detail"));
+            ui.selectable_value(&mut self.config_tab, ConfigTab::Customize, fl!(context.lang_loader(), "tab_customize"));
         });
         ui.separator();
 
         match self.config_tab {
-            ConfigTab::Source => self.source_settings_view(ui),
-            ConfigTab::Appearance => self.appearance_settings_view(ui),
-            ConfigTab::Detail => self.detail_settings_view(ui),
-            ConfigTab::Customize => self.customize_settings_view(ui),
+            ConfigTab::Source => self.source_settings_view(ui, context),
+            ConfigTab::Appearance => self.appearance_settings_view(ui, context),
+            ConfigTab::Detail => self.detail_settings_view(ui, context),
+            ConfigTab::Customize => self.customize_settings_view(ui, context),
         }
 
         ui.allocate_space(ui.available_size());
@@ -1151,65 +2236,90 @@ impl WindowBattleInformationGroup {
         self.data = Some(data);
     }
 
-    // BattleInformationGroup から 戦歴情報の削除を試みる
-    fn delete_data(&mut self) -> bool {
+    // BattleInformationGroup から 戦歴情報をゴミ箱に入れるのを試みて、入れたものを返す
+    fn delete_data(&mut self, context: &AppContext) -> Option<SmashbrosData> {
         if self.data.is_none() {
-            return false;
+            return None;
         }
 
-        if BATTLE_HISTORY().get_mut().delete_data(self.data.as_ref().unwrap()).is_ok() {
+        if context.use_battle_history(|battle_history| battle_history.delete_data(self.data.as_ref().unwrap())).is_ok() {
             log::info!("delete battle data: {:?}", self.data);
-            self.data = None;
 
-            true
+            self.data.take()
         } else {
-            false
+            None
         }
     }
 
-    // WindowBattleInformationGroup を削除ボタン付きで一覧表示する
-    pub fn show_group_list_with_delete(ui: &mut egui::Ui, group_list: &mut Vec<WindowBattleInformationGroup>) -> bool {
+    // WindowBattleInformationGroup を修正, 削除ボタン付きで一覧表示して、削除したものを返す
+    pub fn show_group_list_with_delete(ui: &mut egui::Ui, group_list: &mut Vec<WindowBattleInformationGroup>, battle_editor: &mut WindowBattleEditor, context: &AppContext) -> Option<SmashbrosData> {
+        let len = group_list.len();
+        Self::show_group_range_with_delete(ui, group_list, 0..len, battle_editor, context)
+    }
+
+    // group_list の range の範囲だけを修正, 削除ボタン付きで表示して、削除したものを返す
+    pub fn show_group_range_with_delete(ui: &mut egui::Ui, group_list: &mut Vec<WindowBattleInformationGroup>, range: std::ops::Range<usize>, battle_editor: &mut WindowBattleEditor, context: &AppContext) -> Option<SmashbrosData> {
         let mut remove_index = None;
+        let mut edit_index = None;
         let len = group_list.len();
 
-        for (index, group) in group_list.iter_mut().enumerate() {
+        for (index, group) in group_list.iter_mut().enumerate().skip(range.start).take(range.len()) {
+            let manual_field_list = group.data.as_ref().map(|data| data.get_manual_field_list()).unwrap_or_default();
+            let is_manual_entry = group.data.as_ref().map_or(false, |data| data.is_manual_entry());
+            let (tag_list, note) = group.data.as_ref().map(|data| (data.get_tag_list(), data.get_note())).unwrap_or_default();
             group.show_ui(ui, |ui: &mut egui::Ui| {
-                // 戦歴一覧だけ削除できるボタンを設置する
+                // 戦歴一覧だけ修正, 削除できるボタンを設置する
                 ui.add_space(16.0);
                 ui.add(egui::Separator::default().vertical());
+                if ui.button("✏").clicked() {
+                    edit_index = Some(index);
+                }
+                if !manual_field_list.is_empty() {
+                    // 手動で修正したもの
+                    ui.label("✍").on_hover_text(manual_field_list.join("\n"));
+                }
+                if is_manual_entry {
+                    // 手動で入力したもの
+                    ui.label("⌨").on_hover_text(fl!(context.lang_loader(), "new_battle"));
+                }
+                if !tag_list.is_empty() || !note.is_empty() {
+                    // タグかメモが付いたもの
+                    let hover_text = tag_list.iter().map(|tag| format!("🏷 {}", tag)).chain(std::iter::once(note.clone())).collect::<Vec<_>>().join("\n");
+                    ui.label("🏷").on_hover_text(hover_text.trim_end());
+                }
                 if ui.add(egui::Button::new("❌🗑").fill(egui::Color32::RED)).clicked() {
                     remove_index = Some(index);
                 }
-            });
+            }, context);
 
             if index < len - 1 {
                 ui.separator();
             }
         };
 
-        if let Some(index) = remove_index {
-            let mut group = group_list.remove(index);
-            if !group.delete_data() {
-                group_list.insert(index, group);
+        if let Some(data) = edit_index.and_then(|index| group_list[index].data.as_ref()) {
+            battle_editor.open(data, context);
+        }
 
-                false
-            } else {
-                true
-            }
-        } else {
-            false
+        let index = remove_index?;
+        let mut group = group_list.remove(index);
+        let data = group.delete_data(context);
+        if data.is_none() {
+            group_list.insert(index, group);
         }
+
+        data
     }
 
     // キャラと順位の表示
-    fn show_player_chara(ui: &mut egui::Ui, data: &mut SmashbrosData, player_id: i32) {
-        let button = if let Some(order_texture) = SMASHBROS_RESOURCE().get_mut().get_order_handle(data.get_order(player_id)) {
+    fn show_player_chara(ui: &mut egui::Ui, data: &mut SmashbrosData, player_id: i32, context: &AppContext) {
+        let button = if let Some(order_texture) = context.smashbros_resource().get_order_handle(data.get_order(player_id)) {
             egui::Button::image_and_text(order_texture.id(), order_texture.size_vec2() * egui::Vec2::new(0.25, 0.25), "")
         } else {
             egui::Button::new("?")
         };
 
-        if let Some(chara_image) = GUI::get_chara_image( data.as_ref().get_character(player_id), egui::Vec2::new(32.0, 32.0) ) {
+        if let Some(chara_image) = GUI::get_chara_image( data.as_ref().get_character(player_id), egui::Vec2::new(32.0, 32.0), context ) {
             ui.add_sized( [32.0, 32.0], chara_image);
         } else {
             ui.add_sized( [32.0, 32.0], egui::Label::new(format!("{}p", player_id + 1)) );
@@ -1243,17 +2353,17 @@ impl WindowBattleInformationGroup {
                         data.set_order(1, 2);
                     }
 
-                    data.update_battle();
+                    data.update_battle_with(&mut context.clone());
                 }
             });
     }
 
     // ストックの表示 (3 ストック以下ならアイコン表示、それ以上ならアイコンと数値を表示)
-    fn show_player_stock(ui: &mut egui::Ui, data: &mut SmashbrosData, player_id: i32) {
+    fn show_player_stock(ui: &mut egui::Ui, data: &mut SmashbrosData, player_id: i32, context: &AppContext) {
         let stock = data.get_stock(player_id);
         for i in 0..3 {
             if (0 != stock) && (i < stock || 0 == i) {
-                if let Some(chara_image) = GUI::get_chara_image( data.get_character(player_id), egui::Vec2::new(16.0, 16.0) ) {
+                if let Some(chara_image) = GUI::get_chara_image( data.get_character(player_id), egui::Vec2::new(16.0, 16.0), context ) {
                     ui.add_sized( [16.0, 16.0], chara_image);
                 } else {
                     ui.add_sized( [16.0, 16.0], egui::Label::new("?"));
@@ -1344,7 +2454,7 @@ impl WindowBattleInformationGroup {
         }
     }
 
-    fn show_ui(&mut self, ui: &mut egui::Ui, add_ui: impl FnOnce(&mut egui::Ui)) {
+    fn show_ui(&mut self, ui: &mut egui::Ui, add_ui: impl FnOnce(&mut egui::Ui), context: &AppContext) {
         /*
          * [対戦情報グループ]
          * .1pキャラアイコン vs 2pキャラアイコン
@@ -1372,9 +2482,9 @@ impl WindowBattleInformationGroup {
                 // [ham vs spam] の表示
                 GUI::new_grid("character_icons", 3, egui::Vec2::new(5.0, 0.0))
                     .show(ui, |ui| {
-                        Self::show_player_chara(ui, data, 0);
+                        Self::show_player_chara(ui, data, 0, context);
                         ui.add_sized( [16.0, 16.0], egui::Label::new("vs") );
-                        Self::show_player_chara(ui, data, 1);
+                        Self::show_player_chara(ui, data, 1, context);
                         ui.end_row();
                     });
 
@@ -1390,9 +2500,9 @@ impl WindowBattleInformationGroup {
                 // ストックの表示
                 GUI::new_grid("stocks_icons", 3, egui::Vec2::new(0.0, 0.0))
                     .show(ui, |ui| {
-                        Self::show_player_stock(ui, data, 0);
+                        Self::show_player_stock(ui, data, 0, context);
                         ui.end_row();
-                        Self::show_player_stock(ui, data, 1);
+                        Self::show_player_stock(ui, data, 1, context);
                     });
 
                 // 追加の UI の表示
@@ -1533,7 +2643,7 @@ impl WindowWinsGraph {
         }
     }
 
-    fn show_graph(&self, ui: &mut egui::Ui, plot_name: &String) {
+    fn show_graph(&self, ui: &mut egui::Ui, plot_name: &String, context: &AppContext) {
         let theme_color = if ui.ctx().style().visuals == Visuals::dark() {
             egui::Color32::RED
         } else {
@@ -1571,7 +2681,7 @@ impl WindowWinsGraph {
                         .color(theme_color)
                         .name(format!("{}\n{}", plot_name, match self.kind {
                             WinsGraphKind::Gsp => format!("{}", if -1 == self.last_power {
-                                format!("{}", fl!(LANG_LOADER().get(), "empty"))
+                                format!("{}", fl!(context.lang_loader(), "empty"))
                             } else {
                                 format!("{}", self.last_power)
                             }),
@@ -1581,7 +2691,7 @@ impl WindowWinsGraph {
             });
     }
 
-    fn show_wins_group(&self, ui: &mut egui::Ui, available_size: egui::Vec2) {
+    fn show_wins_group(&self, ui: &mut egui::Ui, available_size: egui::Vec2, context: &AppContext) {
         GUI::new_grid("wins_group", 2, egui::Vec2::new(5.0, 0.0))
         // .min_col_width(0.0)
         .min_col_width(available_size.x / 5.0)
@@ -1595,15 +2705,15 @@ impl WindowWinsGraph {
             }
 
             // キャラ画像
-            if GUI_CONFIG().get_mut().gui_state_config.chara_image {
+            if context.gui_config().gui_state_config.chara_image {
                 ui.scope(|ui| {
-                    if let Some(image) = GUI::get_chara_image( now_data.as_ref().get_character(0), egui::Vec2::new(16.0, 16.0) ) {
+                    if let Some(image) = GUI::get_chara_image( now_data.as_ref().get_character(0), egui::Vec2::new(16.0, 16.0), context ) {
                         ui.add(image);
                     } else {
                         ui.label(GUI::bi_label("1p"));
                     }
                     ui.label(GUI::bi_label("x"));
-                    if let Some(image) = GUI::get_chara_image( now_data.as_ref().get_character(1), egui::Vec2::new(16.0, 16.0) ) {
+                    if let Some(image) = GUI::get_chara_image( now_data.as_ref().get_character(1), egui::Vec2::new(16.0, 16.0), context ) {
                         ui.add(image);
                     } else {
                         ui.label(GUI::bi_label("2p"));
@@ -1611,7 +2721,7 @@ impl WindowWinsGraph {
                 });
             }
             // 勝率表示
-            if GUI_CONFIG().get_mut().gui_state_config.win_rate {
+            if context.gui_config().gui_state_config.win_rate {
                 ui.scope(|ui| {
                     match self.kind {
                         WinsGraphKind::Gsp => {
@@ -1631,13 +2741,13 @@ impl WindowWinsGraph {
             }
 
             // 連勝表示
-            if GUI_CONFIG().get_mut().gui_state_config.wins {
+            if context.gui_config().gui_state_config.wins {
                 ui.scope(|ui| {
                     ui.label( GUI::bi_label(&format!( "{}", self.wins)) );
-                    ui.label( GUI::bi_label(&format!( "{}", fl!(LANG_LOADER().get(), "wins"))) );
+                    ui.label( GUI::bi_label(&format!( "{}", fl!(context.lang_loader(), "wins"))) );
                 });
             }
-            if GUI_CONFIG().get_mut().gui_state_config.win_lose {
+            if context.gui_config().gui_state_config.win_lose {
                 ui.scope(|ui| {
                     match self.kind {
                         WinsGraphKind::Gsp => {
@@ -1657,35 +2767,35 @@ impl WindowWinsGraph {
     }
 
     const MAX_FONT_WIDTH: i32 = 32;
-    fn show_ui(&self, ui: &mut egui::Ui, plot_name: String) {
+    fn show_ui(&self, ui: &mut egui::Ui, plot_name: String, context: &AppContext) {
         let available_size = ui.available_size();
         GUI::new_grid("wins_graph_group", 2, egui::Vec2::new(0.0, 0.0))
             .min_col_width(120.0)
             .show(ui, |ui| {
-                if GUI_CONFIG().get_mut().gui_state_config.is_show_wins_group() {
-                    self.show_wins_group(ui, available_size);
+                if context.gui_config().gui_state_config.is_show_wins_group() {
+                    self.show_wins_group(ui, available_size, context);
                 }
 
                 // 世界戦闘力グラフの表示
-                if GUI_CONFIG().get_mut().gui_state_config.gsp {
-                    let font_size = GUI_CONFIG().get_mut().font_size.unwrap_or(16);
+                if context.gui_config().gui_state_config.gsp {
+                    let font_size = context.gui_config().font_size.unwrap_or(16);
                     if Self::MAX_FONT_WIDTH < font_size {
                         // 見切れる場合は戦闘力を100万単位にする
                         let gsp = self.last_power as f32 / 10_000.0;
                         ui.scope(|ui| {
-                            let gsp_string = if -1 == self.last_power { fl!(LANG_LOADER().get(), "empty") } else { format!( "{:.0}", gsp ) };
+                            let gsp_string = if -1 == self.last_power { fl!(context.lang_loader(), "empty") } else { format!( "{:.0}", gsp ) };
                             ui.label( GUI::bi_label(&gsp_string) );
-                            ui.label( GUI::bi_label(&format!( "{}", fl!(LANG_LOADER().get(), "million") )) );
+                            ui.label( GUI::bi_label(&format!( "{}", fl!(context.lang_loader(), "million") )) );
                         });
                     } else {
                         ui.scope(|ui| {
-                            let gsp_string = if -1 == self.last_power { fl!(LANG_LOADER().get(), "empty") } else { format!( "{}", self.last_power ) };
+                            let gsp_string = if -1 == self.last_power { fl!(context.lang_loader(), "empty") } else { format!( "{}", self.last_power ) };
                             ui.label( GUI::bi_label(&format!( "{}", gsp_string )) );
-                            ui.label( GUI::bi_label(&format!( "{}", fl!(LANG_LOADER().get(), "gsp") )) );
+                            ui.label( GUI::bi_label(&format!( "{}", fl!(context.lang_loader(), "gsp") )) );
                         });
                     }
-                } else if GUI_CONFIG().get_mut().gui_state_config.graph {
-                    self.show_graph(ui, &plot_name);
+                } else if context.gui_config().gui_state_config.graph {
+                    self.show_graph(ui, &plot_name, context);
                 }
             });
     }
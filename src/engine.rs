@@ -1,48 +1,68 @@
 
 use linked_hash_map::LinkedHashMap;
+use std::cell::Cell;
+use std::rc::Rc;
 
 
 use crate::capture::*;
+use crate::context::AppContext;
 use crate::data::*;
+use crate::event::*;
 use crate::resource::{
-    BATTLE_HISTORY,
-    GUI_CONFIG,
+    BattleHistoryFilter,
+    BattleHistoryTrait,
+    BattleStats,
+    BattleStatsGroup,
 };
 use crate::scene::*;
 
+pub mod worker;
+pub use worker::*;
+
 
 /// スマブラを管理するコントローラークラス
 pub struct SmashBrogEngine {
+    context: AppContext,
+    scene_manager: SceneManager,
+    // ResultReplayEnded になったことを SceneManager のイベントから受け取る
+    is_end_result_replay: Rc<Cell<bool>>,
     data_latest: Vec<SmashbrosData>,
     data_latest_by_chara: Vec<SmashbrosData>,
-    data_all_by_chara: Vec<SmashbrosData>,
+    stats_by_opponent: Vec<BattleStats>,
     result_max: i64,
     is_updated: bool,
 }
-impl Default for SmashBrogEngine {
-    fn default() -> Self { Self::new() }
-}
 impl SmashBrogEngine {
     pub const DEFAULT_RESULT_LIMIT: i64 = 10;
     const GET_LIMIT: i64 = 1000;
-    const FIND_LIMIT: i64 = 10000;
 
     pub const fn get_default_result_limit() -> i64 { Self::DEFAULT_RESULT_LIMIT }
 
-    fn new() -> Self {
+    pub fn new(mut context: AppContext) -> Self {
+        let mut scene_manager = SceneManager::new(
+            Box::new(CaptureFromEmpty::new().unwrap()),
+            context.clone(),
+        );
+
+        // 更新するタイミングを登録
+        let is_end_result_replay = Rc::new(Cell::new(false));
+        let is_end_result_replay_event = Rc::clone(&is_end_result_replay);
+        scene_manager.subscribe(Box::new(move |event: &EngineEvent| {
+            if let EngineEvent::ResultReplayEnded { .. } = event {
+                is_end_result_replay_event.set(true);
+            }
+        }));
+
         let mut own = Self {
-            data_latest: BATTLE_HISTORY().get_mut().find_data_limit(Self::DEFAULT_RESULT_LIMIT).unwrap_or(Vec::new()),
+            data_latest: context.find_data_limit(Self::DEFAULT_RESULT_LIMIT).unwrap_or(Vec::new()),
             data_latest_by_chara: Vec::new(),
-            data_all_by_chara: Vec::new(),
+            stats_by_opponent: Vec::new(),
             result_max: Self::DEFAULT_RESULT_LIMIT,
             is_updated: false,
+            context,
+            scene_manager,
+            is_end_result_replay,
         };
-
-        // 更新するタイミングを登録
-        SCENE_MANAGER().get_mut().registory_scene_event(
-            SceneList::Unknown, SceneList::EndResultReplay,
-            Box::new(|_smashbros_data: &mut SmashbrosData| SMASHBROS_ENGINE().get_mut().update_now_data() ),
-        );
         own.update_now_data();
 
         own
@@ -102,6 +122,35 @@ impl SmashBrogEngine {
         result
     }
 
+    /// 指定データ (新しい順) の直近の連勝数を返す (連敗なら負の数)
+    pub fn get_streak_by_data_list(data_list: &Vec<SmashbrosData>) -> i32 {
+        let mut streak = 0;
+        for data in data_list {
+            match data.is_win() {
+                Some(true) if 0 <= streak => streak += 1,
+                Some(false) if streak <= 0 => streak -= 1,
+                _ => break,
+            }
+        }
+
+        streak
+    }
+
+    /// 指定データ (新しい順) から、誤検出ではない 1P の戦闘力を古い順に返す
+    pub fn get_power_list_by_data_list(data_list: &Vec<SmashbrosData>) -> Vec<i32> {
+        let mut prev_power_list = Vec::new();
+        let mut prev_chara_list = Vec::new();
+        data_list.iter().rev().filter_map(|data| {
+            if Some(true) != data.is_valid_power(0, data.get_power(0), Some(&prev_power_list), Some(&prev_chara_list), false) {
+                return None;
+            }
+            prev_power_list = vec![data.get_power(0), data.get_power(1)];
+            prev_chara_list = vec![data.get_character(0), data.get_character(1)];
+
+            Some(data.get_power(0))
+        }).collect()
+    }
+
     /// 現在のデータから更新があったかどうか
     pub fn is_update_now_data(&mut self) -> bool {
         self.is_updated
@@ -113,12 +162,12 @@ impl SmashBrogEngine {
         self.update_chara_find_data();
 
         self.is_updated = true;
-        log::info!("now updated. {}, {}, {}", self.data_latest.len(), self.data_latest_by_chara.len(), self.data_all_by_chara.len());
+        log::info!("now updated. {}, {}, {}", self.data_latest.len(), self.data_latest_by_chara.len(), self.stats_by_opponent.len());
     }
 
     /// 直近 result_max 件のデータを更新する
     pub fn update_latest_n_data(&mut self){
-        if let Some(data_latest) = BATTLE_HISTORY().get_mut().find_data_limit(self.result_max) {
+        if let Some(data_latest) = self.context.find_data_limit(self.result_max) {
             self.data_latest = data_latest;
         }
         self.is_updated = true;
@@ -129,11 +178,18 @@ impl SmashBrogEngine {
         let prev_chara_list = vec![
             self.get_now_data().get_character(0), self.get_now_data().get_character(1)
         ];
-        if let Some(data_latest_by_chara) = BATTLE_HISTORY().get_mut().find_data_by_chara_list(prev_chara_list.clone(), Self::GET_LIMIT, false) {
+        if let Some(data_latest_by_chara) = self.context.find_data_by_chara_list(prev_chara_list.clone(), Self::GET_LIMIT, false) {
             self.data_latest_by_chara = data_latest_by_chara;
         }
-        if let Some(data_all_by_chara) = BATTLE_HISTORY().get_mut().find_data_by_chara_list(prev_chara_list.clone(), Self::FIND_LIMIT, true) {
-            self.data_all_by_chara = data_all_by_chara;
+        // 相手キャラ別の勝敗数は件数の上限なく DB 側で集計する (自分のキャラが分からなければ全キャラで)
+        // 以前の自分か相手のキャラが含まれる戦歴 (直近 10000 戦) ではなく、自分 (1P) が今のキャラで戦った戦歴だけを数える
+        // 対戦中のキャラ名は保存される前なので、保存されているキャラ名にして絞り込む
+        let character = Some(prev_chara_list[0].clone())
+            .filter(|chara_name| !chara_name.is_empty() && chara_name != SmashbrosData::CHARACTER_NAME_UNKNOWN)
+            .map(|chara_name| BattleHistoryFilter::normalize_character(&chara_name, &self.context.smashbros_resource()));
+        let filter = BattleHistoryFilter { character, ..Default::default() };
+        if let Some(stats_by_opponent) = self.context.aggregate_stats(&filter, BattleStatsGroup::Opponent) {
+            self.stats_by_opponent = stats_by_opponent;
         }
         self.is_updated = true;
     }
@@ -142,7 +198,17 @@ impl SmashBrogEngine {
     pub fn update(&mut self) -> anyhow::Result<()> {
         self.is_updated = false;
 
-        Ok( SCENE_MANAGER().get_mut().update_scene_list()? )
+        let result = self.scene_manager.update_scene_list();
+        if self.is_end_result_replay.replace(false) {
+            self.update_now_data();
+        }
+
+        Ok( result? )
+    }
+
+    /// 使用しているコンテキストを返す
+    pub fn get_context(&self) -> &AppContext {
+        &self.context
     }
 
     /// 検出方法の変更
@@ -153,6 +219,7 @@ impl SmashBrogEngine {
         }
 
         let capture: opencv::Result<Box<dyn CaptureTrait>> = match capture_mode {
+            #[cfg(windows)]
             CaptureMode::Desktop(_) => match CaptureFromDesktop::new() {
                 Err(e) => anyhow::bail!(e),
                 Ok(capture) => Ok(Box::new(capture)),
@@ -162,14 +229,17 @@ impl SmashBrogEngine {
                 Err(e) => anyhow::bail!(e),
                 Ok(capture) => Ok(Box::new(capture)),
             },
+            #[cfg(windows)]
             CaptureMode::Window(_, win_caption) => match CaptureFromWindow::new(win_caption) {
                 Err(e) => anyhow::bail!(e),
                 Ok(capture) => Ok(Box::new(capture)),
             },
+            #[cfg(not(windows))]
+            CaptureMode::Desktop(_) | CaptureMode::Window(_, _) => anyhow::bail!("unsupported capture mode on this platform"),
         };
 
         if let Ok(capture) = capture {
-            SCENE_MANAGER().get_mut().capture = capture;
+            self.scene_manager.capture = capture;
         }
 
         Ok(())
@@ -177,26 +247,32 @@ impl SmashBrogEngine {
 
     /// 言語の変更
     pub fn change_language(&mut self) {
-        SCENE_MANAGER().get_mut().change_language();
+        self.scene_manager.change_language();
     }
 
     /// 限界取得数の変更
     pub fn change_result_max(&mut self) {
-        if self.result_max == GUI_CONFIG().get_mut().result_max {
+        let result_max = self.context.gui_config().result_max;
+        if self.result_max == result_max {
             return;
         }
         self.update_latest_n_data();
-        self.result_max = GUI_CONFIG().get_mut().result_max;
+        self.result_max = result_max;
+    }
+
+    /// 発行される EngineEvent を全て受け取る
+    pub fn subscribe(&mut self, callback: EngineEventCallback) -> SubscriptionId {
+        self.scene_manager.subscribe(callback)
     }
 
-    /// シーンイベントの登録
-    pub fn registory_scene_event(&mut self, before_scene: SceneList, after_scene: SceneList, scene_event: SceneEventCallback) {
-        SCENE_MANAGER().get_mut().registory_scene_event(before_scene, after_scene, scene_event);
+    /// subscribe したものを受け取らないようにする
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.scene_manager.unsubscribe(id)
     }
 
     // 現在の検出されたデータの参照を返す
     pub fn ref_now_data(&self) -> &SmashbrosData {
-        SCENE_MANAGER().get_mut().ref_now_data()
+        self.scene_manager.ref_now_data()
     }
 
     /// 直近 result_max 件のデータを返す (result_max 未満も返る)
@@ -212,15 +288,15 @@ impl SmashBrogEngine {
         self.data_latest_by_chara.clone()
     }
 
-    /// 現在キャラクター指定での全データを返す (一応上限を FIND_LIMIT で定めておく)
-    pub fn get_data_all_by_now_chara(&mut self) -> Vec<SmashbrosData> {
-        self.data_all_by_chara.clone()
+    /// 現在の自分のキャラクターで戦った全ての戦歴の、相手キャラ別の勝敗数を返す (相手だけが今のキャラの戦歴は含まない)
+    pub fn get_stats_by_opponent(&mut self) -> Vec<BattleStats> {
+        self.stats_by_opponent.clone()
     }
 
     /// 現在対戦中のデータを返す
     pub fn get_now_data(&self) -> SmashbrosData {
         if self.data_latest.len() < 1 {
-            SCENE_MANAGER().get_mut().get_now_data()
+            self.scene_manager.get_now_data()
         } else {
             self.data_latest[0].clone()
         }
@@ -228,45 +304,16 @@ impl SmashBrogEngine {
 
     /// 現在検出中の Mat を返す
     pub fn get_now_image(&self) -> &opencv::core::Mat {
-        SCENE_MANAGER().get_mut().get_now_image()
+        self.scene_manager.get_now_image()
     }
 
     /// 現在検出中のシーン名を返す
     pub fn get_captured_scene(&self) -> SceneList {
-        SCENE_MANAGER().get_mut().get_now_scene()
+        self.scene_manager.get_now_scene()
     }
 
     /// 検出しようとしたシーンの前回の一致度合いを返す
     pub fn get_prev_match_ratio(&mut self) -> f64 {
-        SCENE_MANAGER().get_mut().get_prev_match_ratio()
-    }
-}
-
-/// シングルトンで Engine を保持するため
-pub struct WrappedSmashBrogEngine {
-    smashbrog_engine: Option<SmashBrogEngine>,
-}
-impl WrappedSmashBrogEngine {
-    // 参照して返さないと、unwrap() で move 違反がおきてちぬ！
-    pub fn get(&mut self) -> &SmashBrogEngine {
-        if self.smashbrog_engine.is_none() {
-            self.smashbrog_engine = Some(SmashBrogEngine::default());
-        }
-        self.smashbrog_engine.as_ref().unwrap()
+        self.scene_manager.get_prev_match_ratio()
     }
-
-    // mut 版
-    pub fn get_mut(&mut self) -> &mut SmashBrogEngine {
-        if self.smashbrog_engine.is_none() {
-            self.smashbrog_engine = Some(SmashBrogEngine::default());
-        }
-        self.smashbrog_engine.as_mut().unwrap()
-    }
-}
-static mut _SMASHBROS_ENGINE: WrappedSmashBrogEngine = WrappedSmashBrogEngine {
-    smashbrog_engine: None,
-};
-#[allow(non_snake_case)]
-pub fn SMASHBROS_ENGINE() -> &'static mut WrappedSmashBrogEngine {
-    unsafe { &mut _SMASHBROS_ENGINE }
 }
@@ -0,0 +1,213 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use super::*;
+
+
+/// ワーカースレッドへの命令
+pub enum EngineCommand {
+    /// 検出方法の変更
+    ChangeCaptureMode(CaptureMode),
+    /// 言語の変更 (AppContext の言語を変更した後に送る)
+    ChangeLanguage,
+    /// 限界取得数の変更 (GUIConfig の result_max を変更した後に送る)
+    ChangeResultMax,
+    /// 直近のデータを取得し直す
+    UpdateLatestData,
+    /// キャラ検索のデータを取得し直す
+    UpdateCharaFindData,
+    /// 1 秒間に解析する目標フレーム数の変更 (0 以下で無制限)
+    ChangeTargetFps(f32),
+    /// ワーカースレッドの終了
+    Quit,
+}
+
+/// 戦歴情報の変動があったときだけ送るデータ
+pub struct EngineDataList {
+    pub now_data: SmashbrosData,
+    pub data_latest: Vec<SmashbrosData>,
+    pub data_latest_by_chara: Vec<SmashbrosData>,
+    pub stats_by_opponent: Vec<BattleStats>,
+}
+
+/// 1 フレーム解析した後の状態
+pub struct EngineSnapshot {
+    pub now_scene: SceneList,
+    pub prev_match_ratio: f64,
+    pub ref_now_data: SmashbrosData,
+    // GUIConfig の show_captured が有効なときだけ送る
+    pub now_image: Option<opencv::core::Mat>,
+    pub data_list: Option<EngineDataList>,
+}
+
+/// ワーカースレッドからの通知
+pub enum EngineMessage {
+    Snapshot(Box<EngineSnapshot>),
+    /// エンジンで発行された EngineEvent (発行された順に届く)
+    Event(EngineEvent),
+    /// 解析が続けられなくなった (ワーカースレッドは終了している)
+    Error(String),
+}
+
+/// キャプチャからシーン判定までを別スレッドで動かすクラス
+pub struct EngineWorker {
+    command_sender: mpsc::Sender<EngineCommand>,
+    message_receiver: mpsc::Receiver<EngineMessage>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+impl Drop for EngineWorker {
+    fn drop(&mut self) {
+        let _ = self.command_sender.send(EngineCommand::Quit);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+impl EngineWorker {
+    pub const DEFAULT_TARGET_FPS: f32 = 15.0;
+
+    pub fn get_default_target_fps() -> f32 { Self::DEFAULT_TARGET_FPS }
+
+    /// context を使う SmashBrogEngine をワーカースレッドで作成して動かす
+    pub fn spawn(context: AppContext, target_fps: f32) -> anyhow::Result<Self> {
+        let (command_sender, command_receiver) = mpsc::channel();
+        let (message_sender, message_receiver) = mpsc::channel();
+
+        let handle = thread::Builder::new()
+            .name("smabrog-engine".to_string())
+            .spawn(move || {
+                let _guard = context.enter();
+                // Mat や Box<dyn CaptureTrait> はスレッドを跨げないので、engine はこのスレッドで作る
+                let mut engine = SmashBrogEngine::new(context.clone());
+
+                // イベントはそのまま GUI スレッドに送る
+                let event_sender = message_sender.clone();
+                engine.subscribe(Box::new(move |event: &EngineEvent| {
+                    let _ = event_sender.send(EngineMessage::Event(event.clone()));
+                }));
+
+                EngineWorkerLoop {
+                    context,
+                    engine,
+                    command_receiver,
+                    message_sender,
+                    target_fps,
+                }.run();
+            })?;
+
+        Ok(Self {
+            command_sender,
+            message_receiver,
+            handle: Some(handle),
+        })
+    }
+
+    /// ワーカースレッドに命令を送る
+    pub fn send(&self, command: EngineCommand) {
+        if self.command_sender.send(command).is_err() {
+            log::warn!("engine worker is already finished.");
+        }
+    }
+
+    /// ワーカースレッドからの通知を溜まっている分だけ受け取る
+    pub fn try_recv_all(&self) -> Vec<EngineMessage> {
+        self.message_receiver.try_iter().collect()
+    }
+}
+
+/// ワーカースレッド側で持つもの
+struct EngineWorkerLoop {
+    context: AppContext,
+    engine: SmashBrogEngine,
+    command_receiver: mpsc::Receiver<EngineCommand>,
+    message_sender: mpsc::Sender<EngineMessage>,
+    target_fps: f32,
+}
+impl EngineWorkerLoop {
+    fn run(mut self) {
+        loop {
+            let frame_start_time = Instant::now();
+
+            if let Err(e) = self.engine.update() {
+                let _ = self.message_sender.send(EngineMessage::Error(e.to_string()));
+                return;
+            }
+
+            // update で is_updated が戻されるので、命令は update の後に処理する
+            if !self.handle_command_list() {
+                return;
+            }
+
+            if self.message_sender.send(EngineMessage::Snapshot(Box::new(self.make_snapshot()))).is_err() {
+                // 受け取り側がいなくなった
+                return;
+            }
+
+            if 0.0 < self.target_fps {
+                let frame_duration = Duration::from_secs_f32(1.0 / self.target_fps);
+                let elapsed = frame_start_time.elapsed();
+                if elapsed < frame_duration {
+                    thread::sleep(frame_duration - elapsed);
+                }
+            }
+        }
+    }
+
+    /// 溜まっている命令を処理する
+    /// @return bool false:終了する
+    fn handle_command_list(&mut self) -> bool {
+        while let Ok(command) = self.command_receiver.try_recv() {
+            match command {
+                EngineCommand::ChangeCaptureMode(capture_mode) => {
+                    let _ = self.engine.change_capture_mode(&capture_mode).map_err(|e|{
+                        match capture_mode {
+                            CaptureMode::Empty(_) => (
+                                // デフォルト値に選択したことは知らせなくていいため、何もしない
+                            ),
+                            _ => log::warn!("{}", e),
+                        }
+                    });
+                },
+                EngineCommand::ChangeLanguage => self.engine.change_language(),
+                EngineCommand::ChangeResultMax => self.engine.change_result_max(),
+                EngineCommand::UpdateLatestData => self.engine.update_latest_n_data(),
+                EngineCommand::UpdateCharaFindData => self.engine.update_chara_find_data(),
+                EngineCommand::ChangeTargetFps(target_fps) => self.target_fps = target_fps,
+                EngineCommand::Quit => return false,
+            }
+        }
+
+        true
+    }
+
+    fn make_snapshot(&mut self) -> EngineSnapshot {
+        let now_image = if self.context.gui_config().gui_state_config.show_captured {
+            Some(self.engine.get_now_image().clone())
+        } else {
+            None
+        };
+
+        let data_list = if self.engine.is_update_now_data() {
+            Some(EngineDataList {
+                now_data: self.engine.get_now_data(),
+                data_latest: self.engine.get_data_latest(),
+                data_latest_by_chara: self.engine.get_data_latest_by_now_chara(),
+                stats_by_opponent: self.engine.get_stats_by_opponent(),
+            })
+        } else {
+            None
+        };
+
+        EngineSnapshot {
+            now_scene: self.engine.get_captured_scene(),
+            prev_match_ratio: self.engine.get_prev_match_ratio(),
+            ref_now_data: self.engine.ref_now_data().clone(),
+            now_image,
+            data_list,
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::sync::mpsc;
+
+use crate::data::*;
+use crate::scene::SceneList;
+
+
+/// エンジンから発行されるイベント
+#[derive(Clone, Debug)]
+pub enum EngineEvent {
+    /// シーンが遷移した
+    SceneChanged { before: SceneList, after: SceneList, data: SmashbrosData },
+    /// ルールと最大ストック、時間が決まった
+    RuleDecided { rule: BattleRule, data: SmashbrosData },
+    /// BGM 名が決まった
+    BgmDecided { bgm_name: String, data: SmashbrosData },
+    /// プレイヤーのキャラクターが確定した
+    CharacterDecided { player_number: i32, character: String },
+    /// プレイヤーのストックが変わった
+    StockChanged { player_number: i32, stock: i32 },
+    /// プレイヤーの戦闘力が確定した
+    PowerDecided { player_number: i32, power: i32 },
+    /// 戦歴が保存/更新された
+    BattleSaved { data: SmashbrosData },
+    /// 結果画面のリプレイが終わった
+    ResultReplayEnded { data: SmashbrosData },
+}
+impl EngineEvent {
+    /// to_json の形式を変えたら上げる
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    /// snake_case のイベント名
+    pub fn get_type_name(&self) -> &'static str {
+        match self {
+            Self::SceneChanged { .. } => "scene_changed",
+            Self::RuleDecided { .. } => "rule_decided",
+            Self::BgmDecided { .. } => "bgm_decided",
+            Self::CharacterDecided { .. } => "character_decided",
+            Self::StockChanged { .. } => "stock_changed",
+            Self::PowerDecided { .. } => "power_decided",
+            Self::BattleSaved { .. } => "battle_saved",
+            Self::ResultReplayEnded { .. } => "result_replay_ended",
+        }
+    }
+
+    /// 外部に送る為の JSON にする
+    /// { "schema_version": 1, "type": "stock_changed", "payload": { "player_number": 0, "stock": 2 } }
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::json;
+        let payload = match self {
+            Self::SceneChanged { before, after, data } => json!({ "before": before, "after": after, "data": Self::data_to_json(data) }),
+            Self::RuleDecided { rule, data } => json!({ "rule": format!("{:?}", rule), "data": Self::data_to_json(data) }),
+            Self::BgmDecided { bgm_name, data } => json!({ "bgm_name": bgm_name, "data": Self::data_to_json(data) }),
+            Self::CharacterDecided { player_number, character } => json!({ "player_number": player_number, "character": character }),
+            Self::StockChanged { player_number, stock } => json!({ "player_number": player_number, "stock": stock }),
+            Self::PowerDecided { player_number, power } => json!({ "player_number": player_number, "power": power }),
+            Self::BattleSaved { data } | Self::ResultReplayEnded { data } => json!({ "data": Self::data_to_json(data) }),
+        };
+
+        json!({
+            "schema_version": Self::SCHEMA_VERSION,
+            "type": self.get_type_name(),
+            "payload": payload,
+        })
+    }
+
+    /// DB の形式に id を足したもの
+    pub fn data_to_json(data: &SmashbrosData) -> serde_json::Value {
+        let mut value = serde_json::to_value(data).unwrap_or_default();
+        if let Some(object) = value.as_object_mut() {
+            object.insert("id".to_string(), serde_json::json!(data.get_id()));
+        }
+
+        value
+    }
+}
+
+/// イベントを受け取るコールバックの型
+pub type EngineEventCallback = Box<dyn FnMut(&EngineEvent)>;
+
+/// subscribe した時に返される、unsubscribe する為のハンドル
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct SubscriptionId(u64);
+
+/// EngineEvent を購読者に配るクラス
+/// - イベントは publish された順に配られる
+/// - 1 つのイベントは subscribe された順に全ての購読者に配られてから、次のイベントが配られる
+/// - unsubscribe した購読者には、その後に publish されたイベントは配られない
+#[derive(Default)]
+pub struct EventBus {
+    next_id: u64,
+    subscriber_list: Vec<(SubscriptionId, Subscriber)>,
+}
+
+// 購読者 (コールバックとチャンネルを同じ順番で扱う為にまとめる)
+enum Subscriber {
+    Callback(EngineEventCallback),
+    Channel(mpsc::Sender<EngineEvent>),
+}
+impl EventBus {
+    pub fn new() -> Self { Self::default() }
+
+    /// 全てのイベントを callback で受け取る
+    pub fn subscribe(&mut self, callback: EngineEventCallback) -> SubscriptionId {
+        self.push_subscriber(Subscriber::Callback(callback))
+    }
+
+    /// 全てのイベントを別スレッドで受け取れるように、チャンネルに流す
+    /// (Receiver が drop されたら、次のイベントを配る時に自動で unsubscribe される)
+    pub fn subscribe_channel(&mut self) -> (SubscriptionId, mpsc::Receiver<EngineEvent>) {
+        let (sender, receiver) = mpsc::channel();
+
+        (self.push_subscriber(Subscriber::Channel(sender)), receiver)
+    }
+
+    fn push_subscriber(&mut self, subscriber: Subscriber) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscriber_list.push((id, subscriber));
+
+        id
+    }
+
+    /// 購読をやめる
+    /// @return bool 購読していたかどうか
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.subscriber_list.len();
+        self.subscriber_list.retain(|(subscriber_id, _)| *subscriber_id != id);
+
+        len != self.subscriber_list.len()
+    }
+
+    /// 購読者の数
+    pub fn len(&self) -> usize {
+        self.subscriber_list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscriber_list.is_empty()
+    }
+
+    /// イベントを全ての購読者に配る
+    pub fn publish(&mut self, event: &EngineEvent) {
+        let mut index = 0;
+        while index < self.subscriber_list.len() {
+            let is_alive = match &mut self.subscriber_list[index].1 {
+                Subscriber::Callback(callback) => {
+                    callback(event);
+                    true
+                },
+                Subscriber::Channel(sender) => sender.send(event.clone()).is_ok(),
+            };
+
+            if is_alive {
+                index += 1;
+            } else {
+                self.subscriber_list.remove(index);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn stock_event(stock: i32) -> EngineEvent {
+        EngineEvent::StockChanged { player_number: 0, stock }
+    }
+
+    #[test]
+    fn test_event_bus_order() {
+        let mut event_bus = EventBus::new();
+        let received_list = Rc::new(RefCell::new(Vec::new()));
+
+        let mut id_list = Vec::new();
+        for subscriber_number in 0..2 {
+            let received_list = Rc::clone(&received_list);
+            id_list.push(event_bus.subscribe(Box::new(move |event: &EngineEvent| {
+                if let EngineEvent::StockChanged { stock, .. } = event {
+                    received_list.borrow_mut().push((subscriber_number, *stock));
+                }
+            })));
+        }
+
+        event_bus.publish(&stock_event(3));
+        event_bus.publish(&stock_event(2));
+        assert_eq!(*received_list.borrow(), vec![(0, 3), (1, 3), (0, 2), (1, 2)]);
+
+        // unsubscribe した後のイベントは届かない
+        assert!(event_bus.unsubscribe(id_list[0]));
+        assert!(!event_bus.unsubscribe(id_list[0]));
+        event_bus.publish(&stock_event(1));
+        assert_eq!(received_list.borrow().last(), Some(&(1, 1)));
+        assert_eq!(received_list.borrow().len(), 5);
+    }
+
+    #[test]
+    fn test_event_bus_channel() {
+        let mut event_bus = EventBus::new();
+        let (_, receiver) = event_bus.subscribe_channel();
+
+        event_bus.publish(&stock_event(3));
+        assert!(matches!(receiver.try_recv(), Ok(EngineEvent::StockChanged { stock: 3, .. })));
+
+        // 受け取り側がいなくなったら購読をやめる
+        drop(receiver);
+        event_bus.publish(&stock_event(2));
+        assert!(event_bus.is_empty());
+    }
+
+    #[test]
+    fn test_event_to_json() {
+        let json = stock_event(2).to_json();
+        assert_eq!(json["schema_version"], EngineEvent::SCHEMA_VERSION);
+        assert_eq!(json["type"], "stock_changed");
+        assert_eq!(json["payload"]["stock"], 2);
+
+        let mut data = SmashbrosData::default();
+        data.initialize_battle(2, true);
+        data.set_id(Some("test_data_id".to_string()));
+        let json = EngineEvent::BattleSaved { data }.to_json();
+        assert_eq!(json["type"], "battle_saved");
+        assert_eq!(json["payload"]["data"]["id"], "test_data_id");
+        assert_eq!(json["payload"]["data"]["player_count"], 2);
+    }
+}
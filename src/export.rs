@@ -0,0 +1,308 @@
+use simple_excel_writer as excel;
+use std::path::Path;
+
+use crate::data::*;
+use crate::engine::SmashBrogEngine;
+
+
+/// 書き出すファイルの形式
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+}
+impl Default for ExportFormat {
+    fn default() -> Self { Self::Csv }
+}
+impl ExportFormat {
+    /// 拡張子から形式を決める
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "xlsx" => Some(Self::Xlsx),
+            _ => None,
+        }
+    }
+
+    pub fn get_extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Xlsx => "xlsx",
+        }
+    }
+}
+
+/// 1 行に何を入れるか
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExportLayout {
+    /// 1 試合のプレイヤー毎に 1 行
+    PerPlayer,
+    /// 1 試合を 1 行にして、プレイヤー毎の列を横に並べる
+    PerBattle,
+}
+impl Default for ExportLayout {
+    fn default() -> Self { Self::PerPlayer }
+}
+
+/// セルの値
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExportValue {
+    Empty,
+    Number(f64),
+    Text(String),
+}
+impl ExportValue {
+    // 未検出 (-1 など) は空にする
+    fn number(value: i32) -> Self {
+        if value < 0 { Self::Empty } else { Self::Number(value as f64) }
+    }
+
+    fn text(value: String) -> Self {
+        if value.is_empty() { Self::Empty } else { Self::Text(value) }
+    }
+
+    // CSV の 1 セル分 (必要なら " で囲む)
+    fn to_csv(&self) -> String {
+        match self {
+            Self::Empty => String::new(),
+            Self::Number(value) if value.fract() == 0.0 => format!("{}", *value as i64),
+            Self::Number(value) => value.to_string(),
+            Self::Text(value) if value.contains(&[',', '"', '\r', '\n'][..]) => format!("\"{}\"", value.replace('"', "\"\"")),
+            Self::Text(value) => value.clone(),
+        }
+    }
+
+    fn add_to_row(&self, row: &mut excel::Row) {
+        match self {
+            Self::Empty => row.add_empty_cells(1),
+            Self::Number(value) => row.add_cell(*value),
+            Self::Text(value) => row.add_cell(value.as_str()),
+        }
+    }
+}
+
+/// 戦歴を表計算ソフトで読める形式で書き出す
+pub struct BattleHistoryExporter;
+impl BattleHistoryExporter {
+    const BATTLE_HEADER_LIST: [&'static str; 10] = ["id", "start_time", "end_time", "rule", "max_time", "player_count", "result", "bgm", "tags", "note"];
+    const PLAYER_HEADER_LIST: [&'static str; 6] = ["character", "group", "stock", "max_stock", "order", "power"];
+    const DATE_TIME_FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
+
+    /// (見出し, 行) の表にする
+    pub fn make_table(data_list: &[SmashbrosData], layout: ExportLayout) -> (Vec<String>, Vec<Vec<ExportValue>>) {
+        let mut header_list: Vec<String> = Self::BATTLE_HEADER_LIST.iter().map(|header| header.to_string()).collect();
+        let mut row_list = Vec::new();
+        match layout {
+            ExportLayout::PerPlayer => {
+                header_list.push("player".to_string());
+                header_list.extend(Self::PLAYER_HEADER_LIST.iter().map(|header| header.to_string()));
+                for data in data_list {
+                    for player_number in 0..data.get_player_count() {
+                        let mut row = Self::make_battle_value_list(data);
+                        row.push(ExportValue::Number((player_number + 1) as f64));
+                        row.extend(Self::make_player_value_list(data, player_number));
+                        row_list.push(row);
+                    }
+                }
+            },
+            ExportLayout::PerBattle => {
+                // 一番多い人数に合わせて列を作る
+                let max_player_count = data_list.iter().map(|data| data.get_player_count()).max().unwrap_or(2).max(1);
+                for player_number in 0..max_player_count {
+                    header_list.extend(Self::PLAYER_HEADER_LIST.iter().map(|header| format!("{}_{}p", header, player_number + 1)));
+                }
+                for data in data_list {
+                    let mut row = Self::make_battle_value_list(data);
+                    for player_number in 0..max_player_count {
+                        if player_number < data.get_player_count() {
+                            row.extend(Self::make_player_value_list(data, player_number));
+                        } else {
+                            row.extend(vec![ExportValue::Empty; Self::PLAYER_HEADER_LIST.len()]);
+                        }
+                    }
+                    row_list.push(row);
+                }
+            },
+        }
+
+        (header_list, row_list)
+    }
+
+    fn make_battle_value_list(data: &SmashbrosData) -> Vec<ExportValue> {
+        let date_time = |date_time: Option<chrono::DateTime<chrono::Local>>| match date_time {
+            Some(date_time) => ExportValue::Text(date_time.format(Self::DATE_TIME_FORMAT).to_string()),
+            None => ExportValue::Empty,
+        };
+        let max_time = data.get_max_time().as_secs();
+
+        vec![
+            ExportValue::text(data.get_id().unwrap_or_default()),
+            date_time(data.get_start_time()),
+            date_time(data.get_end_time()),
+            ExportValue::Text(format!("{:?}", data.get_rule())),
+            if 0 == max_time { ExportValue::Empty } else { ExportValue::Number(max_time as f64) },
+            ExportValue::Number(data.get_player_count() as f64),
+            match data.is_win() {
+                Some(true) => ExportValue::Text("win".to_string()),
+                Some(false) => ExportValue::Text("lose".to_string()),
+                None => ExportValue::Empty,
+            },
+            ExportValue::text(data.get_bgm_name()),
+            ExportValue::text(data.get_tag_list().join(",")),
+            ExportValue::text(data.get_note()),
+        ]
+    }
+
+    fn make_player_value_list(data: &SmashbrosData, player_number: i32) -> Vec<ExportValue> {
+        vec![
+            ExportValue::text(data.get_character(player_number)),
+            ExportValue::Text(format!("{:?}", data.get_group(player_number))),
+            ExportValue::number(data.get_stock(player_number)),
+            ExportValue::number(data.get_max_stock(player_number)),
+            ExportValue::number(data.get_order(player_number)),
+            ExportValue::number(data.get_power(player_number)),
+        ]
+    }
+
+    /// 相手キャラ別の (相手キャラ, 勝率, 試合数) と、最後に全体のもの
+    pub fn make_summary(data_list: &[SmashbrosData]) -> Vec<(String, f32, i32)> {
+        let data_list = data_list.to_vec();
+        let mut summary_list: Vec<(String, f32, i32)> = SmashBrogEngine::get_wins_by_data_list_groupby_character(&data_list)
+            .into_iter()
+            .map(|(character, (win_rate, battle_count))| (character, win_rate, battle_count))
+            .collect();
+        let (win_rate, battle_count) = SmashBrogEngine::get_wins_by_data_list(&data_list);
+        summary_list.push(("(all)".to_string(), win_rate, battle_count));
+
+        summary_list
+    }
+
+    /// CSV にする (Excel で文字化けしないように BOM を付ける)
+    pub fn to_csv(data_list: &[SmashbrosData], layout: ExportLayout) -> String {
+        let (header_list, row_list) = Self::make_table(data_list, layout);
+        let mut csv = String::from("\u{feff}");
+        csv.push_str(&header_list.join(","));
+        csv.push_str("\r\n");
+        for row in row_list {
+            csv.push_str(&row.iter().map(ExportValue::to_csv).collect::<Vec<_>>().join(","));
+            csv.push_str("\r\n");
+        }
+
+        csv
+    }
+
+    /// XLSX にする (戦歴のシートと、相手キャラ別の勝率のシート)
+    pub fn write_xlsx(path: &Path, data_list: &[SmashbrosData], layout: ExportLayout) -> anyhow::Result<()> {
+        let path = match path.to_str() {
+            Some(path) => path,
+            None => anyhow::bail!("invalid path: {:?}", path),
+        };
+        let (header_list, row_list) = Self::make_table(data_list, layout);
+        let summary_list = Self::make_summary(data_list);
+
+        let mut workbook = excel::Workbook::create(path);
+        let mut battle_sheet = workbook.create_sheet("battle_history");
+        workbook.write_sheet(&mut battle_sheet, |sheet_writer| {
+            let header_row = header_list.iter().map(|header| ExportValue::Text(header.clone())).collect::<Vec<_>>();
+            sheet_writer.append_row(Self::make_excel_row(&header_row))?;
+            for row in &row_list {
+                sheet_writer.append_row(Self::make_excel_row(row))?;
+            }
+
+            Ok(())
+        })?;
+
+        let mut summary_sheet = workbook.create_sheet("summary");
+        workbook.write_sheet(&mut summary_sheet, |sheet_writer| {
+            let header_row = ["opponent", "win_rate", "battle_count"].iter().map(|header| ExportValue::Text(header.to_string())).collect::<Vec<_>>();
+            sheet_writer.append_row(Self::make_excel_row(&header_row))?;
+            for (character, win_rate, battle_count) in &summary_list {
+                sheet_writer.append_row(Self::make_excel_row(&[
+                    ExportValue::Text(character.clone()),
+                    ExportValue::Number(*win_rate as f64),
+                    ExportValue::Number(*battle_count as f64),
+                ]))?;
+            }
+
+            Ok(())
+        })?;
+        workbook.close()?;
+
+        Ok(())
+    }
+
+    fn make_excel_row(value_list: &[ExportValue]) -> excel::Row {
+        let mut row = excel::Row::new();
+        for value in value_list {
+            value.add_to_row(&mut row);
+        }
+
+        row
+    }
+
+    /// path の拡張子 (csv/xlsx) に合わせて書き出す
+    pub fn write(path: &Path, data_list: &[SmashbrosData], layout: ExportLayout) -> anyhow::Result<()> {
+        match ExportFormat::from_path(path) {
+            Some(ExportFormat::Csv) => std::fs::write(path, Self::to_csv(data_list, layout))?,
+            Some(ExportFormat::Xlsx) => Self::write_xlsx(path, data_list, layout)?,
+            None => anyhow::bail!("unsupported export format: {:?} (csv or xlsx)", path),
+        }
+        log::info!("exported {} battles to {:?}", data_list.len(), path);
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_csv() {
+        let mut data_list = vec![
+            SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, true, 5000000),
+            SmashbrosData::new_for_test("MARIO", "Mr. GAME, \"WATCH\"", BattleRule::Stock, false, 5000000),
+        ];
+        data_list[0].set_tag_list(vec!["laggy".to_string(), "tilted".to_string()]);
+
+        let csv = BattleHistoryExporter::to_csv(&data_list, ExportLayout::PerBattle);
+        let line_list = csv.trim_start_matches('\u{feff}').lines().collect::<Vec<_>>();
+        assert_eq!(line_list.len(), 3);
+        assert!(line_list[0].ends_with("power_1p,character_2p,group_2p,stock_2p,max_stock_2p,order_2p,power_2p"));
+        assert!(line_list[1].contains(",win,"));
+        assert!(line_list[1].contains(",MARIO,"));
+        assert!(line_list[1].contains(",\"laggy,tilted\","));
+        assert!(line_list[2].contains(",\"Mr. GAME, \"\"WATCH\"\"\","));
+
+        let csv = BattleHistoryExporter::to_csv(&data_list, ExportLayout::PerPlayer);
+        assert_eq!(csv.lines().count(), 1 + 2 * 2);
+    }
+
+    #[test]
+    fn test_export_summary() {
+        let data_list = vec![
+            SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, true, 5000000),
+            SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, false, 5000000),
+            SmashbrosData::new_for_test("MARIO", "KIRBY", BattleRule::Stock, true, 5000000),
+        ];
+
+        let summary_list = BattleHistoryExporter::make_summary(&data_list);
+        assert_eq!(summary_list[0], ("LINK".to_string(), 0.5, 2));
+        assert_eq!(summary_list[1], ("KIRBY".to_string(), 1.0, 1));
+        assert_eq!(summary_list[2].2, 3);
+    }
+
+    #[test]
+    fn test_export_xlsx() {
+        let path = std::env::temp_dir().join(format!("smabrog_export_{}.xlsx", std::process::id()));
+        let data_list = vec![SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, true, 5000000)];
+
+        BattleHistoryExporter::write(&path, &data_list, ExportLayout::PerBattle).unwrap();
+        // zip (PK) になっている
+        assert_eq!(&std::fs::read(&path).unwrap()[..2], b"PK");
+        assert!(BattleHistoryExporter::write(Path::new("history.txt"), &data_list, ExportLayout::PerBattle).is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
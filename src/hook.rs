@@ -0,0 +1,280 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::{
+    Command,
+    ExitStatus,
+    Stdio,
+};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use crate::data::*;
+use crate::event::EngineEvent;
+use crate::output::render_template;
+use crate::scene::SceneList;
+
+
+/// フックを実行するタイミング
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum HookEvent {
+    /// 試合が始まった (GamePlaying になった)
+    BattleStart,
+    /// ルールと最大ストック、時間が決まった
+    RuleDecided,
+    /// ルールが決まった時に、最大ストックが警告未満だった
+    StockUnder,
+    /// 戦歴が保存された
+    BattleSaved,
+    /// 戦歴が保存された時に勝っていた
+    Win,
+    /// 戦歴が保存された時に負けていた
+    Lose,
+}
+impl HookEvent {
+    /// event で実行するものと、その時のデータを返す
+    pub fn from_engine_event(event: &EngineEvent, stock_warning_under: i32) -> Vec<(Self, &SmashbrosData)> {
+        match event {
+            EngineEvent::SceneChanged { after: SceneList::GamePlaying, data, .. } => vec![(Self::BattleStart, data)],
+            EngineEvent::RuleDecided { data, .. } => {
+                let mut hook_event_list = vec![(Self::RuleDecided, data)];
+                if data.is_decided_max_stock(0) && data.get_max_stock(0) < stock_warning_under {
+                    hook_event_list.push((Self::StockUnder, data));
+                }
+
+                hook_event_list
+            },
+            EngineEvent::BattleSaved { data } => {
+                let mut hook_event_list = vec![(Self::BattleSaved, data)];
+                match data.is_win() {
+                    Some(true) => hook_event_list.push((Self::Win, data)),
+                    Some(false) => hook_event_list.push((Self::Lose, data)),
+                    None => (),
+                }
+
+                hook_event_list
+            },
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// イベント毎に実行するコマンド
+/// シェルを通さずに program を実行するので、リダイレクトなどが必要なら program にシェルを指定する
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct HookCommand {
+    pub event: HookEvent,
+    pub program: String,
+    /// {chara_1p} などを置き換えて渡す
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// これを過ぎても終わらなければ kill する
+    #[serde(default = "HookCommand::default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+impl HookCommand {
+    // 終了を確認する間隔
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+    // 終わった後に出力を読み終わるのを待つ時間
+    // (program が起動したプロセスがパイプを持ったまま残ると読み終わらないので、それ以上は待たずに読んだスレッドを放っておく)
+    const READER_TIMEOUT: Duration = Duration::from_secs(1);
+
+    pub fn default_timeout_secs() -> u64 { 30 }
+
+    /// path を OS に関連付けられたアプリで開くコマンド (残基 警告 のファイルなど)
+    pub fn open_file(event: HookEvent, path: &str) -> Self {
+        let program = if cfg!(windows) {
+            "explorer"
+        } else if cfg!(target_os = "macos") {
+            "open"
+        } else {
+            "xdg-open"
+        };
+
+        Self {
+            event,
+            program: program.to_string(),
+            args: vec![path.to_string()],
+            timeout_secs: Self::default_timeout_secs(),
+        }
+    }
+
+    /// テンプレートで使える値
+    /// {event}, {id}, {rule}, {max_stock}, {bgm}, {result} (win/lose),
+    /// {chara_1p}, {chara_2p}, {power_1p}, {power_2p}, {stock_1p}, {stock_2p}, {order_1p}, {order_2p}
+    pub fn make_value_list(event: HookEvent, data: &SmashbrosData) -> HashMap<&'static str, String> {
+        let number = |value: i32| if value < 0 { String::new() } else { value.to_string() };
+
+        let mut value_list = HashMap::new();
+        value_list.insert("event", format!("{:?}", event));
+        value_list.insert("id", data.get_id().unwrap_or_default());
+        value_list.insert("rule", format!("{:?}", data.get_rule()));
+        value_list.insert("max_stock", number(data.get_max_stock(0)));
+        value_list.insert("bgm", data.get_bgm_name());
+        value_list.insert("result", match data.is_win() {
+            Some(true) => "win".to_string(),
+            Some(false) => "lose".to_string(),
+            None => String::new(),
+        });
+        let key_list = [
+            (0, ["chara_1p", "power_1p", "stock_1p", "order_1p"]),
+            (1, ["chara_2p", "power_2p", "stock_2p", "order_2p"]),
+        ];
+        for (player_number, [chara_key, power_key, stock_key, order_key]) in key_list {
+            value_list.insert(chara_key, data.get_character(player_number));
+            value_list.insert(power_key, number(data.get_power(player_number)));
+            value_list.insert(stock_key, number(data.get_stock(player_number)));
+            value_list.insert(order_key, number(data.get_order(player_number)));
+        }
+
+        value_list
+    }
+
+    /// args のテンプレートを置き換えたもの
+    pub fn make_args(&self, value_list: &HashMap<&str, String>) -> Vec<String> {
+        self.args.iter().map(|arg| render_template(arg, value_list)).collect()
+    }
+
+    /// program を実行して、終わるか timeout を過ぎるまで待つ (標準出力と標準エラーはログに出す)
+    /// @return None timeout を過ぎたので kill した
+    pub fn run(&self, args: &[String], timeout: Duration) -> std::io::Result<Option<ExitStatus>> {
+        let mut command = Command::new(&self.program);
+        command.args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        #[cfg(windows)]
+        {
+            // コンソールウィンドウを出さない (CREATE_NO_WINDOW)
+            use std::os::windows::process::CommandExt;
+            command.creation_flags(0x08000000);
+        }
+        let mut child = command.spawn()?;
+
+        // 出力が多くてもパイプが詰まらないように、終わるのを待つ間も読んでおく
+        let stdout_reader = Self::spawn_reader(child.stdout.take());
+        let stderr_reader = Self::spawn_reader(child.stderr.take());
+
+        let start_time = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if timeout <= start_time.elapsed() {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+            thread::sleep(Self::POLL_INTERVAL);
+        };
+
+        let reader_deadline = Instant::now() + Self::READER_TIMEOUT;
+        let stdout = stdout_reader.recv_timeout(reader_deadline.saturating_duration_since(Instant::now())).unwrap_or_default();
+        let stderr = stderr_reader.recv_timeout(reader_deadline.saturating_duration_since(Instant::now())).unwrap_or_default();
+        match status {
+            Some(status) if status.success() => log::info!("hook [{:?}] {}: {}", self.event, self.program, stdout.trim_end()),
+            Some(status) => log::warn!("hook [{:?}] {} exited with {}: {}", self.event, self.program, status, stderr.trim_end()),
+            None => log::warn!("hook [{:?}] {} timed out after {:?}: {}", self.event, self.program, timeout, stderr.trim_end()),
+        }
+
+        Ok(status)
+    }
+
+    // 読み終わったら送ってくる (join しないので、読み終わらなくても待たずに済む)
+    fn spawn_reader(pipe: Option<impl Read + Send + 'static>) -> mpsc::Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut output = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut output);
+            }
+
+            let _ = sender.send(String::from_utf8_lossy(&output).to_string());
+        });
+
+        receiver
+    }
+}
+
+/// event に該当する hook_command_list を、それぞれ別スレッドで実行する
+pub fn run_hooks(event: &EngineEvent, stock_warning_under: i32, hook_command_list: &[HookCommand]) {
+    for (hook_event, data) in HookEvent::from_engine_event(event, stock_warning_under) {
+        let value_list = HookCommand::make_value_list(hook_event, data);
+        for hook_command in hook_command_list.iter().filter(|hook_command| hook_command.event == hook_event) {
+            let hook_command = hook_command.clone();
+            let args = hook_command.make_args(&value_list);
+            let result = thread::Builder::new()
+                .name("smabrog-hook".to_string())
+                .spawn(move || {
+                    if let Err(e) = hook_command.run(&args, Duration::from_secs(hook_command.timeout_secs)) {
+                        log::error!("failed to run hook [{:?}] {}: {}", hook_command.event, hook_command.program, e);
+                    }
+                });
+            if let Err(e) = result {
+                log::error!("{}", e);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_event_from_engine_event() {
+        let event = EngineEvent::BattleSaved { data: SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, false, 5000000) };
+        let hook_event_list = HookEvent::from_engine_event(&event, 3).into_iter().map(|(hook_event, _)| hook_event).collect::<Vec<_>>();
+        assert_eq!(hook_event_list, vec![HookEvent::BattleSaved, HookEvent::Lose]);
+
+        let event = EngineEvent::SceneChanged { before: SceneList::HamVsSpam, after: SceneList::GamePlaying, data: SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, true, 5000000) };
+        assert_eq!(HookEvent::from_engine_event(&event, 3)[0].0, HookEvent::BattleStart);
+
+        let event = EngineEvent::StockChanged { player_number: 0, stock: 1 };
+        assert!(HookEvent::from_engine_event(&event, 3).is_empty());
+    }
+
+    #[test]
+    fn test_hook_command_args() {
+        let hook_command = HookCommand {
+            event: HookEvent::Win,
+            program: "notify".to_string(),
+            args: vec!["{chara_1p} vs {chara_2p}".to_string(), "{power_1p}".to_string(), "{result}".to_string()],
+            timeout_secs: HookCommand::default_timeout_secs(),
+        };
+        let value_list = HookCommand::make_value_list(HookEvent::Win, &SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, true, 5000000));
+        assert_eq!(hook_command.make_args(&value_list), vec!["MARIO vs LINK", "5000000", "win"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hook_command_run() {
+        let hook_command = HookCommand {
+            event: HookEvent::BattleSaved,
+            program: "sleep".to_string(),
+            args: Vec::new(),
+            timeout_secs: HookCommand::default_timeout_secs(),
+        };
+        assert!(hook_command.run(&["0".to_string()], Duration::from_secs(5)).unwrap().unwrap().success());
+        // 終わらなければ kill される
+        assert!(hook_command.run(&["5".to_string()], Duration::from_millis(100)).unwrap().is_none());
+
+        // kill したものが起動したプロセスが出力のパイプを持ったまま残っても待たない
+        let hook_command = HookCommand { program: "sh".to_string(), ..hook_command };
+        let start_time = Instant::now();
+        assert!(hook_command.run(&["-c".to_string(), "sleep 5 & sleep 5".to_string()], Duration::from_millis(100)).unwrap().is_none());
+        assert!(start_time.elapsed() < Duration::from_secs(3));
+
+        // 見つからないコマンドはエラー
+        let hook_command = HookCommand { program: "smabrog-not-found-command".to_string(), ..hook_command };
+        assert!(hook_command.run(&[], Duration::from_secs(1)).is_err());
+    }
+}
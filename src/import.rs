@@ -0,0 +1,534 @@
+use chrono::TimeZone;
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::data::*;
+use crate::resource::{
+    BattleHistoryFilter,
+    BattleHistoryTrait,
+    SmashbrosResource,
+};
+
+
+/// 取り込む 1 試合分
+#[derive(Clone, Debug)]
+pub struct ImportRecord {
+    /// 元の何件目か (1 から, CSV なら見出しを除いた行)
+    pub line: usize,
+    pub data: SmashbrosData,
+    /// キャラ名を推測で変換したものや、読めずに空にした値など
+    pub warning_list: Vec<String>,
+    /// 既にある戦歴 (か、取り込むものの中) に同じ試合がある
+    pub is_duplicated: bool,
+}
+
+/// 取り込む前に確認する為のもの
+#[derive(Clone, Debug, Default)]
+pub struct ImportPreview {
+    pub record_list: Vec<ImportRecord>,
+    /// 取り込めなかったもの (何件目か, 理由)
+    pub error_list: Vec<(usize, String)>,
+}
+impl ImportPreview {
+    /// 重複していないものの数
+    pub fn get_new_count(&self) -> usize {
+        self.record_list.iter().filter(|record| !record.is_duplicated).count()
+    }
+
+    /// 差分の形式で返す (+ 取り込む, = 重複しているので取り込まない, ! 取り込めない)
+    pub fn to_text(&self) -> String {
+        let mut line_list = Vec::new();
+        for record in &self.record_list {
+            let data = &record.data;
+            line_list.push(format!("{} {:>5}: {} {} vs {} {:?} {}",
+                if record.is_duplicated { "=" } else { "+" },
+                record.line,
+                data.get_start_time().map_or("----".to_string(), |start_time| start_time.format("%Y-%m-%d %H:%M:%S").to_string()),
+                data.get_character(0), data.get_character(1),
+                data.get_rule(),
+                match data.is_win() {
+                    Some(true) => "win",
+                    Some(false) => "lose",
+                    None => "-",
+                },
+            ));
+            for warning in &record.warning_list {
+                line_list.push(format!("  {:>5}  ({})", "", warning));
+            }
+        }
+        for (line, error) in &self.error_list {
+            line_list.push(format!("! {:>5}: {}", line, error));
+        }
+        line_list.push(format!("{} new, {} duplicated, {} error", self.get_new_count(), self.record_list.len() - self.get_new_count(), self.error_list.len()));
+
+        line_list.join("\n")
+    }
+
+    /// 既にある戦歴と、開始時刻 (秒まで) とキャラが同じものを重複とする (取り込むものの中で同じものも)
+    pub fn mark_duplicated(&mut self, battle_history: &mut dyn BattleHistoryTrait) {
+        let mut key_set = HashSet::new();
+        for record in &mut self.record_list {
+            let data = &record.data;
+            let start_time = match data.get_start_time() {
+                Some(start_time) => start_time.timestamp(),
+                None => {
+                    record.warning_list.push("no start_time, skipped duplicate check".to_string());
+                    continue;
+                },
+            };
+            if !key_set.insert(( start_time, data.get_character(0), data.get_character(1) )) {
+                record.is_duplicated = true;
+                continue;
+            }
+
+            let since = chrono::Local.timestamp(start_time, 0);
+            let filter = BattleHistoryFilter {
+                character: Some(data.get_character(0)),
+                opponent: Some(data.get_character(1)),
+                since: Some(since),
+                until: Some(since + chrono::Duration::seconds(1)),
+                ..Default::default()
+            };
+            record.is_duplicated = battle_history.find_data_by_filter(&filter, 0, 1)
+                .map_or(false, |data_list| !data_list.is_empty());
+        }
+    }
+
+    /// 重複していないものを保存して、保存できた数を返す
+    /// (キャラ名は読み込んだ時に SmashbrosResource::normalize_character_name で保存する時の名前にしてある)
+    pub fn import(&self, battle_history: &mut dyn BattleHistoryTrait) -> usize {
+        self.record_list.iter()
+            .filter(|record| !record.is_duplicated)
+            .filter(|record| battle_history.insert_data(&record.data).is_some())
+            .count()
+    }
+}
+
+/// 外部の戦歴 (CSV, JSON, 旧 Python 版の JSON) を SmashbrosData にする
+pub struct BattleHistoryImporter;
+impl BattleHistoryImporter {
+    // (取り込む時の名前, CSV の見出しや JSON のキーとして受け付ける名前)
+    const COLUMN_ALIAS_LIST: [(&'static str, &'static [&'static str]); 15] = [
+        ("start_time", &["start_time", "date", "datetime", "now", "日時", "日付"]),
+        ("end_time", &["end_time"]),
+        ("rule", &["rule", "rule_name", "ルール"]),
+        ("max_time", &["max_time", "time", "時間"]),
+        ("character_1p", &["character_1p", "character", "chara", "自キャラ"]),
+        ("character_2p", &["character_2p", "opponent", "相手キャラ", "相手"]),
+        ("power_1p", &["power_1p", "power", "gsp", "戦闘力"]),
+        ("power_2p", &["power_2p", "opponent_power", "相手の戦闘力"]),
+        ("stock_1p", &["stock_1p", "stock", "残機"]),
+        ("stock_2p", &["stock_2p", "opponent_stock"]),
+        ("max_stock_1p", &["max_stock_1p", "max_stock"]),
+        ("max_stock_2p", &["max_stock_2p"]),
+        ("order_1p", &["order_1p", "order", "順位"]),
+        ("order_2p", &["order_2p"]),
+        ("result", &["result", "勝敗"]),
+    ];
+    // 推測したキャラ名を使う一致率
+    const CHARACTER_MIN_RATIO: f32 = 0.8;
+
+    /// path の拡張子 (csv/json) に合わせて読み込む
+    pub fn read(path: &Path, smashbros_resource: &SmashbrosResource) -> anyhow::Result<ImportPreview> {
+        let text = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_lowercase()).as_deref() {
+            Some("csv") => Self::read_csv(&text, smashbros_resource),
+            Some("json") => Self::read_json(&text, smashbros_resource),
+            _ => anyhow::bail!("unsupported import format: {:?} (csv or json)", path),
+        }
+    }
+
+    /// 1 行目を見出しとした CSV (smabrog の [1試合1行] のエクスポートもそのまま読める)
+    pub fn read_csv(text: &str, smashbros_resource: &SmashbrosResource) -> anyhow::Result<ImportPreview> {
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(text.trim_start_matches('\u{feff}').as_bytes());
+        let column_list: Vec<Option<&str>> = reader.headers()?.iter().map(Self::find_column).collect();
+
+        let mut preview = ImportPreview::default();
+        for (index, record) in reader.records().enumerate() {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    preview.error_list.push(( index + 1, e.to_string() ));
+                    continue;
+                },
+            };
+            let row = column_list.iter().zip(record.iter())
+                .filter_map(|(column, value)| Some(( (*column)?, value.to_string() )))
+                .filter(|(_, value)| !value.is_empty())
+                .collect::<HashMap<_, _>>();
+            Self::push_row(&mut preview, index + 1, &row, smashbros_resource);
+        }
+
+        Ok(preview)
+    }
+
+    /// smabrog の JSON (/api/history, DB の形式) か、旧 Python 版の JSON
+    /// 1 試合のオブジェクトか、その配列か、{ "data_list": [...] }
+    pub fn read_json(text: &str, smashbros_resource: &SmashbrosResource) -> anyhow::Result<ImportPreview> {
+        let value: serde_json::Value = serde_json::from_str(text.trim_start_matches('\u{feff}'))?;
+        let value_list = match value {
+            serde_json::Value::Array(value_list) => value_list,
+            serde_json::Value::Object(mut object) => match object.remove("data_list") {
+                Some(serde_json::Value::Array(value_list)) => value_list,
+                _ => vec![serde_json::Value::Object(object)],
+            },
+            _ => anyhow::bail!("json is not an object or array"),
+        };
+
+        let mut preview = ImportPreview::default();
+        for (index, value) in value_list.into_iter().enumerate() {
+            let row = match value {
+                serde_json::Value::Object(object) if object.contains_key("chara_list") => Self::json_to_row(&object),
+                serde_json::Value::Object(object) => Self::legacy_json_to_row(&object),
+                _ => {
+                    preview.error_list.push(( index + 1, "not an object".to_string() ));
+                    continue;
+                },
+            };
+            Self::push_row(&mut preview, index + 1, &row, smashbros_resource);
+        }
+
+        Ok(preview)
+    }
+
+    fn find_column(header: &str) -> Option<&'static str> {
+        let header = header.trim().to_lowercase();
+        Self::COLUMN_ALIAS_LIST.iter()
+            .find(|(_, alias_list)| alias_list.contains(&header.as_str()))
+            .map(|(column, _)| *column)
+    }
+
+    fn json_value_to_string(value: &serde_json::Value) -> Option<String> {
+        match value {
+            serde_json::Value::String(value) => Some(value.clone()),
+            serde_json::Value::Number(value) => Some(value.to_string()),
+            serde_json::Value::Bool(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    // smabrog の形式 (chara_list などのリスト) を 1 行にする
+    fn json_to_row(object: &serde_json::Map<String, serde_json::Value>) -> HashMap<&'static str, String> {
+        let mut row = HashMap::new();
+        for (key, column) in [("start_time", "start_time"), ("end_time", "end_time"), ("rule_name", "rule")] {
+            if let Some(value) = object.get(key).and_then(Self::json_value_to_string) {
+                row.insert(column, value);
+            }
+        }
+        if let Some(max_time) = object.get("max_time").and_then(|max_time| max_time.as_array()) {
+            let max_time = max_time.iter().filter_map(|time| time.as_i64()).collect::<Vec<_>>();
+            if let [minutes, seconds] = max_time.as_slice() {
+                row.insert("max_time", (minutes * 60 + seconds).to_string());
+            }
+        }
+        let list_column_list = [
+            ("chara_list", ["character_1p", "character_2p"]),
+            ("power_list", ["power_1p", "power_2p"]),
+            ("stock_list", ["stock_1p", "stock_2p"]),
+            ("max_stock_list", ["max_stock_1p", "max_stock_2p"]),
+            ("order_list", ["order_1p", "order_2p"]),
+        ];
+        for (key, column_list) in list_column_list {
+            let value_list = match object.get(key).and_then(|value_list| value_list.as_array()) {
+                Some(value_list) => value_list,
+                None => continue,
+            };
+            for (column, value) in column_list.iter().zip(value_list) {
+                if let Some(value) = Self::json_value_to_string(value) {
+                    row.insert(*column, value);
+                }
+            }
+        }
+
+        row
+    }
+
+    // 旧 Python 版の形式を 1 行にする
+    // { "now": 日時, "rule": "stock" か { "name", "time", "stock" }, "player": [{ "name", "order", "power", "stock" }, ...] }
+    fn legacy_json_to_row(object: &serde_json::Map<String, serde_json::Value>) -> HashMap<&'static str, String> {
+        let mut row = HashMap::new();
+        for (key, value) in object {
+            let column = match Self::find_column(key) {
+                Some(column) => column,
+                None => continue,
+            };
+            if let Some(value) = Self::json_value_to_string(value) {
+                row.insert(column, value);
+            }
+        }
+        if let Some(rule) = object.get("rule").and_then(|rule| rule.as_object()) {
+            let rule_column_list = [("name", "rule"), ("time", "max_time"), ("stock", "max_stock_1p")];
+            for (key, column) in rule_column_list {
+                if let Some(value) = rule.get(key).and_then(Self::json_value_to_string) {
+                    row.insert(column, value);
+                }
+            }
+            // 旧版の時間は分
+            if let Some(max_time) = row.get("max_time").and_then(|max_time| max_time.parse::<u64>().ok()) {
+                row.insert("max_time", (max_time * 60).to_string());
+            }
+            if let Some(max_stock) = row.get("max_stock_1p").cloned() {
+                row.insert("max_stock_2p", max_stock);
+            }
+        }
+
+        let player_list = object.get("player").or_else(|| object.get("players")).and_then(|player_list| player_list.as_array());
+        let player_column_list = [
+            (&["name", "character", "chara"][..], ["character_1p", "character_2p"]),
+            (&["power"][..], ["power_1p", "power_2p"]),
+            (&["stock"][..], ["stock_1p", "stock_2p"]),
+            (&["order"][..], ["order_1p", "order_2p"]),
+        ];
+        for (player_number, player) in player_list.into_iter().flatten().take(2).enumerate() {
+            for (key_list, column_list) in &player_column_list {
+                let value = key_list.iter().find_map(|key| player.get(*key)).and_then(Self::json_value_to_string);
+                if let Some(value) = value {
+                    row.insert(column_list[player_number], value);
+                }
+            }
+        }
+
+        row
+    }
+
+    fn push_row(preview: &mut ImportPreview, line: usize, row: &HashMap<&str, String>, smashbros_resource: &SmashbrosResource) {
+        match Self::row_to_data(row, smashbros_resource) {
+            Ok((data, warning_list)) => preview.record_list.push(ImportRecord { line, data, warning_list, is_duplicated: false }),
+            Err(e) => preview.error_list.push(( line, e )),
+        }
+    }
+
+    // 1 行を SmashbrosData にする (1 on 1 のみ)
+    fn row_to_data(row: &HashMap<&str, String>, smashbros_resource: &SmashbrosResource) -> Result<(SmashbrosData, Vec<String>), String> {
+        let mut warning_list = Vec::new();
+        let mut data = SmashbrosData::default();
+        data.initialize_battle(2, true);
+
+        for (player_number, column) in [(0, "character_1p"), (1, "character_2p")] {
+            let character = match row.get(column) {
+                Some(character) => character,
+                None => return Err(format!("{} is empty", column)),
+            };
+            data.set_character(player_number, Self::normalize_character(character, &mut warning_list, smashbros_resource));
+        }
+
+        let mut parse_number = |column: &str| -> Option<i32> {
+            let value = row.get(column)?;
+            match value.replace(',', "").parse::<i32>() {
+                Ok(number) => Some(number),
+                Err(_) => {
+                    warning_list.push(format!("{} is not a number: {}", column, value));
+                    None
+                },
+            }
+        };
+        for player_number in 0..2 {
+            let suffix = if 0 == player_number { "1p" } else { "2p" };
+            if let Some(power) = parse_number(&format!("power_{}", suffix)) {
+                data.set_power(player_number, power);
+            }
+            if let Some(stock) = parse_number(&format!("stock_{}", suffix)) {
+                data.set_stock(player_number, stock);
+            }
+            if let Some(max_stock) = parse_number(&format!("max_stock_{}", suffix)) {
+                data.set_max_stock(player_number, max_stock);
+            }
+            if let Some(order) = parse_number(&format!("order_{}", suffix)) {
+                data.set_order(player_number, order);
+            }
+        }
+
+        // 順位が無ければ勝敗から決める
+        if !data.is_decided_order(0) {
+            if let Some(is_win) = row.get("result").and_then(|result| Self::parse_result(result)) {
+                data.set_order(0, if is_win { 1 } else { 2 });
+                data.set_order(1, if is_win { 2 } else { 1 });
+            }
+        }
+
+        if let Some(rule) = row.get("rule") {
+            // stock, STOCK なども受け付ける
+            let mut rule_name = rule.to_lowercase();
+            if let Some(first) = rule_name.get_mut(0..1) {
+                first.make_ascii_uppercase();
+            }
+            match BattleRule::from_str(&rule_name) {
+                Ok(BattleRule::Unknown) | Err(_) => warning_list.push(format!("unknown rule: {}", rule)),
+                Ok(rule) => data.set_rule(rule),
+            }
+        }
+        if let Some(max_time) = row.get("max_time") {
+            match Self::parse_max_time(max_time) {
+                Some(max_time) => data.set_max_time(max_time),
+                None => warning_list.push(format!("max_time is not a time: {}", max_time)),
+            }
+        }
+        for (column, is_start) in [("start_time", true), ("end_time", false)] {
+            let value = match row.get(column) {
+                Some(value) => value,
+                None => continue,
+            };
+            match Self::parse_date_time(value) {
+                Some(date_time) if is_start => data.set_start_time(Some(date_time)),
+                Some(date_time) => data.set_end_time(Some(date_time)),
+                None => warning_list.push(format!("{} is not a date: {}", column, value)),
+            }
+        }
+
+        Ok((data, warning_list))
+    }
+
+    // 保存する時のキャラ名にする (推測したものは warning_list に残す)
+    fn normalize_character(character: &str, warning_list: &mut Vec<String>, smashbros_resource: &SmashbrosResource) -> String {
+        match smashbros_resource.convert_character_name(character.to_uppercase()) {
+            Some((chara_name, ratio)) if 1.0 <= ratio => smashbros_resource.normalize_character_name(&chara_name),
+            Some((chara_name, ratio)) if Self::CHARACTER_MIN_RATIO <= ratio => {
+                warning_list.push(format!("{} -> {} ({:.0}%)", character, chara_name, ratio * 100.0));
+                smashbros_resource.normalize_character_name(&chara_name)
+            },
+            _ => {
+                warning_list.push(format!("unknown character: {}", character));
+                SmashbrosData::CHARACTER_NAME_UNKNOWN.to_string()
+            },
+        }
+    }
+
+    fn parse_result(result: &str) -> Option<bool> {
+        match result.trim().to_lowercase().as_str() {
+            "win" | "w" | "o" | "○" | "勝ち" | "勝" | "1" | "true" => Some(true),
+            "lose" | "l" | "x" | "×" | "負け" | "負" | "0" | "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    // 秒か m:ss
+    fn parse_max_time(max_time: &str) -> Option<std::time::Duration> {
+        let seconds = match max_time.split_once(':') {
+            Some((minutes, seconds)) => minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?,
+            None => max_time.parse::<u64>().ok()?,
+        };
+
+        Some(std::time::Duration::from_secs(seconds))
+    }
+
+    // RFC3339 (smabrog の形式), YYYY-MM-DD HH:MM(:SS), YYYY/MM/DD HH:MM(:SS), YYYY-MM-DD, YYYY/MM/DD
+    fn parse_date_time(value: &str) -> Option<chrono::DateTime<chrono::Local>> {
+        if let Some(date_time) = BattleHistoryFilter::parse_date_time(value) {
+            return Some(date_time);
+        }
+
+        let value = value.replace('/', "-");
+        ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"].iter()
+            .find_map(|format| chrono::NaiveDateTime::parse_from_str(&value, format).ok())
+            .or_else(|| chrono::NaiveDate::parse_from_str(&value, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+            .and_then(|date_time| chrono::Local.from_local_datetime(&date_time).single())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::AppContext;
+    use crate::resource::InMemoryBattleHistory;
+
+    #[test]
+    fn test_import_csv() {
+        let csv = "\u{feff}date,character,opponent,result,gsp,rule,time\r\n\
+            2022/01/02 12:34:56,MARIO,LINK,win,\"5,000,000\",stock,7:00\r\n\
+            2022/01/02 12:40:00,MARIO,LINK,負け,abc,Stock,420\r\n\
+            2022/01/02 12:45:00,MARIO,,win,,,\r\n";
+        let context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
+        let preview = BattleHistoryImporter::read_csv(csv, &context.smashbros_resource()).unwrap();
+
+        assert_eq!(preview.record_list.len(), 2);
+        let data = &preview.record_list[0].data;
+        assert_eq!(data.get_character(0), "MARIO");
+        assert_eq!(data.get_character(1), "LINK");
+        assert_eq!(data.is_win(), Some(true));
+        assert_eq!(data.get_power(0), 5000000);
+        assert_eq!(data.get_rule(), BattleRule::Stock);
+        assert_eq!(data.get_max_time().as_secs(), 420);
+        assert_eq!(data.get_start_time().unwrap().format("%Y-%m-%d %H:%M:%S").to_string(), "2022-01-02 12:34:56");
+
+        assert_eq!(preview.record_list[1].data.is_win(), Some(false));
+        assert_eq!(preview.record_list[1].warning_list.len(), 1);
+        assert_eq!(preview.error_list[0].0, 3);
+    }
+
+    #[test]
+    fn test_import_json() {
+        let json = r#"[
+            { "now": "2021-05-01 20:00:00", "rule": { "name": "stock", "time": 7, "stock": 3 },
+              "player": [{ "name": "MARIO", "order": 2, "power": 4000000, "stock": 0 }, { "name": "LINK", "order": 1, "power": 4100000, "stock": 1 }] },
+            { "start_time": "2022-01-02T12:34:56+09:00", "rule_name": "Time", "max_time": [3, 0],
+              "chara_list": ["MARIO", "KIRBY"], "order_list": [1, 2], "power_list": [5000000, 5100000] }
+        ]"#;
+        let context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
+        let preview = BattleHistoryImporter::read_json(json, &context.smashbros_resource()).unwrap();
+
+        assert_eq!(preview.record_list.len(), 2);
+        let data = &preview.record_list[0].data;
+        assert_eq!(data.get_character(1), "LINK");
+        assert_eq!(data.is_win(), Some(false));
+        assert_eq!(data.get_max_time().as_secs(), 7 * 60);
+        assert_eq!(data.get_max_stock(1), 3);
+        let data = &preview.record_list[1].data;
+        assert_eq!(data.get_rule(), BattleRule::Time);
+        assert_eq!(data.get_power(1), 5100000);
+        assert_eq!(data.is_win(), Some(true));
+    }
+
+    #[test]
+    fn test_import_duplicated() {
+        let mut context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
+        let _guard = context.enter();
+        let csv = "start_time,character_1p,character_2p,result\n\
+            2022-01-02 12:34:56,MARIO,LINK,win\n\
+            2022-01-02 12:34:56,MARIO,LINK,win\n\
+            2022-01-02 12:40:00,MARIO,LINK,win\n";
+
+        let mut preview = BattleHistoryImporter::read_csv(csv, &context.smashbros_resource()).unwrap();
+        preview.mark_duplicated(&mut context);
+        // 取り込むものの中での重複
+        assert_eq!(preview.get_new_count(), 2);
+        assert!(preview.to_text().contains("2 new, 1 duplicated, 0 error"));
+        assert_eq!(preview.import(&mut context), 2);
+
+        // 既に取り込んだものとの重複
+        let mut preview = BattleHistoryImporter::read_csv(csv, &context.smashbros_resource()).unwrap();
+        preview.mark_duplicated(&mut context);
+        assert_eq!(preview.get_new_count(), 0);
+        assert_eq!(context.find_data_limit(10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_import_en_alias() {
+        use i18n_embed::unic_langid::LanguageIdentifier;
+        let csv = "start_time,character_1p,character_2p,result\n\
+            2022-01-02 12:34:56,Bowser,Incineroar,win\n";
+
+        // 表示している言語に関係なく、保存する時のキャラ名 (ja) にする
+        for lang in ["ja-JP", "en-US"] {
+            let mut context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
+            context.change_language(lang.parse::<LanguageIdentifier>().unwrap());
+            context.smashbros_resource_mut().change_language(context.lang_loader());
+
+            let preview = BattleHistoryImporter::read_csv(csv, &context.smashbros_resource()).unwrap();
+            let record = &preview.record_list[0];
+            assert_eq!(record.data.get_character(0), "KOOPA", "{}", lang);
+            assert_eq!(record.data.get_character(1), "GAOGAEN", "{}", lang);
+            assert!(record.warning_list.is_empty(), "{}: {:?}", lang, record.warning_list);
+
+            assert_eq!(preview.import(&mut context), 1);
+            assert_eq!(context.find_data_limit(10).unwrap()[0].get_character(0), "KOOPA");
+        }
+    }
+}
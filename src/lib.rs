@@ -1,8 +1,17 @@
 
 pub mod capture;
+pub mod context;
 pub mod data;
+#[cfg(windows)]
 pub mod egui;
 pub mod engine;
+pub mod event;
+pub mod export;
+pub mod hook;
+pub mod import;
+pub mod output;
 pub mod resource;
 pub mod scene;
+pub mod server;
 pub mod utils;
+pub mod webhook;
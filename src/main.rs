@@ -13,6 +13,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     init_logger();
 
     // smabrog::gui::make_gui_run().unwrap();
+    #[cfg(windows)]
     smabrog::egui::run_gui().await.unwrap();
 
     Ok(())
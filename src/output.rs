@@ -0,0 +1,394 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+use std::sync::{
+    mpsc,
+    Arc,
+};
+use std::thread;
+use std::time::Duration;
+
+use crate::context::AppContext;
+use crate::data::*;
+use crate::engine::SmashBrogEngine;
+use crate::event::EngineEvent;
+use crate::resource::{
+    BattleHistoryFilter,
+    BattleHistoryTrait,
+    BattleStatsGroup,
+};
+use crate::scene::SceneList;
+
+
+/// 書き出すテキストファイル 1 つ分
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TextOutputFile {
+    /// 出力先のフォルダからのファイル名
+    pub file_name: String,
+    /// {character} などを置き換えて書き出す内容
+    pub template: String,
+}
+impl TextOutputFile {
+    pub fn new(file_name: &str, template: &str) -> Self {
+        Self {
+            file_name: file_name.to_string(),
+            template: template.to_string(),
+        }
+    }
+}
+
+/// 配信ツール向けのテキスト出力の設定
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TextOutputConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "TextOutputConfig::default_folder")]
+    pub folder: String,
+    #[serde(default = "TextOutputConfig::default_file_list")]
+    pub file_list: Vec<TextOutputFile>,
+}
+impl Default for TextOutputConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            folder: Self::default_folder(),
+            file_list: Self::default_file_list(),
+        }
+    }
+}
+impl TextOutputConfig {
+    pub fn default_folder() -> String { "output".to_string() }
+    pub fn default_file_list() -> Vec<TextOutputFile> {
+        vec![
+            TextOutputFile::new("current_matchup.txt", "{character} vs {opponent}"),
+            TextOutputFile::new("session_record.txt", "{win} - {lose} ({win_rate}%)"),
+            TextOutputFile::new("streak.txt", "{streak}"),
+            TextOutputFile::new("gsp.txt", "{gsp} ({gsp_diff})"),
+        ]
+    }
+}
+
+/// template の {name} を value_list の値に置き換える (知らない name はそのまま残す)
+pub fn render_template(template: &str, value_list: &HashMap<&str, String>) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest.find('}').and_then(|end| Some(( value_list.get(&rest[1..end])?, end )));
+        match value {
+            Some((value, end)) => {
+                result.push_str(value);
+                rest = &rest[end+1..];
+            },
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// path に content を書き込む
+/// 同じフォルダの一時ファイルに書いてから置き換えるので、OBS などが書きかけのファイルを読むことはない
+pub fn write_atomic(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut temp_file_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_file_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_file_name);
+    {
+        let mut file = std::fs::File::create(&temp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+    }
+
+    std::fs::rename(&temp_path, path)
+}
+
+// 戦歴から求める値 (戦歴が保存された時だけ取得し直す)
+#[derive(Clone, Debug, Default)]
+struct TextOutputStats {
+    win: i32,
+    lose: i32,
+    win_rate: f32,
+    battle_count: i32,
+    streak: i32,
+    power_list: Vec<i32>,
+}
+
+// イベントから現在の状態を組み立てて、ファイルに書き出す
+struct TextOutputWriter {
+    context: AppContext,
+    config: TextOutputConfig,
+    session_start_time: chrono::DateTime<chrono::Local>,
+    scene: SceneList,
+    data: SmashbrosData,
+    stats: TextOutputStats,
+    // 内容が変わっていないファイルは書き直さない
+    prev_content_list: HashMap<PathBuf, String>,
+}
+impl TextOutputWriter {
+    const LATEST_LIMIT: i64 = 50;
+
+    fn new(context: AppContext, config: TextOutputConfig) -> Self {
+        Self {
+            context,
+            config,
+            session_start_time: chrono::Local::now(),
+            scene: SceneList::default(),
+            data: SmashbrosData::default(),
+            stats: TextOutputStats::default(),
+            prev_content_list: HashMap::new(),
+        }
+    }
+
+    fn update_stats(&mut self) {
+        let latest_list = self.context.find_data_limit(Self::LATEST_LIMIT).unwrap_or_default();
+        let session_filter = BattleHistoryFilter { since: Some(self.session_start_time), ..Default::default() };
+        let session = self.context.aggregate_stats(&session_filter, BattleStatsGroup::All)
+            .and_then(|mut stats_list| stats_list.pop())
+            .unwrap_or_default();
+
+        self.stats = TextOutputStats {
+            win: session.win,
+            lose: session.lose,
+            win_rate: session.get_win_rate(),
+            battle_count: session.get_battle_count(),
+            streak: SmashBrogEngine::get_streak_by_data_list(&latest_list),
+            power_list: SmashBrogEngine::get_power_list_by_data_list(&latest_list),
+        };
+    }
+
+    fn update(&mut self, event: &EngineEvent) {
+        match event {
+            EngineEvent::SceneChanged { after, data, .. } => {
+                self.scene = *after;
+                self.data = data.clone();
+            },
+            EngineEvent::RuleDecided { data, .. } | EngineEvent::BgmDecided { data, .. } => self.data = data.clone(),
+            EngineEvent::CharacterDecided { player_number, character } if *player_number < self.data.get_player_count() => {
+                self.data.set_character(*player_number, character.clone());
+            },
+            EngineEvent::StockChanged { player_number, stock } if *player_number < self.data.get_player_count() => {
+                self.data.set_stock(*player_number, *stock);
+            },
+            EngineEvent::PowerDecided { player_number, power } if *player_number < self.data.get_player_count() => {
+                self.data.set_power(*player_number, *power);
+            },
+            EngineEvent::BattleSaved { data } | EngineEvent::ResultReplayEnded { data } => {
+                self.data = data.clone();
+                self.update_stats();
+            },
+            _ => (),
+        }
+    }
+
+    // 未検出のものは空にしておく
+    fn make_value_list(&self) -> HashMap<&'static str, String> {
+        let data = &self.data;
+        let stats = &self.stats;
+        let character = |player_number| match data.get_character(player_number) {
+            character if character == SmashbrosData::CHARACTER_NAME_UNKNOWN => String::new(),
+            character => character,
+        };
+        let number = |value: i32| if value < 0 { String::new() } else { value.to_string() };
+        let max_time = data.get_max_time().as_secs();
+        let gsp = stats.power_list.last().copied();
+        let gsp_diff = match stats.power_list.as_slice() {
+            [.., prev, last] => format!("{:+}", last - prev),
+            _ => String::new(),
+        };
+
+        let mut value_list = HashMap::new();
+        value_list.insert("scene", format!("{:?}", self.scene));
+        value_list.insert("character", character(0));
+        value_list.insert("opponent", character(1));
+        value_list.insert("rule", match data.get_rule() {
+            BattleRule::Unknown => String::new(),
+            rule => format!("{:?}", rule),
+        });
+        value_list.insert("max_stock", number(data.get_max_stock(0)));
+        value_list.insert("max_time", if 0 == max_time { String::new() } else { format!("{}:{:02}", max_time / 60, max_time % 60) });
+        value_list.insert("stock", number(data.get_stock(0)));
+        value_list.insert("opponent_stock", number(data.get_stock(1)));
+        value_list.insert("power", number(data.get_power(0)));
+        value_list.insert("opponent_power", number(data.get_power(1)));
+        value_list.insert("bgm", data.get_bgm_name());
+        value_list.insert("win", stats.win.to_string());
+        value_list.insert("lose", stats.lose.to_string());
+        value_list.insert("win_rate", format!("{:.1}", stats.win_rate * 100.0));
+        value_list.insert("battle_count", stats.battle_count.to_string());
+        value_list.insert("streak", stats.streak.to_string());
+        value_list.insert("win_streak", stats.streak.max(0).to_string());
+        value_list.insert("lose_streak", (-stats.streak).max(0).to_string());
+        value_list.insert("gsp", gsp.map_or(String::new(), |gsp| gsp.to_string()));
+        value_list.insert("gsp_diff", gsp_diff);
+
+        value_list
+    }
+
+    fn write_all(&mut self) {
+        let value_list = self.make_value_list();
+        let folder = Path::new(&self.config.folder);
+        for file in &self.config.file_list {
+            let path = folder.join(&file.file_name);
+            let content = render_template(&file.template, &value_list);
+            if self.prev_content_list.get(&path) == Some(&content) {
+                continue;
+            }
+
+            match write_atomic(&path, &content) {
+                Ok(()) => {
+                    self.prev_content_list.insert(path, content);
+                },
+                // 読まれている最中などで置き換えられなかったら、次のイベントで書き直す
+                Err(e) => log::warn!("failed to write text output {:?}: {}", path, e),
+            }
+        }
+    }
+}
+
+/// EngineEvent を受け取る度に、配信ツール向けのテキストファイルを書き出すスレッド
+/// {scene}, {character}, {opponent}, {rule}, {max_stock}, {max_time}, {stock}, {opponent_stock},
+/// {power}, {opponent_power}, {bgm} : 対戦中のデータ
+/// {win}, {lose}, {win_rate}, {battle_count} : 起動してからの成績
+/// {streak}, {win_streak}, {lose_streak}, {gsp}, {gsp_diff} : 直近の連勝数 (連敗なら負の数), 世界戦闘力とその前回からの差
+pub struct TextOutput {
+    is_finished: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+impl Drop for TextOutput {
+    fn drop(&mut self) {
+        self.is_finished.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+impl TextOutput {
+    // 終了を確認する間隔
+    const RECV_TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// config.folder に config.file_list を書き出し、event_receiver に届く度に書き直す
+    pub fn spawn(context: AppContext, config: TextOutputConfig, event_receiver: mpsc::Receiver<EngineEvent>) -> anyhow::Result<Self> {
+        if let Err(e) = std::fs::create_dir_all(&config.folder) {
+            anyhow::bail!("failed to create text output folder {}: {}", config.folder, e);
+        }
+        let is_finished = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let is_finished = Arc::clone(&is_finished);
+            thread::Builder::new()
+                .name("smabrog-text-output".to_string())
+                .spawn(move || {
+                    let _guard = context.enter();
+                    let mut writer = TextOutputWriter::new(context, config);
+                    writer.update_stats();
+                    writer.write_all();
+
+                    while !is_finished.load(Ordering::Relaxed) {
+                        match event_receiver.recv_timeout(Self::RECV_TIMEOUT) {
+                            Ok(event) => writer.update(&event),
+                            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                        }
+                        writer.write_all();
+                    }
+                })?
+        };
+
+        Ok(Self {
+            is_finished,
+            handle: Some(handle),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::InMemoryBattleHistory;
+
+    fn make_folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("smabrog_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+
+        folder
+    }
+
+    #[test]
+    fn test_render_template() {
+        let mut value_list = HashMap::new();
+        value_list.insert("character", "MARIO".to_string());
+        value_list.insert("opponent", "LINK".to_string());
+
+        assert_eq!(render_template("{character} vs {opponent}", &value_list), "MARIO vs LINK");
+        assert_eq!(render_template("{unknown} {character}", &value_list), "{unknown} MARIO");
+        assert_eq!(render_template("{{character}} {", &value_list), "{MARIO} {");
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let folder = make_folder("write_atomic");
+        let path = folder.join("streak.txt");
+
+        write_atomic(&path, "1").unwrap();
+        write_atomic(&path, "2").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "2");
+        // 一時ファイルは残らない
+        assert_eq!(std::fs::read_dir(&folder).unwrap().count(), 1);
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn test_text_output_writer() {
+        let folder = make_folder("text_output");
+        let mut context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
+        context.insert_data(&SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, false, 5000000));
+        context.insert_data(&SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, true, 5100000));
+        context.insert_data(&SmashbrosData::new_for_test("MARIO", "KIRBY", BattleRule::Stock, true, 5200000));
+
+        let mut writer = TextOutputWriter::new(context, TextOutputConfig {
+            enabled: true,
+            folder: folder.to_string_lossy().to_string(),
+            ..Default::default()
+        });
+        writer.session_start_time = chrono::Local::now() - chrono::Duration::hours(1);
+        writer.update_stats();
+
+        writer.update(&EngineEvent::SceneChanged {
+            before: SceneList::Matching,
+            after: SceneList::HamVsSpam,
+            data: SmashbrosData::new_for_test("FOX", "unknown", BattleRule::Stock, true, -1),
+        });
+        writer.update(&EngineEvent::CharacterDecided { player_number: 1, character: "PIKACHU".to_string() });
+        // プレイヤー数を超えるものは無視する
+        writer.update(&EngineEvent::CharacterDecided { player_number: 3, character: "PIKACHU".to_string() });
+        writer.write_all();
+
+        let read = |file_name: &str| std::fs::read_to_string(folder.join(file_name)).unwrap();
+        assert_eq!(read("current_matchup.txt"), "FOX vs PIKACHU");
+        assert_eq!(read("session_record.txt"), "2 - 1 (66.7%)");
+        assert_eq!(read("streak.txt"), "2");
+        assert_eq!(read("gsp.txt"), "5200000 (+100000)");
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+}
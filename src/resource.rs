@@ -1,9 +1,6 @@
 
 use i18n_embed::{
-    fluent::{
-        fluent_language_loader,
-        FluentLanguageLoader
-    },
+    fluent::FluentLanguageLoader,
     LanguageLoader,
     unic_langid::LanguageIdentifier,
 };
@@ -27,14 +24,18 @@ use serde::{
 use std::collections::HashMap;
 use std::io::BufReader;
 
-use crate::data::SmashbrosData;
+use crate::data::{
+    BattleRule,
+    SmashbrosData,
+};
 
 
 // #[cfg(dependencies = "eframe")]
 pub mod eframe_resource;
 pub use eframe_resource::{
-    GUI_CONFIG,
-    SMASHBROS_RESOURCE,
+    GUIConfig,
+    GUIStateConfig,
+    SavedSearch,
     SmashbrosResource,
 };
 
@@ -43,42 +44,6 @@ pub use eframe_resource::{
 #[derive(RustEmbed)]
 #[folder = "locales/"]
 pub struct Localizations;
-/// シングルトンで i18n を保持するため
-pub struct WrappedFluentLanguageLoader {
-    lang: Option<FluentLanguageLoader>,
-}
-impl WrappedFluentLanguageLoader {
-    pub fn get(&mut self) -> &FluentLanguageLoader {
-        if self.lang.is_none() {
-            let loader = fluent_language_loader!();
-            
-            match loader.load_fallback_language(&Localizations) {
-                Ok(()) => self.lang = Some(loader),
-                Err(_e) => {
-                    self.lang = Some(FluentLanguageLoader::new(
-                        "ja-JP",
-                        LanguageIdentifier::from_bytes("ja-JP".as_bytes()).expect("lang parsing failed"),
-                    ));
-                },
-            }
-        }
-        self.lang.as_mut().unwrap()
-    }
-
-    // 言語の変更
-    pub fn change(&mut self, lang: LanguageIdentifier) {
-        let loader = fluent_language_loader!();
-        let _result = i18n_embed::select(&loader, &Localizations, &[lang]);
-
-        self.lang = Some(loader);
-    }
-}
-static mut _LANG_LOADER: WrappedFluentLanguageLoader = WrappedFluentLanguageLoader {
-    lang: None,
-};
-#[allow(non_snake_case)]
-pub fn LANG_LOADER() -> &'static mut WrappedFluentLanguageLoader { unsafe { &mut _LANG_LOADER } }
-pub fn is_lang_english() -> bool { LANG_LOADER().get().current_language().language.as_str() == "en" }
 
 
 /// スマブラ情報が入ったリソースファイル(serde_jsonで読み込むためのコンテナ)
@@ -93,25 +58,23 @@ pub struct SmashbrosResourceText {
     #[serde(default)]
     bgm_list: HashMap<String, bool>,
 }
-impl Default for SmashbrosResourceText {
-    fn default() -> Self {
-        Self::new()
-    }
-}
 impl SmashbrosResourceText {
     const FILE_PATH: &'static str = "smashbros_resource.yml";
-    fn new() -> Self {
-        let lang = LANG_LOADER().get().current_language().language.clone();
+    fn new(lang_loader: &FluentLanguageLoader) -> Self {
+        let lang = lang_loader.current_language().language.clone();
         let path = format!("{}_{}", lang.as_str(), SmashbrosResourceText::FILE_PATH);
         let mut own = Self::load_resources(&path);
         log::info!("loaded SmashBros by {} resource version [{}.*.*]", lang.as_str(), own.version);
 
-        // icon_list, bgm_list は全言語のを読み込んでおく
-        for lang in LANG_LOADER().get().available_languages(&Localizations).unwrap() {
+        // icon_list, bgm_list, i18n_convert_list は全言語のを読み込んでおく
+        // (表示している言語に関係なく、どの言語のキャラ名も保存する時のキャラ名に変換できるように)
+        for lang in lang_loader.available_languages(&Localizations).unwrap() {
             let path = format!("{}_{}", lang.language.as_str(), SmashbrosResourceText::FILE_PATH);
+            let resources = Self::load_resources(&path);
 
-            own.icon_list.extend(Self::load_resources(&path).icon_list);
-            own.bgm_list.extend(Self::load_resources(&path).bgm_list);
+            own.icon_list.extend(resources.icon_list);
+            own.bgm_list.extend(resources.bgm_list);
+            own.i18n_convert_list.extend(resources.i18n_convert_list);
         }
 
         own
@@ -134,6 +97,455 @@ impl SmashbrosResourceText {
 }
 
 
+/// 戦歴の保存先
+pub trait BattleHistoryTrait {
+    /// 戦歴情報を挿入して、ID を返す
+    fn insert_data(&mut self, data: &SmashbrosData) -> Option<String>;
+    /// 戦歴情報を更新
+    fn update_data(&mut self, data: &SmashbrosData) -> Option<String>;
+    /// 戦歴情報をゴミ箱に入れる (ゴミ箱の戦歴は find_deleted_data 以外では取得されない)
+    fn delete_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()>;
+    /// ゴミ箱に入れた戦歴情報を元に戻す
+    fn restore_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()>;
+    /// 戦歴情報を完全に削除
+    fn purge_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()>;
+    /// 戦歴情報を 直近 result_max 件 取得
+    fn find_data_limit(&mut self, result_max: i64) -> Option<Vec<SmashbrosData>>;
+    /// 特定のキャラクターの戦歴を直近 limit 件取得 (use_in なら、どれかのキャラが含まれているもの)
+    fn find_data_by_chara_list(&mut self, character_list: Vec<String>, limit: i64, use_in: bool) -> Option<Vec<SmashbrosData>>;
+    /// filter に合う戦歴を新しい順に skip 件飛ばして limit 件取得
+    fn find_data_by_filter(&mut self, filter: &BattleHistoryFilter, skip: i64, limit: i64) -> Option<Vec<SmashbrosData>>;
+    /// filter に合う戦歴を新しい順に、cursor (前のページの next_cursor) より古いものから limit 件取得
+    /// skip と違って、件数が多くても先頭から数え直さない
+    fn find_data_by_cursor(&mut self, filter: &BattleHistoryFilter, cursor: Option<String>, limit: i64) -> Option<BattleHistoryPage>;
+    /// 戦歴に付いているタグを名前順に重複なしで取得
+    fn find_tag_list(&mut self) -> Option<Vec<String>>;
+    /// ゴミ箱の戦歴を新しく入れた順に limit 件取得
+    fn find_deleted_data(&mut self, limit: i64) -> Option<Vec<SmashbrosData>>;
+    /// until より前にゴミ箱に入れた戦歴を完全に削除して、削除した数を返す
+    fn purge_deleted_data(&mut self, until: chrono::DateTime<chrono::Local>) -> anyhow::Result<usize>;
+    /// filter に合う戦歴の勝敗数を group ごとに、試合数の多い順に集計する (件数の上限なく全ての戦歴を集計する)
+    fn aggregate_stats(&mut self, filter: &BattleHistoryFilter, group: BattleStatsGroup) -> Option<Vec<BattleStats>>;
+}
+
+/// find_data_by_cursor で取得した 1 ページ分の戦歴
+#[derive(Clone, Debug, Default)]
+pub struct BattleHistoryPage {
+    pub data_list: Vec<SmashbrosData>,
+    /// 次のページを取得する為の cursor (次のページが無ければ None)
+    pub next_cursor: Option<String>,
+}
+impl BattleHistoryPage {
+    /// limit より 1 件多く取得した data_list から作る (多く取得できていれば次のページがある)
+    pub fn new(mut data_list: Vec<SmashbrosData>, limit: i64) -> Self {
+        let limit = limit.max(0) as usize;
+        let next_cursor = if limit < data_list.len() {
+            data_list.truncate(limit);
+            data_list.last().and_then(BattleHistoryCursor::from_data).map(|cursor| cursor.to_string())
+        } else {
+            None
+        };
+
+        Self { data_list, next_cursor }
+    }
+}
+
+/// 戦歴を新しい順に並べる (start_time, ID) の組 (find_data_by_cursor の cursor)
+/// 手動で入力した試合は ID と開始時刻の順が違うので、ID だけでは並べない
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BattleHistoryCursor {
+    /// 開始時刻のミリ秒 (DB に保存される精度, 未検出なら None で一番古い扱い)
+    pub start_time: Option<i64>,
+    pub id: String,
+}
+impl std::fmt::Display for BattleHistoryCursor {
+    /// "ID:開始時刻のミリ秒" (未検出なら "ID")
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.start_time {
+            Some(start_time) => write!(f, "{}:{}", self.id, start_time),
+            None => write!(f, "{}", self.id),
+        }
+    }
+}
+impl BattleHistoryCursor {
+    /// 保存されている (ID がある) 戦歴の位置
+    pub fn from_data(data: &SmashbrosData) -> Option<Self> {
+        use crate::data::SmashbrosDataTrait;
+        Some(Self {
+            start_time: data.get_start_time().map(|start_time| start_time.timestamp_millis()),
+            id: data.get_id()?,
+        })
+    }
+
+    /// to_string した文字列から作る
+    /// @return Err(理由) ID か開始時刻が解釈できなかった
+    pub fn parse(cursor: &str) -> Result<Self, String> {
+        let (id, start_time) = match cursor.split_once(':') {
+            Some((id, start_time)) => (id, Some(start_time.parse::<i64>().map_err(|e| format!("{} is not a cursor. {}", cursor, e))?)),
+            None => (cursor, None),
+        };
+        if let Err(e) = bson::oid::ObjectId::with_string(id) {
+            return Err(format!("{} is not a cursor. {}", cursor, e));
+        }
+
+        Ok(Self { start_time, id: id.to_string() })
+    }
+
+    /// DB でこの位置より古い戦歴の条件
+    pub fn to_document(&self) -> anyhow::Result<Document> {
+        use chrono::TimeZone;
+        use mongodb::bson::doc;
+        let id = bson::oid::ObjectId::with_string(&self.id)?;
+        // 開始時刻が null のものは一番古いので、開始時刻がある位置からは全て続きになる
+        let condition_list = match self.start_time {
+            Some(start_time) => {
+                let start_time = bson::Bson::DateTime(chrono::Utc.timestamp_millis(start_time));
+                vec![
+                    doc! { "start_time": { "$lt": start_time.clone() } },
+                    doc! { "start_time": start_time, "_id": { "$lt": id } },
+                    doc! { "start_time": null },
+                ]
+            },
+            None => vec![doc! { "start_time": null, "_id": { "$lt": id } }],
+        };
+
+        Ok(doc! { "$or": condition_list })
+    }
+
+    /// data_list を新しい順 (開始時刻が同じなら ID 順) に並べる
+    pub fn sort_list(data_list: &mut Vec<SmashbrosData>) {
+        data_list.sort_by(|a, b| Self::from_data(b).cmp(&Self::from_data(a)));
+    }
+}
+
+/// aggregate_stats で勝敗数をまとめる単位
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BattleStatsGroup {
+    /// 全体で 1 つ
+    All,
+    /// 自分 (1P) のキャラクター別
+    Character,
+    /// 相手 (2P) のキャラクター別
+    Opponent,
+    /// 自分と相手のキャラクターの組み合わせ別
+    Matchup,
+    /// タグ別 (複数のタグが付いた試合はそれぞれに数えて、タグの無い試合は数えない)
+    Tag,
+}
+
+/// aggregate_stats で集計した勝敗数 (まとめる単位に使わないキャラクターとタグは空)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BattleStats {
+    pub character: String,
+    pub opponent: String,
+    pub tag: String,
+    pub win: i32,
+    pub lose: i32,
+}
+impl BattleStats {
+    /// group でまとめる単位に使うキャラクターとタグだけを持ったものを作る
+    pub fn new(group: BattleStatsGroup, character: String, opponent: String, tag: String) -> Self {
+        let tag = if BattleStatsGroup::Tag == group { tag } else { String::new() };
+        let (character, opponent) = match group {
+            BattleStatsGroup::All | BattleStatsGroup::Tag => (String::new(), String::new()),
+            BattleStatsGroup::Character => (character, String::new()),
+            BattleStatsGroup::Opponent => (String::new(), opponent),
+            BattleStatsGroup::Matchup => (character, opponent),
+        };
+
+        Self { character, opponent, tag, win: 0, lose: 0 }
+    }
+
+    pub fn get_battle_count(&self) -> i32 {
+        self.win + self.lose
+    }
+
+    /// 勝率 (試合が無ければ 0)
+    pub fn get_win_rate(&self) -> f32 {
+        if 0 == self.get_battle_count() {
+            return 0.0;
+        }
+
+        self.win as f32 / self.get_battle_count() as f32
+    }
+
+    /// 試合数の多い順 (同じならキャラクター名, タグ名順) に並べる
+    pub fn sort_list(stats_list: &mut Vec<Self>) {
+        stats_list.sort_by(|a, b| {
+            b.get_battle_count().cmp(&a.get_battle_count())
+                .then_with(|| a.character.cmp(&b.character))
+                .then_with(|| a.opponent.cmp(&b.opponent))
+                .then_with(|| a.tag.cmp(&b.tag))
+        });
+    }
+}
+
+/// 戦歴を絞り込む条件 (None のものは絞り込まない)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BattleHistoryFilter {
+    /// 自分 (1P) のキャラクター
+    pub character: Option<String>,
+    /// 相手 (2P) のキャラクター
+    pub opponent: Option<String>,
+    pub rule: Option<BattleRule>,
+    /// この時刻以降に始まった試合
+    pub since: Option<chrono::DateTime<chrono::Local>>,
+    /// この時刻より前に始まった試合
+    pub until: Option<chrono::DateTime<chrono::Local>>,
+    /// 手動で入力した試合だけ (true) か、キャプチャした試合だけ (false)
+    pub manual_entry: Option<bool>,
+    /// この全てのタグが付いた試合 (空なら絞り込まない)
+    pub tag_list: Vec<String>,
+    /// 自分 (1P) の最大ストック数
+    pub max_stock: Option<i32>,
+    /// 勝った試合だけ (true) か、負けた試合だけ (false)
+    pub is_win: Option<bool>,
+    /// 自分 (1P) の世界戦闘力がこれ以上
+    pub min_power: Option<i32>,
+    /// 自分 (1P) の世界戦闘力がこれ以下
+    pub max_power: Option<i32>,
+    pub bgm: Option<String>,
+    pub player_count: Option<i32>,
+}
+impl BattleHistoryFilter {
+    /// character, opponent, rule, since, until, manual_entry, tag (, 区切り),
+    /// max_stock, result (win か lose), min_power, max_power, bgm, player_count の文字列から作る (API のクエリや CLI の引数)
+    /// character, opponent は smashbros_resource で保存されているキャラ名にする
+    /// @return Err(理由) 解釈できない値があった
+    pub fn from_query(query: &HashMap<String, String>, smashbros_resource: &SmashbrosResource) -> Result<Self, String> {
+        use std::str::FromStr;
+        let rule = match query.get("rule") {
+            // from_str は知らない名前を Unknown にするので、明示的に Unknown を指定された時だけ通す
+            Some(rule_name) => match BattleRule::from_str(rule_name) {
+                Ok(BattleRule::Unknown) if rule_name != "Unknown" => return Err(format!("{} is not a rule.", rule_name)),
+                Ok(rule) => Some(rule),
+                Err(_) => return Err(format!("{} is not a rule.", rule_name)),
+            },
+            None => None,
+        };
+        let parse_date_time = |key: &str| match query.get(key) {
+            Some(value) => Self::parse_date_time(value)
+                .map(Some)
+                .ok_or(format!("{} is not a date (YYYY-MM-DD or RFC3339).", key)),
+            None => Ok(None),
+        };
+        let manual_entry = match query.get("manual_entry").map(|value| value.as_str()) {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            Some(value) => return Err(format!("{} is not a bool (true or false).", value)),
+            None => None,
+        };
+        let tag_list = match query.get("tag") {
+            Some(tag_list) => Self::parse_tag_list(tag_list),
+            None => Vec::new(),
+        };
+        let is_win = match query.get("result").map(|value| value.as_str()) {
+            Some("win") => Some(true),
+            Some("lose") => Some(false),
+            Some(value) => return Err(format!("{} is not a result (win or lose).", value)),
+            None => None,
+        };
+        let parse_number = |key: &str| match query.get(key) {
+            Some(value) => value.parse::<i32>()
+                .map(Some)
+                .map_err(|_| format!("{} is not a number.", key)),
+            None => Ok(None),
+        };
+
+        let normalize_character = |key: &str| query.get(key)
+            .map(|chara_name| Self::normalize_character(chara_name, smashbros_resource));
+
+        Ok(Self {
+            character: normalize_character("character"),
+            opponent: normalize_character("opponent"),
+            rule,
+            since: parse_date_time("since")?,
+            until: parse_date_time("until")?,
+            manual_entry,
+            tag_list,
+            max_stock: parse_number("max_stock")?,
+            is_win,
+            min_power: parse_number("min_power")?,
+            max_power: parse_number("max_power")?,
+            bgm: query.get("bgm").cloned(),
+            player_count: parse_number("player_count")?,
+        })
+    }
+
+    /// 入力されたキャラ名 (公式英名, 公式名, 各言語名) を保存されているキャラ名にする
+    /// 推測しかできなかったものは入力のままにする (推測したキャラで絞り込まないように)
+    pub fn normalize_character(chara_name: &str, smashbros_resource: &SmashbrosResource) -> String {
+        match smashbros_resource.convert_character_name(chara_name.trim().to_uppercase()) {
+            Some((converted_name, ratio)) if 1.0 <= ratio => smashbros_resource.normalize_character_name(&converted_name),
+            _ => chara_name.to_string(),
+        }
+    }
+
+    /// , 区切りのタグを分ける (前後の空白と空のものは除く)
+    pub fn parse_tag_list(value: &str) -> Vec<String> {
+        value.split(',')
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| tag.to_string())
+            .collect()
+    }
+
+    /// YYYY-MM-DD (その日の 0 時) か RFC3339 の日時
+    pub fn parse_date_time(value: &str) -> Option<chrono::DateTime<chrono::Local>> {
+        use chrono::TimeZone;
+        if let Ok(date_time) = chrono::DateTime::parse_from_rfc3339(value) {
+            return Some( date_time.with_timezone(&chrono::Local) );
+        }
+
+        chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .and_then(|date_time| chrono::Local.from_local_datetime(&date_time).single())
+    }
+
+    /// MongoDB の検索条件にする (ゴミ箱の戦歴は含まない)
+    pub fn to_document(&self) -> Document {
+        let mut filter = Document::new();
+        filter.insert("deleted_time", bson::Bson::Null);
+        if let Some(character) = &self.character {
+            filter.insert("chara_list.0", character.clone());
+        }
+        if let Some(opponent) = &self.opponent {
+            filter.insert("chara_list.1", opponent.clone());
+        }
+        if let Some(rule) = &self.rule {
+            filter.insert("rule_name", format!("{:?}", rule));
+        }
+        if let Some(manual_entry) = self.manual_entry {
+            // is_manual_entry が無い (schema_version 3 までの) ものはキャプチャした試合
+            if manual_entry {
+                filter.insert("is_manual_entry", true);
+            } else {
+                filter.insert("is_manual_entry", mongodb::bson::doc! { "$ne": true });
+            }
+        }
+        if !self.tag_list.is_empty() {
+            filter.insert("tag_list", mongodb::bson::doc! { "$all": self.tag_list.clone() });
+        }
+        if let Some(max_stock) = self.max_stock {
+            filter.insert("max_stock_list.0", max_stock);
+        }
+        if let Some(is_win) = self.is_win {
+            let (my_order, other_order) = Self::order_expression();
+            let mut compare_order = Document::new();
+            compare_order.insert(if is_win { "$lt" } else { "$gt" }, vec![my_order, other_order]);
+            let mut condition_list = Self::decided_order_condition_list();
+            condition_list.push(compare_order);
+            filter.insert("$expr", mongodb::bson::doc! { "$and": condition_list });
+        }
+        let mut power = Document::new();
+        if let Some(min_power) = self.min_power {
+            power.insert("$gte", min_power);
+        }
+        if let Some(max_power) = self.max_power {
+            power.insert("$lte", max_power);
+        }
+        if !power.is_empty() {
+            filter.insert("power_list.0", power);
+        }
+        if let Some(bgm) = &self.bgm {
+            filter.insert("bgm_name", bgm.clone());
+        }
+        if let Some(player_count) = self.player_count {
+            filter.insert("player_count", player_count);
+        }
+
+        // start_time は BSON の日時 (UTC) で保存されている
+        let mut start_time = Document::new();
+        if let Some(since) = &self.since {
+            start_time.insert("$gte", bson::Bson::DateTime(since.with_timezone(&chrono::Utc)));
+        }
+        if let Some(until) = &self.until {
+            start_time.insert("$lt", bson::Bson::DateTime(until.with_timezone(&chrono::Utc)));
+        }
+        if !start_time.is_empty() {
+            filter.insert("start_time", start_time);
+        }
+
+        filter
+    }
+
+    /// 集計式で使う (自分 (1P) の順位, 相手 (2P) の順位)
+    pub fn order_expression() -> (Document, Document) {
+        (
+            mongodb::bson::doc! { "$arrayElemAt": ["$order_list", 0] },
+            mongodb::bson::doc! { "$arrayElemAt": ["$order_list", 1] },
+        )
+    }
+
+    /// 2 人の順位が決まっている $expr の条件 (SmashbrosData::is_win と同じく、これに加えて順位が別々のものだけ勝敗がある)
+    pub fn decided_order_condition_list() -> Vec<Document> {
+        let (my_order, other_order) = Self::order_expression();
+
+        vec![
+            mongodb::bson::doc! { "$eq": ["$player_count", 2] },
+            mongodb::bson::doc! { "$ne": [my_order, -1] },
+            mongodb::bson::doc! { "$ne": [other_order, -1] },
+        ]
+    }
+
+    /// data が条件に合うか (ゴミ箱の戦歴は合わない)
+    pub fn is_match(&self, data: &SmashbrosData) -> bool {
+        use crate::data::SmashbrosDataTrait;
+        if data.is_deleted() {
+            return false;
+        }
+        if let Some(character) = &self.character {
+            if &data.get_character(0) != character {
+                return false;
+            }
+        }
+        if let Some(opponent) = &self.opponent {
+            if &data.get_character(1) != opponent {
+                return false;
+            }
+        }
+        if let Some(rule) = &self.rule {
+            if &data.get_rule() != rule {
+                return false;
+            }
+        }
+        if let Some(manual_entry) = self.manual_entry {
+            if data.is_manual_entry() != manual_entry {
+                return false;
+            }
+        }
+        if !self.tag_list.iter().all(|tag| data.has_tag(tag)) {
+            return false;
+        }
+        if self.max_stock.map_or(false, |max_stock| data.get_max_stock(0) != max_stock) {
+            return false;
+        }
+        if self.is_win.is_some() && data.is_win() != self.is_win {
+            return false;
+        }
+        if self.min_power.map_or(false, |min_power| data.get_power(0) < min_power) || self.max_power.map_or(false, |max_power| max_power < data.get_power(0)) {
+            return false;
+        }
+        if self.bgm.as_ref().map_or(false, |bgm| &data.get_bgm_name() != bgm) {
+            return false;
+        }
+        if self.player_count.map_or(false, |player_count| data.get_player_count() != player_count) {
+            return false;
+        }
+        if self.since.is_some() || self.until.is_some() {
+            let start_time = match data.get_start_time() {
+                Some(start_time) => start_time,
+                None => return false,
+            };
+            if self.since.map_or(false, |since| start_time < since) || self.until.map_or(false, |until| until <= start_time) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
 /// 戦歴を管理するクラス
 pub struct BattleHistory {
     db_client: Client,
@@ -148,10 +560,31 @@ impl AsRef<BattleHistory> for BattleHistory {
     fn as_ref(&self) -> &Self { self }
 }
 impl BattleHistory {
+    // BSON の日時で保存するフィールド
+    const DATE_TIME_FIELD_LIST: [&'static str; 3] = ["start_time", "end_time", "deleted_time"];
+    // migration_col に済んだ印を残す、日時の変換の名前
+    const DATE_TIME_MIGRATION: &'static str = "date_time";
+
     pub fn new() -> Self {
-        Self {
+        let mut battle_history = Self {
             db_client: Self::get_client(),
+        };
+        // 日時の変換は戦歴を全て調べるので、済んでいたら起動の度にはしない
+        match battle_history.is_migrated(Self::DATE_TIME_MIGRATION) {
+            Ok(true) => (),
+            Ok(false) => match battle_history.migrate_date_time() {
+                Ok(count) => {
+                    log::info!("migrated date time of {} battles.", count);
+                    if let Err(e) = battle_history.set_migrated(Self::DATE_TIME_MIGRATION) {
+                        log::error!("failed save migration {}. {}", Self::DATE_TIME_MIGRATION, e);
+                    }
+                },
+                Err(e) => log::error!("failed migrate date time. {}", e),
+            },
+            Err(e) => log::error!("failed find migration {}. {}", Self::DATE_TIME_MIGRATION, e),
         }
+
+        battle_history
     }
 
     // DB への接続のための Client を返す
@@ -164,6 +597,141 @@ impl BattleHistory {
         Client::with_options(options).expect("Failed connecting to MongoDB")
     }
 
+    // SmashbrosData を DB に保存するドキュメントにする (日時は BSON の日時にする)
+    fn data_to_document(data: &SmashbrosData) -> Option<Document> {
+        let mut document = match bson::to_bson(data) {
+            Ok(bson::Bson::Document(document)) => document,
+            result => {
+                log::error!("failed serialize data {:?}. {:?}", data, result);
+                return None;
+            },
+        };
+        for field in &Self::DATE_TIME_FIELD_LIST {
+            let date_time = match document.get_str(field) {
+                Ok(value) => SmashbrosData::parse_date_time(value).ok(),
+                Err(_) => None,
+            };
+            if let Some(date_time) = date_time {
+                document.insert(*field, bson::Bson::DateTime(date_time.with_timezone(&chrono::Utc)));
+            }
+        }
+
+        Some(document)
+    }
+
+    // DB のドキュメントを SmashbrosData にする
+    fn document_to_data(mut document: Document) -> Result<SmashbrosData, bson::de::Error> {
+        for field in &Self::DATE_TIME_FIELD_LIST {
+            let date_time = match document.get_datetime(field) {
+                Ok(date_time) => date_time.with_timezone(&chrono::Local),
+                Err(_) => continue,
+            };
+            document.insert(*field, SmashbrosData::date_time_to_string(&date_time));
+        }
+
+        bson::from_bson(bson::Bson::Document(document))
+    }
+
+    /// migration_col に name の変換が済んだ印があるかどうか
+    pub fn is_migrated(&mut self, name: &str) -> anyhow::Result<bool> {
+        use mongodb::bson::doc;
+        let database = self.db_client.database("smabrog-db");
+        let collection_ref = database.collection("migration_col").clone();
+
+        let document = async_std::task::block_on(async {
+            async_std::future::timeout(
+                std::time::Duration::from_secs(5),
+                collection_ref.find_one(doc! { "_id": name }, None)
+            ).await
+        })??;
+
+        Ok(document.is_some())
+    }
+
+    /// migration_col に name の変換が済んだ印を残す
+    pub fn set_migrated(&mut self, name: &str) -> anyhow::Result<()> {
+        use mongodb::bson::doc;
+        let database = self.db_client.database("smabrog-db");
+        let collection_ref = database.collection("migration_col").clone();
+
+        let migrated_time = bson::Bson::DateTime(chrono::Utc::now());
+        async_std::task::block_on(async {
+            async_std::future::timeout(
+                std::time::Duration::from_secs(5),
+                collection_ref.update_one(
+                    doc! { "_id": name },
+                    UpdateModifications::Document(doc! { "$set": { "migrated_time": migrated_time } }),
+                    mongodb::options::UpdateOptions::builder().upsert(true).build()
+                )
+            ).await
+        })??;
+
+        Ok(())
+    }
+
+    /// 以前の形式 ({:?} の文字列) で保存されている日時を BSON の日時にして、変換した数を返す
+    /// (BattleHistory::new で、migration_col に済んだ印が無い時だけ呼ばれる)
+    pub fn migrate_date_time(&mut self) -> anyhow::Result<usize> {
+        use async_std::prelude::*;
+        use mongodb::bson::doc;
+        let database = self.db_client.database("smabrog-db");
+        let collection_ref = database.collection("battle_data_col").clone();
+
+        let filter_list = Self::DATE_TIME_FIELD_LIST.iter()
+            .map(|field| {
+                let mut filter = Document::new();
+                filter.insert(*field, doc! { "$type": "string" });
+                bson::Bson::Document(filter)
+            })
+            .collect::<Vec<_>>();
+        let mut cursor = async_std::task::block_on(async {
+            async_std::future::timeout(
+                std::time::Duration::from_secs(5),
+                collection_ref.find(doc! { "$or": filter_list }, None)
+            ).await
+        })??;
+
+        let mut count = 0;
+        while let Some(document) = async_std::task::block_on(async{ cursor.next().await }) {
+            let document = document?;
+            let id = match document.get_object_id("_id") {
+                Ok(id) => id.clone(),
+                Err(_) => continue,
+            };
+
+            let mut set_document = Document::new();
+            for field in &Self::DATE_TIME_FIELD_LIST {
+                let value = match document.get_str(field) {
+                    Ok(value) => value,
+                    Err(_) => continue,
+                };
+                match SmashbrosData::parse_date_time(value) {
+                    Ok(date_time) => {
+                        set_document.insert(*field, bson::Bson::DateTime(date_time.with_timezone(&chrono::Utc)));
+                    },
+                    Err(e) => log::error!("[migrate err] failed parse {} {:?} of {}. {}", field, value, id.to_hex(), e),
+                }
+            }
+            if set_document.is_empty() {
+                continue;
+            }
+
+            async_std::task::block_on(async {
+                async_std::future::timeout(
+                    std::time::Duration::from_secs(5),
+                    collection_ref.update_one(
+                        doc!{ "_id": id },
+                        UpdateModifications::Document(doc! { "$set": set_document }),
+                        None
+                    )
+                ).await
+            })??;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
     /// コレクションから検索して返す
     pub fn find_data(&mut self, filter: Option<Document>, find_options: FindOptions) -> Option<Vec<SmashbrosData>> {
         let database = self.db_client.database("smabrog-db");
@@ -187,8 +755,12 @@ impl BattleHistory {
         use async_std::prelude::*;
         let mut data_list: Vec<SmashbrosData> = Vec::new();
         while let Some(document) = async_std::task::block_on(async{ cursor.next().await }) {
-            let data: SmashbrosData = bson::from_bson(bson::Bson::Document(document.unwrap())).unwrap();
-            data_list.push(data);
+            let document = document.unwrap();
+            let id = document.get_object_id("_id").map(|id| id.to_hex()).unwrap_or_default();
+            match Self::document_to_data(document) {
+                Ok(data) => data_list.push(data),
+                Err(e) => log::error!("[find err] failed parse data {}. {}", id, e),
+            }
         }
         
         Some(data_list)
@@ -198,8 +770,7 @@ impl BattleHistory {
     pub fn insert_data(&mut self, data: &SmashbrosData) -> Option<String> {
         let database = self.db_client.database("smabrog-db");
         let collection_ref = database.collection("battle_data_col").clone();
-        let serialized_data = bson::to_bson(data).unwrap();
-        let data_document = serialized_data.as_document().unwrap();
+        let data_document = Self::data_to_document(data)?;
 
         // mongodb のポインタ的なものをもらう
         let result = match async_std::task::block_on(async {
@@ -233,8 +804,7 @@ impl BattleHistory {
 
         let database = self.db_client.database("smabrog-db");
         let collection_ref = database.collection("battle_data_col").clone();
-        let serialized_data = bson::to_bson(data).unwrap();
-        let data_document = serialized_data.as_document().unwrap();
+        let data_document = Self::data_to_document(data)?;
 
         match async_std::task::block_on(async {
             async_std::future::timeout(
@@ -261,15 +831,69 @@ impl BattleHistory {
         return None;
     }
 
-    /// コレクションから戦歴情報を削除
+    /// コレクションの戦歴情報をゴミ箱に入れる
     pub fn delete_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()> {
+        self.update_deleted_time(data, Some(chrono::Local::now()))
+    }
+
+    /// ゴミ箱に入れた戦歴情報を元に戻す
+    pub fn restore_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()> {
+        self.update_deleted_time(data, None)
+    }
+
+    // deleted_time だけを更新する
+    fn update_deleted_time(&mut self, data: &SmashbrosData, deleted_time: Option<chrono::DateTime<chrono::Local>>) -> anyhow::Result<()> {
+        use mongodb::bson::doc;
+        use crate::data::SmashbrosDataTrait;
+        let id = match data.get_id() {
+            Some(id) => id,
+            None => {
+                log::error!("[delete err] failed update deleted_time. id is None.");
+                return Err(anyhow::anyhow!("failed update deleted_time. id is None."));
+            },
+        };
+
+        let database = self.db_client.database("smabrog-db");
+        let collection_ref = database.collection("battle_data_col").clone();
+        let deleted_time = match deleted_time {
+            Some(deleted_time) => bson::Bson::DateTime(deleted_time.with_timezone(&chrono::Utc)),
+            None => bson::Bson::Null,
+        };
+
+        match async_std::task::block_on(async {
+            async_std::future::timeout(
+                std::time::Duration::from_secs(5),
+                collection_ref.update_one(
+                    doc!{ "_id": mongodb::bson::oid::ObjectId::with_string(&id).unwrap() },
+                    UpdateModifications::Document(doc! { "$set": { "deleted_time": deleted_time } }),
+                    None
+                )
+            ).await
+        }) {
+            Ok(result) => {
+                if 1 == result.as_ref().ok().unwrap().matched_count {
+                    return Ok(());
+                }
+                log::error!("[delete err] failed update deleted_time {:?}.\ndata: [{:?}]", result, data);
+            },
+            Err(_e) => {    // async_std::future::TimeoutError( _private: () )
+                log::error!("update timeout. please restart smabrog.");
+                return Err(anyhow::anyhow!("update timeout. please restart smabrog."));
+            },
+        }
+
+        Err(anyhow::anyhow!("failed update deleted_time."))
+    }
+
+    /// コレクションから戦歴情報を完全に削除
+    pub fn purge_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()> {
         use mongodb::bson::doc;
         use crate::data::SmashbrosDataTrait;
         let id = match data.get_id() {
             Some(id) => id,
             None => {
-                log::error!("[delete err] failed delete_data. id is None.");
-                return Err(anyhow::anyhow!("failed delete_data. id is None."));
+                log::error!("[delete err] failed purge_data. id is None.");
+                return Err(anyhow::anyhow!("failed purge_data. id is None."));
             },
         };
 
@@ -300,12 +924,17 @@ impl BattleHistory {
         Err(anyhow::anyhow!("failed delete data."))
     }
 
+    // 戦歴を新しい順 (開始時刻が同じなら ID 順) に並べる sort (BattleHistoryCursor と同じ順)
+    fn newest_first_sort() -> Document {
+        mongodb::bson::doc! { "start_time": -1, "_id": -1 }
+    }
+
     /// battle_data コレクションから戦歴情報を 直近 result_max 件 取得
     pub fn find_data_limit(&mut self, result_max: i64) -> Option<Vec<SmashbrosData>> {
         self.find_data(
-            None,
+            Some(mongodb::bson::doc! { "deleted_time": null }),
             FindOptions::builder()
-                .sort(mongodb::bson::doc! { "_id": -1 })
+                .sort(Self::newest_first_sort())
                 .limit(result_max)
                 .build()
         )
@@ -315,50 +944,432 @@ impl BattleHistory {
     pub fn find_data_by_chara_list(&mut self, character_list: Vec<String>, limit: i64, use_in: bool) -> Option<Vec<SmashbrosData>> {
         use mongodb::bson::doc;
         let filter = if use_in {
-            doc! { "chara_list": {"$in": character_list } }
+            doc! { "chara_list": {"$in": character_list }, "deleted_time": null }
         } else {
-            doc! { "chara_list": character_list }
+            doc! { "chara_list": character_list, "deleted_time": null }
         };
 
         self.find_data(
             Some(filter),
             FindOptions::builder()
-                .sort(doc! { "_id": -1 })
+                .sort(Self::newest_first_sort())
+                .limit(limit)
+                .build()
+        )
+    }
+
+    /// filter に合う戦歴を新しい順に skip 件飛ばして limit 件取得
+    pub fn find_data_by_filter(&mut self, filter: &BattleHistoryFilter, skip: i64, limit: i64) -> Option<Vec<SmashbrosData>> {
+        self.find_data(
+            Some(filter.to_document()),
+            FindOptions::builder()
+                .sort(Self::newest_first_sort())
+                .skip(skip)
                 .limit(limit)
                 .build()
         )
     }
+
+    /// filter に合う戦歴を新しい順に、cursor (ObjectId) より古いものから limit 件取得
+    pub fn find_data_by_cursor(&mut self, filter: &BattleHistoryFilter, cursor: Option<String>, limit: i64) -> Option<BattleHistoryPage> {
+        use mongodb::bson::doc;
+        let mut filter_document = filter.to_document();
+        if let Some(cursor) = &cursor {
+            let cursor_document = match BattleHistoryCursor::parse(cursor).map_err(anyhow::Error::msg).and_then(|cursor| cursor.to_document()) {
+                Ok(cursor_document) => cursor_document,
+                Err(e) => {
+                    log::error!("[find err] invalid cursor {}. {}", cursor, e);
+                    return None;
+                },
+            };
+            // filter の条件と混ざらないように $and でつなぐ
+            filter_document = doc! { "$and": [filter_document, cursor_document] };
+        }
+
+        // 次のページがあるかを知る為に 1 件多く取る
+        let data_list = self.find_data(
+            Some(filter_document),
+            FindOptions::builder()
+                .sort(Self::newest_first_sort())
+                .limit(limit.max(0) + 1)
+                .build()
+        )?;
+
+        Some(BattleHistoryPage::new(data_list, limit))
+    }
+
+    /// battle_data コレクションの戦歴に付いているタグを名前順に取得
+    pub fn find_tag_list(&mut self) -> Option<Vec<String>> {
+        let database = self.db_client.database("smabrog-db");
+        let collection_ref = database.collection("battle_data_col").clone();
+
+        let value_list = match async_std::task::block_on(async {
+            async_std::future::timeout(
+                std::time::Duration::from_secs(5),
+                collection_ref.distinct("tag_list", mongodb::bson::doc! { "deleted_time": null }, None)
+            ).await
+        }) {
+            Ok(Ok(value_list)) => value_list,
+            Ok(Err(e)) => {
+                log::error!("[find err] failed find tag_list. {}", e);
+                return None;
+            },
+            Err(_e) => {    // async_std::future::TimeoutError( _private: () )
+                log::error!("find timeout. please restart smabrog.");
+                return None;
+            },
+        };
+
+        let mut tag_list: Vec<String> = value_list.iter()
+            .filter_map(|value| value.as_str().map(|tag| tag.to_string()))
+            .collect();
+        tag_list.sort();
+
+        Some(tag_list)
+    }
+
+    /// battle_data コレクションからゴミ箱の戦歴を新しく入れた順に limit 件取得
+    pub fn find_deleted_data(&mut self, limit: i64) -> Option<Vec<SmashbrosData>> {
+        use mongodb::bson::doc;
+        self.find_data(
+            Some(doc! { "deleted_time": { "$ne": null } }),
+            FindOptions::builder()
+                .sort(doc! { "deleted_time": -1 })
+                .limit(limit)
+                .build()
+        )
+    }
+
+    /// battle_data コレクションから until より前にゴミ箱に入れた戦歴を完全に削除
+    pub fn purge_deleted_data(&mut self, until: chrono::DateTime<chrono::Local>) -> anyhow::Result<usize> {
+        use mongodb::bson::doc;
+        let database = self.db_client.database("smabrog-db");
+        let collection_ref = database.collection("battle_data_col").clone();
+
+        let result = async_std::task::block_on(async {
+            async_std::future::timeout(
+                std::time::Duration::from_secs(5),
+                collection_ref.delete_many(doc! { "deleted_time": { "$lt": bson::Bson::DateTime(until.with_timezone(&chrono::Utc)) } }, None)
+            ).await
+        })??;
+
+        Ok(result.deleted_count as usize)
+    }
+
+    /// aggregate_stats で battle_data コレクションに渡す pipeline
+    /// Tag では tag_list を $unwind して、タグ 1 つずつの試合としてまとめる
+    pub fn aggregate_stats_pipeline(filter: &BattleHistoryFilter, group: BattleStatsGroup) -> Vec<Document> {
+        use mongodb::bson::doc;
+        let (my_order, other_order) = BattleHistoryFilter::order_expression();
+        let character = doc! { "$arrayElemAt": ["$chara_list", 0] };
+        let opponent = doc! { "$arrayElemAt": ["$chara_list", 1] };
+        let group_id = match group {
+            BattleStatsGroup::All => bson::Bson::Null,
+            BattleStatsGroup::Character => doc! { "character": character }.into(),
+            BattleStatsGroup::Opponent => doc! { "opponent": opponent }.into(),
+            BattleStatsGroup::Matchup => doc! { "character": character, "opponent": opponent }.into(),
+            BattleStatsGroup::Tag => doc! { "tag": "$tag_list" }.into(),
+        };
+        let mut condition_list = BattleHistoryFilter::decided_order_condition_list();
+        condition_list.push(doc! { "$ne": [my_order.clone(), other_order.clone()] });
+        let mut pipeline = vec![
+            doc! { "$match": filter.to_document() },
+            doc! { "$match": { "$expr": { "$and": condition_list } } },
+        ];
+        if BattleStatsGroup::Tag == group {
+            // タグの無い試合は $unwind で無くなる
+            pipeline.push(doc! { "$unwind": "$tag_list" });
+        }
+        pipeline.push(doc! { "$group": {
+            "_id": group_id,
+            "win": { "$sum": { "$cond": [{ "$lt": [my_order.clone(), other_order.clone()] }, 1, 0] } },
+            "lose": { "$sum": { "$cond": [{ "$gt": [my_order, other_order] }, 1, 0] } }
+        } });
+
+        pipeline
+    }
+
+    /// battle_data コレクションで filter に合う戦歴の勝敗数を group ごとに集計する (戦歴自体は取得しない)
+    pub fn aggregate_stats(&mut self, filter: &BattleHistoryFilter, group: BattleStatsGroup) -> Option<Vec<BattleStats>> {
+        let database = self.db_client.database("smabrog-db");
+        let collection_ref = database.collection("battle_data_col").clone();
+        let pipeline = Self::aggregate_stats_pipeline(filter, group);
+
+        let mut cursor = match async_std::task::block_on(async {
+            async_std::future::timeout(
+                std::time::Duration::from_secs(5),
+                collection_ref.aggregate(pipeline, None)
+            ).await
+        }) {
+            Ok(Ok(cursor)) => cursor,
+            Ok(Err(e)) => {
+                log::error!("[find err] failed aggregate stats. {}", e);
+                return None;
+            },
+            Err(_e) => {    // async_std::future::TimeoutError( _private: () )
+                log::error!("find timeout. please restart smabrog.");
+                return None;
+            },
+        };
+
+        use async_std::prelude::*;
+        let mut stats_list = Vec::new();
+        while let Some(document) = async_std::task::block_on(async{ cursor.next().await }) {
+            let document = match document {
+                Ok(document) => document,
+                Err(e) => {
+                    log::error!("[find err] failed aggregate stats. {}", e);
+                    return None;
+                },
+            };
+            let id = document.get_document("_id").ok();
+            let get_name = |key: &str| id.and_then(|id| id.get_str(key).ok()).unwrap_or_default().to_string();
+            // $sum は数によって Int32 か Int64 になる
+            let get_count = |key: &str| match document.get(key) {
+                Some(bson::Bson::Int32(count)) => *count,
+                Some(bson::Bson::Int64(count)) => *count as i32,
+                _ => 0,
+            };
+            stats_list.push(BattleStats {
+                character: get_name("character"),
+                opponent: get_name("opponent"),
+                tag: get_name("tag"),
+                win: get_count("win"),
+                lose: get_count("lose"),
+            });
+        }
+        BattleStats::sort_list(&mut stats_list);
+
+        Some(stats_list)
+    }
 }
-/// シングルトンでDBを保持するため
-pub struct WrappedBattleHistory {
-    battle_history: Option<BattleHistory>,
+impl BattleHistoryTrait for BattleHistory {
+    fn insert_data(&mut self, data: &SmashbrosData) -> Option<String> { BattleHistory::insert_data(self, data) }
+    fn update_data(&mut self, data: &SmashbrosData) -> Option<String> { BattleHistory::update_data(self, data) }
+    fn delete_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()> { BattleHistory::delete_data(self, data) }
+    fn restore_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()> { BattleHistory::restore_data(self, data) }
+    fn purge_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()> { BattleHistory::purge_data(self, data) }
+    fn find_data_limit(&mut self, result_max: i64) -> Option<Vec<SmashbrosData>> { BattleHistory::find_data_limit(self, result_max) }
+    fn find_data_by_chara_list(&mut self, character_list: Vec<String>, limit: i64, use_in: bool) -> Option<Vec<SmashbrosData>> {
+        BattleHistory::find_data_by_chara_list(self, character_list, limit, use_in)
+    }
+    fn find_data_by_filter(&mut self, filter: &BattleHistoryFilter, skip: i64, limit: i64) -> Option<Vec<SmashbrosData>> {
+        BattleHistory::find_data_by_filter(self, filter, skip, limit)
+    }
+    fn find_data_by_cursor(&mut self, filter: &BattleHistoryFilter, cursor: Option<String>, limit: i64) -> Option<BattleHistoryPage> {
+        BattleHistory::find_data_by_cursor(self, filter, cursor, limit)
+    }
+    fn find_tag_list(&mut self) -> Option<Vec<String>> { BattleHistory::find_tag_list(self) }
+    fn find_deleted_data(&mut self, limit: i64) -> Option<Vec<SmashbrosData>> { BattleHistory::find_deleted_data(self, limit) }
+    fn purge_deleted_data(&mut self, until: chrono::DateTime<chrono::Local>) -> anyhow::Result<usize> {
+        BattleHistory::purge_deleted_data(self, until)
+    }
+    fn aggregate_stats(&mut self, filter: &BattleHistoryFilter, group: BattleStatsGroup) -> Option<Vec<BattleStats>> {
+        BattleHistory::aggregate_stats(self, filter, group)
+    }
 }
-impl WrappedBattleHistory {
-    // 参照して返さないと、unwrap() で move 違反がおきてちぬ！
-    pub fn get(&mut self) -> &BattleHistory {
-        if self.battle_history.is_none() {
-            self.battle_history = Some(BattleHistory::new());
-        }
-        self.battle_history.as_ref().unwrap()
+
+/// メモリ上だけで戦歴を管理するクラス (テストやリプレイ用)
+/// clone したものとはデータを共有する
+#[derive(Clone, Default)]
+pub struct InMemoryBattleHistory {
+    data_list: std::sync::Arc<std::sync::Mutex<Vec<SmashbrosData>>>,
+}
+impl InMemoryBattleHistory {
+    pub fn new() -> Self { Self::default() }
+
+    /// 保存された戦歴情報を保存された順に返す (ゴミ箱の戦歴も含む)
+    pub fn get_data_list(&self) -> Vec<SmashbrosData> {
+        self.data_list.lock().unwrap().clone()
+    }
+
+    // 保存された戦歴情報を DB と同じく新しい順 (開始時刻が同じなら ID 順) に返す
+    fn get_newest_first_list(&self) -> Vec<SmashbrosData> {
+        let mut data_list = self.get_data_list();
+        BattleHistoryCursor::sort_list(&mut data_list);
+
+        data_list
     }
 
-    // mut 版
-    pub fn get_mut(&mut self) -> &mut BattleHistory {
-        if self.battle_history.is_none() {
-            self.battle_history = Some(BattleHistory::new());
+    // 保存された戦歴の deleted_time だけを更新する
+    fn update_deleted_time(&mut self, data: &SmashbrosData, deleted_time: Option<chrono::DateTime<chrono::Local>>) -> anyhow::Result<()> {
+        use crate::data::SmashbrosDataTrait;
+        let id = match data.get_id() {
+            Some(id) => id,
+            None => return Err(anyhow::anyhow!("failed update deleted_time. id is None.")),
+        };
+        let mut data_list = self.data_list.lock().unwrap();
+        match data_list.iter_mut().find(|saved_data| saved_data.get_id().as_ref() == Some(&id)) {
+            Some(saved_data) => saved_data.set_deleted_time(deleted_time),
+            None => return Err(anyhow::anyhow!("failed update deleted_time.")),
         }
-        self.battle_history.as_mut().unwrap()
+
+        Ok(())
     }
 }
-static mut _BATTLE_HISTORY: WrappedBattleHistory = WrappedBattleHistory {
-    battle_history: None,
-};
-#[allow(non_snake_case)]
-pub fn BATTLE_HISTORY() -> &'static mut WrappedBattleHistory {
-    unsafe { &mut _BATTLE_HISTORY }
+impl BattleHistoryTrait for InMemoryBattleHistory {
+    fn insert_data(&mut self, data: &SmashbrosData) -> Option<String> {
+        use crate::data::SmashbrosDataTrait;
+        // DB と同じ形式の ID にしておく
+        let id = bson::oid::ObjectId::new().to_hex();
+        let mut data = data.clone();
+        data.set_id(Some(id.clone()));
+        self.data_list.lock().unwrap().push(data);
+
+        Some(id)
+    }
+
+    fn update_data(&mut self, data: &SmashbrosData) -> Option<String> {
+        use crate::data::SmashbrosDataTrait;
+        let id = data.get_id()?;
+        let mut data_list = self.data_list.lock().unwrap();
+        let saved_data = data_list.iter_mut().find(|saved_data| saved_data.get_id().as_ref() == Some(&id))?;
+        *saved_data = data.clone();
+
+        Some(id)
+    }
+
+    fn delete_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()> {
+        self.update_deleted_time(data, Some(chrono::Local::now()))
+    }
+
+    fn restore_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()> {
+        self.update_deleted_time(data, None)
+    }
+
+    fn purge_data(&mut self, data: &SmashbrosData) -> anyhow::Result<()> {
+        use crate::data::SmashbrosDataTrait;
+        let id = match data.get_id() {
+            Some(id) => id,
+            None => return Err(anyhow::anyhow!("failed purge_data. id is None.")),
+        };
+        let mut data_list = self.data_list.lock().unwrap();
+        let index = match data_list.iter().position(|saved_data| saved_data.get_id().as_ref() == Some(&id)) {
+            Some(index) => index,
+            None => return Err(anyhow::anyhow!("failed delete data.")),
+        };
+        data_list.remove(index);
+
+        Ok(())
+    }
+
+    fn find_data_limit(&mut self, result_max: i64) -> Option<Vec<SmashbrosData>> {
+        use crate::data::SmashbrosDataTrait;
+        Some(
+            self.get_newest_first_list().into_iter()
+                .filter(|data| !data.is_deleted())
+                .take(result_max.max(0) as usize)
+                .collect()
+        )
+    }
+
+    fn find_data_by_chara_list(&mut self, character_list: Vec<String>, limit: i64, use_in: bool) -> Option<Vec<SmashbrosData>> {
+        use crate::data::SmashbrosDataTrait;
+        Some(
+            self.get_newest_first_list().into_iter()
+                .filter(|data| !data.is_deleted())
+                .filter(|data| {
+                    let chara_list = (0..data.get_player_count()).map(|player_number| data.get_character(player_number)).collect::<Vec<_>>();
+                    if use_in {
+                        chara_list.iter().any(|chara_name| character_list.contains(chara_name))
+                    } else {
+                        chara_list == character_list
+                    }
+                })
+                .take(limit.max(0) as usize)
+                .collect()
+        )
+    }
+
+    fn find_data_by_filter(&mut self, filter: &BattleHistoryFilter, skip: i64, limit: i64) -> Option<Vec<SmashbrosData>> {
+        Some(
+            self.get_newest_first_list().into_iter()
+                .filter(|data| filter.is_match(data))
+                .skip(skip.max(0) as usize)
+                .take(limit.max(0) as usize)
+                .collect()
+        )
+    }
+
+    fn find_data_by_cursor(&mut self, filter: &BattleHistoryFilter, cursor: Option<String>, limit: i64) -> Option<BattleHistoryPage> {
+        let cursor = match cursor.map(|cursor| BattleHistoryCursor::parse(&cursor)).transpose() {
+            Ok(cursor) => cursor,
+            Err(e) => {
+                log::error!("[find err] invalid cursor. {}", e);
+                return None;
+            },
+        };
+        let data_list = self.get_newest_first_list().into_iter()
+            .filter(|data| cursor.as_ref().map_or(true, |cursor| BattleHistoryCursor::from_data(data).map_or(false, |position| &position < cursor)))
+            .filter(|data| filter.is_match(data))
+            .take(limit.max(0) as usize + 1)
+            .collect();
+
+        Some(BattleHistoryPage::new(data_list, limit))
+    }
+
+    fn find_tag_list(&mut self) -> Option<Vec<String>> {
+        use crate::data::SmashbrosDataTrait;
+        let mut tag_list: Vec<String> = self.data_list.lock().unwrap().iter()
+            .filter(|data| !data.is_deleted())
+            .flat_map(|data| data.get_tag_list())
+            .collect();
+        tag_list.sort();
+        tag_list.dedup();
+
+        Some(tag_list)
+    }
+
+    fn find_deleted_data(&mut self, limit: i64) -> Option<Vec<SmashbrosData>> {
+        use crate::data::SmashbrosDataTrait;
+        let mut data_list: Vec<SmashbrosData> = self.data_list.lock().unwrap().iter()
+            .filter(|data| data.is_deleted())
+            .cloned()
+            .collect();
+        data_list.sort_by(|a, b| b.get_deleted_time().cmp(&a.get_deleted_time()));
+        data_list.truncate(limit.max(0) as usize);
+
+        Some(data_list)
+    }
+
+    fn purge_deleted_data(&mut self, until: chrono::DateTime<chrono::Local>) -> anyhow::Result<usize> {
+        use crate::data::SmashbrosDataTrait;
+        let mut data_list = self.data_list.lock().unwrap();
+        let count = data_list.len();
+        data_list.retain(|data| data.get_deleted_time().map_or(true, |deleted_time| until <= deleted_time));
+
+        Ok(count - data_list.len())
+    }
+
+    fn aggregate_stats(&mut self, filter: &BattleHistoryFilter, group: BattleStatsGroup) -> Option<Vec<BattleStats>> {
+        use crate::data::SmashbrosDataTrait;
+        let mut stats_by_group: HashMap<(String, String, String), BattleStats> = HashMap::new();
+        for data in self.data_list.lock().unwrap().iter().filter(|data| filter.is_match(data)) {
+            let is_win = match data.is_win() {
+                Some(is_win) => is_win,
+                None => continue,
+            };
+            // Tag ではタグ 1 つずつに数える
+            let tag_list = if BattleStatsGroup::Tag == group { data.get_tag_list() } else { vec![String::new()] };
+            for tag in tag_list {
+                let stats = BattleStats::new(group, data.get_character(0), data.get_character(1), tag);
+                let stats = stats_by_group.entry((stats.character.clone(), stats.opponent.clone(), stats.tag.clone())).or_insert(stats);
+                if is_win {
+                    stats.win += 1;
+                } else {
+                    stats.lose += 1;
+                }
+            }
+        }
+        let mut stats_list: Vec<BattleStats> = stats_by_group.into_values().collect();
+        BattleStats::sort_list(&mut stats_list);
+
+        Some(stats_list)
+    }
 }
 
 
+
 pub enum SoundType {
     Bgm, File, Beep,
 }
@@ -543,31 +1554,133 @@ impl SoundManager {
         false
     }
 }
-/// シングルトンで SoundManager を保持するため
-pub struct WrappedSoundManager {
-    sound_manager: Option<SoundManager>,
-}
-impl WrappedSoundManager {
-    // 参照して返さないと、unwrap() で move 違反がおきてちぬ！
-    pub fn get(&mut self) -> &SoundManager {
-        if self.sound_manager.is_none() {
-            self.sound_manager = Some(SoundManager::default());
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::AppContext;
+    use crate::data::SmashbrosDataTrait;
+
+    #[test]
+    fn test_in_memory_battle_history_trash() {
+        let mut battle_history = InMemoryBattleHistory::new();
+        let mut context = AppContext::with_battle_history(Box::new(battle_history.clone()));
+        let mut data = SmashbrosData::default();
+        data.initialize_battle(2, true);
+        data.guess_character_name(0, "MARIO".to_string(), &context.smashbros_resource());
+        data.start_battle();
+        data.finish_battle();
+        data.save_battle_with(&mut context);
+
+        // 削除したものはゴミ箱に入って、検索されなくなる
+        battle_history.delete_data(&data).unwrap();
+        assert!(battle_history.get_data_list()[0].is_deleted());
+        assert!(battle_history.find_data_limit(10).unwrap().is_empty());
+        assert_eq!(battle_history.find_deleted_data(10).unwrap().len(), 1);
+
+        // ゴミ箱から戻せる
+        battle_history.restore_data(&data).unwrap();
+        assert_eq!(battle_history.find_data_limit(10).unwrap().len(), 1);
+
+        // 古くなったゴミ箱の戦歴だけ完全に削除される
+        battle_history.delete_data(&data).unwrap();
+        assert_eq!(battle_history.purge_deleted_data(chrono::Local::now() - chrono::Duration::days(1)).unwrap(), 0);
+        assert_eq!(battle_history.purge_deleted_data(chrono::Local::now() + chrono::Duration::seconds(1)).unwrap(), 1);
+        assert!(battle_history.get_data_list().is_empty());
+    }
+
+    #[test]
+    fn test_battle_history_cursor() {
+        let mut battle_history = InMemoryBattleHistory::new();
+        let now = chrono::Local::now();
+        // 後から手動で入力した古い試合や、開始時刻が同じ試合、開始時刻が未検出の試合が混ざっている
+        for (rule, start_time) in [
+            (BattleRule::Stock, Some(now)),
+            (BattleRule::Time, None),
+            (BattleRule::Stamina, Some(now - chrono::Duration::days(1))),
+            (BattleRule::Tournament, Some(now)),
+        ] {
+            let mut data = SmashbrosData::default();
+            data.initialize_battle(2, true);
+            data.set_rule(rule);
+            data.set_start_time(start_time);
+            battle_history.insert_data(&data);
         }
-        self.sound_manager.as_ref().unwrap()
+
+        // 開始時刻の新しい順 (同じなら後に保存した順) で、未検出は一番古い
+        let rule_list = |data_list: &Vec<SmashbrosData>| data_list.iter().map(|data| data.get_rule()).collect::<Vec<_>>();
+        let data_list = battle_history.find_data_limit(10).unwrap();
+        assert_eq!(rule_list(&data_list), vec![BattleRule::Tournament, BattleRule::Stock, BattleRule::Stamina, BattleRule::Time]);
+
+        // cursor で続きを取得しても同じ順になる
+        let mut page_list = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = battle_history.find_data_by_cursor(&BattleHistoryFilter::default(), cursor, 1).unwrap();
+            page_list.extend(page.data_list);
+            cursor = match page.next_cursor {
+                Some(next_cursor) => Some(next_cursor),
+                None => break,
+            };
+        }
+        assert_eq!(rule_list(&page_list), rule_list(&data_list));
+
+        // cursor は ID と開始時刻の組で、解釈できないものは取得しない
+        let cursor = BattleHistoryCursor::from_data(&data_list[0]).unwrap();
+        assert_eq!(BattleHistoryCursor::parse(&cursor.to_string()), Ok(cursor.clone()));
+        assert!(cursor.to_document().unwrap().get_array("$or").unwrap().len() == 3);
+        let cursor = BattleHistoryCursor::from_data(&data_list[3]).unwrap();
+        assert_eq!(BattleHistoryCursor::parse(&cursor.to_string()), Ok(cursor.clone()));
+        assert!(cursor.to_document().unwrap().get_array("$or").unwrap().len() == 1);
+        assert!(BattleHistoryCursor::parse("abc").is_err());
+        assert!(battle_history.find_data_by_cursor(&BattleHistoryFilter::default(), Some(format!("{}:yesterday", cursor.id)), 1).is_none());
+    }
+
+    #[test]
+    fn test_aggregate_stats_pipeline() {
+        let filter = BattleHistoryFilter { character: Some("MARIO".to_string()), ..Default::default() };
+        let get_stage_list = |pipeline: &Vec<Document>| pipeline.iter()
+            .map(|stage| stage.keys().next().unwrap().clone())
+            .collect::<Vec<_>>();
+
+        // 絞り込んでから、順位の決まった試合だけをまとめる
+        let pipeline = BattleHistory::aggregate_stats_pipeline(&filter, BattleStatsGroup::All);
+        assert_eq!(get_stage_list(&pipeline), vec!["$match", "$match", "$group"]);
+        assert_eq!(pipeline[0].get_document("$match").unwrap(), &filter.to_document());
+        let group = pipeline[2].get_document("$group").unwrap();
+        assert_eq!(group.get("_id"), Some(&bson::Bson::Null));
+        assert!(group.contains_key("win") && group.contains_key("lose"));
+
+        let pipeline = BattleHistory::aggregate_stats_pipeline(&filter, BattleStatsGroup::Matchup);
+        let group_id = pipeline[2].get_document("$group").unwrap().get_document("_id").unwrap();
+        assert_eq!(group_id.keys().collect::<Vec<_>>(), vec!["character", "opponent"]);
+
+        // タグ別は tag_list を $unwind してからまとめる
+        let pipeline = BattleHistory::aggregate_stats_pipeline(&filter, BattleStatsGroup::Tag);
+        assert_eq!(get_stage_list(&pipeline), vec!["$match", "$match", "$unwind", "$group"]);
+        assert_eq!(pipeline[2].get_str("$unwind").unwrap(), "$tag_list");
+        let group_id = pipeline[3].get_document("$group").unwrap().get_document("_id").unwrap();
+        assert_eq!(group_id.get_str("tag").unwrap(), "$tag_list");
     }
 
-    // mut 版
-    pub fn get_mut(&mut self) -> &mut SoundManager {
-        if self.sound_manager.is_none() {
-            self.sound_manager = Some(SoundManager::default());
+    #[test]
+    fn test_in_memory_aggregate_stats_by_tag() {
+        let mut battle_history = InMemoryBattleHistory::new();
+        for (tag_list, is_win) in [(vec!["laggy", "tilted"], true), (vec!["laggy"], false), (vec![], true)] {
+            let mut data = SmashbrosData::default();
+            data.initialize_battle(2, true);
+            data.set_order(0, if is_win { 1 } else { 2 });
+            data.set_order(1, if is_win { 2 } else { 1 });
+            data.set_tag_list(tag_list.into_iter().map(String::from).collect());
+            battle_history.insert_data(&data);
         }
-        self.sound_manager.as_mut().unwrap()
+
+        // 複数のタグが付いた試合はそれぞれのタグで数えて、タグの無い試合は数えない
+        let stats_list = battle_history.aggregate_stats(&BattleHistoryFilter::default(), BattleStatsGroup::Tag).unwrap();
+        assert_eq!(stats_list.len(), 2);
+        assert_eq!((stats_list[0].tag.as_str(), stats_list[0].win, stats_list[0].lose), ("laggy", 1, 1));
+        assert_eq!((stats_list[1].tag.as_str(), stats_list[1].win, stats_list[1].lose), ("tilted", 1, 0));
+        assert!(stats_list.iter().all(|stats| stats.character.is_empty() && stats.opponent.is_empty()));
     }
 }
-static mut _SOUND_MANAGER: WrappedSoundManager = WrappedSoundManager {
-    sound_manager: None,
-};
-#[allow(non_snake_case)]
-pub fn SOUND_MANAGER() -> &'static mut WrappedSoundManager {
-    unsafe { &mut _SOUND_MANAGER }
-}
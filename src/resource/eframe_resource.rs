@@ -15,6 +15,8 @@ pub struct SmashbrosResource {
     pub version: String,
     pub character_list: HashMap<String, String>,
     pub icon_list: HashMap<String, TextureHandle>,
+    /// キャラ名と icon のファイルパス (オーバーレイなど GUI 以外で使う)
+    pub icon_path_list: HashMap<String, String>,
     pub order_image_list: HashMap<i32, TextureHandle>,
     pub i18n_convert_list: HashMap<String, String>,
     pub bgm_list: HashMap<String, bool>,
@@ -44,8 +46,14 @@ impl SmashbrosResource {
         Self::load_texture_handle(path, ctx, &converted_image)
     }
 
-    pub fn new(ctx: &egui::Context) -> Self {
-        let text = SmashbrosResourceText::new();
+    fn get_icon_path_list(text: &SmashbrosResourceText) -> HashMap<String, String> {
+        text.icon_list.iter()
+            .map(|(character_name, file_name)| (character_name.clone(), format!("icon/{}", file_name)))
+            .collect()
+    }
+
+    pub fn new(ctx: &egui::Context, lang_loader: &FluentLanguageLoader) -> Self {
+        let text = SmashbrosResourceText::new(lang_loader);
         let mut icon_list: HashMap<String, TextureHandle> = HashMap::new();
         for (character_name, file_name) in text.icon_list.iter() {
             let texture_handle = SmashbrosResource::get_texture_handle(&format!("icon/{}", file_name), ctx);
@@ -63,6 +71,7 @@ impl SmashbrosResource {
         }
 
         Self {
+            icon_path_list: Self::get_icon_path_list(&text),
             version: text.version,
             character_list: text.character_list,
             icon_list,
@@ -72,11 +81,12 @@ impl SmashbrosResource {
         }
     }
 
-    pub fn new_for_test() -> Self {
-        let text = SmashbrosResourceText::new();
+    pub fn new_for_test(lang_loader: &FluentLanguageLoader) -> Self {
+        let text = SmashbrosResourceText::new(lang_loader);
         // icon および image は GUI フレームワークからもらう frame が必要なので、test では空のままにしておく
 
         Self {
+            icon_path_list: Self::get_icon_path_list(&text),
             version: text.version,
             character_list: text.character_list,
             icon_list: HashMap::new(),
@@ -87,13 +97,13 @@ impl SmashbrosResource {
     }
 
     /// BGM 名が一致したものを返す。そうでない場合は推測して推測率と返す
-    pub fn convert_bgm_list(maybe_bgm_name: String) -> Option<(String, f32)> {
-        if let Some(bgm_name) = SMASHBROS_RESOURCE().get_mut().bgm_list.iter().find(|&c| c.0 == &maybe_bgm_name) {
+    pub fn convert_bgm_list(&self, maybe_bgm_name: String) -> Option<(String, f32)> {
+        if let Some(bgm_name) = self.bgm_list.iter().find(|&c| c.0 == &maybe_bgm_name) {
             // 完全一致(公式名)
             return Some(( bgm_name.0.clone(), 1.0 ));
         } else {
             // 完全一致(公式名)から一番一致率が高い名前を設定する
-            let bgm_list = SMASHBROS_RESOURCE().get_mut().bgm_list.clone().into_keys().collect::<Vec<String>>();
+            let bgm_list = self.bgm_list.clone().into_keys().collect::<Vec<String>>();
             let (bgm_name, ratio) = Self::matcher(&bgm_list, &maybe_bgm_name, None);
             if !bgm_name.is_empty() {
                 return Some(( bgm_name, ratio ));
@@ -104,29 +114,29 @@ impl SmashbrosResource {
     }
 
     /// キャラ名が一致したものを返す。そうでない場合は推測して推測率と返す
-    pub fn convert_character_name(maybe_character_name: String) -> Option<(String, f32)> {
-        if SMASHBROS_RESOURCE().get_mut().character_list.contains_key(&maybe_character_name) {
+    pub fn convert_character_name(&self, maybe_character_name: String) -> Option<(String, f32)> {
+        if self.character_list.contains_key(&maybe_character_name) {
             // 完全一致(公式英名)
             return Some(( maybe_character_name.clone(), 1.0 ));
-        } else if let Some(chara_name) = SMASHBROS_RESOURCE().get_mut().character_list.iter().find(|&c| c.1 == &maybe_character_name) {
+        } else if let Some(chara_name) = self.character_list.iter().find(|&c| c.1 == &maybe_character_name) {
             // 完全一致(公式名)
             return Some(( chara_name.0.clone(), 1.0 ));
-        } else if let Some(chara_name) = SMASHBROS_RESOURCE().get_mut().i18n_convert_list.get(&maybe_character_name) {
+        } else if let Some(chara_name) = self.i18n_convert_list.get(&maybe_character_name) {
             // i18n(各言語名)
             return Some(( chara_name.clone(), 1.0 ));
         } else {
             // 完全一致(公式英名)から一番一致率が高い名前を設定する
-            let chara_list = SMASHBROS_RESOURCE().get_mut().character_list.clone().into_keys().collect::<Vec<String>>();
+            let chara_list = self.character_list.clone().into_keys().collect::<Vec<String>>();
             let (chara_name, ratio) = Self::matcher(&chara_list, &maybe_character_name, Some(SmashbrosData::CHARACTER_NAME_UNKNOWN));
             if chara_name != SmashbrosData::CHARACTER_NAME_UNKNOWN {
                 return Some(( chara_name, ratio ));
             }
 
             // 完全一致(公式名)から一番一致率が高い名前を設定する
-            let chara_list = SMASHBROS_RESOURCE().get_mut().character_list.clone().into_values().collect::<Vec<String>>();
+            let chara_list = self.character_list.clone().into_values().collect::<Vec<String>>();
             let (chara_name, ratio) = Self::matcher(&chara_list, &maybe_character_name, Some(SmashbrosData::CHARACTER_NAME_UNKNOWN));
             if chara_name != SmashbrosData::CHARACTER_NAME_UNKNOWN {
-                if let Some(( chara_name, _)) = SMASHBROS_RESOURCE().get_mut().character_list.iter().find(|&c| c.1 == &chara_name) {
+                if let Some(( chara_name, _)) = self.character_list.iter().find(|&c| c.1 == &chara_name) {
                     return Some(( chara_name.clone(), ratio ));
                 }
             }
@@ -135,6 +145,14 @@ impl SmashbrosResource {
         None
     }
 
+    /// 保存する時のキャラ名 (i18n_convert_list にあるものだけ変換する)
+    pub fn normalize_character_name(&self, character_name: &str) -> String {
+        match self.i18n_convert_list.get(character_name) {
+            Some(to_name) => to_name.clone(),
+            None => character_name.to_string(),
+        }
+    }
+
     pub fn load_texture_handle(name: &str, ctx: &egui::Context, image: &opencv::core::Mat) -> TextureHandle {
         let image_size = ( image.cols() * image.rows() * 4 ) as usize;
         let image_data_by_slice: &[u8] = unsafe{ std::slice::from_raw_parts(image.datastart(), image_size) };
@@ -165,42 +183,14 @@ impl SmashbrosResource {
     }
 
     // 言語の変更でのリソースの再読み込み
-    pub fn change_language(&mut self) {
-        let text = SmashbrosResourceText::new();
+    pub fn change_language(&mut self, lang_loader: &FluentLanguageLoader) {
+        let text = SmashbrosResourceText::new(lang_loader);
         self.character_list = text.character_list;
         self.i18n_convert_list = text.i18n_convert_list;
     }
 
 }
 
-/// シングルトンでリソースを保持するため
-pub struct WrappedSmashbrosResource {
-    smashbros_resource: Option<SmashbrosResource>
-}
-impl WrappedSmashbrosResource {
-    pub fn init(&mut self, ctx: Option<&egui::Context>) {
-        if self.smashbros_resource.is_none() {
-            self.smashbros_resource = Some(SmashbrosResource::new(ctx.unwrap()));
-        }
-    }
-
-    // 参照して返さないと、unwrap() で move 違反がおきてちぬ！
-    pub fn get_mut(&mut self) -> &mut SmashbrosResource {
-        if self.smashbros_resource.is_none() {
-            log::error!("SmashbrosResource is not initialized. Call init() first.");
-            self.smashbros_resource = Some(SmashbrosResource::new_for_test());
-        }
-        self.smashbros_resource.as_mut().unwrap()
-    }
-}
-static mut _SMASHBROS_RESOURCE: WrappedSmashbrosResource = WrappedSmashbrosResource {
-    smashbros_resource: None,
-};
-#[allow(non_snake_case)]
-pub fn SMASHBROS_RESOURCE() -> &'static mut WrappedSmashbrosResource {
-    unsafe { &mut _SMASHBROS_RESOURCE }
-}
-
 // GUI の状態を保持するためのデータ
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct GUIStateConfig {
@@ -262,6 +252,13 @@ impl GUIStateConfig {
     pub fn default_stock_warning_under() -> i32 { 3 }
 }
 
+// 名前を付けて保存した戦歴の検索条件
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SavedSearch {
+    pub name: String,
+    /// BattleHistoryFilter::from_query で読める形式
+    pub query: HashMap<String, String>,
+}
 
 // 設定ファイル
 #[derive(Debug, Default, Deserialize, Serialize)]
@@ -292,12 +289,28 @@ pub struct GUIConfig {
     pub stock_alert_command: String,
     #[serde(default)]
     pub gui_state_config: GUIStateConfig,
+    #[serde(default = "crate::engine::EngineWorker::get_default_target_fps")]
+    pub target_fps: f32,
+    #[serde(default)]
+    pub api_server: crate::server::ApiServerConfig,
+    #[serde(default)]
+    pub text_output: crate::output::TextOutputConfig,
+    #[serde(default)]
+    pub hook_list: Vec<crate::hook::HookCommand>,
+    #[serde(default)]
+    pub webhook_list: Vec<crate::webhook::WebhookConfig>,
+    /// ゴミ箱の戦歴を完全に削除するまでの日数 (0 なら自動では削除しない)
+    #[serde(default = "GUIConfig::default_trash_retention_days")]
+    pub trash_retention_days: i64,
+    #[serde(default)]
+    pub saved_search_list: Vec<SavedSearch>,
 }
 impl GUIConfig {
     const DEFAULT_CAPTION: &'static str = "smabrog";
     const CONFIG_FILE: &'static str = "config.json";
 
     /// 設定情報の読み込み
+    #[cfg_attr(not(windows), allow(unused_variables))]
     pub fn load_config(&mut self, is_initalize: bool) -> anyhow::Result<()> {
         let file = std::fs::File::open(Self::CONFIG_FILE)?;
         match serde_json::from_reader(std::io::BufReader::new(file)) {
@@ -308,10 +321,13 @@ impl GUIConfig {
                 self.result_max = crate::engine::SmashBrogEngine::get_default_result_limit();
                 self.bgm_playlist_folder = GUIConfig::default_bgm_playlist_folder();
                 self.stock_alert_command = GUIConfig::default_stock_alert_command();
+                self.target_fps = crate::engine::EngineWorker::get_default_target_fps();
+                self.trash_retention_days = GUIConfig::default_trash_retention_days();
             }
         }
 
-        if is_initalize && cfg!(target_os = "windows") {
+        #[cfg(windows)]
+        if is_initalize {
             unsafe {
                 // 位置復元
                 use winapi::um::winuser;
@@ -330,8 +346,10 @@ impl GUIConfig {
         Ok(())
     }
     /// 設定情報の保存
+    #[cfg_attr(not(windows), allow(unused_variables))]
     pub fn save_config(&mut self, is_finalize: bool) -> Result<(), Box<dyn std::error::Error>> {
-        if is_finalize && cfg!(target_os = "windows") {
+        #[cfg(windows)]
+        if is_finalize {
             unsafe {
                 // 位置復元用
                 use winapi::um::winuser;
@@ -380,25 +398,6 @@ impl GUIConfig {
     
     pub fn default_bgm_playlist_folder() -> String { "./playlist".to_string() }
     pub fn default_stock_alert_command() -> String { "./resource/danger.avi".to_string() }
-}
-/// シングルトンで設定ファイルを保持するため
-pub struct WrappedGUIConfig {
-    gui_config: Option<GUIConfig>
-}
-impl WrappedGUIConfig {
-    // 参照して返さないと、unwrap() で move 違反がおきてちぬ！
-    pub fn get_mut(&mut self) -> &mut GUIConfig {
-        if self.gui_config.is_none() {
-            self.gui_config = Some(GUIConfig::default());
-        }
-        self.gui_config.as_mut().unwrap()
-    }
-}
-static mut _GUI_CONFIG: WrappedGUIConfig = WrappedGUIConfig {
-    gui_config: None,
-};
-#[allow(non_snake_case)]
-pub fn GUI_CONFIG() -> &'static mut WrappedGUIConfig {
-    unsafe { &mut _GUI_CONFIG }
+    pub fn default_trash_retention_days() -> i64 { 30 }
 }
 
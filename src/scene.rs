@@ -5,15 +5,24 @@ use opencv::{
     imgproc,
     prelude::*
 };
-use std::collections::HashMap;
+use rayon::prelude::*;
+use serde::{
+    Deserialize,
+    Serialize,
+};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
 use crate::capture::*;
+use crate::context::AppContext;
 use crate::data::*;
+use crate::event::*;
+use crate::resource::SmashbrosResource;
 use crate::utils::utils;
 
 pub mod judgment;
+pub mod prefilter;
+pub mod graph;
 pub mod unknown;
 pub mod loading;
 pub mod dialog;
@@ -24,8 +33,23 @@ pub mod game_start;
 pub mod game_playing;
 pub mod game_end;
 pub mod result;
+pub mod fixture;
 
 pub use judgment::SceneJudgment;
+pub use graph::{
+    JudgmentConfig,
+    JudgmentFormat,
+    JudgmentRoi,
+    ManageEventKind,
+    SceneGraph,
+    SceneGraphIssue,
+    SceneNode,
+};
+pub use prefilter::{
+    JudgmentPrefilter,
+    PrefilterFeature,
+    PrefilterStats,
+};
 pub use unknown::UnknownScene;
 pub use loading::LoadingScene;
 pub use dialog::DialogScene;
@@ -46,13 +70,11 @@ pub enum ColorFormat {
 
 
 /// シーンリスト
-#[derive(Clone, Copy, Debug, EnumIter, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, EnumIter, Eq, Hash, PartialEq, Serialize)]
 pub enum SceneList {
     ReadyToFight = 0, Matching, HamVsSpam,
     GameStart, GamePlaying, GameEnd, Result,
     Dialog, Loading, Unknown,
-    
-    DecidedRules, DecidedBgm, EndResultReplay,
 }
 impl SceneList {
     /// i32 to SceneList
@@ -65,6 +87,23 @@ impl SceneList {
 
         SceneList::Unknown
     }
+
+    /// SceneList に対応するシーンを作成する (疑似シーンは UnknownScene になる)
+    /// 判定は空なので、SceneGraph::apply_judgments で作る
+    pub fn new_scene(&self, context: &AppContext) -> Box<dyn SceneTrait> {
+        match self {
+            SceneList::ReadyToFight => Box::new(ReadyToFightScene::default()),
+            SceneList::Matching => Box::new(MatchingScene::default()),
+            SceneList::HamVsSpam => Box::new(HamVsSpamScene::new(context)),
+            SceneList::GameStart => Box::new(GameStartScene::new(context)),
+            SceneList::GamePlaying => Box::new(GamePlayingScene::default()),
+            SceneList::GameEnd => Box::new(GameEndScene::default()),
+            SceneList::Result => Box::new(ResultScene::new(context)),
+            SceneList::Dialog => Box::new(DialogScene::default()),
+            SceneList::Loading => Box::new(LoadingScene::default()),
+            _ => Box::new(UnknownScene::default()),
+        }
+    }
 }
 impl Default for SceneList {
     fn default() -> Self {
@@ -74,32 +113,35 @@ impl Default for SceneList {
 
 
 /// シーン雛形 (動作は子による)
-pub trait SceneTrait: downcast::Any {
+pub trait SceneTrait: downcast::Any + Send {
     /// 言語の変更
     fn change_language(&mut self) {}
     /// シーン識別ID
     fn get_id(&self) -> i32;
     /// 前回の検出情報
     fn get_prev_match(&self) -> Option<&SceneJudgment>;
-    /// シーンを検出するか
-    fn continue_match(&self, now_scene: SceneList) -> bool;
+    /// scene_graph.yml の judgments の name に対応する SceneJudgment
+    fn get_judgment_mut(&mut self, _name: &str) -> Option<&mut SceneJudgment> { None }
     /// "この"シーンかどうか
     fn is_scene(&mut self, mat: &core::Mat, smashbros_data: Option<&mut SmashbrosData>) -> opencv::Result<bool>;
-    /// 次はどのシーンに移るか
-    fn to_scene(&self, now_scene: SceneList) -> SceneList;
     /// シーンを録画する
     fn recoding_scene(&mut self, capture: &core::Mat) -> opencv::Result<()>;
     /// 録画されたかどうか
     fn is_recoded(&self) -> bool;
     /// シーン毎に録画したものから必要なデータを検出する
     fn detect_data(&mut self, smashbros_data: &mut SmashbrosData) -> opencv::Result<()>;
+    /// 録画を介さずに 1 フレームから必要なデータを検出する (fixture 用)
+    fn detect_frame(&mut self, _capture_image: &core::Mat, _smashbros_data: &mut SmashbrosData) -> opencv::Result<()> { Ok(()) }
+    /// is_scene で matchTemplate するか (false なら scene_graph.yml で prefetch: true の判定も先に判定しない)
+    fn is_prefetchable(&self, _smashbros_data: &SmashbrosData) -> bool { true }
+    /// 簡易判定で matchTemplate を飛ばせた回数 (簡易判定を設定している SceneJudgment の合計)
+    fn get_prefilter_stats(&self) -> PrefilterStats { PrefilterStats::default() }
 }
 downcast::downcast!(dyn SceneTrait);
 
 
-/// シーンイベントのコールバックの型
-pub type SceneEventCallback = Box<dyn FnMut(&mut SmashbrosData) -> ()>;
-pub type ManageEventCallback = Box< dyn FnMut(&mut SceneManager) -> Option<&mut Vec<SceneEventCallback>> >;
+/// 複雑なイベントのコールバックの型 (返したイベントが発行される)
+pub type ManageEventCallback = Box< dyn FnMut(&mut SceneManager) -> Option<EngineEvent> >;
 pub type ManageEventConditions = Box<dyn FnMut(&SceneManager) -> bool>;
 
 struct ManageEventContent {
@@ -107,6 +149,8 @@ struct ManageEventContent {
     pub fire_conditions: ManageEventConditions,
     pub manage_event_callback: ManageEventCallback,
     pub is_fired: bool,
+    // scene_graph の events から登録されたか (scene_graph を変更した時に作り直す)
+    pub is_scene_graph_event: bool,
 }
 
 /// 現在の検出されたデータの変更可能参照を返す
@@ -128,6 +172,8 @@ macro_rules! mut_now_data {
 /// シーン全体を非同期で管理するクラス
 pub struct SceneManager {
     pub capture: Box<dyn CaptureTrait>,
+    // 言語とリソース, 検出した戦歴の保存先
+    pub context: AppContext,
     pub scene_loading: LoadingScene,
     pub scene_list: Vec<Box<dyn SceneTrait>>,
     pub now_scene: SceneList,
@@ -137,24 +183,36 @@ pub struct SceneManager {
     pub prev_match_ratio: f64,
     pub prev_match_scene: SceneList,
     capture_image: core::Mat,
-    scene_event_list: HashMap< (SceneList, SceneList), Vec<SceneEventCallback> >,
+    frame_count: u64,
+    scene_graph: SceneGraph,
+    event_bus: EventBus,
     manage_event_list: Vec<ManageEventContent>,
+    // CharacterDecided, StockChanged, PowerDecided を発行する為に、前のフレームの値を覚えておく
+    prev_decided_chara_list: Vec<bool>,
+    prev_stock_list: Vec<i32>,
+    prev_decided_power_list: Vec<bool>,
 }
-impl Default for SceneManager {
-    fn default() -> Self {
+impl SceneManager {
+    // 簡易判定の飛ばせた割合をログに出す間隔
+    const PREFILTER_REPORT_FRAMES: u64 = 1000;
+
+    /// capture から画像を取得して、検出した戦歴を context の保存先に保存する SceneManager を作成する
+    pub fn new(capture: Box<dyn CaptureTrait>, context: AppContext) -> Self {
+        Self::with_scene_graph(capture, context, SceneGraph::load_or_builtin())
+    }
+
+    /// new と同じで、scene_graph.yml の代わりに scene_graph のシーン遷移で検出する
+    pub fn with_scene_graph(capture: Box<dyn CaptureTrait>, context: AppContext, scene_graph: SceneGraph) -> Self {
+        let scene_list = vec![
+            SceneList::ReadyToFight, SceneList::Matching, SceneList::HamVsSpam,
+            SceneList::GameStart, SceneList::GamePlaying, SceneList::GameEnd, SceneList::Result,
+            SceneList::Dialog,
+        ].iter().map(|scene| scene.new_scene(&context)).collect();
         let mut own = Self {
-            capture: Box::new(CaptureFromEmpty::new().unwrap()),
+            capture,
+            context,
             scene_loading: LoadingScene::default(),
-            scene_list: vec![
-                Box::new(ReadyToFightScene::default()),
-                Box::new(MatchingScene::default()),
-                Box::new(HamVsSpamScene::default()),
-                Box::new(GameStartScene::default()),
-                Box::new(GamePlayingScene::default()),
-                Box::new(GameEndScene::default()),
-                Box::new(ResultScene::default()),
-                Box::new(DialogScene::default()),
-            ],
+            scene_list,
             now_scene: SceneList::default(),
             smashbros_data: SmashbrosData::default(),
             sub_smashbros_data: SmashbrosData::default(),
@@ -162,97 +220,41 @@ impl Default for SceneManager {
             prev_match_ratio: 0.0,
             prev_match_scene: SceneList::default(),
             capture_image: core::Mat::default(),
-            scene_event_list: HashMap::new(),
+            frame_count: 0,
+            scene_graph: SceneGraph::default(),
+            event_bus: EventBus::new(),
             manage_event_list: Vec::new(),
+            prev_decided_chara_list: Vec::new(),
+            prev_stock_list: Vec::new(),
+            prev_decided_power_list: Vec::new(),
         };
+        own.set_scene_graph(scene_graph);
 
-        // DecidedRules イベントを定義
-        own.registory_manage_event(Box::new(|scene_manager: &SceneManager| {
-            scene_manager.now_scene == SceneList::Matching
-        }), Box::new(|scene_manager: &SceneManager| {
-            // ルールと最大ストック、時間が決まった時に DecidedRules を発行
-            scene_manager.ref_now_data().is_decided_rule_all_clause()
-        }), Box::new(|scene_manager: &mut SceneManager| {
-            log::info!("[{:?}] match [Unknown] to [DecidedRules]", scene_manager.now_scene);
-
-            scene_manager.scene_event_list.get_mut(&(SceneList::Unknown, SceneList::DecidedRules))
-        }));
-
-        // DecidedBgm イベントを定義
-        own.registory_manage_event(Box::new(|scene_manager: &SceneManager| {
-            scene_manager.now_scene == SceneList::Matching
-        }), Box::new(|scene_manager: &SceneManager| {
-            // BGM 名が決まった時に DecidedBgm を発行
-            scene_manager.ref_now_data().is_decided_bgm_name()
-        }), Box::new(|scene_manager: &mut SceneManager| {
-            log::info!("[{:?}] match [Unknown] to [DecidedBgm]", scene_manager.now_scene);
-
-            scene_manager.scene_event_list.get_mut(&(SceneList::Unknown, SceneList::DecidedBgm))
-        }));
-
-        // EndResultReplay イベントを定義
-        own.registory_manage_event(Box::new(|scene_manager: &SceneManager| {
-            if let Ok(result_scene) = scene_manager.scene_list[SceneList::Result as usize].downcast_ref::<ResultScene>() {
-                if result_scene.is_recoded() {
-                    // Result シーンの録画が終わると初期化
-                    return true;
-                }
-            }
-
-            false
-        }), Box::new(|scene_manager: &SceneManager| {
-            let result_scene = match scene_manager.scene_list[SceneList::Result as usize].downcast_ref::<ResultScene>() {
-                Ok(result_scene) => result_scene,
-                Err(_) => return false,
-            };
+        own
+    }
 
-            // 結果画面のリプレイが終わった時に EndResultReplay を発行
-            result_scene.buffer.is_replay_end() && scene_manager.sub_smashbros_data != SmashbrosData::default()
-        }), Box::new(|scene_manager: &mut SceneManager| {
-            log::info!("[{:?}] match [Unknown] to [EndResultReplay]", scene_manager.now_scene);
-
-            scene_manager.scene_event_list.get_mut(&(SceneList::Unknown, SceneList::EndResultReplay))
-        }));
-
-        // 試合の開始と終了
-        own.registory_scene_event(SceneList::HamVsSpam, SceneList::GamePlaying, Box::new(|smashbros_data: &mut SmashbrosData| {
-            smashbros_data.start_battle();
-        }));
-        own.registory_scene_event(SceneList::GamePlaying, SceneList::GameEnd, Box::new(|smashbros_data: &mut SmashbrosData| {
-            smashbros_data.finish_battle();
-        }));
-
-        // 初期ストックの代入
-        own.registory_scene_event(SceneList::Unknown, SceneList::DecidedRules, Box::new(|smashbros_data: &mut SmashbrosData| {
-            match smashbros_data.get_rule() {
-                BattleRule::Stock | BattleRule::Stamina => {
-                    for player_number in 0..smashbros_data.get_player_count() {
-                        smashbros_data.set_stock(player_number, smashbros_data.get_max_stock(player_number));
-                    }
-                }
-                _ => (),
-            }
-        }));
+    /// Result で検出した sub のデータを保存/更新して、保存したデータを返す
+    fn save_result_data(&mut self) -> SmashbrosData {
+        if self.sub_smashbros_data.get_id().is_some() {
+            self.sub_smashbros_data.update_battle_with(&mut self.context);
+        } else {
+            self.sub_smashbros_data.save_battle_with(&mut self.context);
+        }
 
-        // Result のリプレイが終わった時に一応 save/update しておく
-        own.registory_scene_event(SceneList::Unknown, SceneList::EndResultReplay, Box::new(|_smashbros_data: &mut SmashbrosData| {
-            if SCENE_MANAGER().get_mut().sub_smashbros_data.get_id().is_some() {
-                SCENE_MANAGER().get_mut().sub_smashbros_data.update_battle();
-            } else {
-                SCENE_MANAGER().get_mut().sub_smashbros_data.save_battle();
-            }
+        let data = self.sub_smashbros_data.clone();
+        if data.get_id().is_some() {
+            self.event_bus.publish(&EngineEvent::BattleSaved { data: data.clone() });
+        }
 
-            // Tournament初期化されていなかったら sub を再び main にする
-            if SCENE_MANAGER().get_mut().smashbros_data.is_finished_battle() {
-                SCENE_MANAGER().get_mut().smashbros_data = SCENE_MANAGER().get_mut().sub_smashbros_data.clone();
-            }
-            SCENE_MANAGER().get_mut().sub_smashbros_data = SmashbrosData::default();
-        }));
+        // Tournament初期化されていなかったら sub を再び main にする
+        if self.smashbros_data.is_finished_battle() {
+            self.smashbros_data = self.sub_smashbros_data.clone();
+        }
+        self.sub_smashbros_data = SmashbrosData::default();
 
-        own
+        data
     }
-}
-impl SceneManager {
+
     // 現在の検出されたデータの参照を返す
     pub fn ref_now_data(&self) -> &SmashbrosData {
         match SceneList::to_scene_list(self.now_scene as i32) {
@@ -290,11 +292,20 @@ impl SceneManager {
         self.prev_match_scene.clone()
     }
 
+    /// 簡易判定を行ったシーン毎の、matchTemplate を飛ばせた回数を返す
+    pub fn get_prefilter_stats_list(&self) -> Vec<(SceneList, PrefilterStats)> {
+        std::iter::once(&self.scene_loading as &dyn SceneTrait)
+            .chain(self.scene_list.iter().map(|scene| scene.as_ref()))
+            .map(|scene| (SceneList::to_scene_list(scene.get_id()), scene.get_prefilter_stats()))
+            .filter(|(_, stats)| 0 < stats.check_count)
+            .collect()
+    }
+
     // 現在検出しようとしているシーンの、前回の検出率を返す
     pub fn get_prev_match_ratio(&mut self) -> f64 {
         self.prev_match_ratio = 0.0;
         for index in 0..self.scene_list.len() {
-            if self.scene_list[index].continue_match(self.now_scene) {
+            if self.continue_match(index) {
                 if let Some(scene_judgment) = self.scene_list[index].get_prev_match() {
                     if self.prev_match_ratio < scene_judgment.prev_match_ratio {
                         self.prev_match_ratio = scene_judgment.prev_match_ratio;
@@ -307,9 +318,14 @@ impl SceneManager {
         self.prev_match_ratio
     }
 
-    // シーンが切り替わった際に呼ばれるイベントを登録する
-    pub fn registory_scene_event(&mut self, before_scene: SceneList, after_scene: SceneList, callback: SceneEventCallback) {
-        self.scene_event_list.entry((before_scene, after_scene)).or_insert(Vec::new()).push(callback);
+    /// 発行される EngineEvent を全て受け取る
+    pub fn subscribe(&mut self, callback: EngineEventCallback) -> SubscriptionId {
+        self.event_bus.subscribe(callback)
+    }
+
+    /// subscribe したものを受け取らないようにする
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.event_bus.unsubscribe(id)
     }
 
     // 複雑なイベントを登録する
@@ -319,30 +335,118 @@ impl SceneManager {
             fire_conditions,
             manage_event_callback,
             is_fired: true, // 初期状態では発火済みとして、初期化条件で初期化されるのを待つ
+            is_scene_graph_event: false,
         });
     }
 
+    // scene_graph の events を複雑なイベントとして登録し直す
+    fn registory_scene_graph_events(&mut self) {
+        self.manage_event_list.retain(|manage_event| !manage_event.is_scene_graph_event);
+
+        let event_list = self.scene_graph.scenes.iter()
+            .flat_map(|node| node.events.iter().map(move |event| (node.scene, *event)))
+            .collect::<Vec<_>>();
+        for (scene, event) in event_list {
+            let (init_conditions, fire_conditions, manage_event_callback) = Self::new_scene_graph_event(scene, event);
+            self.manage_event_list.push(ManageEventContent {
+                init_conditions,
+                fire_conditions,
+                manage_event_callback,
+                is_fired: true,
+                is_scene_graph_event: true,
+            });
+        }
+    }
+
+    // scene で発行を待つ event の 初期化条件, 発火条件, コールバック を返す
+    fn new_scene_graph_event(scene: SceneList, event: ManageEventKind) -> (ManageEventConditions, ManageEventConditions, ManageEventCallback) {
+        match event {
+            ManageEventKind::RuleDecided => (Box::new(move |scene_manager: &SceneManager| {
+                scene_manager.now_scene == scene
+            }), Box::new(|scene_manager: &SceneManager| {
+                // ルールと最大ストック、時間が決まった時に RuleDecided を発行
+                scene_manager.ref_now_data().is_decided_rule_all_clause()
+            }), Box::new(|scene_manager: &mut SceneManager| {
+                log::info!("[{:?}] rule decided", scene_manager.now_scene);
+
+                // 初期ストックの代入
+                let now_scene = scene_manager.now_scene;
+                let smashbros_data = mut_now_data!(scene_manager, now_scene);
+                match smashbros_data.get_rule() {
+                    BattleRule::Stock | BattleRule::Stamina => {
+                        for player_number in 0..smashbros_data.get_player_count() {
+                            smashbros_data.set_stock(player_number, smashbros_data.get_max_stock(player_number));
+                        }
+                    }
+                    _ => (),
+                }
+
+                Some(EngineEvent::RuleDecided {
+                    rule: smashbros_data.get_rule(),
+                    data: smashbros_data.clone(),
+                })
+            })),
+            ManageEventKind::BgmDecided => (Box::new(move |scene_manager: &SceneManager| {
+                scene_manager.now_scene == scene
+            }), Box::new(|scene_manager: &SceneManager| {
+                // BGM 名が決まった時に BgmDecided を発行
+                scene_manager.ref_now_data().is_decided_bgm_name()
+            }), Box::new(|scene_manager: &mut SceneManager| {
+                log::info!("[{:?}] bgm decided", scene_manager.now_scene);
+
+                let smashbros_data = scene_manager.ref_now_data();
+                Some(EngineEvent::BgmDecided {
+                    bgm_name: smashbros_data.get_bgm_name(),
+                    data: smashbros_data.clone(),
+                })
+            })),
+            ManageEventKind::ResultReplayEnded => (Box::new(move |scene_manager: &SceneManager| {
+                // Result シーンの録画が終わると初期化
+                scene_manager.scene_list.get(scene as usize).map_or(false, |scene| scene.is_recoded())
+            }), Box::new(move |scene_manager: &SceneManager| {
+                let result_scene = match scene_manager.scene_list.get(scene as usize).map(|scene| scene.downcast_ref::<ResultScene>()) {
+                    Some(Ok(result_scene)) => result_scene,
+                    _ => return false,
+                };
+
+                // 結果画面のリプレイが終わった時に ResultReplayEnded を発行
+                result_scene.buffer.is_replay_end() && scene_manager.sub_smashbros_data != SmashbrosData::default()
+            }), Box::new(|scene_manager: &mut SceneManager| {
+                log::info!("[{:?}] result replay ended", scene_manager.now_scene);
+
+                // Result のリプレイが終わった時に一応 save/update しておく
+                let data = scene_manager.save_result_data();
+
+                Some(EngineEvent::ResultReplayEnded { data })
+            })),
+        }
+    }
+
     // イベントの更新
     pub fn update_event(&mut self) {
+        // 条件やコールバックに self を渡すので、処理している間は一旦取り出しておく
+        let mut manage_event_list = std::mem::take(&mut self.manage_event_list);
+
         // 複雑なイベントの処理
-        for manage_event in &mut self.manage_event_list {
+        for manage_event in &mut manage_event_list {
             if manage_event.is_fired {
                 // 発火済みなら初期化条件を満たすまで監視
-                if manage_event.init_conditions.as_mut()(SCENE_MANAGER().get_mut()) {
+                if manage_event.init_conditions.as_mut()(self) {
                     manage_event.is_fired = false;
                 }
-            } else if manage_event.fire_conditions.as_mut()(SCENE_MANAGER().get_mut()) {
-                // 発火条件を満たしたら manage_event_callback が返す SceneEventCallback のリストを実行
+            } else if manage_event.fire_conditions.as_mut()(self) {
+                // 発火条件を満たしたら manage_event_callback が返すイベントを発行
                 manage_event.is_fired = true;
 
-                let now_scene = self.now_scene.clone();
-                if let Some(scene_event_list) = manage_event.manage_event_callback.as_mut()(SCENE_MANAGER().get_mut()) {
-                    for scene_event in scene_event_list {
-                        scene_event(mut_now_data!(self, now_scene));
-                    }
+                if let Some(event) = manage_event.manage_event_callback.as_mut()(self) {
+                    self.event_bus.publish(&event);
                 }
             }
         }
+
+        // 処理中に登録されたものがあれば後ろに足す
+        manage_event_list.append(&mut self.manage_event_list);
+        self.manage_event_list = manage_event_list;
     }
 
     // シーンを更新する
@@ -356,24 +460,33 @@ impl SceneManager {
 
         // 読込中の画面(真っ黒に近い)はテンプレートマッチングで 1.0 がでてしまうので回避
         // よけいな match をさけるため(is_scene すること自体が結構コストが高い)
-        if is_loading || !self.scene_list[index].continue_match(self.now_scene) {
+        if is_loading || !self.continue_match(index) {
             return;
         }
 
         // 遷移?
         if self.scene_list[index].is_scene(&capture_image, Some(mut_now_data!(self, index))).unwrap_or(false) {
-            let to_scene = self.scene_list[index].to_scene(self.now_scene);
+            let to_scene = self.scene_graph.to_scene(SceneList::to_scene_list(index as i32), self.now_scene);
             log::info!(
                 "[{:?}]({:2.3}%) match {:?} to {:?}",
                 SceneList::to_scene_list(self.scene_list[index].get_id()), self.scene_list[index].get_prev_match().unwrap().prev_match_ratio,
                 self.now_scene, to_scene
             );
 
-            // シーンが切り替わった際に呼ばれるイベントを発火
-            if let Some(scene_event_list) = self.scene_event_list.get_mut(&(self.now_scene, to_scene)) {
-                for event in scene_event_list {
-                    event.as_mut()(mut_now_data!(self, index));
-                }
+            // 試合の開始と終了
+            match (self.now_scene, to_scene) {
+                (SceneList::HamVsSpam, SceneList::GamePlaying) => mut_now_data!(self, index).start_battle(),
+                (SceneList::GamePlaying, SceneList::GameEnd) => mut_now_data!(self, index).finish_battle(),
+                _ => (),
+            }
+
+            if self.now_scene != to_scene {
+                let event = EngineEvent::SceneChanged {
+                    before: self.now_scene,
+                    after: to_scene,
+                    data: mut_now_data!(self, index).clone(),
+                };
+                self.event_bus.publish(&event);
             }
 
             if to_scene == SceneList::GameEnd {
@@ -391,6 +504,10 @@ impl SceneManager {
 
         let is_loading = self.scene_loading.is_scene(&capture_image, None)?;
 
+        // 重いテンプレートマッチングだけ先に並列で行っておく
+        // (遷移の順番は下の scene_list 順の逐次処理で決まるので、並列にしても結果は変わらない)
+        self.prefetch_scene_list(&capture_image, is_loading);
+
         // 現在キャプチャと比較して遷移する
         for index in 0..self.scene_list.len() {
             async_std::task::block_on(async {
@@ -399,43 +516,135 @@ impl SceneManager {
         }
 
         self.update_event();
+        self.publish_data_event();
+
+        self.frame_count += 1;
+        if 0 == self.frame_count % Self::PREFILTER_REPORT_FRAMES {
+            for (scene, stats) in self.get_prefilter_stats_list() {
+                log::info!("prefilter {:?}: skipped {}/{} ({:.1}%)", scene, stats.skip_count, stats.check_count, stats.skip_rate() * 100.0);
+            }
+        }
 
         Ok(())
     }
 
+    // 前のフレームからキャラが確定したり、ストックが変わったり、戦闘力が確定したらイベントを発行する
+    fn publish_data_event(&mut self) {
+        let smashbros_data = self.ref_now_data();
+        let player_count = smashbros_data.get_player_count().max(0) as usize;
+        let decided_chara_list = (0..player_count as i32).map(|player_number| smashbros_data.is_decided_character_name(player_number)).collect::<Vec<_>>();
+        let chara_list = (0..player_count as i32).map(|player_number| smashbros_data.get_character(player_number)).collect::<Vec<_>>();
+        let stock_list = (0..player_count as i32).map(|player_number| smashbros_data.get_stock(player_number)).collect::<Vec<_>>();
+        let decided_power_list = (0..player_count as i32).map(|player_number| smashbros_data.is_decided_power(player_number)).collect::<Vec<_>>();
+        let power_list = (0..player_count as i32).map(|player_number| smashbros_data.get_power(player_number)).collect::<Vec<_>>();
+
+        // 人数が変わったら新しい試合なので、前の値は捨てる
+        if self.prev_stock_list.len() != player_count {
+            self.prev_decided_chara_list = vec![false; player_count];
+            self.prev_stock_list = vec![-1; player_count];
+            self.prev_decided_power_list = vec![false; player_count];
+        }
+
+        let mut event_list = Vec::new();
+        for player_number in 0..player_count {
+            if decided_chara_list[player_number] && !self.prev_decided_chara_list[player_number] {
+                event_list.push(EngineEvent::CharacterDecided { player_number: player_number as i32, character: chara_list[player_number].clone() });
+            }
+            if stock_list[player_number] != self.prev_stock_list[player_number] && -1 != stock_list[player_number] {
+                event_list.push(EngineEvent::StockChanged { player_number: player_number as i32, stock: stock_list[player_number] });
+            }
+            if decided_power_list[player_number] && !self.prev_decided_power_list[player_number] {
+                event_list.push(EngineEvent::PowerDecided { player_number: player_number as i32, power: power_list[player_number] });
+            }
+        }
+        self.prev_decided_chara_list = decided_chara_list;
+        self.prev_stock_list = stock_list;
+        self.prev_decided_power_list = decided_power_list;
+
+        for event in &event_list {
+            self.event_bus.publish(event);
+        }
+    }
+
+    // 現在のシーンから検出する可能性のあるシーンの、scene_graph で prefetch: true の判定を並列で先に行っておく
+    // (rayon のスレッドでは SceneJudgment と複製した Mat しか触らない)
+    fn prefetch_scene_list(&mut self, capture_image: &core::Mat, is_loading: bool) {
+        let now_scene = self.now_scene;
+        let frame_number = self.frame_count;
+        let is_sub_data = self.sub_smashbros_data != SmashbrosData::default();
+        // Mat は Sync ではないので、スレッド毎に渡すものは先に複製しておく
+        let mut prefetch_list = Vec::new();
+        for (index, scene) in self.scene_list.iter_mut().enumerate() {
+            let node = match self.scene_graph.get_node(SceneList::to_scene_list(index as i32)) {
+                Some(node) => node,
+                None => continue,
+            };
+            let config = match node.judgments.iter().find(|config| config.prefetch) {
+                Some(config) => config,
+                None => continue,
+            };
+
+            // mut_now_data! と同じものを参照する
+            let now_data = match SceneList::to_scene_list(index as i32) {
+                SceneList::Matching => &self.smashbros_data,
+                _ => if is_sub_data {
+                    &self.sub_smashbros_data
+                } else {
+                    &self.smashbros_data
+                },
+            };
+            let is_prefetch = !is_loading && node.is_from(now_scene) && scene.is_prefetchable(now_data);
+
+            if let Some(scene_judgment) = scene.get_judgment_mut(&config.name) {
+                // prefetch したのに is_scene されなかった結果は、フレーム番号が変わるので次のフレームでは使われない
+                scene_judgment.set_frame_number(frame_number);
+                if is_prefetch {
+                    prefetch_list.push((scene_judgment, capture_image.clone()));
+                }
+            }
+        }
+
+        prefetch_list.into_par_iter().for_each(|(scene_judgment, capture_image)| {
+            if let Err(e) = scene_judgment.prefetch_captured_scene(&capture_image) {
+                // is_scene で改めて判定されるので、ここでは無視する
+                log::debug!("prefetch error: {}", e);
+            }
+        });
+    }
+
     // 言語の変更をする
     pub fn change_language(&mut self) {
         for scene in self.scene_list.iter_mut() {
             scene.change_language();
         }
+
+        // {lang} の判定を変更した言語で作り直す
+        self.apply_scene_graph_judgments();
     }
-}
 
-/// シングルトンで SceneManager を保持するため
-pub struct WrappedSceneManager {
-    scene_manager: Option<SceneManager>,
-}
-impl WrappedSceneManager {
-    // 参照して返さないと、unwrap() で move 違反がおきてちぬ！
-    pub fn get(&mut self) -> &SceneManager {
-        if self.scene_manager.is_none() {
-            self.scene_manager = Some(SceneManager::default());
-        }
-        self.scene_manager.as_ref().unwrap()
+    /// シーン遷移の定義を変更して、定義にある判定とイベントでシーンの判定とイベントを作り直す
+    pub fn set_scene_graph(&mut self, scene_graph: SceneGraph) {
+        self.scene_graph = scene_graph;
+        self.apply_scene_graph_judgments();
+        self.registory_scene_graph_events();
+    }
+
+    /// 使用しているシーン遷移の定義を返す
+    pub fn get_scene_graph(&self) -> &SceneGraph {
+        &self.scene_graph
     }
 
-    // mut 版
-    pub fn get_mut(&mut self) -> &mut SceneManager {
-        if self.scene_manager.is_none() {
-            self.scene_manager = Some(SceneManager::default());
+    // scene_list[index] のシーンを、現在のシーンから検出するか
+    fn continue_match(&self, index: usize) -> bool {
+        self.scene_graph.continue_match(SceneList::to_scene_list(index as i32), self.now_scene)
+    }
+
+    // scene_graph の judgments をそれぞれのシーンに設定する
+    fn apply_scene_graph_judgments(&mut self) {
+        let lang = self.context.lang_code();
+        self.scene_graph.apply_judgments(&mut self.scene_loading, &lang);
+        for scene in self.scene_list.iter_mut() {
+            self.scene_graph.apply_judgments(scene.as_mut(), &lang);
         }
-        self.scene_manager.as_mut().unwrap()
     }
 }
-static mut _SCENE_MANAGER: WrappedSceneManager = WrappedSceneManager {
-    scene_manager: None,
-};
-#[allow(non_snake_case)]
-pub fn SCENE_MANAGER() -> &'static mut WrappedSceneManager {
-    unsafe { &mut _SCENE_MANAGER }
-}
@@ -0,0 +1,499 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use super::*;
+use crate::resource::InMemoryBattleHistory;
+
+/// fixture を置くディレクトリ
+/// test/fixture/<任意の名前>/expected.yml と PNG のフレームを置く
+/// (SceneManager が検出するシーン毎に 1 つ以上置く。足りないと test_scene_fixture が失敗する)
+///
+/// ```yaml
+/// scene: Result
+/// min_accuracy: 0.9
+/// frames:
+///   - file: result_1.png
+///     max_stock: 3
+///     chara: [MARIO, LINK]
+///     is_scene: true
+///     stock: [1, 0]
+///     order: [1, 2]
+///     power: [5123456, 4987654]
+/// ```
+pub const FIXTURE_ROOT: &str = "test/fixture";
+/// 期待値を記述するファイル名
+pub const EXPECTED_FILE: &str = "expected.yml";
+/// 1 セッション(連続したキャプチャ)を置くディレクトリ
+/// test/session/<任意の名前>/expected.yml と 連番画像のディレクトリ か 動画ファイル を置く
+/// (1 つも無いと test_session_fixture が失敗する, chara はどの言語のキャラ名でもいい)
+///
+/// ```yaml
+/// source: session.mp4
+/// battles:
+///   - chara: [MARIO, LINK]
+///     rule: Stock
+///     winner: 0
+///     stock: [2, 0]
+///     power: [5123456, 4987654]
+/// ```
+pub const SESSION_ROOT: &str = "test/session";
+
+
+/// 1 フレーム分の入力と期待値 (None のものは検証しない)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FixtureFrame {
+    pub file: String,
+    // 入力
+    pub player_count: Option<i32>,
+    pub rule: Option<String>,
+    pub max_stock: Option<i32>,
+    // 期待値
+    pub is_scene: Option<bool>,
+    pub chara: Option<Vec<String>>,
+    pub stock: Option<Vec<i32>>,
+    pub power: Option<Vec<i32>>,
+    pub order: Option<Vec<i32>>,
+}
+impl FixtureFrame {
+    /// フレームを検出させる前のデータを作成する
+    /// chara は Result などの前提になるので、入力としても扱う
+    fn make_data(&self) -> SmashbrosData {
+        let player_count = self.player_count.unwrap_or(2);
+        let mut smashbros_data = SmashbrosData::default();
+        smashbros_data.initialize_battle(player_count, true);
+
+        if let Some(rule) = &self.rule {
+            smashbros_data.set_rule(BattleRule::from_str(rule).unwrap_or(BattleRule::Unknown));
+        }
+        if let Some(max_stock) = self.max_stock {
+            for player_number in 0..player_count {
+                smashbros_data.set_max_stock(player_number, max_stock);
+            }
+        }
+
+        smashbros_data
+    }
+}
+
+/// fixture ディレクトリ毎の定義
+#[derive(Debug, Deserialize)]
+pub struct SceneFixture {
+    pub scene: SceneList,
+    #[serde(default = "SceneFixture::default_min_accuracy")]
+    pub min_accuracy: f32,
+    #[serde(default = "SceneFixture::default_repeat")]
+    pub repeat: i32,
+    pub frames: Vec<FixtureFrame>,
+}
+impl SceneFixture {
+    fn default_min_accuracy() -> f32 { 1.0 }
+    // ValueGuesser が値を確定させるまで同じフレームを流す
+    fn default_repeat() -> i32 { ValueGuesser::<i32>::DEFAULT_MAX_BORDER }
+
+    /// dir/expected.yml を読み込む
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(dir.join(EXPECTED_FILE))?;
+
+        Ok(serde_yaml::from_reader(std::io::BufReader::new(file))?)
+    }
+}
+
+
+/// セッション内の 1 試合分の期待値 (None のものは検証しない)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SessionBattle {
+    pub chara: Option<Vec<String>>,
+    pub rule: Option<String>,
+    /// order が 1 になるプレイヤー
+    pub winner: Option<i32>,
+    pub stock: Option<Vec<i32>>,
+    pub power: Option<Vec<i32>>,
+    pub order: Option<Vec<i32>>,
+}
+
+/// セッションディレクトリ毎の定義
+#[derive(Debug, Deserialize)]
+pub struct SessionFixture {
+    pub source: String,
+    #[serde(default = "SceneFixture::default_min_accuracy")]
+    pub min_accuracy: f32,
+    pub battles: Vec<SessionBattle>,
+}
+impl SessionFixture {
+    /// dir/expected.yml を読み込む
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(dir.join(EXPECTED_FILE))?;
+
+        Ok(serde_yaml::from_reader(std::io::BufReader::new(file))?)
+    }
+}
+
+
+/// fixture の実行結果
+#[derive(Debug, Default)]
+pub struct FixtureReport {
+    pub name: String,
+    pub scene: SceneList,
+    pub min_accuracy: f32,
+    pub frame_count: usize,
+    pub check_count: usize,
+    pub correct_count: usize,
+    pub failure_list: Vec<String>,
+}
+impl FixtureReport {
+    /// 検証した項目のうち、一致した割合
+    pub fn accuracy(&self) -> f32 {
+        if 0 == self.check_count {
+            return 1.0;
+        }
+
+        self.correct_count as f32 / self.check_count as f32
+    }
+
+    pub fn is_passed(&self) -> bool {
+        self.min_accuracy <= self.accuracy()
+    }
+
+    fn check<T: PartialEq + std::fmt::Debug>(&mut self, file: &str, field: &str, expected: T, actual: T) {
+        self.check_count += 1;
+        if expected == actual {
+            self.correct_count += 1;
+        } else {
+            self.failure_list.push(format!("{}: {} expected {:?}, but {:?}", file, field, expected, actual));
+        }
+    }
+
+    /// プレイヤー毎の値を検証する
+    fn check_list<T: PartialEq + std::fmt::Debug>(&mut self, file: &str, field: &str, expected_list: &Option<Vec<T>>, getter: impl Fn(i32) -> T) {
+        if let Some(expected_list) = expected_list {
+            for (player_number, expected) in expected_list.iter().enumerate() {
+                self.check(file, &format!("{}[{}]", field, player_number), expected, &getter(player_number as i32));
+            }
+        }
+    }
+}
+impl std::fmt::Display for FixtureReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f, "[{}] {:?}: {}/{} ({:.1}%) frames:{} {}",
+            if self.is_passed() { "ok" } else { "NG" },
+            self.scene, self.correct_count, self.check_count, self.accuracy() * 100.0,
+            self.frame_count, self.name
+        )?;
+        for failure in &self.failure_list {
+            writeln!(f, "    {}", failure)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+/// フレームを読み込んで、シーンが期待しているサイズ(640x360)にする
+fn load_frame(path: &Path) -> anyhow::Result<core::Mat> {
+    let image = imgcodecs::imread(&path.to_string_lossy(), imgcodecs::IMREAD_COLOR)?;
+    if image.empty() {
+        anyhow::bail!("failed to read frame: {:?}", path);
+    }
+    if image.cols() == 640 && image.rows() == 360 {
+        return Ok(image);
+    }
+
+    let mut resized_image = core::Mat::default();
+    imgproc::resize(&image, &mut resized_image, core::Size { width: 640, height: 360 }, 0.0, 0.0, imgproc::INTER_LINEAR)?;
+
+    Ok(resized_image)
+}
+
+/// fixture ディレクトリ 1 つ分を、SceneManager と同じ scene_graph.yml の判定で実行する
+pub fn run_fixture(dir: &Path) -> anyhow::Result<FixtureReport> {
+    run_fixture_with(dir, &SceneGraph::load_or_builtin())
+}
+
+/// fixture ディレクトリ 1 つ分を、scene_graph の判定で実行する
+pub fn run_fixture_with(dir: &Path, scene_graph: &SceneGraph) -> anyhow::Result<FixtureReport> {
+    let fixture = SceneFixture::load(dir)?;
+    let mut report = FixtureReport {
+        name: dir.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+        scene: fixture.scene,
+        min_accuracy: fixture.min_accuracy,
+        ..Default::default()
+    };
+
+    // 言語とリソースだけ使う (戦歴は保存しない)
+    let context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
+    for frame in &fixture.frames {
+        // 前のフレームの状態を持ち越さないように、フレーム毎にシーンを作り直す
+        let mut scene = fixture.scene.new_scene(&context);
+        scene_graph.apply_judgments(scene.as_mut(), &context.lang_code());
+        let capture_image = load_frame(&dir.join(&frame.file))?;
+        let mut smashbros_data = frame.make_data();
+        if let Some(chara_list) = &frame.chara {
+            if SceneList::HamVsSpam != fixture.scene {
+                for (player_number, chara_name) in chara_list.iter().enumerate() {
+                    smashbros_data.guess_character_name(player_number as i32, chara_name.clone(), &context.smashbros_resource());
+                }
+            }
+        }
+
+        // is_scene が true を返さないシーン(Result など)もあるので、判定の一致具合も見る
+        let mut is_scene = false;
+        for _ in 0..fixture.repeat {
+            is_scene |= scene.is_scene(&capture_image, Some(&mut smashbros_data))?;
+            is_scene |= scene.get_prev_match().map_or(false, |judgment| judgment.is_near_match());
+            scene.detect_frame(&capture_image, &mut smashbros_data)?;
+        }
+
+        report.frame_count += 1;
+        if let Some(expected) = frame.is_scene {
+            report.check(&frame.file, "is_scene", expected, is_scene);
+        }
+        if SceneList::HamVsSpam == fixture.scene {
+            report.check_list(&frame.file, "chara", &frame.chara, |player_number| smashbros_data.get_character(player_number));
+        }
+        report.check_list(&frame.file, "stock", &frame.stock, |player_number| smashbros_data.get_stock(player_number));
+        report.check_list(&frame.file, "power", &frame.power, |player_number| smashbros_data.get_power(player_number));
+        report.check_list(&frame.file, "order", &frame.order, |player_number| smashbros_data.get_order(player_number));
+    }
+
+    Ok(report)
+}
+
+/// セッションディレクトリ 1 つ分を SceneManager に流して、保存された戦歴を検証する
+pub fn run_session(dir: &Path) -> anyhow::Result<FixtureReport> {
+    let battle_history = InMemoryBattleHistory::new();
+    let context = AppContext::with_battle_history(Box::new(battle_history.clone()));
+
+    run_session_with(dir, SceneGraph::load_or_builtin(), context, &battle_history)
+}
+
+/// セッションディレクトリ 1 つ分を scene_graph と context (言語とリソース) の SceneManager に流して、
+/// context の保存先である battle_history に保存された戦歴を検証する
+pub fn run_session_with(dir: &Path, scene_graph: SceneGraph, context: AppContext, battle_history: &InMemoryBattleHistory) -> anyhow::Result<FixtureReport> {
+    let session = SessionFixture::load(dir)?;
+    let mut report = FixtureReport {
+        name: dir.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+        scene: SceneList::Unknown,
+        min_accuracy: session.min_accuracy,
+        ..Default::default()
+    };
+
+    let mut scene_manager = SceneManager::with_scene_graph(
+        Box::new(CaptureFromFile::new(&dir.join(&session.source))?),
+        context.clone(),
+        scene_graph
+    );
+
+    // 最後まで読むと Err が返ってくる
+    while scene_manager.update_scene_list().is_ok() {
+        report.frame_count += 1;
+    }
+
+    let data_list = battle_history.get_data_list();
+    report.check(&session.source, "battles.len", session.battles.len(), data_list.len());
+    for (battle_number, (battle, data)) in session.battles.iter().zip(data_list.iter()).enumerate() {
+        let name = format!("{}#{}", session.source, battle_number);
+        if let Some(rule) = &battle.rule {
+            report.check(&name, "rule", BattleRule::from_str(rule).unwrap_or(BattleRule::Unknown), data.get_rule());
+        }
+        if let Some(winner) = battle.winner {
+            report.check(&name, "order[winner]", 1, data.get_order(winner));
+        }
+        // 期待値はどの言語のキャラ名で書いてもいい
+        let chara_list = battle.chara.as_ref().map(|chara_list| {
+            chara_list.iter().map(|chara_name| context.smashbros_resource().normalize_character_name(chara_name)).collect::<Vec<_>>()
+        });
+        report.check_list(&name, "chara", &chara_list, |player_number| data.get_character(player_number));
+        report.check_list(&name, "stock", &battle.stock, |player_number| data.get_stock(player_number));
+        report.check_list(&name, "power", &battle.power, |player_number| data.get_power(player_number));
+        report.check_list(&name, "order", &battle.order, |player_number| data.get_order(player_number));
+    }
+
+    Ok(report)
+}
+
+/// root 以下の expected.yml があるディレクトリを返す
+fn find_fixture_dir_list(root: &Path) -> anyhow::Result<Vec<std::path::PathBuf>> {
+    if !root.is_dir() {
+        log::warn!("not found fixture directory: {:?}", root);
+        return Ok(Vec::new());
+    }
+
+    let mut dir_list = std::fs::read_dir(root)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.join(EXPECTED_FILE).is_file())
+        .collect::<Vec<_>>();
+    dir_list.sort();
+
+    Ok(dir_list)
+}
+
+/// root 以下の fixture ディレクトリをすべて実行する (1 つも無ければ検証していないのでエラー)
+pub fn run_fixture_all(root: &Path) -> anyhow::Result<Vec<FixtureReport>> {
+    let dir_list = find_fixture_dir_list(root)?;
+    if dir_list.is_empty() {
+        anyhow::bail!("not found {} in {:?}", EXPECTED_FILE, root);
+    }
+
+    dir_list.iter().map(|dir| run_fixture(dir)).collect()
+}
+
+/// root 以下のセッションディレクトリをすべて実行する (1 つも無ければ検証していないのでエラー)
+pub fn run_session_all(root: &Path) -> anyhow::Result<Vec<FixtureReport>> {
+    let dir_list = find_fixture_dir_list(root)?;
+    if dir_list.is_empty() {
+        anyhow::bail!("not found {} in {:?}", EXPECTED_FILE, root);
+    }
+
+    dir_list.iter().map(|dir| run_session(dir)).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 録画したフレームはリポジトリに含めていないので、test/fixture に置いた時だけ --ignored で実行する
+    #[test]
+    #[ignore = "needs recorded frames and expected.yml for every scene under test/fixture"]
+    fn test_scene_fixture() {
+        let report_list = run_fixture_all(Path::new(FIXTURE_ROOT)).unwrap();
+        for report in &report_list {
+            print!("{}", report);
+        }
+
+        let failed_list = report_list.iter()
+            .filter(|report| !report.is_passed())
+            .map(|report| report.name.clone())
+            .collect::<Vec<_>>();
+        assert!(failed_list.is_empty(), "fixture failed: {:?}", failed_list);
+
+        // SceneManager が検出するシーンは全て 1 つ以上の fixture で検証する
+        let missing_list = [
+            SceneList::ReadyToFight, SceneList::Matching, SceneList::HamVsSpam,
+            SceneList::GameStart, SceneList::GamePlaying, SceneList::GameEnd, SceneList::Result,
+            SceneList::Dialog, SceneList::Loading,
+        ].iter()
+            .filter(|scene| !report_list.iter().any(|report| report.scene == **scene && 0 < report.check_count))
+            .collect::<Vec<_>>();
+        assert!(missing_list.is_empty(), "not found fixture: {:?}", missing_list);
+    }
+
+    #[test]
+    fn test_fixture_report() {
+        let mut report = FixtureReport { name: "report".to_string(), scene: SceneList::Result, min_accuracy: 0.75, ..Default::default() };
+        assert_eq!(report.accuracy(), 1.0);
+
+        report.check("a.png", "is_scene", true, true);
+        report.check_list("a.png", "stock", &Some(vec![0, 5]), |player_number| player_number);
+        report.check_list("a.png", "order", &None, |_| 0);
+        assert_eq!((report.check_count, report.correct_count), (3, 2));
+        assert_eq!(report.failure_list, vec!["a.png: stock[1] expected 5, but 1".to_string()]);
+        assert!(!report.is_passed());
+
+        report.check("b.png", "is_scene", false, false);
+        assert_eq!(report.accuracy(), 0.75);
+        assert!(report.is_passed());
+        assert!(report.to_string().starts_with("[ok] Result: 3/4 (75.0%)"));
+    }
+
+    #[test]
+    fn test_run_fixture_with_synthetic_frame() {
+        let dir = std::env::temp_dir().join(format!("smabrog_fixture_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // 白い四角があるフレームだけを Dialog とする判定
+        let black_image = core::Mat::new_rows_cols_with_default(360, 640, core::CV_8UC3, core::Scalar::all(0.0)).unwrap();
+        let mut dialog_image = black_image.clone();
+        imgproc::rectangle(&mut dialog_image, core::Rect{ x: 160, y: 90, width: 320, height: 180 },
+            core::Scalar::all(255.0), -1, imgproc::LINE_8, 0).unwrap();
+        imgcodecs::imwrite(&dir.join("dialog.png").to_string_lossy(), &dialog_image, &core::Vector::new()).unwrap();
+        imgcodecs::imwrite(&dir.join("black.png").to_string_lossy(), &black_image, &core::Vector::new()).unwrap();
+        let scene_graph: SceneGraph = serde_yaml::from_str(&format!(
+            "scenes:\n  - scene: Dialog\n    from: [Result]\n    judgments:\n      - name: battle_retry\n        color: '{}'\n",
+            dir.join("dialog.png").to_string_lossy()
+        )).unwrap();
+
+        // black.png はわざと間違えた期待値にする
+        std::fs::write(dir.join(EXPECTED_FILE), concat!(
+            "scene: Dialog\n",
+            "min_accuracy: 0.5\n",
+            "repeat: 1\n",
+            "frames:\n",
+            "  - file: dialog.png\n",
+            "    is_scene: true\n",
+            "  - file: black.png\n",
+            "    is_scene: true\n",
+        )).unwrap();
+        let report = run_fixture_with(&dir, &scene_graph).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(report.frame_count, 2);
+        assert_eq!((report.check_count, report.correct_count), (2, 1));
+        assert_eq!(report.failure_list, vec!["black.png: is_scene expected true, but false".to_string()]);
+        assert!(report.is_passed());
+    }
+
+    // 録画したセッションはリポジトリに含めていないので、test/session に置いた時だけ --ignored で実行する
+    #[test]
+    #[ignore = "needs a recorded session and expected.yml under test/session"]
+    fn test_session_fixture() {
+        let report_list = run_session_all(Path::new(SESSION_ROOT)).unwrap();
+        for report in &report_list {
+            print!("{}", report);
+        }
+
+        let failed_list = report_list.iter()
+            .filter(|report| !report.is_passed())
+            .map(|report| report.name.clone())
+            .collect::<Vec<_>>();
+        assert!(failed_list.is_empty(), "session failed: {:?}", failed_list);
+    }
+
+    #[test]
+    fn test_run_session_with_synthetic_frames() {
+        let dir = std::env::temp_dir().join(format!("smabrog_session_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("frames")).unwrap();
+
+        let black_image = core::Mat::new_rows_cols_with_default(360, 640, core::CV_8UC3, core::Scalar::all(0.0)).unwrap();
+        for frame_number in 0..3 {
+            imgcodecs::imwrite(&dir.join("frames").join(format!("{:04}.png", frame_number)).to_string_lossy(), &black_image, &core::Vector::new()).unwrap();
+        }
+        std::fs::write(dir.join(EXPECTED_FILE), "source: frames\nbattles: []\n").unwrap();
+
+        // 何も検出しない scene_graph なので、全フレーム流しても戦歴は保存されない
+        let battle_history = InMemoryBattleHistory::new();
+        let context = AppContext::with_battle_history(Box::new(battle_history.clone()));
+        let report = run_session_with(&dir, SceneGraph::default(), context, &battle_history).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(report.frame_count, 3);
+        assert_eq!((report.check_count, report.correct_count), (1, 1));
+        assert!(report.is_passed());
+    }
+
+    #[test]
+    fn test_in_memory_battle_history() {
+        let battle_history = InMemoryBattleHistory::new();
+        let mut context = AppContext::with_battle_history(Box::new(battle_history.clone()));
+        let mut data = SmashbrosData::default();
+        data.initialize_battle(2, true);
+        data.guess_character_name(0, "MARIO".to_string(), &context.smashbros_resource());
+        data.start_battle();
+        data.finish_battle();
+
+        data.save_battle_with(&mut context);
+        assert!(data.get_id().is_some());
+        assert_eq!(battle_history.get_data_list().len(), 1);
+
+        // 保存済みのものは二重に保存されない
+        data.save_battle_with(&mut context);
+        assert_eq!(battle_history.get_data_list().len(), 1);
+
+        data.set_order(0, 1);
+        data.update_battle_with(&mut context);
+        assert_eq!(battle_history.get_data_list()[0].get_order(0), 1);
+    }
+}
@@ -1,22 +1,12 @@
 use super::*;
 
 /// 試合終わりの検出 ("GAME SET" or "TIME UP")
+#[derive(Default)]
 pub struct GameEndScene {
     game_set_scene_judgment: SceneJudgment,
     time_up_scene_judgment: SceneJudgment,
 }
-impl Default for GameEndScene {
-    fn default() -> Self {
-        Self {
-            game_set_scene_judgment: SceneJudgment::new_gray_with_lang("game_set")
-                .set_border(0.85),
-            time_up_scene_judgment: SceneJudgment::new_gray_with_lang("time_up")
-                .set_border(0.85),
-        }
-    }
-}
 impl SceneTrait for GameEndScene {
-    fn change_language(&mut self) { *self = Self::default(); }
     fn get_id(&self) -> i32 { SceneList::GameEnd as i32 }
     fn get_prev_match(&self) -> Option<&SceneJudgment> {
         // 高い方を返す
@@ -27,10 +17,11 @@ impl SceneTrait for GameEndScene {
         Some(&self.game_set_scene_judgment)
     }
     
-    fn continue_match(&self, now_scene: SceneList) -> bool {
-        match now_scene {
-            SceneList::GamePlaying => true,
-            _ => false,
+    fn get_judgment_mut(&mut self, name: &str) -> Option<&mut SceneJudgment> {
+        match name {
+            "game_set" => Some(&mut self.game_set_scene_judgment),
+            "time_up" => Some(&mut self.time_up_scene_judgment),
+            _ => None,
         }
     }
 
@@ -48,8 +39,6 @@ impl SceneTrait for GameEndScene {
         Ok(self.game_set_scene_judgment.is_near_match() || self.time_up_scene_judgment.is_near_match())
     }
 
-    fn to_scene(&self, _now_scene: SceneList) -> SceneList { SceneList::GameEnd }
-
     fn recoding_scene(&mut self, _capture: &core::Mat) -> opencv::Result<()> { Ok(()) }
     fn is_recoded(&self) -> bool { false }
     fn detect_data(&mut self, _smashbros_data: &mut SmashbrosData) -> opencv::Result<()> { Ok(()) }
@@ -10,22 +10,9 @@ pub struct GamePlayingScene {
 impl Default for GamePlayingScene {
     fn default() -> Self {
         Self {
-            stock_black_scene_judgment: SceneJudgment::new_gray(
-                    imgcodecs::imread("resource/stock_hyphen_color_black.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                    Some(imgcodecs::imread("resource/stock_hyphen_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-                ).unwrap()
-                .set_size(core::Rect{    // 参照される回数が多いので matchTemplate する大きさ減らす
-                    x:0, y:100, width:640, height: 100
-                })
-                .set_border(0.95),
-            stock_white_scene_judgment: SceneJudgment::new_gray(
-                    imgcodecs::imread("resource/stock_hyphen_color_white.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                    Some(imgcodecs::imread("resource/stock_hyphen_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-                ).unwrap()
-                .set_size(core::Rect{
-                    x:0, y:100, width:640, height: 100
-                })
-                .set_border(0.95),
+            // 判定は scene_graph.yml から作られる
+            stock_black_scene_judgment: SceneJudgment::default(),
+            stock_white_scene_judgment: SceneJudgment::default(),
             stock_number_mask: imgcodecs::imread("resource/stock_number_mask.png", imgcodecs::IMREAD_GRAYSCALE).unwrap()
         }
     }
@@ -41,10 +28,11 @@ impl SceneTrait for GamePlayingScene {
         Some(&self.stock_black_scene_judgment)
     }
     
-    fn continue_match(&self, now_scene: SceneList) -> bool {
-        match now_scene {
-            SceneList::GamePlaying => true,
-            _ => false,
+    fn get_judgment_mut(&mut self, name: &str) -> Option<&mut SceneJudgment> {
+        match name {
+            "stock_black" => Some(&mut self.stock_black_scene_judgment),
+            "stock_white" => Some(&mut self.stock_white_scene_judgment),
+            _ => None,
         }
     }
 
@@ -61,9 +49,6 @@ impl SceneTrait for GamePlayingScene {
         }
     }
 
-    // このシーンは [GameEnd] が検出されるまで待つ(つまり現状維持)
-    fn to_scene(&self, _now_scene: SceneList) -> SceneList { SceneList::GamePlaying }
-
     fn recoding_scene(&mut self, _capture_image: &core::Mat) -> opencv::Result<()> { Ok(()) }
     fn is_recoded(&self) -> bool { false }
     fn detect_data(&mut self, _smashbros_data: &mut SmashbrosData) -> opencv::Result<()> { Ok(()) }
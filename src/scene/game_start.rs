@@ -2,17 +2,17 @@ use super::*;
 
 /// 試合開始の検出
 pub struct GameStartScene {
+    // BGM 名の推測に使う
+    context: AppContext,
     scene_judgment: SceneJudgment,
     is_scene: bool,
 }
-impl Default for GameStartScene {
-    fn default() -> Self {
+impl GameStartScene {
+    pub fn new(context: &AppContext) -> Self {
         Self {
-            scene_judgment: SceneJudgment::new(
-                    imgcodecs::imread("resource/battle_time_color.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                    Some(imgcodecs::imread("resource/battle_time_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-                ).unwrap()
-                .set_border(0.90),
+            context: context.clone(),
+            // 判定は scene_graph.yml から作られる
+            scene_judgment: SceneJudgment::default(),
             is_scene: false,
         }
     }
@@ -21,10 +21,10 @@ impl SceneTrait for GameStartScene {
     fn get_id(&self) -> i32 { SceneList::GameStart as i32 }
     fn get_prev_match(&self) -> Option<&SceneJudgment> { Some(&self.scene_judgment) }
     
-    fn continue_match(&self, now_scene: SceneList) -> bool {
-        match now_scene {
-            SceneList::HamVsSpam => true,
-            _ => false,
+    fn get_judgment_mut(&mut self, name: &str) -> Option<&mut SceneJudgment> {
+        match name {
+            "battle_time" => Some(&mut self.scene_judgment),
+            _ => None,
         }
     }
 
@@ -55,9 +55,6 @@ impl SceneTrait for GameStartScene {
         Ok(false)
     }
 
-    // now_scene が GameStart になることはない("GO"を検出した時はもう GamePlaying であるため)
-    fn to_scene(&self, _now_scene: SceneList) -> SceneList { SceneList::GamePlaying }
-
     fn recoding_scene(&mut self, _capture: &core::Mat) -> opencv::Result<()> { Ok(()) }
     fn is_recoded(&self) -> bool { false }
     fn detect_data(&mut self, _smashbros_data: &mut SmashbrosData) -> opencv::Result<()> { Ok(()) }
@@ -100,7 +97,7 @@ impl GameStartScene {
         }
         let bgm_text = bgm_text.replace(" ", "");
 
-        smashbros_data.guess_bgm_name(bgm_text);
+        smashbros_data.guess_bgm_name(bgm_text, &self.context.smashbros_resource());
 
         Ok(())
     }
@@ -0,0 +1,322 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use super::*;
+
+/// シーン遷移の定義 (scene_graph.yml)
+/// 各シーンをどのシーンから検出して、どのシーンに遷移するかと、判定に使うテンプレートを持つ
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SceneGraph {
+    #[serde(default)]
+    pub scenes: Vec<SceneNode>,
+}
+
+/// シーン遷移の 1 シーン分
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SceneNode {
+    pub scene: SceneList,
+    /// このシーンを検出する、現在のシーンの一覧
+    #[serde(default)]
+    pub from: Vec<SceneList>,
+    /// true なら except 以外の全てのシーンから検出する
+    #[serde(default)]
+    pub from_any: bool,
+    #[serde(default)]
+    pub except: Vec<SceneList>,
+    /// 検出した後に遷移するシーン (None なら現在のシーンのまま)
+    #[serde(default)]
+    pub to: Option<SceneList>,
+    #[serde(default)]
+    pub judgments: Vec<JudgmentConfig>,
+    /// このシーンで発行を待つ複雑なイベント
+    #[serde(default)]
+    pub events: Vec<ManageEventKind>,
+}
+impl SceneNode {
+    /// now_scene の時にこのシーンを検出するか
+    pub fn is_from(&self, now_scene: SceneList) -> bool {
+        if self.from_any {
+            !self.except.contains(&now_scene)
+        } else {
+            self.from.contains(&now_scene)
+        }
+    }
+
+    /// judgments で scene の判定を作り直す (読み込めなかった判定は何にも一致しなくなる)
+    pub fn apply_judgments(&self, scene: &mut dyn SceneTrait, lang: &str) {
+        for config in &self.judgments {
+            let scene_judgment = match scene.get_judgment_mut(&config.name) {
+                Some(scene_judgment) => scene_judgment,
+                None => {
+                    log::warn!("scene_graph: {:?} has no judgment named {}.", self.scene, config.name);
+                    continue;
+                },
+            };
+
+            *scene_judgment = match SceneJudgment::from_config(config, lang) {
+                Ok(new_scene_judgment) => new_scene_judgment,
+                Err(e) => {
+                    log::error!("scene_graph: {:?}.{}: {}", self.scene, config.name, e);
+                    SceneJudgment::default()
+                },
+            };
+        }
+    }
+}
+
+/// scene_graph.yml の events に書ける、複数フレームの状態から発行するイベント
+/// (RuleDecided, BgmDecided はそのシーンに遷移した時から、ResultReplayEnded はそのシーンの録画が終わった時から発行を待つ)
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum ManageEventKind {
+    RuleDecided,
+    BgmDecided,
+    ResultReplayEnded,
+}
+
+/// SceneJudgment を作成するための設定
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JudgmentConfig {
+    /// シーン毎に決まっている SceneJudgment の名前
+    pub name: String,
+    /// {lang} は言語コードに置き換わる
+    pub color: String,
+    #[serde(default)]
+    pub mask: Option<String>,
+    #[serde(default)]
+    pub format: JudgmentFormat,
+    #[serde(default)]
+    pub border: Option<f64>,
+    #[serde(default)]
+    pub roi: Option<JudgmentRoi>,
+    #[serde(default)]
+    pub prefilter: JudgmentPrefilter,
+    /// is_scene で必ず最初に判定されるなら true にして、他のシーンと並列で先に判定しておく (シーン毎に 1 つ)
+    #[serde(default)]
+    pub prefetch: bool,
+}
+
+/// テンプレートマッチングする形式
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum JudgmentFormat {
+    Gray, Rgb, Trans,
+}
+impl Default for JudgmentFormat {
+    fn default() -> Self { Self::Rgb }
+}
+
+/// 検出領域
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct JudgmentRoi {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+impl From<JudgmentRoi> for core::Rect {
+    fn from(roi: JudgmentRoi) -> Self {
+        core::Rect{ x: roi.x, y: roi.y, width: roi.width, height: roi.height }
+    }
+}
+
+/// validate で見つかった問題
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SceneGraphIssue {
+    /// 同じシーンが 2 回以上定義されている
+    Duplicated(SceneList),
+    /// 検出できるシーンなのに定義されていない
+    Missing(SceneList),
+    /// 最初のシーン (Unknown) からどう遷移しても検出されない
+    Unreachable(SceneList),
+    /// prefetch: true の判定が 2 つ以上ある
+    MultiplePrefetch(SceneList),
+}
+impl std::fmt::Display for SceneGraphIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Duplicated(scene) => write!(f, "{:?} is defined more than once.", scene),
+            Self::Missing(scene) => write!(f, "{:?} is not defined.", scene),
+            Self::Unreachable(scene) => write!(f, "{:?} is unreachable from Unknown.", scene),
+            Self::MultiplePrefetch(scene) => write!(f, "{:?} has more than one prefetch judgment.", scene),
+        }
+    }
+}
+
+impl SceneGraph {
+    pub const FILE_PATH: &'static str = "scene_graph.yml";
+    // ファイルが無いときに使う、ビルド時の scene_graph.yml
+    const BUILTIN: &'static str = include_str!("../../scene_graph.yml");
+
+    /// path から読み込む
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+
+        Ok(serde_yaml::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    /// ビルド時の定義を返す
+    pub fn builtin() -> Self {
+        serde_yaml::from_str(Self::BUILTIN).expect("invalid builtin scene_graph.")
+    }
+
+    /// FILE_PATH があれば読み込んで、無いか壊れていればビルド時の定義を返す
+    pub fn load_or_builtin() -> Self {
+        let path = Path::new(Self::FILE_PATH);
+        let own = if path.is_file() {
+            match Self::load(path) {
+                Ok(own) => own,
+                Err(e) => {
+                    log::error!("invalid {}: {}", Self::FILE_PATH, e);
+                    Self::builtin()
+                },
+            }
+        } else {
+            Self::builtin()
+        };
+
+        for issue in own.validate() {
+            log::warn!("scene_graph: {}", issue);
+        }
+
+        own
+    }
+
+    /// scene の定義を返す
+    pub fn get_node(&self, scene: SceneList) -> Option<&SceneNode> {
+        self.scenes.iter().find(|node| node.scene == scene)
+    }
+
+    /// scene の判定を定義の judgments で作り直す (定義が無ければ何もしない)
+    pub fn apply_judgments(&self, scene: &mut dyn SceneTrait, lang: &str) {
+        if let Some(node) = self.get_node(SceneList::to_scene_list(scene.get_id())) {
+            node.apply_judgments(scene, lang);
+        }
+    }
+
+    /// now_scene の時に scene を検出するか
+    pub fn continue_match(&self, scene: SceneList, now_scene: SceneList) -> bool {
+        self.get_node(scene).map_or(false, |node| node.is_from(now_scene))
+    }
+
+    /// now_scene の時に scene を検出したら、どのシーンに遷移するか
+    pub fn to_scene(&self, scene: SceneList, now_scene: SceneList) -> SceneList {
+        self.get_node(scene).and_then(|node| node.to).unwrap_or(now_scene)
+    }
+
+    // シーンオブジェクトがあって、検出しうるシーンか
+    fn is_detectable(scene: SceneList) -> bool {
+        SceneList::Unknown != scene
+    }
+
+    /// Unknown から到達できる (現在のシーンになりうる) シーンを返す
+    pub fn reachable_scene_list(&self) -> HashSet<SceneList> {
+        let mut reachable_list = HashSet::new();
+        reachable_list.insert(SceneList::Unknown);
+        loop {
+            let mut next_list = reachable_list.clone();
+            for node in &self.scenes {
+                if let Some(to_scene) = node.to {
+                    if reachable_list.iter().any(|now_scene| node.is_from(*now_scene)) {
+                        next_list.insert(to_scene);
+                    }
+                }
+            }
+
+            if next_list.len() == reachable_list.len() {
+                return reachable_list;
+            }
+            reachable_list = next_list;
+        }
+    }
+
+    /// 定義の問題を探す
+    pub fn validate(&self) -> Vec<SceneGraphIssue> {
+        let mut issue_list = Vec::new();
+
+        let mut defined_list = HashSet::new();
+        for node in &self.scenes {
+            if !defined_list.insert(node.scene) {
+                issue_list.push(SceneGraphIssue::Duplicated(node.scene));
+            }
+        }
+
+        for scene in SceneList::iter().filter(|scene| Self::is_detectable(*scene)) {
+            if !defined_list.contains(&scene) {
+                issue_list.push(SceneGraphIssue::Missing(scene));
+            }
+        }
+
+        let reachable_list = self.reachable_scene_list();
+        for node in &self.scenes {
+            if !reachable_list.iter().any(|now_scene| node.is_from(*now_scene)) {
+                issue_list.push(SceneGraphIssue::Unreachable(node.scene));
+            }
+            if 1 < node.judgments.iter().filter(|config| config.prefetch).count() {
+                issue_list.push(SceneGraphIssue::MultiplePrefetch(node.scene));
+            }
+        }
+
+        issue_list
+    }
+
+    /// Graphviz の dot 形式で遷移図を返す
+    pub fn to_dot(&self) -> String {
+        let mut reachable_list = self.reachable_scene_list().into_iter().collect::<Vec<_>>();
+        reachable_list.sort_by_key(|scene| *scene as i32);
+
+        let mut dot = String::from("digraph scene_graph {\n");
+        for node in &self.scenes {
+            for now_scene in reachable_list.iter().filter(|now_scene| node.is_from(**now_scene)) {
+                if let Some(to_scene) = node.to {
+                    dot += &format!("    {:?} -> {:?} [label={:?}];\n", now_scene, to_scene, format!("{:?}", node.scene));
+                }
+            }
+        }
+        dot += "}\n";
+
+        dot
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_scene_graph() {
+        let scene_graph = SceneGraph::builtin();
+        assert_eq!(scene_graph.validate(), Vec::new());
+
+        assert!(scene_graph.continue_match(SceneList::ReadyToFight, SceneList::Result));
+        assert!(!scene_graph.continue_match(SceneList::ReadyToFight, SceneList::ReadyToFight));
+        assert!(scene_graph.continue_match(SceneList::Loading, SceneList::GamePlaying));
+        assert_eq!(scene_graph.to_scene(SceneList::GameStart, SceneList::HamVsSpam), SceneList::GamePlaying);
+        assert_eq!(scene_graph.to_scene(SceneList::Loading, SceneList::Result), SceneList::Result);
+        assert_eq!(scene_graph.get_node(SceneList::Matching).unwrap().events, vec![ManageEventKind::RuleDecided, ManageEventKind::BgmDecided]);
+        assert_eq!(scene_graph.get_node(SceneList::Result).unwrap().events, vec![ManageEventKind::ResultReplayEnded]);
+
+        let dot = scene_graph.to_dot();
+        assert!(dot.contains("HamVsSpam -> GamePlaying [label=\"GameStart\"];"));
+        assert!(dot.contains("Result -> Unknown [label=\"Dialog\"];"));
+    }
+
+    #[test]
+    fn test_validate_scene_graph() {
+        let mut scene_graph = SceneGraph::builtin();
+        // HamVsSpam に遷移できなくすると GameStart 以降も検出されなくなる
+        scene_graph.scenes.retain(|node| node.scene != SceneList::HamVsSpam);
+        scene_graph.scenes.push(scene_graph.get_node(SceneList::Dialog).unwrap().clone());
+        for config in scene_graph.scenes.iter_mut().find(|node| node.scene == SceneList::Matching).unwrap().judgments.iter_mut() {
+            config.prefetch = true;
+        }
+
+        let issue_list = scene_graph.validate();
+        assert!(issue_list.contains(&SceneGraphIssue::Duplicated(SceneList::Dialog)));
+        assert!(issue_list.contains(&SceneGraphIssue::Missing(SceneList::HamVsSpam)));
+        assert!(issue_list.contains(&SceneGraphIssue::Unreachable(SceneList::GameStart)));
+        assert!(issue_list.contains(&SceneGraphIssue::Unreachable(SceneList::GameEnd)));
+        assert!(!issue_list.contains(&SceneGraphIssue::Unreachable(SceneList::Matching)));
+        assert!(issue_list.contains(&SceneGraphIssue::MultiplePrefetch(SceneList::Matching)));
+        assert!(!issue_list.contains(&SceneGraphIssue::MultiplePrefetch(SceneList::Dialog)));
+    }
+}
@@ -3,6 +3,8 @@ use super::*;
 /// キャラクターが大きく表示されてる画面
 /// save: キャラクター名, ルール名, 取れるなら[時間,ストック,HP]
 pub struct HamVsSpamScene {
+    // 言語と、キャラ名の推測に使う
+    context: AppContext,
     vs_scene_judgment: SceneJudgment,
     rule_stock_scene_judgment: SceneJudgment,
     rule_time_scene_judgment: SceneJudgment,
@@ -10,40 +12,32 @@ pub struct HamVsSpamScene {
     rule_guesser: ValueGuesser<BattleRule>,
     buffer: CaptureFrameStore,
 }
-impl Default for HamVsSpamScene {
-    fn default() -> Self {
+impl HamVsSpamScene {
+    pub fn new(context: &AppContext) -> Self {
         Self {
-            vs_scene_judgment: SceneJudgment::new_with_lang("vs"),
-            rule_stock_scene_judgment: SceneJudgment::new(
-                    imgcodecs::imread("resource/rule_stock_color.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                    Some(imgcodecs::imread("resource/rule_stock_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-                ).unwrap()
-                .set_border(0.95),
-            rule_time_scene_judgment: SceneJudgment::new(
-                    imgcodecs::imread("resource/rule_time_color.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                    Some(imgcodecs::imread("resource/rule_time_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-                ).unwrap()
-                .set_border(0.95),
-            rule_stamina_scene_judgment: SceneJudgment::new(
-                    imgcodecs::imread("resource/rule_hp_color.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                    Some(imgcodecs::imread("resource/rule_hp_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-                ).unwrap()
-                .set_border(0.95),
+            context: context.clone(),
+            // 判定は scene_graph.yml から作られる
+            vs_scene_judgment: SceneJudgment::default(),
+            rule_stock_scene_judgment: SceneJudgment::default(),
+            rule_time_scene_judgment: SceneJudgment::default(),
+            rule_stamina_scene_judgment: SceneJudgment::default(),
             rule_guesser: ValueGuesser::new(BattleRule::Unknown),
-            buffer: CaptureFrameStore::default()
+            buffer: CaptureFrameStore::new(context.codec())
                 .set_file_name("ham_vs_spam.avi".to_string()),
         }
     }
 }
 impl SceneTrait for HamVsSpamScene {
-    fn change_language(&mut self) { *self = Self::default(); }
     fn get_id(&self) -> i32 { SceneList::HamVsSpam as i32 }
     fn get_prev_match(&self) -> Option<&SceneJudgment> { Some(&self.vs_scene_judgment) }
     
-    fn continue_match(&self, now_scene: SceneList) -> bool {
-        match now_scene {
-            SceneList::Matching => true,
-            _ => false,
+    fn get_judgment_mut(&mut self, name: &str) -> Option<&mut SceneJudgment> {
+        match name {
+            "vs" => Some(&mut self.vs_scene_judgment),
+            "rule_stock" => Some(&mut self.rule_stock_scene_judgment),
+            "rule_time" => Some(&mut self.rule_time_scene_judgment),
+            "rule_stamina" => Some(&mut self.rule_stamina_scene_judgment),
+            _ => None,
         }
     }
 
@@ -67,7 +61,10 @@ impl SceneTrait for HamVsSpamScene {
         Ok(self.vs_scene_judgment.is_near_match())
     }
 
-    fn to_scene(&self, _now_scene: SceneList) -> SceneList { SceneList::HamVsSpam }
+    // is_scene と同じく、すべてのプレイヤーが確定している場合は先に判定もしない
+    fn is_prefetchable(&self, smashbros_data: &SmashbrosData) -> bool {
+        !smashbros_data.all_decided_character_name()
+    }
 
     fn recoding_scene(&mut self, capture_image: &core::Mat) -> opencv::Result<()> { self.buffer.recoding_frame(capture_image) }
     fn is_recoded(&self) -> bool { self.buffer.is_filled() }
@@ -80,21 +77,33 @@ impl SceneTrait for HamVsSpamScene {
             rule_stamina_scene_judgment,
             rule_guesser,
             buffer,
+            context,
             ..
         } = self;
 
+        let smashbros_resource = context.smashbros_resource();
         buffer.replay_frame(|frame| {
             Self::captured_rules(&frame, smashbros_data, rule_guesser, rule_stock_scene_judgment, rule_time_scene_judgment, rule_stamina_scene_judgment)?;
-            Self::captured_character_name(&frame, smashbros_data)?;
+            Self::captured_character_name(&frame, smashbros_data, &smashbros_resource)?;
 
             Ok(false)
         })?;
 
         Ok(())
     }
+
+    fn detect_frame(&mut self, capture_image: &core::Mat, smashbros_data: &mut SmashbrosData) -> opencv::Result<()> {
+        Self::captured_rules(
+            capture_image, smashbros_data, &mut self.rule_guesser,
+            &mut self.rule_stock_scene_judgment, &mut self.rule_time_scene_judgment, &mut self.rule_stamina_scene_judgment
+        )?;
+        Self::captured_character_name(capture_image, smashbros_data, &self.context.smashbros_resource())?;
+
+        Ok(())
+    }
 }
 impl HamVsSpamScene {
-    pub fn captured_character_name(capture_image: &core::Mat, smashbros_data: &mut SmashbrosData) -> opencv::Result<bool> {
+    pub fn captured_character_name(capture_image: &core::Mat, smashbros_data: &mut SmashbrosData, smashbros_resource: &SmashbrosResource) -> opencv::Result<bool> {
         if smashbros_data.all_decided_character_name() {
             return Ok(true);
         }
@@ -136,7 +145,7 @@ impl HamVsSpamScene {
             // tesseract でキャラ名取得して, 余計な文字を排除
             let text = &async_std::task::block_on(utils::run_ocr_with_upper_alpha(&name_area_image)).unwrap();
             if let Some(caps) = re.captures( text ) {
-                smashbros_data.guess_character_name( player_count, String::from(&caps[1]) );
+                smashbros_data.guess_character_name( player_count, String::from(&caps[1]), smashbros_resource );
             }
         }
 
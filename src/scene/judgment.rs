@@ -10,6 +10,13 @@ pub struct SceneJudgment {
     pub prev_match_ratio: f64,
     pub prev_match_point: core::Point,
     border_match_ratio: f64,
+    // SceneManager が処理しているフレーム番号と、prefetch_captured_scene で先に判定したフレーム番号
+    frame_number: Option<u64>,
+    prefetched_frame_number: Option<u64>,
+    prefilter: JudgmentPrefilter,
+    // テンプレート側の簡易判定用の特徴量 (初めて使う時に作る)
+    prefilter_feature: Option<PrefilterFeature>,
+    prefilter_stats: PrefilterStats,
 }
 impl Default for SceneJudgment {
     fn default() -> Self {
@@ -22,30 +29,15 @@ impl Default for SceneJudgment {
             border_match_ratio: 0.98,
             prev_match_ratio: 0f64,
             prev_match_point: Default::default(),
+            frame_number: None,
+            prefetched_frame_number: None,
+            prefilter: JudgmentPrefilter::None,
+            prefilter_feature: None,
+            prefilter_stats: PrefilterStats::default(),
         }
     }
 }
 impl SceneJudgment {
-    // 言語によって読み込むファイルを変えて作成する
-    pub fn news_with_lang<T>(new_func: T, name: &str) -> Self
-    where T: Fn(core::Mat, Option<core::Mat>) -> opencv::Result<Self>
-    {
-        use crate::resource::LANG_LOADER;
-        use i18n_embed::LanguageLoader;
-
-        let lang = LANG_LOADER().get().current_language().language.clone();
-        let path = format!("resource/{}_{}", lang.as_str(), name);
-
-        new_func(
-            imgcodecs::imread(&format!("{}_color.png", path), imgcodecs::IMREAD_UNCHANGED).unwrap(),
-            Some(imgcodecs::imread(&format!("{}_mask.png", path), imgcodecs::IMREAD_UNCHANGED).unwrap())
-        ).unwrap()
-    }
-
-    pub fn new_gray_with_lang(name: &str) -> Self { Self::news_with_lang(Self::new_gray, name) }
-    pub fn new_with_lang(name: &str) -> Self { Self::news_with_lang(Self::new, name) }
-    // fn new_trans_with_lang(name: &str) -> Self { Self::news_with_lang(Self::new_trans, name) }
-
     /// color_format に {hoge}_image を強制して、一致させるシーン
     pub fn new_color_format(color_image: core::Mat, mask_image: Option<core::Mat>, color_format: ColorFormat) -> opencv::Result<Self> {
         let mut converted_color_image = core::Mat::default();
@@ -106,6 +98,39 @@ impl SceneJudgment {
         })
     }
 
+    /// scene_graph.yml の設定から作成する ({lang} は lang に置き換える)
+    pub fn from_config(config: &JudgmentConfig, lang: &str) -> anyhow::Result<Self> {
+        let read_image = |path: &str| -> anyhow::Result<core::Mat> {
+            let path = path.replace("{lang}", lang);
+            let image = imgcodecs::imread(&path, imgcodecs::IMREAD_UNCHANGED)?;
+            if 0 == image.cols() {
+                anyhow::bail!("not found template: {}", path);
+            }
+
+            Ok(image)
+        };
+
+        let color_image = read_image(&config.color)?;
+        let mask_image = match &config.mask {
+            Some(mask) => Some(read_image(mask)?),
+            None => None,
+        };
+
+        let mut own = match config.format {
+            JudgmentFormat::Gray => Self::new_gray(color_image, mask_image)?,
+            JudgmentFormat::Rgb => Self::new(color_image, mask_image)?,
+            JudgmentFormat::Trans => Self::new_trans(color_image, mask_image)?,
+        };
+        if let Some(border) = config.border {
+            own = own.set_border(border);
+        }
+        if let Some(roi) = config.roi {
+            own = own.set_size(roi.into());
+        }
+
+        Ok(own.set_prefilter(config.prefilter))
+    }
+
     /// 一致率の上限の設定 [default = 0.99]
     pub fn set_border(mut self, border_match_ratio: f64) -> Self {
         self.border_match_ratio = border_match_ratio;
@@ -125,12 +150,63 @@ impl SceneJudgment {
         if let Some(mask_image) = self.mask_image {
             self.mask_image = Some(core::Mat::roi(&mask_image, new_size).unwrap());
         }
+        self.prefilter_feature = None;
 
         self
     }
 
+    /// matchTemplate の前に行う簡易判定の設定 [default = None]
+    pub fn set_prefilter(mut self, prefilter: JudgmentPrefilter) -> Self {
+        self.prefilter = prefilter;
+        self.prefilter_feature = None;
+
+        self
+    }
+
+    /// 簡易判定で matchTemplate を飛ばせた回数を返す
+    pub fn get_prefilter_stats(&self) -> PrefilterStats {
+        self.prefilter_stats
+    }
+
     /// キャプチャされた画像とシーンとをテンプレートマッチングして、一致した確率と位置を返す
+    /// 同じフレーム番号で prefetch_captured_scene されていればその結果を使う
     pub async fn match_captured_scene(&mut self, captured_image: &core::Mat) -> opencv::Result<()> {
+        if self.is_prefetched() {
+            // 同じフレームで 2 回目に判定する時は matchTemplate しなおす
+            self.prefetched_frame_number = None;
+            return Ok(());
+        }
+
+        self.match_template_captured_scene(captured_image)
+    }
+
+    /// SceneManager が処理しているフレーム番号を設定する (prefetch した結果は同じフレーム番号の間だけ使われる)
+    pub fn set_frame_number(&mut self, frame_number: u64) {
+        self.frame_number = Some(frame_number);
+    }
+
+    /// 同じフレームの match_captured_scene の前に、別スレッドから先にテンプレートマッチングしておく
+    /// (別スレッドで呼ばれるので、self と captured_image 以外は触らない)
+    pub fn prefetch_captured_scene(&mut self, captured_image: &core::Mat) -> opencv::Result<()> {
+        self.prefetched_frame_number = None;
+        self.match_template_captured_scene(captured_image)?;
+        self.prefetched_frame_number = self.frame_number;
+
+        Ok(())
+    }
+
+    // 今のフレーム番号で prefetch_captured_scene した結果があるか
+    fn is_prefetched(&self) -> bool {
+        self.frame_number.is_some() && self.prefetched_frame_number == self.frame_number
+    }
+
+    fn match_template_captured_scene(&mut self, captured_image: &core::Mat) -> opencv::Result<()> {
+        if self.color_image.empty() {
+            // scene_graph から作られていない判定は何にも一致しない
+            self.prev_match_ratio = 0.0;
+            return Ok(());
+        }
+
         let mut result = core::Mat::default();
         let mut converted_captured_image = core::Mat::default();
         if let Some(image_size) = self.image_size {
@@ -159,12 +235,20 @@ impl SceneJudgment {
                     converted_captured_image = temp_captured_image;
                 }
 
+                if !self.is_plausible_by_prefilter(&converted_captured_image)? {
+                    return Ok(());
+                }
+
                 imgproc::match_template(&converted_captured_image, &self.color_image, &mut result,
                     imgproc::TM_CCOEFF_NORMED, &core::no_array())?;
             },
             ColorFormat::RGBA => {
                 // 透過画像の場合は普通に trans_mask 付きでテンプレートマッチング
                 // 透過画像の時はそもそも None の状態になることはない
+                if !self.is_plausible_by_prefilter(&converted_captured_image)? {
+                    return Ok(());
+                }
+
                 if let Some(trans_mask_image) = &self.trans_mask_image {
                     imgproc::match_template(&converted_captured_image, &self.color_image, &mut result,
                         imgproc::TM_CCORR_NORMED, &trans_mask_image)?;
@@ -184,6 +268,57 @@ impl SceneJudgment {
         Ok(())
     }
 
+    // 簡易判定をして、matchTemplate するまでもなく一致しないなら一致度を 0 にして false を返す
+    fn is_plausible_by_prefilter(&mut self, converted_captured_image: &core::Mat) -> opencv::Result<bool> {
+        if JudgmentPrefilter::None == self.prefilter {
+            return Ok(true);
+        }
+
+        if self.prefilter_feature.is_none() {
+            // キャプチャ側と同じようにマスクをかけたテンプレートから作る
+            let masked_color_image = self.mask_for_prefilter(&self.color_image)?;
+            self.prefilter_feature = self.prefilter.make_feature(&masked_color_image)?;
+        }
+
+        // 透過画像はキャプチャ側にも透過マスクをかけて、透過している部分を比べないようにする ([2値 | RGB]画像は既にかかっている)
+        let captured_feature = match self.judgment_type {
+            ColorFormat::RGBA => self.prefilter.make_feature(&self.mask_for_prefilter(converted_captured_image)?)?,
+            _ => self.prefilter.make_feature(converted_captured_image)?,
+        };
+        let (template_feature, captured_feature) = match (&self.prefilter_feature, captured_feature) {
+            (Some(template_feature), Some(captured_feature)) => (template_feature, captured_feature),
+            _ => return Ok(true),
+        };
+
+        self.prefilter_stats.check_count += 1;
+        if self.prefilter.is_plausible(template_feature, &captured_feature)? {
+            return Ok(true);
+        }
+
+        self.prefilter_stats.skip_count += 1;
+        self.prev_match_ratio = 0.0;
+
+        Ok(false)
+    }
+
+    // matchTemplate でかけるマスク (透過画像は透過マスク) を簡易判定用にかける (大きさが違えばかけない)
+    fn mask_for_prefilter(&self, image: &core::Mat) -> opencv::Result<core::Mat> {
+        let mask_image = match self.judgment_type {
+            ColorFormat::RGB | ColorFormat::GRAY => self.mask_image.as_ref(),
+            ColorFormat::RGBA => self.trans_mask_image.as_ref(),
+            _ => None,
+        };
+
+        let mut masked_image = image.clone();
+        if let Some(mask_image) = mask_image {
+            if mask_image.size()? == image.size()? {
+                core::bitwise_and(image, mask_image, &mut masked_image, &core::no_array())?;
+            }
+        }
+
+        Ok(masked_image)
+    }
+
     /// 前回のテンプレートマッチングで大体一致しているか
     pub fn is_near_match(&self) -> bool {
         self.border_match_ratio <= self.prev_match_ratio
@@ -194,3 +329,72 @@ impl SceneJudgment {
         self.border_match_ratio
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trans_prefilter() {
+        // 左半分だけ見る透過画像
+        let mut color_image = core::Mat::new_rows_cols_with_default(90, 160, core::CV_8UC3, core::Scalar::all(0.0)).unwrap();
+        imgproc::rectangle(&mut color_image, core::Rect{ x: 0, y: 0, width: 40, height: 90 },
+            core::Scalar::all(255.0), -1, imgproc::LINE_8, 0).unwrap();
+        let mut mask_image = core::Mat::new_rows_cols_with_default(90, 160, core::CV_8UC3, core::Scalar::all(0.0)).unwrap();
+        imgproc::rectangle(&mut mask_image, core::Rect{ x: 0, y: 0, width: 80, height: 90 },
+            core::Scalar::all(255.0), -1, imgproc::LINE_8, 0).unwrap();
+
+        // 透過している右半分だけ違うキャプチャは matchTemplate させる
+        let mut captured_image = color_image.clone();
+        imgproc::rectangle(&mut captured_image, core::Rect{ x: 80, y: 0, width: 80, height: 45 },
+            core::Scalar::all(255.0), -1, imgproc::LINE_8, 0).unwrap();
+
+        let mut scene_judgment = SceneJudgment::new_trans(color_image, Some(mask_image)).unwrap()
+            .set_prefilter(JudgmentPrefilter::AverageHash { max_distance: 0 });
+        scene_judgment.prefetch_captured_scene(&captured_image).unwrap();
+        assert_eq!(scene_judgment.get_prefilter_stats(), PrefilterStats { check_count: 1, skip_count: 0 });
+
+        // 見えている左半分が違うキャプチャは飛ばす
+        let black_image = core::Mat::new_rows_cols_with_default(90, 160, core::CV_8UC3, core::Scalar::all(0.0)).unwrap();
+        scene_judgment.prefetch_captured_scene(&black_image).unwrap();
+        assert_eq!(scene_judgment.get_prefilter_stats(), PrefilterStats { check_count: 2, skip_count: 1 });
+        assert_eq!(scene_judgment.prev_match_ratio, 0.0);
+    }
+
+    #[test]
+    fn test_prefetch_frame_number() {
+        let black_image = core::Mat::new_rows_cols_with_default(90, 160, core::CV_8UC3, core::Scalar::all(0.0)).unwrap();
+        let mut white_image = black_image.clone();
+        imgproc::rectangle(&mut white_image, core::Rect{ x: 0, y: 0, width: 160, height: 90 },
+            core::Scalar::all(255.0), -1, imgproc::LINE_8, 0).unwrap();
+        let mut half_image = black_image.clone();
+        imgproc::rectangle(&mut half_image, core::Rect{ x: 0, y: 0, width: 80, height: 90 },
+            core::Scalar::all(255.0), -1, imgproc::LINE_8, 0).unwrap();
+        let half_template = core::Mat::roi(&half_image, core::Rect{ x: 40, y: 0, width: 80, height: 90 }).unwrap();
+
+        let mut scene_judgment = SceneJudgment::new(half_template.clone(), None).unwrap();
+        let matched_ratio = |scene_judgment: &mut SceneJudgment, image: &core::Mat| {
+            async_std::task::block_on(scene_judgment.match_captured_scene(image)).unwrap();
+            scene_judgment.prev_match_ratio
+        };
+        let half_ratio = matched_ratio(&mut scene_judgment, &half_image);
+        assert!(scene_judgment.is_near_match());
+
+        // 同じフレーム番号の間だけ prefetch した結果を使う
+        scene_judgment.set_frame_number(1);
+        scene_judgment.prefetch_captured_scene(&half_image).unwrap();
+        assert_eq!(matched_ratio(&mut scene_judgment, &white_image), half_ratio);
+        assert!(matched_ratio(&mut scene_judgment, &white_image) < half_ratio);
+
+        // 使われなかった prefetch は次のフレームに持ち越さない
+        scene_judgment.prefetch_captured_scene(&half_image).unwrap();
+        scene_judgment.set_frame_number(2);
+        assert!(matched_ratio(&mut scene_judgment, &white_image) < half_ratio);
+
+        // フレーム番号が無い (SceneManager の外) なら prefetch した結果は使わない
+        let mut scene_judgment = SceneJudgment::new(half_template, None).unwrap();
+        scene_judgment.prefetch_captured_scene(&half_image).unwrap();
+        assert!(matched_ratio(&mut scene_judgment, &white_image) < half_ratio);
+    }
+}
@@ -2,47 +2,14 @@ use super::*;
 
 /// マッチング中の画面 (CPUと戦えるあの画面)
 /// save: プレイヤー人数(2p, 4p)
+#[derive(Default)]
 pub struct MatchingScene {
     scene_judgment: SceneJudgment,
     scene_judgment_with4: SceneJudgment,
     scene_judgment_ooo_tournament: SceneJudgment,
     scene_judgment_smash_tournament: SceneJudgment,
 }
-impl Default for MatchingScene {
-    fn default() -> Self {
-        Self {
-            scene_judgment: SceneJudgment::new_with_lang("ready_ok")
-                .set_border(0.92)
-                .set_size(core::Rect{    // 参照される回数が多いので matchTemplate する大きさ減らす
-                    x:0, y:270, width:320, height: 90
-                }),
-            scene_judgment_with4: SceneJudgment::new_with_lang("with_4_battle")
-                .set_size(core::Rect{
-                    x:0, y:270, width:640, height: 90
-                }),
-            scene_judgment_ooo_tournament: SceneJudgment::new(
-                    imgcodecs::imread("resource/ooo_tournament_color.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                    Some(imgcodecs::imread("resource/tournament_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-                )
-                .unwrap()
-                .set_border(0.95)
-                .set_size(core::Rect{
-                    x:0, y:0, width:640, height: 30
-                }),
-            scene_judgment_smash_tournament: SceneJudgment::new(
-                    imgcodecs::imread("resource/smash_tournament_color.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                    Some(imgcodecs::imread("resource/tournament_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-                )
-                .unwrap()
-                .set_border(0.95)
-                .set_size(core::Rect{
-                    x:0, y:0, width:640, height: 30
-                }),
-        }
-    }
-}
 impl SceneTrait for MatchingScene {
-    fn change_language(&mut self) { *self = Self::default(); }
     fn get_id(&self) -> i32 { SceneList::Matching as i32 }
     fn get_prev_match(&self) -> Option<&SceneJudgment> {
         // 一番高い確率のものを返す
@@ -60,10 +27,13 @@ impl SceneTrait for MatchingScene {
         Some(most_scene_judgment)
     }
     
-    fn continue_match(&self, now_scene: SceneList) -> bool {
-        match now_scene {
-            SceneList::Unknown | SceneList::ReadyToFight | SceneList::GameEnd | SceneList::Result => true,
-            _ => false,
+    fn get_judgment_mut(&mut self, name: &str) -> Option<&mut SceneJudgment> {
+        match name {
+            "ready_ok" => Some(&mut self.scene_judgment),
+            "with_4" => Some(&mut self.scene_judgment_with4),
+            "ooo_tournament" => Some(&mut self.scene_judgment_ooo_tournament),
+            "smash_tournament" => Some(&mut self.scene_judgment_smash_tournament),
+            _ => None,
         }
     }
 
@@ -109,8 +79,6 @@ impl SceneTrait for MatchingScene {
         Ok(false)
     }
 
-    fn to_scene(&self, _now_scene: SceneList) -> SceneList { SceneList::Matching }
-
     fn recoding_scene(&mut self, _capture: &core::Mat) -> opencv::Result<()> { Ok(()) }
     fn is_recoded(&self) -> bool { false }
     fn detect_data(&mut self, _smashbros_data: &mut SmashbrosData) -> opencv::Result<()> { Ok(()) }
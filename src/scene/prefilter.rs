@@ -0,0 +1,146 @@
+use super::*;
+
+/// テンプレートマッチングの前に行う簡易判定の種類
+/// (matchTemplate は処理コストが高いので、明らかに違うフレームはここで弾く)
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum JudgmentPrefilter {
+    /// 簡易判定をしない [default]
+    None,
+    /// 8x8 の平均ハッシュのハミング距離が max_distance 以下なら matchTemplate する
+    AverageHash { max_distance: u32 },
+    /// 白黒ヒストグラムの相関が min_correlation 以上なら matchTemplate する
+    Histogram { min_correlation: f64 },
+}
+impl Default for JudgmentPrefilter {
+    fn default() -> Self { Self::None }
+}
+impl JudgmentPrefilter {
+    const HASH_SIZE: i32 = 8;
+    const HISTOGRAM_BINS: i32 = 32;
+
+    /// 比較用の特徴量を作成する
+    pub fn make_feature(&self, image: &core::Mat) -> opencv::Result<Option<PrefilterFeature>> {
+        let mut gray_image = core::Mat::default();
+        utils::cvt_color_to(image, &mut gray_image, ColorFormat::GRAY as i32)?;
+
+        Ok(match self {
+            Self::None => None,
+            Self::AverageHash { .. } => Some(PrefilterFeature::AverageHash( Self::average_hash(&gray_image)? )),
+            Self::Histogram { .. } => Some(PrefilterFeature::Histogram( Self::histogram(&gray_image)? )),
+        })
+    }
+
+    /// 特徴量同士を比べて、matchTemplate する価値があるか
+    pub fn is_plausible(&self, template_feature: &PrefilterFeature, captured_feature: &PrefilterFeature) -> opencv::Result<bool> {
+        Ok(match (self, template_feature, captured_feature) {
+            (Self::AverageHash { max_distance }, PrefilterFeature::AverageHash(template_hash), PrefilterFeature::AverageHash(captured_hash)) => {
+                (template_hash ^ captured_hash).count_ones() <= *max_distance
+            },
+            (Self::Histogram { min_correlation }, PrefilterFeature::Histogram(template_hist), PrefilterFeature::Histogram(captured_hist)) => {
+                *min_correlation <= imgproc::compare_hist(template_hist, captured_hist, imgproc::HISTCMP_CORREL)?
+            },
+            // 種類が違うものは比べられないので、素直に matchTemplate させる
+            _ => true,
+        })
+    }
+
+    // 縮小した画像の平均より明るいかどうかを 1bit ずつ詰める
+    fn average_hash(gray_image: &core::Mat) -> opencv::Result<u64> {
+        let mut small_image = core::Mat::default();
+        imgproc::resize(gray_image, &mut small_image,
+            core::Size{ width: Self::HASH_SIZE, height: Self::HASH_SIZE },
+            0.0, 0.0, imgproc::INTER_AREA)?;
+
+        let mean = core::mean(&small_image, &core::no_array())?[0];
+        let mut hash = 0u64;
+        for y in 0..Self::HASH_SIZE {
+            for x in 0..Self::HASH_SIZE {
+                hash <<= 1;
+                if mean < *small_image.at_2d::<u8>(y, x)? as f64 {
+                    hash |= 1;
+                }
+            }
+        }
+
+        Ok(hash)
+    }
+
+    // 大きさに依存しないように正規化したヒストグラム
+    fn histogram(gray_image: &core::Mat) -> opencv::Result<core::Mat> {
+        let mut images = core::Vector::<core::Mat>::new();
+        images.push(gray_image.clone());
+        let mut hist = core::Mat::default();
+        imgproc::calc_hist(&images, &core::Vector::from_slice(&[0]), &core::no_array(), &mut hist,
+            &core::Vector::from_slice(&[Self::HISTOGRAM_BINS]), &core::Vector::from_slice(&[0.0f32, 256.0f32]), false)?;
+
+        let mut normalized_hist = core::Mat::default();
+        core::normalize(&hist, &mut normalized_hist, 0.0, 1.0, core::NORM_MINMAX, -1, &core::no_array())?;
+
+        Ok(normalized_hist)
+    }
+}
+
+/// 簡易判定に使う特徴量
+#[derive(Clone)]
+pub enum PrefilterFeature {
+    AverageHash(u64),
+    Histogram(core::Mat),
+}
+
+/// 簡易判定で matchTemplate を飛ばせた割合
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PrefilterStats {
+    /// 簡易判定を行った回数
+    pub check_count: u64,
+    /// そのうち matchTemplate を飛ばした回数
+    pub skip_count: u64,
+}
+impl std::ops::AddAssign for PrefilterStats {
+    fn add_assign(&mut self, other: Self) {
+        self.check_count += other.check_count;
+        self.skip_count += other.skip_count;
+    }
+}
+impl PrefilterStats {
+    /// 飛ばした割合 [0.0 - 1.0] (一度も判定していなければ 0.0)
+    pub fn skip_rate(&self) -> f64 {
+        if 0 == self.check_count {
+            return 0.0;
+        }
+
+        self.skip_count as f64 / self.check_count as f64
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefilter_stats() {
+        let mut stats = PrefilterStats::default();
+        assert_eq!(stats.skip_rate(), 0.0);
+
+        stats += PrefilterStats { check_count: 3, skip_count: 1 };
+        stats += PrefilterStats { check_count: 1, skip_count: 1 };
+        assert_eq!(stats, PrefilterStats { check_count: 4, skip_count: 2 });
+        assert_eq!(stats.skip_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_average_hash_prefilter() {
+        let black_image = core::Mat::new_rows_cols_with_default(90, 160, core::CV_8UC3, core::Scalar::all(0.0)).unwrap();
+        let mut half_image = black_image.clone();
+        imgproc::rectangle(&mut half_image, core::Rect{ x: 0, y: 0, width: 80, height: 90 },
+            core::Scalar::all(255.0), -1, imgproc::LINE_8, 0).unwrap();
+
+        let prefilter = JudgmentPrefilter::AverageHash { max_distance: 4 };
+        let half_feature = prefilter.make_feature(&half_image).unwrap().unwrap();
+        let black_feature = prefilter.make_feature(&black_image).unwrap().unwrap();
+
+        assert!(prefilter.is_plausible(&half_feature, &half_feature).unwrap());
+        assert!(!prefilter.is_plausible(&half_feature, &black_feature).unwrap());
+        assert!(JudgmentPrefilter::None.make_feature(&half_image).unwrap().is_none());
+    }
+}
@@ -2,13 +2,10 @@ use super::*;
 
 /// Ready to Fight が表示されているシーン]
 /// スマブラがちゃんとキャプチャされているかで使用
+#[derive(Default)]
 pub struct ReadyToFightScene {
     pub grad_scene_judgment: SceneJudgment,
     pub red_scene_judgment: SceneJudgment,
-    pub scene_judgment_skip_wait: i32,
-}
-impl Default for ReadyToFightScene {
-    fn default() -> Self { Self::new_gray() }
 }
 impl SceneTrait for ReadyToFightScene {
     fn get_id(&self) -> i32 { SceneList::ReadyToFight as i32 }
@@ -21,29 +18,16 @@ impl SceneTrait for ReadyToFightScene {
         Some(&self.red_scene_judgment)
     }
 
-    // 回線切断などの原因で最初に戻ることは常にあるので gray match だし常に判定だけしておく
-    fn continue_match(&self, now_scene: SceneList) -> bool {
-        match now_scene {
-            SceneList::ReadyToFight => false,
-            _ => true,
+    fn get_judgment_mut(&mut self, name: &str) -> Option<&mut SceneJudgment> {
+        match name {
+            "grad" => Some(&mut self.grad_scene_judgment),
+            "red" => Some(&mut self.red_scene_judgment),
+            _ => None,
         }
     }
-    
-    fn is_scene(&mut self, capture_image: &core::Mat, smashbros_data: Option<&mut SmashbrosData>) -> opencv::Result<bool> {
-        if let Some(data) = smashbros_data.as_ref() {
-            if data.is_playing_battle() {
-                // 試合中の検出は数回に一回で十分
-                if 0 < self.scene_judgment_skip_wait {
-                    self.scene_judgment_skip_wait -= 1;
-                    return Ok(false)
-                }
-
-                self.scene_judgment_skip_wait = 10;
-            } else if 0 < self.scene_judgment_skip_wait {
-                self.scene_judgment_skip_wait = 0;
-            }
-        }
 
+    // 試合中もずっと判定するので、明らかに違う画面は scene_graph.yml の prefilter で matchTemplate しない
+    fn is_scene(&mut self, capture_image: &core::Mat, _smashbros_data: Option<&mut SmashbrosData>) -> opencv::Result<bool> {
         // 多分 grad版 ReadyToFight のほうが多いので先にする
         // (grad:カーソルが on_cursor の状態, red: わざとカーソルを READY to FIGHT からずらしている状態)
         async_std::task::block_on(async {
@@ -58,44 +42,40 @@ impl SceneTrait for ReadyToFightScene {
         Ok( self.grad_scene_judgment.is_near_match() || self.red_scene_judgment.is_near_match() )
     }
 
-    fn to_scene(&self, _now_scene: SceneList) -> SceneList { SceneList::ReadyToFight }
+    fn get_prefilter_stats(&self) -> PrefilterStats {
+        let mut stats = self.grad_scene_judgment.get_prefilter_stats();
+        stats += self.red_scene_judgment.get_prefilter_stats();
+
+        stats
+    }
 
     fn recoding_scene(&mut self, _capture: &core::Mat) -> opencv::Result<()> { Ok(()) }
     fn is_recoded(&self) -> bool { false }
     fn detect_data(&mut self, _smashbros_data: &mut SmashbrosData) -> opencv::Result<()> { Ok(()) }
 }
 impl ReadyToFightScene {
+    /// scene_graph.yml の判定で作成する (ReadyToFight の判定は言語によらない)
     pub fn new_gray() -> Self {
-        Self {
-            grad_scene_judgment: SceneJudgment::new_gray(
-                imgcodecs::imread("resource/ready_to_fight_color_0.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                Some(imgcodecs::imread("resource/ready_to_fight_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-            ).unwrap()
-            .set_size(core::Rect{    // 参照される回数が多いので matchTemplate する大きさ減らす
-                x:0, y:0, width:640, height: 180
-            }),
-            red_scene_judgment: SceneJudgment::new_gray(
-                imgcodecs::imread("resource/ready_to_fight_color_1.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                Some(imgcodecs::imread("resource/ready_to_fight_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-            ).unwrap()
-            .set_size(core::Rect{
-                x:0, y:0, width:640, height: 180
-            }),
-            scene_judgment_skip_wait: 0,
-        }
+        let mut own = Self::default();
+        SceneGraph::load_or_builtin().apply_judgments(&mut own, "");
+
+        own
     }
 
+    /// scene_graph.yml の判定を透過画像にして、キャプチャ全体から検出するもの (解像度や座標の特定に使う)
     pub fn new_trans() -> Self {
-        Self {
-            grad_scene_judgment: SceneJudgment::new_trans(
-                imgcodecs::imread("resource/ready_to_fight_color_0.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                Some(imgcodecs::imread("resource/ready_to_fight_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-            ).unwrap(),
-            red_scene_judgment: SceneJudgment::new_trans(
-                imgcodecs::imread("resource/ready_to_fight_color_1.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                Some(imgcodecs::imread("resource/ready_to_fight_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-            ).unwrap(),
-            scene_judgment_skip_wait: 0,
+        let mut own = Self::default();
+        if let Some(node) = SceneGraph::load_or_builtin().get_node(SceneList::ReadyToFight) {
+            let mut node = node.clone();
+            for config in node.judgments.iter_mut() {
+                config.format = JudgmentFormat::Trans;
+                config.border = None;
+                config.roi = None;
+                config.prefilter = JudgmentPrefilter::None;
+            }
+            node.apply_judgments(&mut own, "");
         }
+
+        own
     }
 }
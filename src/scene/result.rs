@@ -10,8 +10,8 @@ pub struct ResultScene {
     retry_battle_scene_judgment: SceneJudgment,
     result_power_mask: core::Mat
 }
-impl Default for ResultScene {
-    fn default() -> Self {
+impl ResultScene {
+    pub fn new(context: &AppContext) -> Self {
         let mut scene_judgment_list = vec![];
         for player_number in 1..=4 {
             let path = format!("resource/result_player_order_{}_", player_number);
@@ -26,21 +26,11 @@ impl Default for ResultScene {
 
         Self {
             scene_judgment_list: scene_judgment_list,
-            count_down_scene_judgment: SceneJudgment::new(
-                imgcodecs::imread("resource/result_time_color.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                Some(imgcodecs::imread("resource/result_time_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-            ).unwrap()
-            .set_border(0.90),
-            result_stock_color: SceneJudgment::new(
-                imgcodecs::imread("resource/result_stock_color.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                Some(imgcodecs::imread("resource/result_stock_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-            ).unwrap()
-            .set_border(0.95),
-            retry_battle_scene_judgment: SceneJudgment::new(
-                imgcodecs::imread("resource/battle_retry_color.png", imgcodecs::IMREAD_UNCHANGED).unwrap(),
-                Some(imgcodecs::imread("resource/battle_retry_mask.png", imgcodecs::IMREAD_UNCHANGED).unwrap())
-            ).unwrap(),
-            buffer: CaptureFrameStore::default()
+            // 判定は scene_graph.yml から作られる
+            count_down_scene_judgment: SceneJudgment::default(),
+            result_stock_color: SceneJudgment::default(),
+            retry_battle_scene_judgment: SceneJudgment::default(),
+            buffer: CaptureFrameStore::new(context.codec())
                 .set_file_name("result.avi".to_string()),
             result_power_mask: imgcodecs::imread("resource/result_power_mask.png", imgcodecs::IMREAD_GRAYSCALE).unwrap(),
         }
@@ -50,10 +40,12 @@ impl SceneTrait for ResultScene {
     fn get_id(&self) -> i32 { SceneList::Result as i32 }
     fn get_prev_match(&self) -> Option<&SceneJudgment> { Some(&self.count_down_scene_judgment) }
 
-    fn continue_match(&self, now_scene: SceneList) -> bool {
-        match now_scene {
-            SceneList::GameEnd => true,
-            _ => false,
+    fn get_judgment_mut(&mut self, name: &str) -> Option<&mut SceneJudgment> {
+        match name {
+            "count_down" => Some(&mut self.count_down_scene_judgment),
+            "result_stock" => Some(&mut self.result_stock_color),
+            "retry_battle" => Some(&mut self.retry_battle_scene_judgment),
+            _ => None,
         }
     }
 
@@ -78,9 +70,6 @@ impl SceneTrait for ResultScene {
         }
     }
 
-    // 結果画面からは ReadyToFight の検出もあるけど、Dialog によって連戦が予想されるので Result へ
-    fn to_scene(&self, _now_scene: SceneList) -> SceneList { SceneList::Result }
-
     fn recoding_scene(&mut self, capture_image: &core::Mat) -> opencv::Result<()> {
         async_std::task::block_on(async {
             self.retry_battle_scene_judgment.match_captured_scene(capture_image).await
@@ -106,6 +95,14 @@ impl SceneTrait for ResultScene {
         })?;
         Ok(())
     }
+
+    fn detect_frame(&mut self, capture_image: &core::Mat, smashbros_data: &mut SmashbrosData) -> opencv::Result<()> {
+        Self::captured_order(capture_image, smashbros_data, &mut self.scene_judgment_list)?;
+        Self::captured_power(capture_image, smashbros_data, &self.result_power_mask)?;
+        Self::capture_result_stock(capture_image, smashbros_data, &mut self.result_stock_color)?;
+
+        Ok(())
+    }
 }
 impl ResultScene {
     // 順位の検出位置 [c2[1, 2, _, _], c4[1, 2, 3, 4]]
@@ -306,13 +303,17 @@ mod test {
 
     #[test]
     fn test_result_stock() {
+        use crate::resource::InMemoryBattleHistory;
+
+        let context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
         let mut data = SmashbrosData::default();
         data.initialize_battle(2, true);
-        data.guess_character_name(0, "MARIO".to_string());
+        data.guess_character_name(0, "MARIO".to_string(), &context.smashbros_resource());
         data.set_max_stock(0, 3);
         data.set_max_stock(1, 3);
 
-        let mut result_scene = ResultScene::default();
+        let mut result_scene = ResultScene::new(&context);
+        SceneGraph::builtin().apply_judgments(&mut result_scene, &context.lang_code());
         ResultScene::capture_result_stock(
             &imgcodecs::imread("test/resource/result_stock.png", imgcodecs::IMREAD_COLOR).unwrap(),
             &mut data,
@@ -0,0 +1,637 @@
+use i18n_embed_fl::fl;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::collections::HashMap;
+use std::sync::{
+    mpsc,
+    Arc,
+    RwLock,
+};
+use std::thread;
+
+use crate::context::AppContext;
+use crate::data::*;
+use crate::engine::SmashBrogEngine;
+use crate::event::EngineEvent;
+use crate::resource::{
+    BattleHistoryCursor,
+    BattleHistoryFilter,
+    BattleHistoryTrait,
+    BattleStats,
+    BattleStatsGroup,
+    SmashbrosResource,
+};
+use crate::scene::SceneList;
+
+pub mod overlay;
+pub use overlay::*;
+pub mod websocket;
+pub use websocket::*;
+
+
+/// ローカル API サーバーの設定
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ApiServerConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "ApiServerConfig::default_port")]
+    pub port: u16,
+}
+impl Default for ApiServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: Self::default_port(),
+        }
+    }
+}
+impl ApiServerConfig {
+    pub fn default_port() -> u16 { 28080 }
+}
+
+/// API から見える現在の検出状態 (GUI スレッドが EngineSnapshot を受け取る度に更新する)
+#[derive(Clone, Debug, Default)]
+pub struct ApiLiveState {
+    pub scene: SceneList,
+    pub match_ratio: f64,
+    pub data: SmashbrosData,
+}
+
+/// 配信ツールなどから戦歴を取得する為の HTTP/JSON API (localhost からのみ受け付ける)
+/// - GET /api/now     : 現在のシーン, 一致度, 対戦中のデータ
+/// - GET /api/history : 戦歴 (page か cursor, per_page, character, opponent, rule, since, until)
+/// - GET /api/stats   : 勝率と試合数, 相手キャラ別とキャラの組み合わせ別とタグ別の勝率と試合数 (character, opponent, rule, since, until)
+/// - GET /api/overlay : オーバーレイに表示するもの (対戦カード, 連勝数, 起動してからの勝率, 戦闘力の推移)
+/// - GET /overlay/    : OBS のブラウザソース用のオーバーレイ
+/// - WebSocket /api/events : EngineEvent を EngineEvent::to_json の形式で流し続ける
+pub struct ApiServer {
+    server: Arc<tiny_http::Server>,
+    live_state: Arc<RwLock<ApiLiveState>>,
+    event_stream: EventStream,
+    port: u16,
+    handle: Option<thread::JoinHandle<()>>,
+}
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+impl ApiServer {
+    /// 127.0.0.1:port で待ち受けるスレッドを作成する (port が 0 なら空いているポートを使う)
+    /// event_receiver に届いた EngineEvent は /api/events に流す
+    pub fn spawn(context: AppContext, port: u16, event_receiver: mpsc::Receiver<EngineEvent>) -> anyhow::Result<Self> {
+        let server = match tiny_http::Server::http(("127.0.0.1", port)) {
+            Ok(server) => Arc::new(server),
+            Err(e) => anyhow::bail!("failed to listen api server on {}: {}", port, e),
+        };
+        let port = server.server_addr().to_ip().map_or(port, |addr| addr.port());
+        let live_state = Arc::new(RwLock::new(ApiLiveState::default()));
+        let event_stream = EventStream::spawn(event_receiver)?;
+
+        let handle = {
+            let server = Arc::clone(&server);
+            let live_state = Arc::clone(&live_state);
+            let client_list = event_stream.get_client_list();
+            thread::Builder::new()
+                .name("smabrog-api".to_string())
+                .spawn(move || {
+                    let _guard = context.enter();
+                    let mut handler = ApiHandler {
+                        context,
+                        live_state,
+                        session_start_time: chrono::Local::now(),
+                    };
+
+                    // unblock されるまで 1 つずつ処理する
+                    for request in server.incoming_requests() {
+                        let request = if request.url().trim_end_matches('/') == EventStream::PATH {
+                            match client_list.accept(request) {
+                                Ok(()) => continue,
+                                Err(request) => request,
+                            }
+                        } else {
+                            request
+                        };
+
+                        let response = handler.make_response(request.method(), request.url());
+                        if let Err(e) = request.respond(response) {
+                            log::warn!("failed to respond api: {}", e);
+                        }
+                    }
+                })?
+        };
+        log::info!("api server listening on http://127.0.0.1:{}/", port);
+
+        Ok(Self {
+            server,
+            live_state,
+            event_stream,
+            port,
+            handle: Some(handle),
+        })
+    }
+
+    /// 待ち受けているポート番号
+    pub fn get_port(&self) -> u16 {
+        self.port
+    }
+
+    /// /api/events に繋がっているクライアントの数
+    pub fn get_event_client_count(&self) -> usize {
+        self.event_stream.get_client_list().len()
+    }
+
+    /// 現在の検出状態を更新する
+    pub fn set_live_state(&self, live_state: ApiLiveState) {
+        *self.live_state.write().unwrap() = live_state;
+    }
+}
+
+// 戦歴を id 付きで返す為のもの
+#[derive(Serialize)]
+struct ApiBattleData<'a> {
+    id: Option<String>,
+    #[serde(flatten)]
+    data: &'a SmashbrosData,
+}
+impl<'a> ApiBattleData<'a> {
+    fn new(data: &'a SmashbrosData) -> Self {
+        Self { id: data.get_id(), data }
+    }
+}
+
+// (ステータスコード, エラーメッセージ)
+type ApiResult = Result<serde_json::Value, (u16, String)>;
+
+/// リクエストを処理するもの (サーバーのスレッドで持つ)
+struct ApiHandler {
+    context: AppContext,
+    live_state: Arc<RwLock<ApiLiveState>>,
+    // オーバーレイの勝率はこれ以降の試合で出す
+    session_start_time: chrono::DateTime<chrono::Local>,
+}
+impl ApiHandler {
+    const DEFAULT_PER_PAGE: i64 = 20;
+    const MAX_PER_PAGE: i64 = 100;
+    // オーバーレイの連勝数と戦闘力の推移に使う直近の戦歴の数
+    const OVERLAY_LIMIT: i64 = 50;
+
+    fn header(field: &str, value: &str) -> tiny_http::Header {
+        tiny_http::Header::from_bytes(field.as_bytes(), value.as_bytes()).unwrap()
+    }
+
+    /// /overlay はファイルを、それ以外は JSON を返す
+    fn make_response(&mut self, method: &tiny_http::Method, url: &str) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+        let (status_code, content_type, body) = match url.strip_prefix(Overlay::PATH) {
+            Some(path) if &tiny_http::Method::Get == method => {
+                let path = path.split_once('?').map_or(path, |(path, _)| path);
+                Overlay::get_file(path, &self.context)
+            },
+            _ => {
+                let (status_code, body) = self.handle(method, url);
+                (status_code, "application/json; charset=utf-8", body.to_string().into_bytes())
+            },
+        };
+
+        // ブラウザで開いている他のサイトから戦歴を読まれないように CORS のヘッダは付けない (オーバーレイは同じオリジン)
+        tiny_http::Response::from_data(body)
+            .with_status_code(status_code)
+            .with_header(Self::header("Content-Type", content_type))
+    }
+
+    /// @return (ステータスコード, 返す JSON)
+    fn handle(&mut self, method: &tiny_http::Method, url: &str) -> (u16, serde_json::Value) {
+        if &tiny_http::Method::Get != method {
+            return (405, serde_json::json!({ "error": "method not allowed." }));
+        }
+
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let query = parse_query(query);
+        let result = match path.trim_end_matches('/') {
+            "/api/now" => Ok(self.now()),
+            "/api/history" => self.history(&query),
+            "/api/stats" => self.stats(&query),
+            "/api/overlay" => self.overlay(),
+            EventStream::PATH => Err((400, "websocket upgrade is required.".to_string())),
+            _ => Err((404, format!("{} is not found.", path))),
+        };
+
+        match result {
+            Ok(body) => (200, body),
+            Err((status_code, message)) => (status_code, serde_json::json!({ "error": message })),
+        }
+    }
+
+    fn now(&self) -> serde_json::Value {
+        let live_state = self.live_state.read().unwrap();
+
+        serde_json::json!({
+            "scene": live_state.scene,
+            "match_ratio": live_state.match_ratio,
+            "data": ApiBattleData::new(&live_state.data),
+        })
+    }
+
+    fn history(&mut self, query: &HashMap<String, String>) -> ApiResult {
+        let filter = parse_filter(query, &self.context.smashbros_resource())?;
+        let page = parse_number(query, "page", 1)?.max(1);
+        let per_page = parse_number(query, "per_page", Self::DEFAULT_PER_PAGE)?.clamp(1, Self::MAX_PER_PAGE);
+
+        // cursor (前のページの next_cursor) があればその続きを返す (page と違って、古いページでも遅くならない)
+        if let Some(cursor) = query.get("cursor") {
+            if let Err(e) = BattleHistoryCursor::parse(cursor) {
+                return Err((400, e));
+            }
+            let history_page = self.context.find_data_by_cursor(&filter, Some(cursor.clone()), per_page)
+                .ok_or((503, "failed to find battle history.".to_string()))?;
+
+            return Ok(serde_json::json!({
+                "per_page": per_page,
+                "has_next": history_page.next_cursor.is_some(),
+                "next_cursor": history_page.next_cursor,
+                "data_list": history_page.data_list.iter().map(ApiBattleData::new).collect::<Vec<_>>(),
+            }));
+        }
+
+        // 次のページがあるかを知る為に 1 件多く取る
+        let mut data_list = self.context.find_data_by_filter(&filter, (page - 1) * per_page, per_page + 1)
+            .ok_or((503, "failed to find battle history.".to_string()))?;
+        let has_next = per_page < data_list.len() as i64;
+        data_list.truncate(per_page as usize);
+        let next_cursor = if has_next { data_list.last().and_then(BattleHistoryCursor::from_data).map(|cursor| cursor.to_string()) } else { None };
+
+        Ok(serde_json::json!({
+            "page": page,
+            "per_page": per_page,
+            "has_next": has_next,
+            "next_cursor": next_cursor,
+            "data_list": data_list.iter().map(ApiBattleData::new).collect::<Vec<_>>(),
+        }))
+    }
+
+    /// filter に合う戦歴の勝敗数を group ごとに集計する
+    fn aggregate_stats(&mut self, filter: &BattleHistoryFilter, group: BattleStatsGroup) -> Result<Vec<BattleStats>, (u16, String)> {
+        self.context.aggregate_stats(filter, group)
+            .ok_or((503, "failed to aggregate battle history.".to_string()))
+    }
+
+    fn stats(&mut self, query: &HashMap<String, String>) -> ApiResult {
+        let filter = parse_filter(query, &self.context.smashbros_resource())?;
+        let total = self.aggregate_stats(&filter, BattleStatsGroup::All)?.pop().unwrap_or_default();
+        let by_opponent = self.aggregate_stats(&filter, BattleStatsGroup::Opponent)?
+            .into_iter()
+            .map(|stats| serde_json::json!({
+                "opponent": stats.opponent,
+                "win": stats.win,
+                "lose": stats.lose,
+                "win_rate": stats.get_win_rate(),
+                "battle_count": stats.get_battle_count(),
+            }))
+            .collect::<Vec<_>>();
+        let by_matchup = self.aggregate_stats(&filter, BattleStatsGroup::Matchup)?
+            .into_iter()
+            .map(|stats| serde_json::json!({
+                "character": stats.character,
+                "opponent": stats.opponent,
+                "win": stats.win,
+                "lose": stats.lose,
+                "win_rate": stats.get_win_rate(),
+                "battle_count": stats.get_battle_count(),
+            }))
+            .collect::<Vec<_>>();
+        let by_tag = self.aggregate_stats(&filter, BattleStatsGroup::Tag)?
+            .into_iter()
+            .map(|stats| serde_json::json!({
+                "tag": stats.tag,
+                "win": stats.win,
+                "lose": stats.lose,
+                "win_rate": stats.get_win_rate(),
+                "battle_count": stats.get_battle_count(),
+            }))
+            .collect::<Vec<_>>();
+
+        Ok(serde_json::json!({
+            "win": total.win,
+            "lose": total.lose,
+            "win_rate": total.get_win_rate(),
+            "battle_count": total.get_battle_count(),
+            "by_opponent": by_opponent,
+            "by_matchup": by_matchup,
+            "by_tag": by_tag,
+        }))
+    }
+
+    fn overlay(&mut self) -> ApiResult {
+        let latest_list = self.context.find_data_limit(Self::OVERLAY_LIMIT)
+            .ok_or((503, "failed to find battle history.".to_string()))?;
+        let session_filter = BattleHistoryFilter { since: Some(self.session_start_time), ..Default::default() };
+        let session = self.aggregate_stats(&session_filter, BattleStatsGroup::All)?.pop().unwrap_or_default();
+
+        // 対戦中でなければ、最後の試合の対戦カードを出しておく
+        let live_data = self.live_state.read().unwrap().data.clone();
+        let data = if 2 == live_data.get_player_count() {
+            Some(&live_data)
+        } else {
+            latest_list.first()
+        };
+        let player_list = match data {
+            Some(data) => {
+                let smashbros_resource = self.context.smashbros_resource();
+                (0..data.get_player_count()).map(|player_number| {
+                    let character = data.get_character(player_number);
+                    serde_json::json!({
+                        "has_icon": smashbros_resource.icon_path_list.contains_key(&character),
+                        "character": character,
+                        "power": data.get_power(player_number),
+                    })
+                }).collect::<Vec<_>>()
+            },
+            None => Vec::new(),
+        };
+
+        Ok(serde_json::json!({
+            "player_list": player_list,
+            "streak": SmashBrogEngine::get_streak_by_data_list(&latest_list),
+            // 連勝/連敗の表示 (設定の言語で)
+            "streak_label": {
+                "win": fl!(self.context.lang_loader(), "wins"),
+                "lose": fl!(self.context.lang_loader(), "losses"),
+            },
+            "session": {
+                "win": session.win,
+                "lose": session.lose,
+                "win_rate": session.get_win_rate(),
+                "battle_count": session.get_battle_count(),
+            },
+            "power_list": SmashBrogEngine::get_power_list_by_data_list(&latest_list),
+        }))
+    }
+}
+
+// クエリ文字列を分解する (空の値は無かったことにする)
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query.split('&')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = decode_query_component(value);
+            if key.is_empty() || value.is_empty() {
+                return None;
+            }
+
+            Some(( decode_query_component(key), value ))
+        })
+        .collect()
+}
+
+// %XX と + を戻す (キャラ名は日本語なども来るので、バイト列に戻してから UTF-8 にする)
+fn decode_query_component(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let byte = bytes.get(index+1 .. index+3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match byte {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        index += 2;
+                    },
+                    None => decoded.push(b'%'),
+                }
+            },
+            byte => decoded.push(byte),
+        }
+        index += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn parse_number(query: &HashMap<String, String>, key: &str, default: i64) -> Result<i64, (u16, String)> {
+    match query.get(key) {
+        Some(value) => value.parse::<i64>().map_err(|_| (400, format!("{} is not a number.", key))),
+        None => Ok(default),
+    }
+}
+
+fn parse_filter(query: &HashMap<String, String>, smashbros_resource: &SmashbrosResource) -> Result<BattleHistoryFilter, (u16, String)> {
+    BattleHistoryFilter::from_query(query, smashbros_resource).map_err(|e| (400, e))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::InMemoryBattleHistory;
+
+    fn make_handler() -> ApiHandler {
+        let mut context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
+        let mut data = SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, true, -1);
+        data.set_tag_list(vec!["laggy".to_string()]);
+        context.insert_data(&data);
+        let mut data = SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, false, -1);
+        data.set_tag_list(vec!["laggy".to_string(), "tilted".to_string()]);
+        context.insert_data(&data);
+        context.insert_data(&SmashbrosData::new_for_test("MARIO", "KIRBY", BattleRule::Time, true, -1));
+        context.insert_data(&SmashbrosData::new_for_test("FOX", "LINK", BattleRule::Stock, true, -1));
+
+        ApiHandler {
+            context,
+            live_state: Arc::new(RwLock::new(ApiLiveState::default())),
+            session_start_time: chrono::Local::now() - chrono::Duration::hours(1),
+        }
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = parse_query("character=MARIO&opponent=%E3%83%AA%E3%83%B3%E3%82%AF&rule=&name=Mr.+GAME+%26+WATCH&broken=%E");
+        assert_eq!(query.get("character"), Some(&"MARIO".to_string()));
+        assert_eq!(query.get("opponent"), Some(&"リンク".to_string()));
+        assert_eq!(query.get("name"), Some(&"Mr. GAME & WATCH".to_string()));
+        assert_eq!(query.get("broken"), Some(&"%E".to_string()));
+        assert!(!query.contains_key("rule"));
+    }
+
+    #[test]
+    fn test_api_history() {
+        let mut handler = make_handler();
+
+        let (status_code, body) = handler.handle(&tiny_http::Method::Get, "/api/history?opponent=LINK&per_page=2");
+        assert_eq!(status_code, 200);
+        assert_eq!(body["data_list"].as_array().unwrap().len(), 2);
+        assert_eq!(body["data_list"][0]["chara_list"][0], "FOX");
+        assert!(body["data_list"][0]["id"].is_string());
+        assert_eq!(body["has_next"], true);
+
+        let (_, body) = handler.handle(&tiny_http::Method::Get, "/api/history?opponent=LINK&per_page=2&page=2");
+        assert_eq!(body["data_list"].as_array().unwrap().len(), 1);
+        assert_eq!(body["has_next"], false);
+        assert!(body["next_cursor"].is_null());
+
+        // cursor で続きを取得しても page と同じ結果になる
+        let (_, body) = handler.handle(&tiny_http::Method::Get, "/api/history?opponent=LINK&per_page=2");
+        let next_cursor = body["next_cursor"].as_str().unwrap().to_string();
+        assert!(next_cursor.starts_with(body["data_list"][1]["id"].as_str().unwrap()));
+        let (status_code, body) = handler.handle(&tiny_http::Method::Get, &format!("/api/history?opponent=LINK&per_page=2&cursor={}", next_cursor));
+        assert_eq!(status_code, 200);
+        assert_eq!(body["data_list"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data_list"][0]["chara_list"][0], "MARIO");
+        assert_eq!(body["has_next"], false);
+        assert_eq!(handler.handle(&tiny_http::Method::Get, "/api/history?cursor=abc").0, 400);
+        let cursor_id = next_cursor.split(':').next().unwrap();
+        assert_eq!(handler.handle(&tiny_http::Method::Get, &format!("/api/history?cursor={}:yesterday", cursor_id)).0, 400);
+
+        let (_, body) = handler.handle(&tiny_http::Method::Get, "/api/history?character=MARIO&rule=Time");
+        assert_eq!(body["data_list"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data_list"][0]["chara_list"][1], "KIRBY");
+
+        // キャラ名は公式名や小文字でも、保存されているキャラ名で絞り込む (推測しかできないものはそのまま)
+        let (_, body) = handler.handle(&tiny_http::Method::Get, "/api/history?character=mario&opponent=%E3%83%AA%E3%83%B3%E3%82%AF");
+        assert_eq!(body["data_list"].as_array().unwrap().len(), 2);
+        let (_, body) = handler.handle(&tiny_http::Method::Get, "/api/history?character=MARI");
+        assert!(body["data_list"].as_array().unwrap().is_empty());
+
+        assert_eq!(handler.handle(&tiny_http::Method::Get, "/api/history?rule=Foo").0, 400);
+        assert_eq!(handler.handle(&tiny_http::Method::Get, "/api/history?since=yesterday").0, 400);
+        assert_eq!(handler.handle(&tiny_http::Method::Get, "/api/history?page=a").0, 400);
+
+        // 勝敗や人数などを組み合わせて絞り込む
+        let (_, body) = handler.handle(&tiny_http::Method::Get, "/api/history?result=win");
+        assert_eq!(body["data_list"].as_array().unwrap().len(), 3);
+        let (_, body) = handler.handle(&tiny_http::Method::Get, "/api/history?character=MARIO&result=lose&player_count=2");
+        assert_eq!(body["data_list"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data_list"][0]["chara_list"][1], "LINK");
+        let (_, body) = handler.handle(&tiny_http::Method::Get, "/api/history?player_count=4");
+        assert!(body["data_list"].as_array().unwrap().is_empty());
+        assert_eq!(handler.handle(&tiny_http::Method::Get, "/api/history?result=draw").0, 400);
+        assert_eq!(handler.handle(&tiny_http::Method::Get, "/api/history?min_power=a").0, 400);
+    }
+
+    #[test]
+    fn test_api_stats() {
+        let mut handler = make_handler();
+
+        let (status_code, body) = handler.handle(&tiny_http::Method::Get, "/api/stats?character=MARIO");
+        assert_eq!(status_code, 200);
+        assert_eq!(body["battle_count"], 3);
+        assert_eq!(body["win"], 2);
+        let by_opponent = body["by_opponent"].as_array().unwrap();
+        let link = by_opponent.iter().find(|stats| stats["opponent"] == "LINK").unwrap();
+        assert_eq!(link["battle_count"], 2);
+        assert_eq!(link["win_rate"], 0.5);
+        let by_tag = body["by_tag"].as_array().unwrap();
+        assert_eq!(by_tag.len(), 2);
+        let laggy = by_tag.iter().find(|stats| stats["tag"] == "laggy").unwrap();
+        assert_eq!(laggy["battle_count"], 2);
+        assert_eq!(laggy["lose"], 1);
+        assert_eq!(laggy["win_rate"], 0.5);
+
+        // キャラの組み合わせ別は試合数の多い順
+        let (_, body) = handler.handle(&tiny_http::Method::Get, "/api/stats?opponent=LINK");
+        assert_eq!(body["battle_count"], 3);
+        let by_matchup = body["by_matchup"].as_array().unwrap();
+        assert_eq!(by_matchup.len(), 2);
+        assert_eq!(by_matchup[0]["character"], "MARIO");
+        assert_eq!(by_matchup[0]["lose"], 1);
+        assert_eq!(by_matchup[1]["character"], "FOX");
+        assert_eq!(by_matchup[1]["win_rate"], 1.0);
+
+        // 全てのタグが付いたものだけに絞り込む
+        let (_, body) = handler.handle(&tiny_http::Method::Get, "/api/stats?tag=laggy");
+        assert_eq!(body["battle_count"], 2);
+        let (_, body) = handler.handle(&tiny_http::Method::Get, "/api/history?tag=laggy,%20tilted");
+        assert_eq!(body["data_list"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data_list"][0]["tag_list"][1], "tilted");
+    }
+
+    #[test]
+    fn test_api_now() {
+        let mut handler = make_handler();
+        *handler.live_state.write().unwrap() = ApiLiveState {
+            scene: SceneList::GamePlaying,
+            match_ratio: 0.99,
+            data: SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, true, -1),
+        };
+
+        let (status_code, body) = handler.handle(&tiny_http::Method::Get, "/api/now/");
+        assert_eq!(status_code, 200);
+        assert_eq!(body["scene"], "GamePlaying");
+        assert_eq!(body["data"]["chara_list"][1], "LINK");
+
+        assert_eq!(handler.handle(&tiny_http::Method::Get, "/api/unknown").0, 404);
+        assert_eq!(handler.handle(&tiny_http::Method::Post, "/api/now").0, 405);
+    }
+
+    #[test]
+    fn test_api_overlay() {
+        let mut handler = make_handler();
+
+        let (status_code, body) = handler.handle(&tiny_http::Method::Get, "/api/overlay");
+        assert_eq!(status_code, 200);
+        // 対戦中でなければ最後の試合
+        assert_eq!(body["player_list"][0]["character"], "FOX");
+        assert_eq!(body["player_list"][1]["character"], "LINK");
+        assert_eq!(body["streak"], 2);
+        assert_eq!(body["streak_label"]["win"], fl!(handler.context.lang_loader(), "wins"));
+        assert_ne!(body["streak_label"]["win"], body["streak_label"]["lose"]);
+        assert_eq!(body["session"]["win"], 3);
+        assert_eq!(body["session"]["lose"], 1);
+
+        assert_eq!(Overlay::get_file("/", &handler.context).0, 200);
+        assert_eq!(Overlay::get_file("/overlay.js", &handler.context).1, "text/javascript; charset=utf-8");
+        assert_eq!(Overlay::get_file("/user.css", &handler.context).0, 200);
+        assert_eq!(Overlay::get_file("/icon/not_a_character", &handler.context).0, 404);
+        assert_eq!(Overlay::get_file("/not_found.html", &handler.context).0, 404);
+    }
+
+    #[test]
+    fn test_api_event_stream() {
+        let context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
+        let (event_sender, event_receiver) = mpsc::channel();
+        let api_server = ApiServer::spawn(context, 0, event_receiver).unwrap();
+
+        // ローカルのテストクライアントで繋いで、届いた JSON を確認する
+        let (mut client, _) = tungstenite::connect(format!("ws://127.0.0.1:{}{}", api_server.get_port(), EventStream::PATH)).unwrap();
+        let start_time = std::time::Instant::now();
+        while 0 == api_server.get_event_client_count() {
+            assert!(start_time.elapsed() < std::time::Duration::from_secs(5), "websocket client is not accepted.");
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        event_sender.send(EngineEvent::SceneChanged {
+            before: SceneList::HamVsSpam,
+            after: SceneList::GamePlaying,
+            data: SmashbrosData::default(),
+        }).unwrap();
+        event_sender.send(EngineEvent::PowerDecided { player_number: 0, power: 1234567 }).unwrap();
+
+        let mut read_json = || -> serde_json::Value {
+            serde_json::from_str(&client.read_message().unwrap().into_text().unwrap()).unwrap()
+        };
+        let json = read_json();
+        assert_eq!(json["schema_version"], EngineEvent::SCHEMA_VERSION);
+        assert_eq!(json["type"], "scene_changed");
+        assert_eq!(json["payload"]["after"], "GamePlaying");
+        let json = read_json();
+        assert_eq!(json["type"], "power_decided");
+        assert_eq!(json["payload"]["power"], 1234567);
+
+        // 切断したクライアントは送れなくなった時点で外れる
+        client.close(None).unwrap();
+        drop(client);
+        while 0 != api_server.get_event_client_count() {
+            event_sender.send(EngineEvent::StockChanged { player_number: 0, stock: 1 }).unwrap();
+            assert!(start_time.elapsed() < std::time::Duration::from_secs(5), "websocket client is not removed.");
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use rust_embed::RustEmbed;
+
+use super::*;
+
+
+/// OBS のブラウザソース用のファイル (ビルド時に埋め込む)
+#[derive(RustEmbed)]
+#[folder = "overlay/"]
+struct OverlayAssets;
+
+/// OBS のブラウザソース用のオーバーレイ
+/// http://127.0.0.1:{port}/overlay/?layout={standard|vertical|compact|minimal}
+pub struct Overlay;
+impl Overlay {
+    /// オーバーレイのパス
+    pub const PATH: &'static str = "/overlay";
+    /// 利用者が見た目を変える為の CSS (実行ファイルと同じ場所に置く)
+    pub const USER_CSS_PATH: &'static str = "overlay_user.css";
+
+    /// path ("/overlay" 以降) のファイルを返す
+    /// @return (ステータスコード, Content-Type, 内容)
+    pub fn get_file(path: &str, context: &AppContext) -> (u16, &'static str, Vec<u8>) {
+        let path = path.trim_start_matches('/');
+        if path.is_empty() {
+            return Self::get_asset("index.html");
+        }
+        if path == "user.css" {
+            // 無くても読み込みで失敗しないように空にしておく
+            return (200, Self::get_content_type(path), std::fs::read(Self::USER_CSS_PATH).unwrap_or_default());
+        }
+        if let Some(character) = path.strip_prefix("icon/") {
+            let icon_path = context.smashbros_resource().icon_path_list.get(&decode_query_component(character)).cloned();
+            return match icon_path.and_then(|icon_path| Some(( Self::get_content_type(&icon_path), std::fs::read(&icon_path).ok()? ))) {
+                Some((content_type, icon)) => (200, content_type, icon),
+                None => (404, "text/plain", Vec::new()),
+            };
+        }
+
+        Self::get_asset(path)
+    }
+
+    fn get_asset(path: &str) -> (u16, &'static str, Vec<u8>) {
+        match OverlayAssets::get(path) {
+            Some(asset) => (200, Self::get_content_type(path), asset.data.into_owned()),
+            None => (404, "text/plain", Vec::new()),
+        }
+    }
+
+    fn get_content_type(path: &str) -> &'static str {
+        match std::path::Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("html") => "text/html; charset=utf-8",
+            Some("css") => "text/css; charset=utf-8",
+            Some("js") => "text/javascript; charset=utf-8",
+            Some("png") => "image/png",
+            Some("jpg") | Some("jpeg") => "image/jpeg",
+            Some("svg") => "image/svg+xml",
+            _ => "application/octet-stream",
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::*;
+use crate::event::EngineEvent;
+
+
+type EventClient = tungstenite::WebSocket<Box<dyn tiny_http::ReadWrite + Send>>;
+
+/// WebSocket で繋いできたクライアントの一覧 (clone したものは同じ一覧を指す)
+/// 送るのはクライアントごとのスレッドでするので、受け取らないクライアントがいても他を待たせない
+#[derive(Clone, Default)]
+pub struct EventClientList {
+    sender_list: Arc<Mutex<Vec<mpsc::SyncSender<String>>>>,
+}
+impl EventClientList {
+    // 送りきれずにこれだけ溜まったクライアントは、止まっているとみなして外す
+    const CLIENT_QUEUE_SIZE: usize = 64;
+
+    /// request を WebSocket に切り替えて、一覧に追加する
+    /// @return Err(request) WebSocket のリクエストではなかったので、そのまま返す
+    pub fn accept(&self, request: tiny_http::Request) -> Result<(), tiny_http::Request> {
+        let accept_key = request.headers().iter()
+            .find(|header| header.field.equiv("Sec-WebSocket-Key"))
+            .map(|header| tungstenite::handshake::derive_accept_key(header.value.as_bytes()));
+        let is_upgrade = request.headers().iter()
+            .any(|header| header.field.equiv("Upgrade") && header.value.as_str().eq_ignore_ascii_case("websocket"));
+        let accept_key = match accept_key {
+            Some(accept_key) if is_upgrade => accept_key,
+            _ => return Err(request),
+        };
+
+        // Upgrade, Connection は tiny_http が付けてくれる
+        let response = tiny_http::Response::empty(101)
+            .with_header(tiny_http::Header::from_bytes(&b"Sec-WebSocket-Accept"[..], accept_key.as_bytes()).unwrap());
+        let stream = request.upgrade("websocket", response);
+        let client = tungstenite::WebSocket::from_raw_socket(stream, tungstenite::protocol::Role::Server, None);
+        let (sender, receiver) = mpsc::sync_channel(Self::CLIENT_QUEUE_SIZE);
+        let result = thread::Builder::new()
+            .name("smabrog-api-event-client".to_string())
+            .spawn(move || Self::send_loop(client, receiver));
+        match result {
+            Ok(_) => {
+                self.sender_list.lock().unwrap().push(sender);
+                log::info!("websocket client connected. [{}]", self.len());
+            },
+            Err(e) => log::error!("failed spawn websocket client thread. {}", e),
+        }
+
+        Ok(())
+    }
+
+    // receiver から受け取ったものを送り続ける (送れなくなるか、一覧から外されたら切断する)
+    fn send_loop(mut client: EventClient, receiver: mpsc::Receiver<String>) {
+        for message in receiver {
+            if client.write_message(tungstenite::Message::Text(message)).is_err() {
+                return;
+            }
+        }
+        let _ = client.close(None);
+        let _ = client.write_pending();
+    }
+
+    /// 全てのクライアントに送る
+    /// (切断されたクライアントと、送りきれずに溜まったクライアントは外す)
+    pub fn broadcast(&self, message: &str) {
+        let mut sender_list = self.sender_list.lock().unwrap();
+        let len = sender_list.len();
+        sender_list.retain(|sender| match sender.try_send(message.to_string()) {
+            Ok(()) => true,
+            Err(mpsc::TrySendError::Full(_)) => {
+                log::warn!("websocket client is not receiving events. disconnect it.");
+                false
+            },
+            Err(mpsc::TrySendError::Disconnected(_)) => false,
+        });
+        if len != sender_list.len() {
+            log::info!("websocket client disconnected. [{}]", sender_list.len());
+        }
+    }
+
+    /// 繋がっているクライアントの数
+    pub fn len(&self) -> usize {
+        self.sender_list.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// EngineEvent を EngineEvent::to_json の形式で、全てのクライアントに流すスレッド
+pub struct EventStream {
+    client_list: EventClientList,
+    is_finished: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.is_finished.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+impl EventStream {
+    /// クライアントが繋ぐパス
+    pub const PATH: &'static str = "/api/events";
+    // 終了を確認する間隔
+    const RECV_TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// event_receiver から受け取ったものを流し続ける
+    pub fn spawn(event_receiver: mpsc::Receiver<EngineEvent>) -> anyhow::Result<Self> {
+        let client_list = EventClientList::default();
+        let is_finished = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let client_list = client_list.clone();
+            let is_finished = Arc::clone(&is_finished);
+            thread::Builder::new()
+                .name("smabrog-api-event".to_string())
+                .spawn(move || {
+                    while !is_finished.load(Ordering::Relaxed) {
+                        let event = match event_receiver.recv_timeout(Self::RECV_TIMEOUT) {
+                            Ok(event) => event,
+                            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                        };
+
+                        // 誰も聞いていなくてもイベントは捨てるだけ
+                        if !client_list.is_empty() {
+                            client_list.broadcast(&event.to_json().to_string());
+                        }
+                    }
+                })?
+        };
+
+        Ok(Self {
+            client_list,
+            is_finished,
+            handle: Some(handle),
+        })
+    }
+
+    /// クライアントの一覧 (サーバーのスレッドで accept する為)
+    pub fn get_client_list(&self) -> EventClientList {
+        self.client_list.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_broadcast_drop_client() {
+        let client_list = EventClientList::default();
+        // 受け取らずに溜め続けるクライアントと、切断されたクライアント
+        let (sender, _stalled_receiver) = mpsc::sync_channel(EventClientList::CLIENT_QUEUE_SIZE);
+        client_list.sender_list.lock().unwrap().push(sender);
+        let (sender, receiver) = mpsc::sync_channel(EventClientList::CLIENT_QUEUE_SIZE);
+        client_list.sender_list.lock().unwrap().push(sender);
+        drop(receiver);
+
+        client_list.broadcast("{}");
+        assert_eq!(client_list.len(), 1);
+        for _ in 1..EventClientList::CLIENT_QUEUE_SIZE {
+            client_list.broadcast("{}");
+        }
+        assert_eq!(client_list.len(), 1);
+
+        // 溜まりきったら待たずに外す
+        client_list.broadcast("{}");
+        assert!(client_list.is_empty());
+    }
+}
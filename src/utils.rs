@@ -158,6 +158,7 @@ pub mod utils {
     }
 
     /// &str -> WCHAR
+    #[cfg(windows)]
     pub fn to_wchar(value: &str) -> *mut winapi::ctypes::wchar_t {
         use std::os::windows::ffi::OsStrExt;
 
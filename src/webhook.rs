@@ -0,0 +1,412 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::collections::{
+    BTreeMap,
+    HashMap,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+use std::sync::{
+    mpsc,
+    Arc,
+};
+use std::thread;
+use std::time::Duration;
+
+use crate::context::AppContext;
+use crate::data::*;
+use crate::engine::SmashBrogEngine;
+use crate::event::EngineEvent;
+use crate::output::{
+    render_template,
+    write_atomic,
+};
+use crate::resource::BattleHistoryTrait;
+
+
+/// 戦歴が保存された時に POST する送り先
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Content-Type を指定しなければ application/json で送る
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// {data} などを置き換えて body にする
+    #[serde(default = "WebhookConfig::default_payload_template")]
+    pub payload_template: String,
+}
+impl WebhookConfig {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            headers: BTreeMap::new(),
+            payload_template: Self::default_payload_template(),
+        }
+    }
+
+    pub fn default_payload_template() -> String {
+        r#"{"data": {data}, "result": "{result}", "is_win": {is_win}, "gsp_diff": {gsp_diff}}"#.to_string()
+    }
+
+    /// テンプレートで使える値 (JSON にそのまま埋め込めるようにしてある)
+    /// {data} : 戦歴 (EngineEvent::data_to_json の形式), {is_win}, {power_1p}, {power_2p}, {gsp_diff} : JSON の値 (無ければ null)
+    /// {id}, {rule}, {result} (win/lose), {chara_1p}, {chara_2p} : JSON の文字列の中身 (" で囲んで使う)
+    pub fn make_value_list(data: &SmashbrosData, gsp_diff: Option<i32>) -> HashMap<&'static str, String> {
+        let json_string = |value: &str| {
+            // 前後の " を外す
+            let value = serde_json::to_string(value).unwrap_or_default();
+            value[1..value.len()-1].to_string()
+        };
+        let json_number = |value: i32| if value < 0 { "null".to_string() } else { value.to_string() };
+
+        let mut value_list = HashMap::new();
+        value_list.insert("data", EngineEvent::data_to_json(data).to_string());
+        value_list.insert("id", json_string(&data.get_id().unwrap_or_default()));
+        value_list.insert("rule", json_string(&format!("{:?}", data.get_rule())));
+        value_list.insert("result", match data.is_win() {
+            Some(true) => "win".to_string(),
+            Some(false) => "lose".to_string(),
+            None => String::new(),
+        });
+        value_list.insert("is_win", serde_json::json!(data.is_win()).to_string());
+        value_list.insert("chara_1p", json_string(&data.get_character(0)));
+        value_list.insert("chara_2p", json_string(&data.get_character(1)));
+        value_list.insert("power_1p", json_number(data.get_power(0)));
+        value_list.insert("power_2p", json_number(data.get_power(1)));
+        value_list.insert("gsp_diff", gsp_diff.map_or("null".to_string(), |gsp_diff| gsp_diff.to_string()));
+
+        value_list
+    }
+
+    /// data を送る為のジョブを作る (headers は送る時に設定から取る)
+    pub fn make_job(&self, value_list: &HashMap<&str, String>, now_time: i64) -> WebhookJob {
+        WebhookJob {
+            url: self.url.clone(),
+            body: render_template(&self.payload_template, value_list),
+            retry_count: 0,
+            next_time: now_time,
+        }
+    }
+}
+
+/// 送る (送り直す) もの
+/// ファイルに保存されるので、認証などの headers は持たずに url で送る時の設定を探す
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct WebhookJob {
+    pub url: String,
+    pub body: String,
+    pub retry_count: u32,
+    /// 次に送る時間 (UNIX 時間のミリ秒)
+    pub next_time: i64,
+}
+
+/// 送れなかったものを間隔をあけて送り直すキュー (再起動しても残るようにファイルに保存する)
+pub struct WebhookQueue {
+    path: Option<PathBuf>,
+    job_list: Vec<WebhookJob>,
+    retry_interval: Duration,
+}
+impl WebhookQueue {
+    /// キューを保存するファイル
+    pub const QUEUE_FILE: &'static str = "webhook_queue.json";
+    /// 最初に送り直すまでの間隔 (失敗する度に倍にする)
+    pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+    const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+    const MAX_RETRY_COUNT: u32 = 10;
+
+    /// path に保存されているキューを読み込む (path が None なら保存しない)
+    pub fn load(path: Option<&Path>, retry_interval: Duration) -> Self {
+        let job_list: Vec<WebhookJob> = path.and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|text| match serde_json::from_str(&text) {
+                Ok(job_list) => Some(job_list),
+                Err(e) => {
+                    log::warn!("skip broken webhook queue: {}", e);
+                    None
+                },
+            })
+            .unwrap_or_default();
+
+        let queue = Self {
+            path: path.map(|path| path.to_path_buf()),
+            job_list,
+            retry_interval,
+        };
+        // 以前の形式で保存されていた headers をファイルから消す
+        if !queue.is_empty() {
+            queue.save();
+        }
+
+        queue
+    }
+
+    fn save(&self) {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return,
+        };
+        let result = serde_json::to_string(&self.job_list).map_err(std::io::Error::from)
+            .and_then(|text| write_atomic(path, &text));
+        if let Err(e) = result {
+            log::error!("failed to save webhook queue: {}", e);
+        }
+    }
+
+    pub fn push(&mut self, job: WebhookJob) {
+        self.job_list.push(job);
+        self.save();
+    }
+
+    pub fn len(&self) -> usize {
+        self.job_list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.job_list.is_empty()
+    }
+
+    pub fn get_job_list(&self) -> &Vec<WebhookJob> {
+        &self.job_list
+    }
+
+    // retry_count 回目に失敗した後の間隔
+    fn get_retry_interval(&self, retry_count: u32) -> Duration {
+        let retry_interval = self.retry_interval.saturating_mul(2u32.saturating_pow(retry_count.saturating_sub(1)));
+        retry_interval.min(Self::MAX_RETRY_INTERVAL)
+    }
+
+    /// now_time (UNIX 時間のミリ秒) までに送る予定のものを、webhook_list の同じ url の設定で送る
+    /// 送れなかったものは間隔をあけて送り直す (送り先がリクエストを受け付けなかった 4xx と、送り直しすぎたもの、設定から消された送り先のものは捨てる)
+    pub fn send_due(&mut self, agent: &ureq::Agent, webhook_list: &[WebhookConfig], now_time: i64) {
+        if !self.job_list.iter().any(|job| job.next_time <= now_time) {
+            return;
+        }
+
+        let (due_job_list, mut job_list): (Vec<_>, Vec<_>) = std::mem::take(&mut self.job_list)
+            .into_iter().partition(|job| job.next_time <= now_time);
+        for mut job in due_job_list {
+            let webhook = match webhook_list.iter().find(|webhook| webhook.url == job.url) {
+                Some(webhook) => webhook,
+                None => {
+                    log::warn!("webhook skipped to {}: not in webhook_list", job.url);
+                    continue;
+                },
+            };
+            match Self::send(agent, webhook, &job) {
+                Ok(()) => log::info!("webhook sent to {}", job.url),
+                Err(ureq::Error::Status(status_code, _)) if (400..500).contains(&status_code) && status_code != 429 => {
+                    log::error!("webhook rejected by {}: {}", job.url, status_code);
+                },
+                Err(e) => {
+                    job.retry_count += 1;
+                    if Self::MAX_RETRY_COUNT < job.retry_count {
+                        log::error!("webhook gave up to {}: {}", job.url, e);
+                        continue;
+                    }
+                    let retry_interval = self.get_retry_interval(job.retry_count);
+                    log::warn!("webhook failed to {}, retry after {:?}: {}", job.url, retry_interval, e);
+                    job.next_time = now_time + retry_interval.as_millis() as i64;
+                    job_list.push(job);
+                },
+            }
+        }
+        self.job_list = job_list;
+        self.save();
+    }
+
+    fn send(agent: &ureq::Agent, webhook: &WebhookConfig, job: &WebhookJob) -> Result<(), ureq::Error> {
+        let mut request = agent.post(&job.url);
+        if !webhook.headers.keys().any(|name| name.eq_ignore_ascii_case("Content-Type")) {
+            request = request.set("Content-Type", "application/json");
+        }
+        for (name, value) in &webhook.headers {
+            request = request.set(name, value);
+        }
+        request.send_string(&job.body)?;
+
+        Ok(())
+    }
+}
+
+/// 戦歴が保存される度に webhook_list に送るスレッド
+pub struct Webhook {
+    is_finished: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+impl Drop for Webhook {
+    fn drop(&mut self) {
+        self.is_finished.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+impl Webhook {
+    // 終了と送り直しを確認する間隔
+    const RECV_TIMEOUT: Duration = Duration::from_millis(500);
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+    const LATEST_LIMIT: i64 = 50;
+
+    /// event_receiver に BattleSaved が届く度に送る (送れなかったものは queue_path に保存して送り直す)
+    pub fn spawn(context: AppContext, webhook_list: Vec<WebhookConfig>, queue_path: PathBuf, event_receiver: mpsc::Receiver<EngineEvent>) -> anyhow::Result<Self> {
+        let is_finished = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let is_finished = Arc::clone(&is_finished);
+            thread::Builder::new()
+                .name("smabrog-webhook".to_string())
+                .spawn(move || {
+                    let _guard = context.enter();
+                    let mut context = context;
+                    let agent = ureq::AgentBuilder::new().timeout(Self::REQUEST_TIMEOUT).build();
+                    let mut queue = WebhookQueue::load(Some(&queue_path), WebhookQueue::DEFAULT_RETRY_INTERVAL);
+
+                    while !is_finished.load(Ordering::Relaxed) {
+                        match event_receiver.recv_timeout(Self::RECV_TIMEOUT) {
+                            Ok(EngineEvent::BattleSaved { data }) => {
+                                let value_list = WebhookConfig::make_value_list(&data, Self::get_gsp_diff(&mut context, &data));
+                                let now_time = chrono::Local::now().timestamp_millis();
+                                for webhook in &webhook_list {
+                                    queue.push(webhook.make_job(&value_list, now_time));
+                                }
+                            },
+                            Ok(_) | Err(mpsc::RecvTimeoutError::Timeout) => (),
+                            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                        }
+
+                        queue.send_due(&agent, &webhook_list, chrono::Local::now().timestamp_millis());
+                    }
+                })?
+        };
+
+        Ok(Self {
+            is_finished,
+            handle: Some(handle),
+        })
+    }
+
+    /// data の 1P の戦闘力と、それより前の戦歴の最後の戦闘力との差
+    pub fn get_gsp_diff(battle_history: &mut dyn BattleHistoryTrait, data: &SmashbrosData) -> Option<i32> {
+        if data.get_power(0) < 0 {
+            return None;
+        }
+        let data_list = battle_history.find_data_limit(Self::LATEST_LIMIT)?;
+        let prev_index = data_list.iter().position(|prev_data| prev_data.get_id() == data.get_id())
+            .map_or(0, |index| index + 1);
+        let prev_power = *SmashBrogEngine::get_power_list_by_data_list(&data_list[prev_index..].to_vec()).last()?;
+
+        Some(data.get_power(0) - prev_power)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use crate::resource::InMemoryBattleHistory;
+
+    // status_code_list の順に応答する、受け取った X-Token と body を返すだけのサーバー
+    fn spawn_stand_in_server(status_code_list: Vec<u16>) -> (String, mpsc::Receiver<(Option<String>, String)>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}/hook", server.server_addr().to_ip().unwrap().port());
+        let (body_sender, body_receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for status_code in status_code_list {
+                let mut request = server.recv().unwrap();
+                let token = request.headers().iter()
+                    .find(|header| header.field.equiv("X-Token"))
+                    .map(|header| header.value.to_string());
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let _ = request.respond(tiny_http::Response::empty(status_code));
+                body_sender.send((token, body)).unwrap();
+            }
+        });
+
+        (url, body_receiver)
+    }
+
+    #[test]
+    fn test_webhook_payload() {
+        let mut context = AppContext::with_battle_history(Box::new(InMemoryBattleHistory::new()));
+        let mut data = SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, true, 5000000);
+        context.insert_data(&data);
+        data.set_power(0, 5100000);
+        data.set_id(context.insert_data(&data));
+
+        let gsp_diff = Webhook::get_gsp_diff(&mut context, &data);
+        assert_eq!(gsp_diff, Some(100000));
+
+        let value_list = WebhookConfig::make_value_list(&data, gsp_diff);
+        let job = WebhookConfig::new("http://127.0.0.1/").make_job(&value_list, 0);
+        let body: serde_json::Value = serde_json::from_str(&job.body).unwrap();
+        assert_eq!(body["result"], "win");
+        assert_eq!(body["is_win"], true);
+        assert_eq!(body["gsp_diff"], 100000);
+        assert_eq!(body["data"]["chara_list"][1], "LINK");
+        assert_eq!(body["data"]["id"], serde_json::json!(data.get_id()));
+    }
+
+    #[test]
+    fn test_webhook_queue_retry() {
+        let (url, body_receiver) = spawn_stand_in_server(vec![500, 200, 400]);
+        let queue_path = std::env::temp_dir().join(format!("smabrog_webhook_queue_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&queue_path);
+        let agent = ureq::agent();
+
+        let mut queue = WebhookQueue::load(Some(&queue_path), Duration::from_secs(10));
+        let mut webhook = WebhookConfig::new(&url);
+        webhook.headers.insert("X-Token".to_string(), "secret".to_string());
+        let webhook_list = vec![webhook.clone()];
+        queue.push(webhook.make_job(&WebhookConfig::make_value_list(&SmashbrosData::new_for_test("MARIO", "LINK", BattleRule::Stock, false, -1), None), 0));
+
+        // 失敗したら送り直すまで待つ
+        queue.send_due(&agent, &webhook_list, 0);
+        let (token, body) = body_receiver.recv().unwrap();
+        assert_eq!(token, Some("secret".to_string()));
+        assert!(body.contains(r#""result": "lose""#));
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.get_job_list()[0].retry_count, 1);
+        assert_eq!(queue.get_job_list()[0].next_time, 10 * 1000);
+        queue.send_due(&agent, &webhook_list, 5 * 1000);
+        assert_eq!(queue.len(), 1);
+
+        // 再起動しても残っていて、headers はファイルに保存しない
+        assert!(!std::fs::read_to_string(&queue_path).unwrap().contains("secret"));
+        let mut queue = WebhookQueue::load(Some(&queue_path), Duration::from_secs(10));
+        assert_eq!(queue.len(), 1);
+        queue.send_due(&agent, &webhook_list, 10 * 1000);
+        assert_eq!(body_receiver.recv().unwrap().0, Some("secret".to_string()));
+        assert!(queue.is_empty());
+
+        // 4xx は送り直さない
+        queue.push(webhook.make_job(&HashMap::new(), 0));
+        queue.send_due(&agent, &webhook_list, 0);
+        body_receiver.recv().unwrap();
+        assert!(queue.is_empty());
+        assert!(WebhookQueue::load(Some(&queue_path), Duration::from_secs(10)).is_empty());
+
+        // 設定から消された送り先には送らない
+        queue.push(webhook.make_job(&HashMap::new(), 0));
+        queue.send_due(&agent, &[], 0);
+        assert!(queue.is_empty());
+
+        let _ = std::fs::remove_file(&queue_path);
+    }
+
+    #[test]
+    fn test_webhook_retry_interval() {
+        let queue = WebhookQueue::load(None, Duration::from_secs(10));
+        assert_eq!(queue.get_retry_interval(1), Duration::from_secs(10));
+        assert_eq!(queue.get_retry_interval(3), Duration::from_secs(40));
+        assert_eq!(queue.get_retry_interval(30), Duration::from_secs(60 * 60));
+    }
+}